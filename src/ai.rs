@@ -0,0 +1,192 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+    },
+    thread,
+};
+
+use chess_rs::{Game, SearchLimits, SearchResult, Searcher, Tablebase, UciEngine};
+
+use crate::events::EventSender;
+
+enum Job {
+    Search {
+        id: u64,
+        board: Box<Game>,
+        limits: SearchLimits,
+        stop: Arc<AtomicBool>,
+        ponder: Arc<AtomicBool>,
+    },
+    NewGame,
+}
+
+// A finished search for the AI's move
+pub struct AiReply {
+    id: u64,
+    pub result: SearchResult,
+    // Set when the external engine failed and the built-in one stood in for it
+    pub engine_error: Option<String>,
+}
+
+impl AiReply {
+    pub fn is_for(&self, thinking: &Thinking) -> bool {
+        self.id == thinking.id
+    }
+}
+
+// A search handed to the AI thread; dropping it abandons the search
+pub struct Thinking {
+    id: u64,
+    // Hash of the position being searched
+    pub hash: u64,
+    // Started as pondering, whether or not it has been hit since
+    pub pondered: bool,
+    stop: Arc<AtomicBool>,
+    ponder: Arc<AtomicBool>,
+}
+
+impl Thinking {
+    // The predicted move was played: the search goes on, now against the clock
+    pub fn ponder_hit(&self) {
+        self.ponder.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Thinking {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::Relaxed);
+    }
+}
+
+// Searching the position after the predicted reply while the player is on move
+pub struct Ponder {
+    pub search: Thinking,
+    // Hash of the position the player is moving from
+    pub from_hash: u64,
+    // Search result, if it finished before the player moved
+    pub reply: Option<AiReply>,
+}
+
+// Searches for the AI's moves on a worker thread, so the UI stays responsive
+// while it thinks and it can think on the player's time as well. Finished
+// searches come back to the main loop as events
+pub struct AiPlayer {
+    jobs: Sender<Job>,
+    next_id: u64,
+    // Name of the external engine playing, None once the built-in one plays
+    engine_name: Option<String>,
+}
+
+impl AiPlayer {
+    // Plays with the external engine if one is given, otherwise with the built-in
+    // one searching on `threads` threads
+    pub fn spawn(
+        engine: Option<UciEngine>,
+        tablebase: Option<Arc<Tablebase>>,
+        threads: usize,
+        reply_tx: EventSender<AiReply>,
+    ) -> Self {
+        let (jobs, job_rx) = mpsc::channel::<Job>();
+        let engine_name = engine.as_ref().map(|engine| engine.get_name().to_string());
+
+        // The worker exits once the App (and with it the job sender) is dropped
+        thread::spawn(move || {
+            let mut engine = engine;
+            let mut searcher = Searcher::new();
+            searcher.set_tablebase(tablebase);
+            searcher.set_threads(threads);
+            while let Ok(job) = job_rx.recv() {
+                // A broken engine fails its next search too, and is replaced then
+                let Job::Search {
+                    id,
+                    board,
+                    limits,
+                    stop,
+                    ponder,
+                } = job
+                else {
+                    if let Some(external) = engine.as_mut() {
+                        let _ = external.new_game();
+                    }
+                    continue;
+                };
+                let mut engine_error = None;
+                if let Some(external) = engine.as_mut() {
+                    external.set_stop_signal(Some(stop.clone()));
+                    match external.search(&board, limits) {
+                        Ok(result) => {
+                            let _ = reply_tx.send(AiReply {
+                                id,
+                                result,
+                                engine_error,
+                            });
+                            continue;
+                        }
+                        Err(e) => {
+                            engine = None;
+                            engine_error = Some(e.to_string());
+                        }
+                    }
+                }
+                searcher.set_stop_signal(Some(stop));
+                searcher.set_ponder_signal(Some(ponder));
+                let result = searcher.search(&board, limits);
+                let _ = reply_tx.send(AiReply {
+                    id,
+                    result,
+                    engine_error,
+                });
+            }
+        });
+
+        AiPlayer {
+            jobs,
+            next_id: 0,
+            engine_name,
+        }
+    }
+
+    pub fn engine_name(&self) -> Option<&str> {
+        self.engine_name.as_deref()
+    }
+
+    // Only the built-in engine can ponder
+    pub fn can_ponder(&self) -> bool {
+        self.engine_name.is_none()
+    }
+
+    pub fn new_game(&self) {
+        let _ = self.jobs.send(Job::NewGame);
+    }
+
+    // Starts searching the position; with `ponder` the time limit only starts
+    // counting after a ponder hit
+    pub fn think(&mut self, board: &Game, limits: SearchLimits, ponder: bool) -> Thinking {
+        self.next_id += 1;
+        let thinking = Thinking {
+            id: self.next_id,
+            hash: board.hash(),
+            pondered: ponder,
+            stop: Arc::new(AtomicBool::new(false)),
+            ponder: Arc::new(AtomicBool::new(ponder)),
+        };
+        let _ = self.jobs.send(Job::Search {
+            id: thinking.id,
+            board: Box::new(board.clone()),
+            limits,
+            stop: thinking.stop.clone(),
+            ponder: thinking.ponder.clone(),
+        });
+        thinking
+    }
+
+    // Takes note of a finished search, abandoned or not; once the external
+    // engine has failed the built-in one plays on
+    pub fn received(&mut self, reply: &AiReply) {
+        if reply.engine_error.is_some() {
+            self.engine_name = None;
+        }
+    }
+}
@@ -0,0 +1,235 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+use chess_rs::{
+    ColorChess, Game, Move, SearchLimits, SearchResult, Searcher, Tablebase, UciEngine, Wdl,
+};
+
+use crate::experience::ExperienceStore;
+
+// How long the background engine looks at a position for the eval bar alone
+pub const EVAL_TIME: Duration = Duration::from_millis(500);
+
+// How long it keeps analysing while the analysis panel is open
+pub const ANALYSIS_TIME: Duration = Duration::from_secs(60);
+
+// Progress of the background engine on one position, tagged with the position's
+// hash so results for an older position can be told apart
+#[derive(Clone)]
+pub struct Analysis {
+    pub hash: u64,
+    // Centipawns from White's point of view
+    pub white_score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    pub nodes_per_second: u64,
+    // The engine's choice, for the arrow on the board
+    pub best_move: Option<Move>,
+    // The best moves with their lines, best first
+    pub lines: Vec<AnalysisLine>,
+    // The tablebase verdict, e.g. "White wins, DTZ 13", when the position is in them
+    pub exact: Option<String>,
+    // Read back from the experience file rather than searched just now
+    pub saved: bool,
+}
+
+// One candidate move's score from White's point of view and its line in SAN,
+// with move numbers
+#[derive(Clone)]
+pub struct AnalysisLine {
+    pub white_score: i32,
+    pub pv: String,
+}
+
+// Where the analysis comes from
+enum Backend {
+    BuiltIn(Searcher),
+    External(UciEngine),
+}
+
+struct Request {
+    board: Game,
+    time: Duration,
+    multi_pv: usize,
+    stop: Arc<AtomicBool>,
+}
+
+// Runs the engine on a worker thread so the UI never waits for it
+pub struct Analyzer {
+    requests: Sender<Request>,
+    updates: Receiver<Analysis>,
+    // Stops the search for the previous request when a new one is made
+    current_stop: Arc<AtomicBool>,
+}
+
+impl Analyzer {
+    // Analyses with the external engine if one is given, otherwise with the
+    // built-in one searching on `threads` threads. With an experience store,
+    // positions analysed before start from what was saved of them
+    pub fn spawn(
+        engine: Option<UciEngine>,
+        tablebase: Option<Arc<Tablebase>>,
+        threads: usize,
+        mut experience: Option<ExperienceStore>,
+    ) -> Self {
+        let (requests, request_rx) = mpsc::channel::<Request>();
+        let (update_tx, updates) = mpsc::channel();
+
+        // The worker exits once the App (and with it the request sender) is dropped
+        thread::spawn(move || {
+            let built_in = || {
+                let mut searcher = Searcher::new();
+                searcher.set_tablebase(tablebase.clone());
+                searcher.set_threads(threads);
+                Backend::BuiltIn(searcher)
+            };
+            let mut backend = match engine {
+                Some(engine) => Backend::External(engine),
+                None => built_in(),
+            };
+            while let Ok(mut request) = request_rx.recv() {
+                // Only the newest position matters if several moves were made meanwhile
+                while let Ok(newer) = request_rx.try_recv() {
+                    request = newer;
+                }
+                let board = &request.board;
+                let limits = SearchLimits {
+                    multi_pv: request.multi_pv,
+                    ..SearchLimits::time(request.time)
+                };
+                let stop = Some(request.stop.clone());
+                let exact = tablebase
+                    .as_deref()
+                    .and_then(|tablebase| exact_result(tablebase, board));
+                let for_white = |score: i32| match board.get_current_turn() {
+                    ColorChess::White => score,
+                    ColorChess::Black => -score,
+                };
+                // What was saved of the position stands until the search gets
+                // deeper than it
+                let recalled = experience
+                    .as_ref()
+                    .and_then(|experience| experience.recall(board, request.multi_pv));
+                let floor = recalled.as_ref().map_or(0, |analysis| analysis.depth);
+                if let Some(analysis) = recalled {
+                    let _ = update_tx.send(Analysis {
+                        exact: exact.clone(),
+                        ..analysis
+                    });
+                }
+                let mut on_iteration = |result: &SearchResult| {
+                    if result.depth <= floor {
+                        return;
+                    }
+                    let analysis = Analysis {
+                        hash: board.hash(),
+                        white_score: for_white(result.score),
+                        depth: result.depth,
+                        nodes: result.nodes,
+                        nodes_per_second: result.nodes_per_second(),
+                        best_move: result.best_move,
+                        lines: result
+                            .lines
+                            .iter()
+                            .map(|line| AnalysisLine {
+                                white_score: for_white(line.score),
+                                pv: board.line_to_san(&line.pv),
+                            })
+                            .collect(),
+                        exact: exact.clone(),
+                        saved: false,
+                    };
+                    // Saved as it goes, since quitting ends the search
+                    // without warning; a failed write only loses this depth
+                    if let Some(experience) = &mut experience
+                        && experience.remember(&analysis)
+                    {
+                        let _ = experience.save();
+                    }
+                    let _ = update_tx.send(analysis);
+                };
+                let engine_failed = match &mut backend {
+                    Backend::BuiltIn(searcher) => {
+                        searcher.set_stop_signal(stop);
+                        searcher.search_with_progress(board, limits, &mut on_iteration);
+                        false
+                    }
+                    Backend::External(engine) => {
+                        engine.set_stop_signal(stop);
+                        engine
+                            .search_with_progress(board, limits, &mut on_iteration)
+                            .is_err()
+                    }
+                };
+                // An engine that crashed or hung is replaced by the built-in one
+                if engine_failed {
+                    backend = built_in();
+                }
+            }
+        });
+
+        Analyzer {
+            requests,
+            updates,
+            current_stop: Arc::new(AtomicBool::new(false)),
+        }
+    }
+
+    // Starts analysing the best `multi_pv` moves of a new position for up to
+    // `time`, abandoning the old one
+    pub fn request(&mut self, board: &Game, time: Duration, multi_pv: usize) {
+        self.stop();
+        self.current_stop = Arc::new(AtomicBool::new(false));
+        let _ = self.requests.send(Request {
+            board: board.clone(),
+            time,
+            multi_pv,
+            stop: self.current_stop.clone(),
+        });
+    }
+
+    pub fn stop(&self) {
+        self.current_stop.store(true, Ordering::Relaxed);
+    }
+
+    // The most recent update, if any arrived since the last call
+    pub fn latest(&self) -> Option<Analysis> {
+        self.updates.try_iter().last()
+    }
+}
+
+impl Drop for Analyzer {
+    // Lets the worker finish, and shut down an external engine, promptly
+    fn drop(&mut self) {
+        self.stop();
+    }
+}
+
+// Describes the tablebase result from White's or Black's side rather than the mover's
+fn exact_result(tablebase: &Tablebase, board: &Game) -> Option<String> {
+    let wdl = tablebase.probe_wdl(board)?;
+    let mover = board.get_current_turn();
+    let verdict = match wdl {
+        Wdl::Draw => return Some("Draw".to_string()),
+        Wdl::CursedWin | Wdl::BlessedLoss => {
+            return Some("Draw by the fifty-move rule".to_string());
+        }
+        Wdl::Win => mover,
+        Wdl::Loss => mover.opposite(),
+    };
+    let side = match verdict {
+        ColorChess::White => "White",
+        ColorChess::Black => "Black",
+    };
+    Some(match tablebase.probe_dtz(board) {
+        Some(dtz) => format!("{} wins, DTZ {}", side, dtz.abs()),
+        None => format!("{} wins", side),
+    })
+}
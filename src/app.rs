@@ -0,0 +1,4827 @@
+use std::{
+    collections::BTreeMap,
+    path::Path,
+    sync::{Arc, mpsc::Receiver},
+    time::{Duration, Instant},
+};
+
+use chess_rs::clock::{Clock, Stopwatch, TimeControl};
+use chess_rs::eval::evaluate;
+use chess_rs::fog::{FogView, ServerMessage};
+use chess_rs::pgn::{self, MoveTiming, PgnGame};
+use chess_rs::search::{MATE_SCORE, MATE_THRESHOLD, TB_WIN_THRESHOLD};
+use chess_rs::tree::{self, Eval, GameTree, ROOT};
+use chess_rs::{
+    ColorChess, Difficulty, Game, GameResult, Move, MoveList, OpeningBook, PROMOTION_PIECES, Piece,
+    PieceType, SearchResult, Tablebase, Termination, UciEngine, square_name,
+};
+use crossterm::event::{Event as CrosstermEvent, KeyCode, MouseButton, MouseEventKind};
+use serde::{Deserialize, Serialize};
+use tui::layout::Rect;
+
+use crate::ai::{AiPlayer, AiReply, Ponder, Thinking};
+use crate::analysis::{ANALYSIS_TIME, Analysis, Analyzer, EVAL_TIME};
+use crate::broadcast::Broadcast;
+use crate::coach;
+use crate::config::{
+    self, BookConfig, ClockConfig, EngineConfig, FicsConfig, LichessConfig, PlayersConfig,
+    PuzzleConfig, TablebaseConfig,
+};
+use crate::correspondence::{self, CorrespondenceGame, CorrespondenceStore};
+use crate::editor::Editor;
+use crate::endgame::{self, ENDGAMES, Verdict};
+use crate::events::{AppEvent, EventSender, Events, NetworkMsg};
+use crate::experience::ExperienceStore;
+use crate::explorer::OpeningTree;
+use crate::fics::{Fics, FicsEvent, Style12};
+use crate::fog_client::{FogClient, FogEvent};
+use crate::game_db::{self, GameDatabase};
+use crate::guess::{self, GuessRun};
+use crate::import;
+use crate::keymap::{Action, KeyList, KeyMap};
+use crate::layout::{BoardLayout, SQUARE_HEIGHTS};
+use crate::lichess::{self, Challenge, GameState, Lichess, LichessEvent, OnlineGame, Server};
+use crate::move_list::{self, MoveRow};
+use crate::puzzle::{self, MOTIFS, Motif, Puzzle, PuzzleRun, PuzzleStore};
+use crate::puzzle_db::{self, PuzzleFilter};
+use crate::ratings::RatingStore;
+use crate::repertoire::{Drill, Repertoire, RepertoireStore};
+use crate::review::{self, Review, ReviewUpdate};
+use crate::theme::{Blindfold, PieceStyle, Theme};
+use crate::toast::Toasts;
+use crate::tutorial::LESSONS;
+
+// Which screen the TUI is currently showing
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Screen {
+    MainMenu,
+    Settings,
+    ColorSelect,
+    // The player's correspondence games on Lichess
+    Correspondence,
+    // The games of a Lichess broadcast round
+    Broadcast,
+    // Puzzle motifs to drill, with the results of each
+    Tactics,
+    // The engine's verdict on the moves of the game just played
+    Review,
+    // Finished games kept in the local database
+    Games,
+    // The moves played in those games from a position, to be followed
+    Explorer,
+    // The player's ratings against the AI and the hot-seat players'
+    Ratings,
+    // A position being set up piece by piece
+    Editor,
+    Game,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum MenuItem {
+    ResumeGame,
+    NewGame,
+    LoadGame,
+    // Set up a position in the board editor to play or analyse from
+    SetupPosition,
+    VsAi,
+    // Seek a game on Lichess, or accept the challenge that came in
+    Lichess,
+    AcceptChallenge,
+    Correspondence,
+    // Seek a game on the Free Internet Chess Server
+    Fics,
+    // One puzzle a day, solved to keep a streak going
+    DailyPuzzle,
+    Puzzles,
+    // Puzzles of one motif, such as forks or pins
+    Tactics,
+    // Preset endgames to convert against the engine
+    Endgames,
+    // The rules tutorial
+    Tutorial,
+    // The player's ratings, against each difficulty and in hot-seat games
+    Ratings,
+    Settings,
+    Quit,
+}
+
+impl MenuItem {
+    pub fn label(self) -> &'static str {
+        match self {
+            MenuItem::ResumeGame => "Resume game",
+            MenuItem::NewGame => "New game",
+            MenuItem::LoadGame => "Load game",
+            MenuItem::SetupPosition => "Set up position",
+            MenuItem::VsAi => "Play vs AI",
+            MenuItem::Lichess => "Play on Lichess",
+            MenuItem::AcceptChallenge => "Accept challenge",
+            MenuItem::Correspondence => "Correspondence",
+            MenuItem::Fics => "Play on FICS",
+            MenuItem::DailyPuzzle => "Daily puzzle",
+            MenuItem::Puzzles => "Solve puzzles",
+            MenuItem::Tactics => "Tactics by theme",
+            MenuItem::Endgames => "Endgame drills",
+            MenuItem::Tutorial => "Learn the rules",
+            MenuItem::Ratings => "Ratings",
+            MenuItem::Settings => "Settings",
+            MenuItem::Quit => "Quit",
+        }
+    }
+}
+
+// Side picked on the color selection screen
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ColorChoice {
+    White,
+    Black,
+    Random,
+}
+
+pub const COLOR_CHOICES: [ColorChoice; 3] =
+    [ColorChoice::White, ColorChoice::Black, ColorChoice::Random];
+
+impl ColorChoice {
+    pub fn label(self) -> &'static str {
+        match self {
+            ColorChoice::White => "White",
+            ColorChoice::Black => "Black",
+            ColorChoice::Random => "Random",
+        }
+    }
+
+    fn resolve(self) -> ColorChess {
+        match self {
+            ColorChoice::White => ColorChess::White,
+            ColorChoice::Black => ColorChess::Black,
+            ColorChoice::Random => {
+                // The clock's low bits are random enough for a coin flip
+                let nanos = std::time::SystemTime::now()
+                    .duration_since(std::time::UNIX_EPOCH)
+                    .map(|d| d.subsec_nanos())
+                    .unwrap_or(0);
+                if nanos & 1 == 0 {
+                    ColorChess::White
+                } else {
+                    ColorChess::Black
+                }
+            }
+        }
+    }
+}
+
+pub const SETTINGS_COUNT: usize = 16;
+
+// How long a peek shows the pieces in blindfold play
+const PEEK_DURATION: Duration = Duration::from_secs(2);
+
+// File the game is saved to when quitting in the middle of it
+pub const QUIT_SAVE_PATH: &str = "game.pgn";
+
+// File the analysis board's tree is exported to unless another is named
+const ANALYSIS_PATH: &str = "analysis.pgn";
+
+// Most candidate moves the analysis panel can show at once
+const MAX_ANALYSIS_LINES: usize = 5;
+
+// Options changed from the settings screen and saved to the config file
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Settings {
+    pub show_legal_moves: bool,
+    // Hot-seat play: turn the board towards the side to move after every move
+    pub rotate_board: bool,
+    // Hide the board between turns until the next player presses a key
+    pub pass_screen: bool,
+    pub difficulty: Difficulty,
+    pub show_eval_bar: bool,
+    // Candidate moves shown in the analysis panel, each with its own line
+    pub analysis_lines: usize,
+    // Let the AI think on the player's time about the reply it expects
+    pub ponder: bool,
+    // Colors of the board, pieces and text; 't' cycles through them in a game
+    pub theme: Theme,
+    // Glyphs or letters for the pieces, for fonts without the chess symbols
+    pub piece_style: PieceStyle,
+    // Rows each square of the board takes up, from 1 to 4 (and twice as many
+    // columns); the largest that fits the terminal when unset
+    pub square_size: Option<u16>,
+    // Slide moved pieces across the board instead of moving them at once
+    pub animate_moves: bool,
+    // Vim-style controls in a game: ':' commands and 'v' to select squares
+    pub vim_mode: bool,
+    // Time each side gets in new games; untimed when unset
+    pub time_control: Option<TimeControl>,
+    // Fog-of-war chess: each side only sees the squares its pieces stand on,
+    // attack or can move to, until the game is over
+    pub fog_of_war: bool,
+    // Pieces left off the board, with moves typed after '/'
+    pub blindfold: Blindfold,
+    // Warn before playing a move that a quick search finds loses material
+    // or allows mate; never in online games
+    pub coach: bool,
+    // Only set in the config file
+    // Threads the built-in engine searches with, both as the AI and for analysis
+    pub threads: usize,
+    // Keep deep analysis in experience.toml, so that analysing a position
+    // again carries on from where it stopped
+    pub experience: bool,
+    pub engine: EngineConfig,
+    pub book: BookConfig,
+    pub clock: ClockConfig,
+    pub tablebase: TablebaseConfig,
+    pub lichess: LichessConfig,
+    pub fics: FicsConfig,
+    pub puzzles: PuzzleConfig,
+    pub players: PlayersConfig,
+    // The [keys] section: keys for actions, in place of their default ones
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub keys: BTreeMap<Action, KeyList>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            show_legal_moves: true,
+            rotate_board: false,
+            pass_screen: false,
+            difficulty: Difficulty::default(),
+            show_eval_bar: true,
+            analysis_lines: 1,
+            ponder: false,
+            theme: Theme::default(),
+            piece_style: PieceStyle::default(),
+            square_size: None,
+            animate_moves: true,
+            vim_mode: false,
+            time_control: None,
+            fog_of_war: false,
+            blindfold: Blindfold::default(),
+            coach: false,
+            threads: 1,
+            experience: false,
+            engine: EngineConfig::default(),
+            book: BookConfig::default(),
+            clock: ClockConfig::default(),
+            tablebase: TablebaseConfig::default(),
+            lichess: LichessConfig::default(),
+            fics: FicsConfig::default(),
+            puzzles: PuzzleConfig::default(),
+            players: PlayersConfig::default(),
+            keys: BTreeMap::new(),
+        }
+    }
+}
+
+impl Settings {
+    // (label, value) for each entry on the settings screen
+    pub fn entries(&self) -> [(&'static str, String); SETTINGS_COUNT] {
+        [
+            ("Highlight legal moves", on_off(self.show_legal_moves)),
+            ("Hot-seat board rotation", on_off(self.rotate_board)),
+            ("Pass-the-keyboard screen", on_off(self.pass_screen)),
+            ("AI difficulty", self.difficulty.label().to_string()),
+            ("Evaluation bar", on_off(self.show_eval_bar)),
+            ("Analysis lines", self.analysis_lines.to_string()),
+            ("AI pondering", on_off(self.ponder)),
+            ("Theme", self.theme.label().to_string()),
+            ("Piece style", self.piece_style.label().to_string()),
+            ("Square size", self.square_size_label()),
+            ("Move animation", on_off(self.animate_moves)),
+            ("Vim mode", on_off(self.vim_mode)),
+            ("Clock", self.time_control_label()),
+            ("Fog of war", on_off(self.fog_of_war)),
+            ("Blindfold", self.blindfold.label().to_string()),
+            ("Blunder check (coach)", on_off(self.coach)),
+        ]
+    }
+
+    fn toggle(&mut self, index: usize) {
+        match index {
+            0 => self.show_legal_moves = !self.show_legal_moves,
+            1 => self.rotate_board = !self.rotate_board,
+            2 => self.pass_screen = !self.pass_screen,
+            3 => self.difficulty = self.difficulty.next(),
+            4 => self.show_eval_bar = !self.show_eval_bar,
+            5 => self.analysis_lines = self.analysis_lines % MAX_ANALYSIS_LINES + 1,
+            6 => self.ponder = !self.ponder,
+            7 => self.theme = self.theme.next(),
+            8 => self.piece_style = self.piece_style.next(),
+            9 => self.square_size = next_square_size(self.square_size),
+            10 => self.animate_moves = !self.animate_moves,
+            11 => self.vim_mode = !self.vim_mode,
+            12 => self.time_control = TimeControl::cycle(self.time_control, 1),
+            13 => self.fog_of_war = !self.fog_of_war,
+            14 => self.blindfold = self.blindfold.next(),
+            15 => self.coach = !self.coach,
+            _ => {}
+        }
+    }
+
+    fn time_control_label(&self) -> String {
+        match self.time_control {
+            Some(control) => control.label(),
+            None => "Off".to_string(),
+        }
+    }
+
+    fn square_size_label(&self) -> String {
+        match self.square_size {
+            Some(height) => format!("{} row{}", height, if height == 1 { "" } else { "s" }),
+            None => "Auto".to_string(),
+        }
+    }
+}
+
+// Auto, then each square height from the smallest up
+fn next_square_size(size: Option<u16>) -> Option<u16> {
+    let mut sizes = SQUARE_HEIGHTS;
+    sizes.sort_unstable();
+    match size {
+        None => Some(sizes[0]),
+        Some(height) => sizes.into_iter().find(|&h| h > height),
+    }
+}
+
+fn on_off(value: bool) -> String {
+    if value { "On" } else { "Off" }.to_string()
+}
+
+// The analysis board: a position of the game or of a variation, shown from
+// the move list. Moves tried there go into the game tree as variations,
+// while the game itself goes on underneath, untouched
+pub struct Browse {
+    // The shown position's node in the game tree
+    pub node: usize,
+    pub board: Game,
+}
+
+// How long a moved piece takes to slide to its new square
+const ANIMATION_TIME: Duration = Duration::from_millis(200);
+
+// The piece of the last move sliding from its old square to its new one
+pub struct Animation {
+    pub piece: Piece,
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    started: Instant,
+}
+
+impl Animation {
+    // How far along the slide is, from 0 to 1
+    pub fn progress(&self) -> f32 {
+        (self.started.elapsed().as_secs_f32() / ANIMATION_TIME.as_secs_f32()).min(1.0)
+    }
+}
+
+// A game action waiting for a yes/no answer
+#[derive(Clone, Copy, PartialEq)]
+pub enum PendingAction {
+    Resign(ColorChess),
+    DrawOffer(ColorChess),
+    // A move the coach warned about, to be played anyway or taken back
+    ConfirmMove(Move),
+}
+
+// What a line typed on the analysis board is for
+#[derive(Clone, Copy, PartialEq)]
+pub enum Prompt {
+    // Symbols and NAGs for the move shown
+    Annotations,
+    Comment,
+    // The file to export the analysis to
+    Export,
+}
+
+// --- TUI Application State ---
+pub struct App {
+    pub screen: Screen,
+    pub menu_index: usize,
+    pub settings_index: usize,
+    pub correspondence_index: usize,
+    pub broadcast_index: usize,
+    pub tactics_index: usize,
+    pub games_index: usize,
+    pub settings: Settings,
+    // Why config.toml could not be read. The defaults are used instead, and
+    // nothing is saved, so that the file is not written over with them
+    settings_error: Option<String>,
+    pub color_index: usize,
+    // Color given on the command line; skips the color selection screen
+    pub preset_color: Option<ColorChess>,
+    // Time control of the next new game: from --tc or the settings, and
+    // changed on the new-game screen
+    pub time_control: Option<TimeControl>,
+    pub should_quit: bool,
+    // Quitting in the middle of a game waits for the player to confirm it
+    pub confirm_quit: bool,
+    // Which keys do what, from the defaults and the [keys] section
+    pub keys: KeyMap,
+    pub board: Game,
+    // Side the player is playing (the AI takes the other one)
+    pub player_color: ColorChess,
+    // Whether the other side is played by the engine
+    pub vs_ai: bool,
+    // Connection to Lichess, when the [lichess] section has a token
+    lichess: Option<Lichess>,
+    // The Lichess game on the board, if it is an online game
+    pub online: Option<OnlineGame>,
+    // The latest challenge from another Lichess player, until it is answered
+    challenge: Option<Challenge>,
+    // The puzzle on the board in puzzle mode
+    pub puzzle: Option<PuzzleRun>,
+    // Puzzle rating and downloaded puzzles
+    pub puzzles: PuzzleStore,
+    // Ratings from games against the AI and hot-seat games
+    pub ratings: RatingStore,
+    // A puzzle being downloaded, and whether the player is waiting for it
+    puzzle_download: Option<Receiver<Result<Puzzle, String>>>,
+    awaiting_puzzle: bool,
+    // A puzzle being picked from the local database
+    puzzle_pick: Option<Receiver<Result<Puzzle, String>>>,
+    // Today's puzzle while it is being fetched
+    daily_download: Option<Receiver<Puzzle>>,
+    // Puzzles loaded from a file, played before any from Lichess
+    puzzle_file: Vec<Puzzle>,
+    // The motif puzzles are drilled on; None mixes them all
+    pub puzzle_motif: Option<Motif>,
+    // Guessing the moves of master games from a PGN file
+    pub guess: Option<GuessRun>,
+    // Drilling an opening repertoire
+    pub drill: Option<Drill>,
+    // The preset endgame being drilled, by its index in ENDGAMES
+    pub endgame: Option<usize>,
+    // The tutorial lesson on the board, by its index in LESSONS
+    pub lesson: Option<usize>,
+    // The engine's review of the game once it is done, and the search for
+    // it while it runs, with how many positions it has searched of how many
+    pub review: Option<Review>,
+    reviewing: Option<Receiver<ReviewUpdate>>,
+    pub review_progress: Option<(usize, usize)>,
+    // The mistake picked on the review screen
+    pub review_index: usize,
+    // The FICS connection, made when first asked for a game, and whether a
+    // seek waits for the login to finish
+    fics: Option<Fics>,
+    fics_seek: bool,
+    // What a FICS connection reports through
+    fics_events: EventSender<FicsEvent>,
+    // The connection to a fog-of-war server, and the player's view of its
+    // game; the board then holds only what the view shows
+    fog_server: Option<FogClient>,
+    fog_view: Option<FogView>,
+    fog_events: EventSender<FogEvent>,
+    // Correspondence games on Lichess, kept between runs
+    pub correspondence: CorrespondenceStore,
+    // Every game finished here, kept between runs
+    pub games: GameDatabase,
+    // The database's games as a tree of positions, read when the explorer
+    // is first opened and again after a game is added
+    pub opening_tree: Option<OpeningTree>,
+    // The position the explorer is at, and the move picked there
+    pub explorer_board: Game,
+    pub explorer_index: usize,
+    // The position being set up in the board editor
+    pub editor: Editor,
+    // The broadcast round being followed, if any
+    pub broadcast: Option<Broadcast>,
+    // A game being downloaded from Lichess or chess.com
+    import: Option<Receiver<Result<PgnGame, String>>>,
+    // Plays the AI side, with the external engine if one is configured
+    ai: AiPlayer,
+    // Finished searches not yet looked at; held back while the game is paused
+    ai_replies: Vec<AiReply>,
+    // The AI's search for its move while it is on move
+    thinking: Option<Thinking>,
+    // The AI's search on the player's expected move while the player is on move
+    pondering: Option<Ponder>,
+    // Opening book the AI plays from before it starts searching
+    book: Option<OpeningBook>,
+    // Syzygy tablebases, when configured; endgame drills are judged by them
+    tablebase: Option<Arc<Tablebase>>,
+    analyzer: Analyzer,
+    // Latest background engine result; may lag behind the board by a move
+    pub analysis: Option<Analysis>,
+    // Whether the live analysis panel is open
+    pub show_analysis: bool,
+    // Whether the squares are shaded by how many pieces of each side attack them
+    pub show_attacks: bool,
+    // Side shown at the bottom of the board
+    pub player_perspective: ColorChess,
+    pub selected_square: Option<(usize, usize)>, // (row, col) of the currently selected piece
+    // Square under the keyboard cursor; Enter acts on it like a click
+    pub cursor: (usize, usize),
+    // Notifications for the player, newest last
+    pub toasts: Toasts,
+    // How a puzzle, drill or lesson ended, or an online game that has no
+    // result; a game that has one is told by the board's result
+    pub finish_message: Option<String>,
+    // Resignation or draw offer waiting to be confirmed/answered with y/n
+    pub pending_action: Option<PendingAction>,
+    // The player's move while the coach looks it over
+    coach_check: Option<(Move, Receiver<Option<String>>)>,
+    // Store all legal moves for the currently selected piece for highlighting
+    pub possible_moves: Vec<Move>,
+    // Legal moves of the side to move, generated once per position; the hash
+    // tells which position they belong to
+    turn_moves: MoveList,
+    turn_moves_hash: Option<u64>,
+    // The game's moves with the variations tried on the analysis board or
+    // read from a PGN; the game is kept as its main line, ending at game_node
+    pub tree: GameTree,
+    pub game_node: usize,
+    // The move list as laid out for the panel, and what it was laid out
+    // from: the position, the moves played, the tree's revision and the width
+    pub move_rows: Vec<MoveRow>,
+    move_rows_key: Option<(u64, usize, u64, usize)>,
+    // Top row of the move list when scrolled back; None keeps the latest moves in view
+    move_list_scroll: Option<usize>,
+    pub browse: Option<Browse>,
+    // Time spent on the move being played
+    move_timer: Stopwatch,
+    // How long each move took, by ply; undone moves keep theirs for a redo
+    move_times: Vec<Option<MoveTiming>>,
+    // Set when the terminal bell should ring, until the main loop rings it
+    pub bell: bool,
+    // Last move's piece while it is still sliding into place
+    pub animation: Option<Animation>,
+    // Promotion move waiting for the player to pick a piece in the promotion dialog
+    pub pending_promotion: Option<Move>,
+    // Hot-seat handover: the board stays hidden until the next player presses a key
+    pub awaiting_handover: bool,
+    // Game paused: the clocks and the AI wait and the board takes no input
+    pub paused: bool,
+    // Whether the help overlay with the controls is open
+    pub show_help: bool,
+    // Command typed after ':' in vim mode, while it is being typed
+    pub command_line: Option<String>,
+    // Move typed after '/', while it is being typed
+    pub move_input: Option<String>,
+    // A line being typed on the analysis board, and what it is for
+    pub prompt: Option<(Prompt, String)>,
+    // Whether moves, annotations or comments were added on the analysis
+    // board since it was last exported
+    unsaved_analysis: bool,
+    // Until when a peek shows the pieces in blindfold play, and how many
+    // peeks the game has had
+    peek_until: Option<Instant>,
+    peeks: u32,
+    // Terminal size the game screen was last laid out for, and that layout;
+    // None when the terminal is too small for the board
+    pub terminal_area: Rect,
+    pub layout: Option<BoardLayout>,
+}
+
+impl App {
+    // Background work started by the app reports back through `events`
+    pub fn new(
+        preset_color: Option<ColorChess>,
+        time_control: Option<TimeControl>,
+        events: &Events,
+    ) -> App {
+        let board = Game::new();
+        let player_color = preset_color.unwrap_or(ColorChess::White);
+        let mut toasts = Toasts::default();
+        toasts.push("Welcome to Chess! Choose an option from the menu.");
+        let (settings, settings_error) = match config::load_settings() {
+            Ok(settings) => (settings, None),
+            Err(e) => {
+                toasts.push(format!(
+                    "Could not load settings ({}), using defaults. Changes will not be saved.",
+                    e
+                ));
+                (Settings::default(), Some(e))
+            }
+        };
+        let engine = start_engine(&settings.engine, settings.engine.opponent, &mut toasts);
+        let analysis_engine = start_engine(&settings.engine, settings.engine.analysis, &mut toasts);
+        let book = match settings.book.open() {
+            Some(Ok(book)) => Some(book),
+            Some(Err(e)) => {
+                toasts.push(format!("Could not open the opening book ({}).", e));
+                None
+            }
+            None => None,
+        };
+        let keys = KeyMap::new(&settings.keys).unwrap_or_else(|e| {
+            toasts.push(format!("Ignoring the [keys] section ({}).", e));
+            KeyMap::default()
+        });
+        let time_control = time_control.or(settings.time_control);
+        let lichess = settings.lichess.token.as_deref().map(|token| {
+            Lichess::connect(
+                token,
+                events.sender(|event| AppEvent::NetworkMsg(NetworkMsg::Lichess(event))),
+            )
+        });
+        let correspondence = CorrespondenceStore::load().unwrap_or_else(|e| {
+            toasts.push(format!("Could not load the correspondence games ({}).", e));
+            CorrespondenceStore::default()
+        });
+        let games = GameDatabase::load().unwrap_or_else(|e| {
+            toasts.push(format!("Could not load the game database ({}).", e));
+            GameDatabase::default()
+        });
+        let puzzles = PuzzleStore::load().unwrap_or_else(|e| {
+            toasts.push(format!("Could not load the puzzle rating ({}).", e));
+            PuzzleStore::default()
+        });
+        let ratings = RatingStore::load().unwrap_or_else(|e| {
+            toasts.push(format!("Could not load the ratings ({}).", e));
+            RatingStore::default()
+        });
+        let threads = settings.threads.max(1);
+        let experience = settings.experience.then(|| {
+            ExperienceStore::load().unwrap_or_else(|e| {
+                toasts.push(format!("Could not load the analysis experience ({}).", e));
+                ExperienceStore::default()
+            })
+        });
+        let tablebase = match settings.tablebase.open() {
+            Some(Ok(tablebase)) => Some(Arc::new(tablebase)),
+            Some(Err(e)) => {
+                toasts.push(format!("Could not open the tablebases ({}).", e));
+                None
+            }
+            None => None,
+        };
+        App {
+            screen: Screen::MainMenu,
+            menu_index: 0,
+            settings_index: 0,
+            correspondence_index: 0,
+            broadcast_index: 0,
+            tactics_index: 0,
+            games_index: 0,
+            settings,
+            settings_error,
+            color_index: 0,
+            preset_color,
+            time_control,
+            should_quit: false,
+            confirm_quit: false,
+            keys,
+            board,
+            player_color,
+            vs_ai: false,
+            lichess,
+            online: None,
+            challenge: None,
+            puzzle: None,
+            puzzles,
+            ratings,
+            puzzle_download: None,
+            awaiting_puzzle: false,
+            puzzle_pick: None,
+            daily_download: None,
+            puzzle_file: Vec::new(),
+            puzzle_motif: None,
+            guess: None,
+            drill: None,
+            endgame: None,
+            lesson: None,
+            review: None,
+            reviewing: None,
+            review_progress: None,
+            review_index: 0,
+            import: None,
+            correspondence,
+            games,
+            opening_tree: None,
+            explorer_board: Game::new(),
+            explorer_index: 0,
+            editor: Editor::from_board(&Game::new()),
+            fics: None,
+            fics_seek: false,
+            fics_events: events.sender(|event| AppEvent::NetworkMsg(NetworkMsg::Fics(event))),
+            fog_server: None,
+            fog_view: None,
+            fog_events: events.sender(|event| AppEvent::NetworkMsg(NetworkMsg::Fog(event))),
+            broadcast: None,
+            ai: AiPlayer::spawn(
+                engine,
+                tablebase.clone(),
+                threads,
+                events.sender(AppEvent::EngineMove),
+            ),
+            ai_replies: Vec::new(),
+            thinking: None,
+            pondering: None,
+            book,
+            analyzer: Analyzer::spawn(analysis_engine, tablebase.clone(), threads, experience),
+            tablebase,
+            analysis: None,
+            show_analysis: false,
+            show_attacks: false,
+            player_perspective: player_color,
+            selected_square: None,
+            cursor: start_cursor(player_color),
+            toasts,
+            finish_message: None,
+            pending_action: None,
+            coach_check: None,
+            possible_moves: Vec::new(),
+            turn_moves: MoveList::new(),
+            turn_moves_hash: None,
+            tree: GameTree::new(Game::new()),
+            game_node: ROOT,
+            move_rows: Vec::new(),
+            move_rows_key: None,
+            move_list_scroll: None,
+            browse: None,
+            move_timer: Stopwatch::default(),
+            move_times: Vec::new(),
+            bell: false,
+            animation: None,
+            pending_promotion: None,
+            awaiting_handover: false,
+            paused: false,
+            show_help: false,
+            command_line: None,
+            move_input: None,
+            prompt: None,
+            unsaved_analysis: false,
+            peek_until: None,
+            peeks: 0,
+            terminal_area: Rect::default(),
+            layout: None,
+        }
+    }
+
+    // Starts a fresh game; a rematch also swaps the sides
+    fn new_game(&mut self, rematch: bool) {
+        if rematch {
+            self.player_color = self.player_color.opposite();
+        }
+        self.screen = Screen::Game;
+        self.board = Game::new();
+        self.tree = GameTree::new(Game::new());
+        self.unsaved_analysis = false;
+        self.online = None;
+        // Leaving a game on a fog-of-war server forfeits it
+        self.fog_server = None;
+        self.fog_view = None;
+        self.puzzle = None;
+        self.guess = None;
+        self.drill = None;
+        self.endgame = None;
+        self.lesson = None;
+        self.review = None;
+        self.reviewing = None;
+        self.review_progress = None;
+        self.coach_check = None;
+        if let Some(broadcast) = &mut self.broadcast {
+            broadcast.watching = None;
+        }
+        self.player_perspective = self.player_color;
+        self.cursor = start_cursor(self.player_color);
+        self.browse = None;
+        self.animation = None;
+        self.board.set_clock(self.time_control.map(Clock::new));
+        self.move_timer = Stopwatch::default();
+        self.move_times.clear();
+        self.move_list_scroll = None;
+        self.clear_selection();
+        self.finish_message = None;
+        self.pending_action = None;
+        self.awaiting_handover = false;
+        self.peek_until = None;
+        self.peeks = 0;
+        self.paused = false;
+        self.analysis = None;
+        self.thinking = None;
+        self.pondering = None;
+        self.ai.new_game();
+        self.follow_turn();
+        self.request_analysis();
+        self.notify(if rematch {
+            format!("Rematch started. You are playing {:?}.", self.player_color)
+        } else {
+            format!(
+                "New game started. You are playing {:?}. Click a piece to move, '?' for help.",
+                self.player_color
+            )
+        });
+        if self.is_ai_turn() {
+            self.notify("The AI is thinking...");
+        }
+    }
+
+    // Menu entries; "Resume game" is only offered while a game is in progress
+    pub fn menu_items(&self) -> Vec<MenuItem> {
+        let mut items = Vec::new();
+        if self.is_game_in_progress() {
+            items.push(MenuItem::ResumeGame);
+        }
+        if self.challenge.is_some() {
+            items.push(MenuItem::AcceptChallenge);
+        }
+        items.extend([
+            MenuItem::NewGame,
+            MenuItem::LoadGame,
+            MenuItem::SetupPosition,
+            MenuItem::VsAi,
+            MenuItem::Lichess,
+            MenuItem::Correspondence,
+            MenuItem::Fics,
+            MenuItem::DailyPuzzle,
+            MenuItem::Puzzles,
+            MenuItem::Tactics,
+            MenuItem::Endgames,
+            MenuItem::Tutorial,
+            MenuItem::Ratings,
+            MenuItem::Settings,
+            MenuItem::Quit,
+        ]);
+        items
+    }
+
+    // A correspondence game carries on on Lichess, so it is not lost on quitting
+    // Whether the board takes no more moves: the game has a result, or the
+    // exercise on it is done
+    pub fn is_over(&self) -> bool {
+        self.board.result().is_some() || self.finish_message.is_some()
+    }
+
+    // How the game or exercise ended, in words for the player
+    pub fn ending(&self) -> Option<String> {
+        self.finish_message.clone().or_else(|| {
+            self.board
+                .result()
+                .map(|result| format!("{} ({})", result, result.pgn_tag()))
+        })
+    }
+
+    fn is_game_in_progress(&self) -> bool {
+        // A fog-of-war server sends positions without the moves to them
+        (self.board.can_undo() || self.fog_view.is_some())
+            && !self.is_over()
+            && !self.online.as_ref().is_some_and(|game| game.correspondence)
+            && !self.is_watching()
+    }
+
+    fn handle_key(&mut self, key_code: KeyCode) {
+        if self.confirm_quit {
+            self.handle_quit_key(key_code);
+            return;
+        }
+        match self.screen {
+            Screen::MainMenu => self.handle_menu_key(key_code),
+            Screen::Settings => self.handle_settings_key(key_code),
+            Screen::ColorSelect => self.handle_color_select_key(key_code),
+            Screen::Correspondence => self.handle_correspondence_key(key_code),
+            Screen::Tactics => self.handle_tactics_key(key_code),
+            Screen::Review => self.handle_review_key(key_code),
+            Screen::Games => self.handle_games_key(key_code),
+            Screen::Explorer => self.handle_explorer_key(key_code),
+            Screen::Ratings => self.handle_ratings_key(key_code),
+            Screen::Broadcast => self.handle_broadcast_key(key_code),
+            Screen::Editor => self.handle_editor_key(key_code),
+            Screen::Game => self.handle_game_key(key_code),
+        }
+    }
+
+    fn handle_menu_key(&mut self, key_code: KeyCode) {
+        let items = self.menu_items();
+        self.menu_index = self.menu_index.min(items.len() - 1);
+        let actions = [
+            Action::Up,
+            Action::Down,
+            Action::Select,
+            Action::Quit,
+            Action::Menu,
+        ];
+        match self.keys.action(key_code, &actions) {
+            Some(Action::Up) => {
+                self.menu_index = (self.menu_index + items.len() - 1) % items.len();
+            }
+            Some(Action::Down) => {
+                self.menu_index = (self.menu_index + 1) % items.len();
+            }
+            Some(Action::Select) => self.select_menu_item(items[self.menu_index]),
+            Some(Action::Quit | Action::Menu) => self.request_quit(),
+            _ => {}
+        }
+    }
+
+    // Quits straight away unless a game is still being played, which would be lost
+    fn request_quit(&mut self) {
+        if self.is_game_in_progress() {
+            self.confirm_quit = true;
+        } else {
+            self.should_quit = true;
+        }
+    }
+
+    fn handle_quit_key(&mut self, key_code: KeyCode) {
+        if key_code == KeyCode::Char('s') {
+            self.confirm_quit = false;
+            if self.save_pgn(QUIT_SAVE_PATH) {
+                self.should_quit = true;
+            }
+            return;
+        }
+        match self
+            .keys
+            .action(key_code, &[Action::Yes, Action::No, Action::Menu])
+        {
+            Some(Action::Yes) => self.should_quit = true,
+            Some(_) => self.confirm_quit = false,
+            None => {}
+        }
+    }
+
+    fn select_menu_item(&mut self, item: MenuItem) {
+        match item {
+            MenuItem::ResumeGame => {
+                self.screen = Screen::Game;
+                self.follow_turn();
+                self.request_analysis();
+            }
+            MenuItem::NewGame | MenuItem::VsAi => {
+                self.vs_ai = item == MenuItem::VsAi;
+                match self.preset_color {
+                    Some(color) => {
+                        self.player_color = color;
+                        self.new_game(false);
+                    }
+                    None => self.screen = Screen::ColorSelect,
+                }
+            }
+            MenuItem::Lichess => self.seek_online(),
+            MenuItem::AcceptChallenge => self.accept_challenge(),
+            MenuItem::Fics => self.seek_fics(),
+            MenuItem::Correspondence => {
+                self.correspondence_index = 0;
+                self.screen = Screen::Correspondence;
+            }
+            MenuItem::DailyPuzzle => self.start_daily_puzzle(),
+            MenuItem::Puzzles => self.start_puzzles(),
+            MenuItem::Tactics => self.screen = Screen::Tactics,
+            MenuItem::Endgames => self.start_endgame(0),
+            MenuItem::Tutorial => self.start_lesson(0),
+            MenuItem::Ratings => self.screen = Screen::Ratings,
+            MenuItem::Settings => self.screen = Screen::Settings,
+            MenuItem::Quit => self.request_quit(),
+            MenuItem::LoadGame => {
+                self.games_index = 0;
+                self.screen = Screen::Games;
+            }
+            MenuItem::SetupPosition => self.open_editor(),
+        }
+    }
+
+    fn handle_color_select_key(&mut self, key_code: KeyCode) {
+        match key_code {
+            KeyCode::Char('w') => return self.start_with_color(ColorChoice::White),
+            KeyCode::Char('b') => return self.start_with_color(ColorChoice::Black),
+            _ => {}
+        }
+        let actions = [
+            Action::Up,
+            Action::Down,
+            Action::Left,
+            Action::Right,
+            Action::Select,
+            Action::Quit,
+            Action::Menu,
+        ];
+        match self.keys.action(key_code, &actions) {
+            // Left and right pick the time control
+            Some(Action::Left) => self.time_control = TimeControl::cycle(self.time_control, -1),
+            Some(Action::Right) => self.time_control = TimeControl::cycle(self.time_control, 1),
+            Some(Action::Up) => {
+                self.color_index =
+                    (self.color_index + COLOR_CHOICES.len() - 1) % COLOR_CHOICES.len();
+            }
+            Some(Action::Down) => {
+                self.color_index = (self.color_index + 1) % COLOR_CHOICES.len();
+            }
+            Some(Action::Select) => self.start_with_color(COLOR_CHOICES[self.color_index]),
+            Some(Action::Quit | Action::Menu) => self.screen = Screen::MainMenu,
+            _ => {}
+        }
+    }
+
+    fn start_with_color(&mut self, choice: ColorChoice) {
+        self.player_color = choice.resolve();
+        self.new_game(false);
+    }
+
+    fn handle_correspondence_key(&mut self, key_code: KeyCode) {
+        if key_code == KeyCode::Char('s') {
+            return self.seek_correspondence();
+        }
+        let count = self.correspondence.len();
+        let actions = [
+            Action::Up,
+            Action::Down,
+            Action::Select,
+            Action::Quit,
+            Action::Menu,
+        ];
+        match self.keys.action(key_code, &actions) {
+            Some(Action::Up) => {
+                self.correspondence_index = self.correspondence_index.saturating_sub(1);
+            }
+            Some(Action::Down) if self.correspondence_index + 1 < count => {
+                self.correspondence_index += 1;
+            }
+            Some(Action::Select) => {
+                let games = self.correspondence.games();
+                if let Some(game) = games.get(self.correspondence_index) {
+                    let game = (*game).clone();
+                    self.open_correspondence(game);
+                }
+            }
+            Some(Action::Quit | Action::Menu) => self.screen = Screen::MainMenu,
+            _ => {}
+        }
+    }
+
+    // The first entry mixes all motifs, the others follow MOTIFS
+    fn handle_tactics_key(&mut self, key_code: KeyCode) {
+        let actions = [
+            Action::Up,
+            Action::Down,
+            Action::Select,
+            Action::Quit,
+            Action::Menu,
+        ];
+        match self.keys.action(key_code, &actions) {
+            Some(Action::Up) => self.tactics_index = self.tactics_index.saturating_sub(1),
+            Some(Action::Down) if self.tactics_index < MOTIFS.len() => self.tactics_index += 1,
+            Some(Action::Select) => {
+                let motif = self.tactics_index.checked_sub(1).map(|index| MOTIFS[index]);
+                self.drill_motif(motif);
+            }
+            Some(Action::Quit | Action::Menu) => self.screen = Screen::MainMenu,
+            _ => {}
+        }
+    }
+
+    // Enter opens the game picked, newest first, to be stepped through
+    fn handle_games_key(&mut self, key_code: KeyCode) {
+        let count = self.games.len();
+        let actions = [
+            Action::Up,
+            Action::Down,
+            Action::Select,
+            Action::Quit,
+            Action::Menu,
+        ];
+        match self.keys.action(key_code, &actions) {
+            Some(Action::Up) => self.games_index = self.games_index.saturating_sub(1),
+            Some(Action::Down) if self.games_index + 1 < count => self.games_index += 1,
+            Some(Action::Select) => {
+                let Some(game) = self.games.games().nth(self.games_index) else {
+                    return;
+                };
+                match game_db::open(game) {
+                    Ok(game) => self.replay_game(game, "Opened"),
+                    Err(e) => self.notify(format!("Could not open the game: {}", e)),
+                }
+            }
+            Some(Action::Quit | Action::Menu) => self.screen = Screen::MainMenu,
+            _ => {}
+        }
+    }
+
+    fn handle_ratings_key(&mut self, key_code: KeyCode) {
+        if let Some(Action::Quit | Action::Menu) =
+            self.keys.action(key_code, &[Action::Quit, Action::Menu])
+        {
+            self.screen = Screen::MainMenu;
+        }
+    }
+
+    // Enter follows the move picked, ← goes back a move
+    fn handle_explorer_key(&mut self, key_code: KeyCode) {
+        let moves = self
+            .opening_tree
+            .as_ref()
+            .map_or(&[][..], |tree| tree.moves(&self.explorer_board));
+        let count = moves.len();
+        let picked = moves.get(self.explorer_index).map(|stats| stats.mv);
+        let actions = [
+            Action::Up,
+            Action::Down,
+            Action::Left,
+            Action::Select,
+            Action::Quit,
+            Action::Menu,
+        ];
+        match self.keys.action(key_code, &actions) {
+            Some(Action::Up) => self.explorer_index = self.explorer_index.saturating_sub(1),
+            Some(Action::Down) if self.explorer_index + 1 < count => self.explorer_index += 1,
+            Some(Action::Select) => {
+                if let Some(mv) = picked
+                    && self.explorer_board.play_move(mv).is_ok()
+                {
+                    self.explorer_index = 0;
+                }
+            }
+            Some(Action::Left) if self.explorer_board.can_undo() => {
+                self.explorer_board.undo();
+                self.explorer_index = 0;
+            }
+            Some(Action::Quit | Action::Menu) => self.screen = Screen::Game,
+            _ => {}
+        }
+    }
+
+    // Opens the explorer on the position shown
+    fn open_explorer(&mut self) {
+        if self.fog_viewer().is_some() {
+            return self.notify("The explorer would give away what is in the fog.");
+        }
+        if self.opening_tree.is_none() {
+            self.opening_tree = Some(OpeningTree::build(&self.games));
+        }
+        self.explorer_board = self.shown_board().clone();
+        self.explorer_index = 0;
+        self.screen = Screen::Explorer;
+    }
+
+    // Up and down pick a mistake, which Enter shows on the analysis board
+    fn handle_review_key(&mut self, key_code: KeyCode) {
+        let count = self
+            .review
+            .as_ref()
+            .map_or(0, |review| review.key_moves().len());
+        let actions = [
+            Action::Up,
+            Action::Down,
+            Action::Select,
+            Action::Quit,
+            Action::Menu,
+        ];
+        match self.keys.action(key_code, &actions) {
+            Some(Action::Up) => self.review_index = self.review_index.saturating_sub(1),
+            Some(Action::Down) if self.review_index + 1 < count => self.review_index += 1,
+            Some(Action::Select) => self.show_key_move(),
+            Some(Action::Quit | Action::Menu) => self.screen = Screen::Game,
+            _ => {}
+        }
+    }
+
+    fn handle_settings_key(&mut self, key_code: KeyCode) {
+        let actions = [
+            Action::Up,
+            Action::Down,
+            Action::Select,
+            Action::Quit,
+            Action::Menu,
+        ];
+        match self.keys.action(key_code, &actions) {
+            Some(Action::Up) => {
+                self.settings_index = self.settings_index.saturating_sub(1);
+            }
+            Some(Action::Down) if self.settings_index + 1 < SETTINGS_COUNT => {
+                self.settings_index += 1;
+            }
+            Some(Action::Select) => {
+                let time_control = self.settings.time_control;
+                self.settings.toggle(self.settings_index);
+                if self.settings.time_control != time_control {
+                    self.time_control = self.settings.time_control;
+                }
+                // The square size may have changed
+                self.resize(self.terminal_area);
+            }
+            Some(Action::Quit | Action::Menu) => {
+                self.screen = Screen::MainMenu;
+                if let Err(e) = self.save_settings() {
+                    self.notify(format!("Could not save settings: {}", e));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_game_key(&mut self, key_code: KeyCode) {
+        // Any key reveals the board to the next hot-seat player
+        if self.awaiting_handover {
+            self.awaiting_handover = false;
+            return;
+        }
+
+        // Any key closes the help overlay
+        if self.show_help {
+            self.show_help = false;
+            return;
+        }
+
+        // A paused game only takes the key that resumes it, or quitting
+        if self.paused {
+            match self.keys.action(key_code, &[Action::Pause, Action::Quit]) {
+                Some(Action::Pause) => self.toggle_pause(),
+                Some(_) => self.request_quit(),
+                None => self.notify(format!(
+                    "Game paused. Press {} to resume.",
+                    self.keys.describe(Action::Pause)
+                )),
+            }
+            return;
+        }
+
+        // A ':' command takes every key until it is run or abandoned
+        if self.command_line.is_some() {
+            self.handle_command_key(key_code);
+            return;
+        }
+        if self.move_input.is_some() {
+            self.handle_move_input_key(key_code);
+            return;
+        }
+        if self.prompt.is_some() {
+            self.handle_prompt_key(key_code);
+            return;
+        }
+
+        // The promotion dialog captures keys until a piece is chosen or it is cancelled
+        if self.pending_promotion.is_some() {
+            self.handle_promotion_key(key_code);
+            return;
+        }
+
+        // Resignations and draw offers wait for a yes/no answer
+        if self.pending_action.is_some() {
+            match self
+                .keys
+                .action(key_code, &[Action::Yes, Action::No, Action::Menu])
+            {
+                Some(Action::Yes) => return self.answer_pending_action(true),
+                Some(_) => return self.answer_pending_action(false),
+                None => {}
+            }
+        }
+
+        // The analysis board steps back and forth with the arrows as well as
+        // undo and redo, which leave the game alone
+        if self.browse.is_some() {
+            let actions = [
+                Action::Menu,
+                Action::Left,
+                Action::Right,
+                Action::Undo,
+                Action::Redo,
+                Action::PromoteLine,
+                Action::DemoteLine,
+                Action::Annotate,
+                Action::Comment,
+            ];
+            match self.keys.action(key_code, &actions) {
+                Some(Action::Menu) => return self.leave_analysis(),
+                None if key_code == KeyCode::End => return self.leave_analysis(),
+                Some(Action::Left | Action::Undo) => return self.browse_back(),
+                Some(Action::Right | Action::Redo) => return self.browse_forward(),
+                Some(Action::PromoteLine) => return self.reorder_line(true),
+                Some(Action::DemoteLine) => return self.reorder_line(false),
+                Some(Action::Annotate) => return self.start_annotation(),
+                Some(Action::Comment) => return self.start_comment(),
+                _ => {}
+            }
+        }
+
+        // Vim mode: ':' opens the command line and 'v' selects the square under
+        // the cursor, with a piece picked up staying selected as a visual selection
+        // until the move is made or Esc drops it
+        if self.settings.vim_mode {
+            match key_code {
+                KeyCode::Char(':') => {
+                    self.command_line = Some(String::new());
+                    return;
+                }
+                KeyCode::Char('v') => return self.handle_board_click(self.cursor),
+                _ if self.selected_square.is_some()
+                    && self.keys.action(key_code, &[Action::Menu]).is_some() =>
+                {
+                    self.clear_selection();
+                    self.notify("Selection cleared.");
+                    return;
+                }
+                _ => {}
+            }
+        }
+
+        let game_over = self.is_over();
+        let actions = [
+            Action::Help,
+            Action::Quit,
+            Action::Menu,
+            Action::NewGame,
+            Action::Rematch,
+            Action::Resign,
+            Action::OfferDraw,
+            Action::Undo,
+            Action::Redo,
+            Action::Flip,
+            Action::Analysis,
+            Action::AttackMap,
+            Action::Theme,
+            Action::Pause,
+            Action::ScrollUp,
+            Action::ScrollDown,
+            Action::Up,
+            Action::Down,
+            Action::Left,
+            Action::Right,
+            Action::Select,
+            Action::TypeMove,
+            Action::Peek,
+            Action::Explore,
+            Action::Annotate,
+            Action::Comment,
+            Action::Review,
+            Action::Explorer,
+        ];
+        match self.keys.action(key_code, &actions) {
+            Some(Action::Help) => self.show_help = true,
+            Some(Action::Quit) => self.request_quit(),
+            Some(Action::Menu) if self.is_watching() => self.screen = Screen::Broadcast,
+            Some(Action::Menu) => self.screen = Screen::MainMenu,
+            // A puzzle can be skipped or started over at any time
+            Some(Action::NewGame) if self.puzzle.is_some() => self.next_puzzle(),
+            Some(Action::Rematch) if self.puzzle.is_some() => self.retry_puzzle(),
+            Some(Action::NewGame) if self.guess.is_some() => self.next_guess_game(),
+            Some(Action::NewGame) if self.drill.is_some() => self.next_drill_line(),
+            Some(Action::NewGame) if self.endgame.is_some() => self.next_endgame(1),
+            Some(Action::Rematch) if self.endgame.is_some() => self.next_endgame(0),
+            Some(Action::NewGame) if self.lesson.is_some() => self.next_lesson(1),
+            Some(Action::Rematch) if self.lesson.is_some() => self.next_lesson(0),
+            // Post-game screen actions
+            Some(Action::NewGame) if game_over => self.new_game(false),
+            Some(Action::Rematch) if game_over => self.new_game(true),
+            Some(Action::Resign) => self.resign(),
+            Some(Action::OfferDraw) => self.offer_draw(),
+            Some(Action::Undo) => self.undo_move(),
+            Some(Action::Redo) => self.redo_move(),
+            Some(Action::Flip) => self.flip_board(),
+            Some(Action::Analysis) => self.toggle_analysis(),
+            Some(Action::AttackMap) => self.toggle_attack_map(),
+            Some(Action::Theme) => self.next_theme(),
+            Some(Action::Pause) => self.toggle_pause(),
+            Some(Action::ScrollUp) => self.scroll_move_list_page(-1),
+            Some(Action::ScrollDown) => self.scroll_move_list_page(1),
+            // Keyboard alternative to the mouse
+            Some(Action::Up) => self.move_cursor(-1, 0),
+            Some(Action::Down) => self.move_cursor(1, 0),
+            Some(Action::Left) => self.move_cursor(0, -1),
+            Some(Action::Right) => self.move_cursor(0, 1),
+            Some(Action::Select) => self.handle_board_click(self.cursor),
+            Some(Action::TypeMove) => self.move_input = Some(String::new()),
+            Some(Action::Peek) => self.peek(),
+            Some(Action::Explore) => self.toggle_explore(),
+            Some(Action::Review) => self.open_review(),
+            Some(Action::Explorer) => self.open_explorer(),
+            Some(Action::Annotate | Action::Comment) => self.notify(format!(
+                "Moves are annotated on the analysis board: press {} and pick one.",
+                self.keys.describe(Action::Explore)
+            )),
+            _ => {}
+        }
+    }
+
+    fn handle_move_input_key(&mut self, key_code: KeyCode) {
+        let Some(input) = self.move_input.as_mut() else {
+            return;
+        };
+        match key_code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Enter => {
+                let input = self.move_input.take().unwrap_or_default();
+                self.play_typed_move(input.trim());
+            }
+            KeyCode::Esc => self.move_input = None,
+            _ => {}
+        }
+    }
+
+    fn handle_prompt_key(&mut self, key_code: KeyCode) {
+        let Some((prompt, input)) = self.prompt.as_mut() else {
+            return;
+        };
+        match key_code {
+            KeyCode::Char(c) => input.push(c),
+            KeyCode::Backspace => {
+                input.pop();
+            }
+            KeyCode::Enter => {
+                let Some((prompt, input)) = self.prompt.take() else {
+                    return;
+                };
+                match prompt {
+                    Prompt::Annotations => self.annotate(&input),
+                    Prompt::Comment => self.comment(&input),
+                    Prompt::Export => self.export_analysis(input.trim()),
+                }
+            }
+            KeyCode::Esc => {
+                // Declining the export keeps the analysis, without asking
+                // again until more is added
+                if *prompt == Prompt::Export {
+                    self.unsaved_analysis = false;
+                }
+                self.prompt = None;
+            }
+            _ => {}
+        }
+    }
+
+    // Opens the prompt for the annotations of the move on the analysis
+    // board, with those it has already
+    fn start_annotation(&mut self) {
+        let node = self.shown_node();
+        if node == ROOT {
+            return self.notify("Step to a move to annotate it.");
+        }
+        let nags: Vec<String> = self
+            .tree
+            .nags(node)
+            .iter()
+            .map(|&nag| tree::nag_text(nag))
+            .collect();
+        self.prompt = Some((Prompt::Annotations, nags.join(" ")));
+    }
+
+    // Replaces the annotations of the move on the analysis board with those
+    // typed, symbols such as "!?" or NAGs such as "$14"; nothing clears them
+    fn annotate(&mut self, text: &str) {
+        let mut nags = Vec::new();
+        for word in text.split_whitespace() {
+            match tree::parse_nag(word) {
+                Some(nag) if !nags.contains(&nag) => nags.push(nag),
+                Some(_) => {}
+                None => {
+                    return self.notify(format!(
+                        "{} is not an annotation: use !, ?, !!, ??, !?, ?! or $1 to $255.",
+                        word
+                    ));
+                }
+            }
+        }
+        let node = self.shown_node();
+        self.sync_tree();
+        let cleared = nags.is_empty();
+        self.tree.set_nags(node, nags);
+        self.unsaved_analysis = true;
+        if cleared {
+            self.notify(format!(
+                "Annotations of {} cleared.",
+                self.tree.move_label(node)
+            ));
+        } else {
+            self.notify(format!("Annotated {}.", self.tree.move_label(node)));
+        }
+    }
+
+    // Opens the prompt for the comment after the move on the analysis board,
+    // or before the first move on the starting position
+    fn start_comment(&mut self) {
+        let comment = self.tree.comment(self.shown_node()).unwrap_or_default();
+        self.prompt = Some((Prompt::Comment, comment.to_string()));
+    }
+
+    // Replaces the comment on the analysis board's position; nothing
+    // removes it
+    fn comment(&mut self, text: &str) {
+        let node = self.shown_node();
+        self.sync_tree();
+        let text = text.trim();
+        let comment = (!text.is_empty()).then(|| text.to_string());
+        let removed = comment.is_none();
+        self.tree.set_comment(node, comment);
+        self.unsaved_analysis = true;
+        self.notify(if removed {
+            "Comment removed."
+        } else {
+            "Comment saved."
+        });
+    }
+
+    // Leaves the analysis board, offering to export what was added there
+    fn leave_analysis(&mut self) {
+        self.stop_browsing();
+        if self.unsaved_analysis {
+            self.prompt = Some((Prompt::Export, ANALYSIS_PATH.to_string()));
+        }
+    }
+
+    // Writes the game tree as PGN: the game, the moves taken back and every
+    // variation, with their annotations, comments and the engine's evals
+    fn export_analysis(&mut self, path: &str) {
+        if path.is_empty() {
+            return self.notify("Name a file to export the analysis to.");
+        }
+        let pgn = self.game_pgn(usize::MAX);
+        match std::fs::write(path, pgn) {
+            Ok(()) => {
+                self.unsaved_analysis = false;
+                self.notify(format!("Exported the analysis to {}.", path));
+            }
+            Err(e) => {
+                self.notify(format!("Could not export the analysis to {}: {}", path, e));
+                self.prompt = Some((Prompt::Export, path.to_string()));
+            }
+        }
+    }
+
+    // Plays a move typed in SAN, e.g. "Nf3" or "exd8=Q", or in UCI, "e7e8q"
+    fn play_typed_move(&mut self, text: &str) {
+        if text.is_empty() || !self.may_move() {
+            return;
+        }
+        let board = *self.shown_board().position();
+        let parsed = if self.fog_view.is_some() {
+            // A game on a fog-of-war server has no check, so the text is
+            // matched against the moves the server sent rather than parsed
+            let bare = |san: &str| san.trim_end_matches(['+', '#', '!', '?']).to_string();
+            self.turn_moves()
+                .iter()
+                .copied()
+                .find(|&mv| mv.to_uci() == text || bare(&board.move_to_san(mv)) == bare(text))
+        } else {
+            board
+                .parse_san(text)
+                .or_else(|_| board.parse_uci_move(text))
+                .ok()
+        };
+        let Some(mv) = parsed else {
+            return self.notify(format!("{} is not a legal move here.", text));
+        };
+        self.clear_selection();
+        self.play_move(mv);
+    }
+
+    // Shows the pieces for a moment in blindfold play
+    fn peek(&mut self) {
+        if self.settings.blindfold == Blindfold::Off {
+            return self.notify("Peeking is for blindfold play, which is off.");
+        }
+        self.peek_until = Some(Instant::now() + PEEK_DURATION);
+        self.peeks += 1;
+        self.notify(format!("Peek {} this game.", self.peeks));
+    }
+
+    // Whether blindfold play leaves the pieces of a side off the board; the
+    // player is the side to move in hot-seat games, and all is shown once
+    // the game is over or during a peek
+    pub fn is_blindfolded(&self, color: ColorChess) -> bool {
+        if self.is_over() || self.peek_until.is_some_and(|until| Instant::now() < until) {
+            return false;
+        }
+        let player = if self.vs_ai || self.online.is_some() {
+            self.player_color
+        } else {
+            self.board.get_current_turn()
+        };
+        self.settings.blindfold.hides(color, player)
+    }
+
+    fn handle_command_key(&mut self, key_code: KeyCode) {
+        let Some(command) = self.command_line.as_mut() else {
+            return;
+        };
+        match key_code {
+            KeyCode::Char(c) => command.push(c),
+            KeyCode::Backspace if command.pop().is_some() => {}
+            // Deleting past the ':' leaves the command line, as in vim
+            KeyCode::Backspace => self.command_line = None,
+            KeyCode::Enter => {
+                let command = self.command_line.take().unwrap_or_default();
+                self.run_command(&command);
+            }
+            KeyCode::Esc => self.command_line = None,
+            _ => {}
+        }
+    }
+
+    // Runs a command typed after ':' in vim mode
+    fn run_command(&mut self, command: &str) {
+        let mut words = command.split_whitespace();
+        let Some(name) = words.next() else {
+            return;
+        };
+        let argument = words.collect::<Vec<_>>().join(" ");
+        match name {
+            "q" | "quit" => self.request_quit(),
+            "q!" | "quit!" => self.should_quit = true,
+            "w" | "save" => {
+                let path = if argument.is_empty() {
+                    "game.pgn"
+                } else {
+                    argument.as_str()
+                };
+                self.save_pgn(path);
+            }
+            "export" => {
+                let path = if argument.is_empty() {
+                    ANALYSIS_PATH
+                } else {
+                    argument.as_str()
+                };
+                self.export_analysis(path);
+            }
+            "fen" => self.notify(self.shown_board().to_fen()),
+            "resign" => self.resign(),
+            "draw" => self.offer_draw(),
+            "undo" => self.undo_move(),
+            "redo" => self.redo_move(),
+            "flip" => self.flip_board(),
+            "pause" => self.toggle_pause(),
+            "new" => self.new_game(false),
+            "challenge" if !argument.is_empty() => self.challenge_player(&argument),
+            "import" if !argument.is_empty() => self.import_game(&argument),
+            "broadcast" if !argument.is_empty() => self.follow_broadcast(&argument),
+            "puzzles" if !argument.is_empty() => self.load_puzzle_file(&argument),
+            "guess" if !argument.is_empty() => self.start_guessing(&argument),
+            "repertoire" if !argument.is_empty() => {
+                // An optional side after the file: ":repertoire sicilian.pgn black"
+                let (path, color) = match argument.rsplit_once(' ') {
+                    Some((path, "white")) => (path, Some(ColorChess::White)),
+                    Some((path, "black")) => (path, Some(ColorChess::Black)),
+                    _ => (argument.as_str(), None),
+                };
+                self.start_drill(path, color);
+            }
+            "endgame" => self.start_endgame_by_name(&argument),
+            "tutorial" => match argument.parse::<usize>() {
+                Ok(number) if (1..=LESSONS.len()).contains(&number) => {
+                    self.start_lesson(number - 1);
+                }
+                _ if argument.is_empty() => self.start_lesson(0),
+                _ => self.notify(format!("There are lessons 1 to {}.", LESSONS.len())),
+            },
+            "daily" => self.start_daily_puzzle(),
+            "review" => self.open_review(),
+            "tactics" if argument.is_empty() => self.drill_motif(None),
+            "tactics" => match Motif::parse(&argument) {
+                Some(motif) => self.drill_motif(Some(motif)),
+                None => {
+                    let themes: Vec<_> = MOTIFS.iter().map(|motif| motif.theme()).collect();
+                    self.notify(format!(
+                        "Unknown theme: {} (try {})",
+                        argument,
+                        themes.join(", ")
+                    ));
+                }
+            },
+            "help" => self.show_help = true,
+            _ => self.notify(format!("Unknown command: {}", name)),
+        }
+    }
+
+    // Writes the game so far to a PGN file, returning whether that worked
+    fn save_pgn(&mut self, path: &str) -> bool {
+        let pgn = self.game_pgn(self.board.get_history().len());
+        match std::fs::write(path, pgn) {
+            Ok(()) => {
+                self.notify(format!("Saved the game to {}.", path));
+                true
+            }
+            Err(e) => {
+                self.notify(format!("Could not save the game to {}: {}", path, e));
+                false
+            }
+        }
+    }
+
+    // The game tree as PGN, its main line cut after `plies` moves
+    fn game_pgn(&mut self, plies: usize) -> String {
+        let name = |color| match &self.online {
+            Some(game) if color == game.color => match game.server {
+                Server::Lichess => self
+                    .lichess
+                    .as_ref()
+                    .and_then(|lichess| lichess.username.as_deref()),
+                Server::Fics => self.fics.as_ref().and_then(|fics| fics.username.as_deref()),
+                Server::Fog => None,
+            }
+            .unwrap_or("Player"),
+            Some(game) => game.opponent.as_str(),
+            None if self.vs_ai && color != self.player_color => "chess-rs",
+            None if self.vs_ai => "Player",
+            None => match color {
+                ColorChess::White => self.settings.players.white.as_deref(),
+                ColorChess::Black => self.settings.players.black.as_deref(),
+            }
+            .unwrap_or("Player"),
+        };
+        let played = self.board.get_history().len();
+        let timings = &self.move_times[..self.move_times.len().min(played)];
+        self.game_node = self.tree.sync(&self.board);
+        self.tree.to_pgn(
+            plies,
+            name(ColorChess::White),
+            name(ColorChess::Black),
+            self.board.result(),
+            timings,
+        )
+    }
+
+    // Moves the cursor by rows/columns as the board is displayed, stopping at the edge
+    fn move_cursor(&mut self, rows: isize, cols: isize) {
+        // display_to_square is its own inverse, so it also maps squares to the display
+        let display = display_to_square(self.cursor, self.player_perspective);
+        let row = display.0.saturating_add_signed(rows).min(7);
+        let col = display.1.saturating_add_signed(cols).min(7);
+        self.cursor = display_to_square((row, col), self.player_perspective);
+    }
+
+    fn handle_mouse_click(&mut self, mouse_x: u16, mouse_y: u16) {
+        if self.pending_promotion.is_some() {
+            self.notify("Choose a promotion piece first (Q/R/B/N, Esc to cancel).");
+            return;
+        }
+        if self.awaiting_handover {
+            self.awaiting_handover = false;
+            return;
+        }
+        if self.paused {
+            self.notify(format!(
+                "Game paused. Press {} to resume.",
+                self.keys.describe(Action::Pause)
+            ));
+            return;
+        }
+
+        let Some(layout) = self.layout else {
+            return;
+        };
+        if let (_, Some(moves_area)) = layout.side_panels(self.show_analysis)
+            && moves_area.intersects(Rect::new(mouse_x, mouse_y, 1, 1))
+        {
+            self.handle_move_list_click(moves_area, mouse_x, mouse_y);
+            return;
+        }
+        match layout.display_square_at(mouse_x, mouse_y) {
+            Some(display) => {
+                let square = display_to_square(display, self.player_perspective);
+                self.handle_board_click(square);
+            }
+            None => {
+                self.notify(format!(
+                    "Clicked outside board: ({}, {}).",
+                    mouse_x, mouse_y
+                ));
+            }
+        }
+    }
+
+    // Lays the game screen out again for a new terminal size
+    pub fn resize(&mut self, area: Rect) {
+        self.terminal_area = area;
+        self.layout = BoardLayout::new(area, self.square_height());
+    }
+
+    // Square height set in the settings, if any; 0 counts as unset
+    pub fn square_height(&self) -> Option<u16> {
+        self.settings.square_size.filter(|&height| height > 0)
+    }
+
+    // Whether the player may move now, saying why not otherwise; either side
+    // may be moved on the analysis board
+    fn may_move(&mut self) -> bool {
+        let reason = if self.browse.is_some() {
+            return true;
+        } else if self.is_watching() {
+            "You are watching a broadcast game."
+        } else if self.is_over() {
+            "Game is over! Press 'n' for a new game, 'u' to undo or 'q' to quit."
+        } else if self.is_ai_turn() {
+            "The AI is thinking..."
+        } else if self.online.is_some() && self.board.get_current_turn() != self.player_color {
+            "Waiting for your opponent's move."
+        } else {
+            return true;
+        };
+        self.notify(reason);
+        false
+    }
+
+    fn handle_board_click(&mut self, clicked_square: (usize, usize)) {
+        if !self.may_move() {
+            return;
+        }
+
+        let (r, c) = clicked_square;
+        self.cursor = clicked_square;
+        let current_turn_color = self.shown_board().get_current_turn();
+
+        if let Some(start_sq) = self.selected_square {
+            // Second click: attempt to make a move
+            let end_sq = clicked_square;
+
+            // possible_moves only holds legal moves of the selected piece
+            let chosen_move = self
+                .possible_moves
+                .iter()
+                .find(|mv| mv.from == start_sq && mv.to == end_sq)
+                .copied();
+
+            if let Some(mv) = chosen_move {
+                if mv.is_promotion() {
+                    // Keep the selection highlighted while the promotion dialog is open
+                    self.pending_promotion = Some(mv);
+                    self.notify(
+                        "Promote to: [Q]ueen, [R]ook, [B]ishop or k[N]ight (Esc to cancel).",
+                    );
+                } else {
+                    self.play_move(mv);
+                }
+            } else {
+                self.notify("Invalid move, or this move puts your king in check. Try again.");
+                self.selected_square = None; // Clear selection on invalid second click
+                self.possible_moves.clear(); // Clear highlights
+            }
+        } else {
+            // First click: select a piece
+            if let Some(piece) = &self.shown_board().get_piece((r, c)) {
+                if piece.color() == current_turn_color {
+                    self.selected_square = Some(clicked_square);
+                    self.notify(format!(
+                        "Selected {:?} at {}. Now click destination.",
+                        piece.piece_type(),
+                        square_name(clicked_square)
+                    ));
+                    // Calculate and store legal moves for highlighting
+                    self.possible_moves = self
+                        .turn_moves()
+                        .iter()
+                        .filter(|mv| mv.from == clicked_square)
+                        .copied()
+                        .collect();
+                } else {
+                    self.notify(format!(
+                        "That's not your piece. It's {:?}'s turn.",
+                        current_turn_color
+                    ));
+                    self.selected_square = None;
+                    self.possible_moves.clear();
+                }
+            } else {
+                self.notify("No piece at that square. Click a piece to move.");
+                self.selected_square = None;
+                self.possible_moves.clear();
+            }
+        }
+    }
+
+    // Legal moves of the side to move on the board shown, regenerated only
+    // when the position changes
+    fn turn_moves(&mut self) -> &MoveList {
+        let board = self
+            .browse
+            .as_ref()
+            .map_or(&self.board, |browse| &browse.board);
+        let hash = board.hash();
+        if self.turn_moves_hash != Some(hash) {
+            match self.fog_view.as_ref().filter(|_| self.browse.is_none()) {
+                Some(view) => {
+                    self.turn_moves.clear();
+                    self.turn_moves.extend(view.moves_on(board));
+                }
+                None => board.moves_into(&mut self.turn_moves),
+            }
+            self.turn_moves_hash = Some(hash);
+        }
+        &self.turn_moves
+    }
+
+    // Brings the game tree and the move list in line with the board's
+    // history; called before drawing
+    fn update_move_list(&mut self) {
+        let width = self
+            .layout
+            .and_then(|layout| layout.side_panels(self.show_analysis).1)
+            .map_or(0, |area| area.width.saturating_sub(2) as usize);
+        let plies = self.board.get_history().len();
+        let key = (self.board.hash(), plies, self.tree.revision(), width);
+        if self.move_rows_key == Some(key) {
+            return;
+        }
+        self.sync_tree();
+        // Variations would give away moves hidden in the fog
+        let variations = self.fog_viewer().is_none();
+        self.move_rows = move_list::layout(&self.tree, width, variations);
+        self.move_rows_key = Some((key.0, plies, self.tree.revision(), width));
+    }
+
+    // Makes the game the main line of the tree, returning its last node
+    fn sync_tree(&mut self) -> usize {
+        self.game_node = self.tree.sync(&self.board);
+        self.game_node
+    }
+
+    // First row shown when `visible` rows fit in the panel; unless scrolled
+    // back, the latest moves are, or the move on the analysis board
+    pub fn move_list_top(&self, visible: usize) -> usize {
+        let last_top = self.move_rows.len().saturating_sub(visible);
+        if let Some(top) = self.move_list_scroll {
+            return top.min(last_top);
+        }
+        let shown = self.browse.as_ref().and_then(|browse| {
+            self.move_rows
+                .iter()
+                .position(|row| row.cells.iter().any(|cell| cell.node == Some(browse.node)))
+        });
+        match shown {
+            Some(row) if row < last_top => row.saturating_sub(visible / 2),
+            _ => last_top,
+        }
+    }
+
+    // Rows that fit in the move list panel, if it is shown
+    fn move_list_visible(&self) -> Option<usize> {
+        let (_, moves_area) = self.layout?.side_panels(self.show_analysis);
+        Some(moves_area?.height.saturating_sub(2) as usize)
+    }
+
+    fn scroll_move_list(&mut self, rows: isize) {
+        let Some(visible) = self.move_list_visible() else {
+            return;
+        };
+        let last_top = self.move_rows.len().saturating_sub(visible);
+        let top = self.move_list_top(visible).saturating_add_signed(rows);
+        // Scrolling back to the end follows new moves again
+        self.move_list_scroll = (top < last_top).then_some(top);
+    }
+
+    fn scroll_move_list_page(&mut self, pages: isize) {
+        if let Some(visible) = self.move_list_visible() {
+            self.scroll_move_list(pages * visible.max(1) as isize);
+        }
+    }
+
+    // Shows the position after the clicked move, of the game or a variation
+    fn handle_move_list_click(&mut self, area: Rect, x: u16, y: u16) {
+        let Some(visible) = self.move_list_visible() else {
+            return;
+        };
+        if y <= area.y || y >= area.y + area.height - 1 || x <= area.x {
+            return;
+        }
+        let row = self.move_list_top(visible) + (y - area.y - 1) as usize;
+        if let Some(node) = self
+            .move_rows
+            .get(row)
+            .and_then(|row| row.node_at((x - area.x - 1) as usize))
+        {
+            self.browse_to(node);
+        }
+    }
+
+    // Shows the position after a move of the game tree; the game's last move
+    // goes back to the game itself
+    fn browse_to(&mut self, node: usize) {
+        if node == self.sync_tree() {
+            return self.stop_browsing();
+        }
+        self.show_node(node);
+        self.notify_browsing();
+    }
+
+    // Puts the position after a move of the game tree on the analysis board
+    fn show_node(&mut self, node: usize) {
+        let board = self.tree.board_at(node);
+        self.clear_selection();
+        self.browse = Some(Browse { node, board });
+        self.request_analysis();
+    }
+
+    // Says which position is shown
+    fn notify_browsing(&mut self) {
+        let Some(browse) = &self.browse else {
+            return;
+        };
+        let shown = match browse.node {
+            ROOT => "Viewing the starting position.".to_string(),
+            node if self.in_sideline() => {
+                format!("In a variation, after {}.", self.tree.move_label(node))
+            }
+            node => format!("Viewing the position after {}.", self.tree.move_label(node)),
+        };
+        self.notify(format!(
+            "{} ←/→ step through the game, Esc returns to it.",
+            shown
+        ));
+    }
+
+    // Opens the analysis board on the position of the game, or leaves it
+    fn toggle_explore(&mut self) {
+        if self.browse.is_some() {
+            return self.leave_analysis();
+        }
+        let node = self.sync_tree();
+        self.show_node(node);
+        self.notify("Analysis board: try moves, ←/→ step through the game, Esc returns to it.");
+    }
+
+    // Steps back a move, in the game or the variation shown
+    fn browse_back(&mut self) {
+        let Some(browse) = &self.browse else {
+            return;
+        };
+        match self.tree.parent(browse.node) {
+            Some(parent) => {
+                self.show_node(parent);
+                self.notify_browsing();
+            }
+            None => self.notify("This is the starting position."),
+        }
+    }
+
+    // Steps on along the line shown; past the game's last move is the game
+    // itself, unless moves were tried from there
+    fn browse_forward(&mut self) {
+        let Some(node) = self.browse.as_ref().map(|browse| browse.node) else {
+            return;
+        };
+        match self.tree.children(node).first().copied() {
+            Some(next) => self.browse_to(next),
+            None if node == self.sync_tree() => self.stop_browsing(),
+            None => self.notify("End of the line. ← steps back, Esc returns to the game."),
+        }
+    }
+
+    // Plays a move on the analysis board, adding it to the game tree as a
+    // variation unless it is there already
+    fn try_move(&mut self, mv: Move) {
+        let Some(browse) = &mut self.browse else {
+            return;
+        };
+        if browse.board.make(mv).is_err() {
+            return;
+        }
+        let revision = self.tree.revision();
+        browse.node = self.tree.add(browse.node, mv);
+        self.unsaved_analysis |= self.tree.revision() != revision;
+        self.clear_selection();
+        self.request_analysis();
+        self.notify_browsing();
+    }
+
+    // Moves the variation on the analysis board up or down among the
+    // alternatives where it branches off; the game stays the main line
+    fn reorder_line(&mut self, promote: bool) {
+        let Some(browse) = &self.browse else {
+            return;
+        };
+        let mut tree = self.tree.clone();
+        let game = tree.sync(&self.board);
+        let swapped = if promote {
+            tree.promote(browse.node)
+        } else {
+            tree.demote(browse.node)
+        };
+        match swapped {
+            None if promote => self.notify("This is the main line already."),
+            None => self.notify("No line comes after this one."),
+            Some(_) if !tree.is_main_line(game) => {
+                self.notify("The moves of the game stay the main line.")
+            }
+            Some(_) => {
+                self.tree = tree;
+                self.unsaved_analysis = true;
+                self.notify(if promote {
+                    "Variation moved up."
+                } else {
+                    "Variation moved down."
+                });
+            }
+        }
+    }
+
+    fn stop_browsing(&mut self) {
+        if self.browse.take().is_some() {
+            self.request_analysis();
+            self.notify("Back to the game.");
+        }
+    }
+
+    // The node of the position on the board, which is the game's own unless
+    // another is being browsed
+    pub fn shown_node(&self) -> usize {
+        self.browse
+            .as_ref()
+            .map_or(self.game_node, |browse| browse.node)
+    }
+
+    // Whether the analysis board shows a position the game did not have
+    pub fn in_sideline(&self) -> bool {
+        self.browse.as_ref().is_some_and(|browse| {
+            !self.tree.is_main_line(browse.node)
+                || self.tree.depth(browse.node) > self.board.get_history().len()
+        })
+    }
+
+    // How long a move of the game took, where that is known
+    pub fn move_time(&self, ply: usize) -> Option<Duration> {
+        if ply >= self.board.get_history().len() {
+            return None;
+        }
+        self.move_times
+            .get(ply)
+            .copied()
+            .flatten()
+            .map(|timing| timing.spent)
+    }
+
+    pub fn shown_board(&self) -> &Game {
+        self.browse
+            .as_ref()
+            .map_or(&self.board, |browse| &browse.board)
+    }
+
+    // Completes or cancels (None) the move waiting in the promotion dialog
+    fn handle_promotion_choice(&mut self, piece_type: Option<PieceType>) {
+        let Some(pending) = self.pending_promotion.take() else {
+            return;
+        };
+
+        match piece_type {
+            Some(piece_type) => self.play_move(pending.with_promotion(piece_type)),
+            None => {
+                self.notify("Promotion cancelled. Click a piece to move.");
+                self.selected_square = None;
+                self.possible_moves.clear();
+            }
+        }
+    }
+
+    fn handle_promotion_key(&mut self, key_code: KeyCode) {
+        if self.keys.action(key_code, &[Action::Menu]).is_some() {
+            return self.handle_promotion_choice(None);
+        }
+        if let KeyCode::Char(c) = key_code
+            && let Some(&piece_type) = PROMOTION_PIECES
+                .iter()
+                .find(|&&piece_type| promotion_key(piece_type) == c.to_ascii_uppercase())
+        {
+            self.handle_promotion_choice(Some(piece_type));
+        }
+    }
+
+    // Plays a move of the player's, sending it to the server in an online game
+    fn play_move(&mut self, mv: Move) {
+        if self.browse.is_some() {
+            return self.try_move(mv);
+        }
+        if self.puzzle.is_some() {
+            return self.play_puzzle_move(mv);
+        }
+        if self.guess.is_some() {
+            return self.play_guess(mv);
+        }
+        if self.drill.is_some() {
+            return self.play_drill_move(mv);
+        }
+        if self.lesson.is_some() {
+            return self.play_lesson_move(mv);
+        }
+        if self.coach_watches() {
+            return self.check_move(mv);
+        }
+        self.commit_move(mv);
+    }
+
+    // Plays the player's move, on the server too in an online game
+    fn commit_move(&mut self, mv: Move) {
+        if !self.make_move(mv) {
+            return;
+        }
+        match (&self.online, &self.lichess, &self.fics) {
+            (Some(game), Some(lichess), _) if game.server == Server::Lichess => {
+                lichess.play_move(&game.id, &mv.to_uci());
+            }
+            (Some(game), _, Some(fics)) if game.server == Server::Fics => fics.play_move(mv),
+            (Some(game), _, _) if game.server == Server::Fog => {
+                if let Some(fog) = &self.fog_server {
+                    fog.play_move(mv);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Plays a move on the board, returning whether it was allowed
+    fn make_move(&mut self, mv: Move) -> bool {
+        let current_turn_color = self.board.get_current_turn();
+        // The AI's moves are announced by the AI with its search details
+        let ai_move = self.is_ai_turn();
+        let piece = self.board.get_piece(mv.from);
+        // Fog-of-war network games follow dark chess, where a king may be left
+        // in check
+        let played = if self.fog_view.is_some() {
+            self.board.make(mv).map(drop)
+        } else {
+            self.board.play_move(mv)
+        };
+        if let Err(e) = played {
+            self.notify(format!("{}.", e));
+            return false;
+        }
+        if self.settings.animate_moves
+            && let Some(piece) = piece
+        {
+            self.animation = Some(Animation {
+                piece,
+                from: mv.from,
+                to: mv.to,
+                started: Instant::now(),
+            });
+        }
+        if let Some(clock) = self.board.clock_mut() {
+            clock.complete_move(current_turn_color);
+        }
+        // A new move replaces the timings of any moves that were undone
+        let ply = self.board.get_history().len() - 1;
+        self.move_times.resize(ply, None);
+        self.move_times.push(Some(MoveTiming {
+            spent: self.move_timer.lap(),
+            clock: self
+                .board
+                .clock()
+                .map(|clock| clock.get_remaining(current_turn_color)),
+        }));
+        if self.guess.is_some() || self.drill.is_some() || self.lesson.is_some() {
+            // Trainers announce the moves in their own words
+        } else if !ai_move && self.fog_viewer().is_some() {
+            // Where a piece went is hidden in the fog
+            self.notify(format!("Player {:?} moved.", current_turn_color));
+        } else if !ai_move {
+            let mut message = format!(
+                "Player {:?} moved {}-{}",
+                current_turn_color,
+                square_name(mv.from),
+                square_name(mv.to)
+            );
+            if let Some(promotion) = mv.promotion {
+                message.push_str(&format!(" and promoted to {:?}", promotion));
+            }
+            message.push_str(self.check_note());
+            self.notify(message);
+        }
+
+        // Playing a move instead of answering declines a pending draw offer
+        if let Some(PendingAction::DrawOffer(_)) = self.pending_action.take() {
+            self.notify("Draw offer declined.");
+        }
+
+        self.check_game_over();
+        self.check_endgame();
+        // Hands the clock over to the opponent, or stops it for good
+        self.sync_clock();
+        self.clear_selection();
+        // In fog of war the board is hidden between turns, so that neither
+        // player sees what the other can
+        if !self.is_over() && self.is_hot_seat() {
+            self.awaiting_handover = (self.settings.rotate_board && self.settings.pass_screen)
+                || self.settings.fog_of_war;
+        }
+        self.follow_turn();
+        self.request_analysis();
+        if self.is_ai_turn() {
+            self.notify("The AI is thinking...");
+        }
+        true
+    }
+
+    // Points the background engine at the position on the board, for the eval
+    // bar and the analysis panel; earlier positions of a finished game can
+    // still be analysed while browsing
+    fn request_analysis(&mut self) {
+        let board = self.shown_board().clone();
+        // A fog-of-war view is not a position the engine can make sense of
+        if (self.is_over() || self.fog_view.is_some()) && self.browse.is_none() {
+            self.analyzer.stop();
+        } else if self.show_analysis {
+            let lines = self.settings.analysis_lines.clamp(1, MAX_ANALYSIS_LINES);
+            self.analyzer.request(&board, ANALYSIS_TIME, lines);
+        } else if self.settings.show_eval_bar {
+            self.analyzer.request(&board, EVAL_TIME, 1);
+        } else {
+            self.analyzer.stop();
+        }
+    }
+
+    fn toggle_analysis(&mut self) {
+        self.show_analysis = !self.show_analysis;
+        self.notify(if self.show_analysis {
+            "Analysis panel opened. Press 'a' to close it.".to_string()
+        } else {
+            "Analysis panel closed.".to_string()
+        });
+        self.request_analysis();
+    }
+
+    fn toggle_attack_map(&mut self) {
+        self.show_attacks = !self.show_attacks;
+        self.notify(if self.show_attacks {
+            "Attack map on: green squares are yours, red your opponent's; your pieces in danger are marked.".to_string()
+        } else {
+            "Attack map off.".to_string()
+        });
+    }
+
+    // Runs the clock of the side to move, and the timer of the move being
+    // played, while the game is being played and in view; the clock starts
+    // with the first move, and both stop on the menus, behind the help and
+    // hand-over screens, while paused and once the game is over
+    fn sync_clock(&mut self) {
+        let running = self.screen == Screen::Game
+            && !self.show_help
+            && !self.awaiting_handover
+            && !self.confirm_quit
+            && !self.paused
+            && !self.is_over();
+        if running {
+            self.move_timer.start();
+        } else {
+            self.move_timer.stop();
+        }
+        // The clocks start with the first move
+        let running = running && self.board.can_undo();
+        let to_move = self.board.get_current_turn();
+        if let Some(clock) = self.board.clock_mut() {
+            if running {
+                clock.start(to_move);
+            } else {
+                clock.stop();
+            }
+        }
+    }
+
+    // When the running clock's flag falls; Lichess keeps the time of online
+    // games itself
+    pub fn flag_deadline(&self) -> Option<Instant> {
+        if self.online.is_some() {
+            return None;
+        }
+        self.board.clock()?.flag_deadline()
+    }
+
+    // Announces once that the side to move is low on time, and rings the bell
+    // if the [clock] section asks for it
+    fn check_low_time(&mut self) {
+        let threshold = self.low_time_threshold();
+        if threshold.is_zero() {
+            return;
+        }
+        let Some(color) = self
+            .board
+            .clock_mut()
+            .and_then(|clock| clock.warn_low_time(threshold))
+        else {
+            return;
+        };
+        self.notify(format!("{:?} is low on time.", color));
+        self.bell |= self.settings.clock.bell;
+    }
+
+    // Time left under which a clock counts as low; zero when that is never
+    pub fn low_time_threshold(&self) -> Duration {
+        Duration::from_secs(self.settings.clock.low_time)
+    }
+
+    // Ends the game when the running clock has reached zero: the side whose
+    // flag fell loses, unless the other side could never checkmate. The clock
+    // is read again, as it may have been stopped since the deadline was set
+    fn check_flag(&mut self) {
+        // Lichess keeps the time of online games and says when a flag falls
+        if self.online.is_some() {
+            return;
+        }
+        let Some(clock) = self.board.clock() else {
+            return;
+        };
+        let Some(color) = clock.get_running() else {
+            return;
+        };
+        if !clock.get_remaining(color).is_zero() {
+            return;
+        }
+        let winner = color.opposite();
+        if self.board.has_mating_material(winner) {
+            self.end_game(GameResult::win_for(winner, Termination::Timeout));
+        } else {
+            self.end_game(GameResult::draw(Termination::Timeout));
+        }
+        self.sync_clock();
+    }
+
+    // Keeps the engine's verdict on a position of the analysis board in the
+    // game tree, for exporting
+    fn record_eval(&mut self, analysis: &Analysis) {
+        let Some(browse) = &self.browse else {
+            return;
+        };
+        if analysis.hash == browse.board.hash() && analysis.depth > 0 {
+            let eval = Eval {
+                white_score: analysis.white_score,
+                depth: analysis.depth,
+            };
+            self.tree.record_eval(browse.node, eval);
+        }
+    }
+
+    // Whether a moved piece is still sliding, so the screen needs redrawing often
+    pub fn is_animating(&self) -> bool {
+        self.animation
+            .as_ref()
+            .is_some_and(|animation| animation.progress() < 1.0)
+    }
+
+    // Brings the app up to date with an event; what is drawn follows from
+    // the state this leaves
+    pub fn update(&mut self, event: AppEvent) {
+        match event {
+            AppEvent::Input(event) => self.handle_input(event),
+            AppEvent::Tick => self.poll_background(),
+            AppEvent::EngineMove(reply) => {
+                self.ai.received(&reply);
+                self.ai_replies.push(reply);
+            }
+            AppEvent::NetworkMsg(NetworkMsg::Lichess(event)) => self.handle_lichess_event(event),
+            AppEvent::NetworkMsg(NetworkMsg::Fics(event)) => self.handle_fics_event(event),
+            AppEvent::NetworkMsg(NetworkMsg::Fog(event)) => self.handle_fog_event(event),
+            AppEvent::ClockExpired => self.check_flag(),
+        }
+        if !self.is_animating() {
+            self.animation = None;
+        }
+        self.sync_clock();
+        // The AI's reply is held back until the game is resumed
+        if !self.paused {
+            self.poll_ai();
+        }
+        if self.screen == Screen::Game {
+            self.update_move_list();
+        }
+    }
+
+    fn handle_input(&mut self, event: CrosstermEvent) {
+        match event {
+            CrosstermEvent::Key(key) => self.handle_key(key.code),
+            CrosstermEvent::Mouse(mouse) if self.screen == Screen::Game => match mouse.kind {
+                MouseEventKind::Down(MouseButton::Left) => {
+                    self.handle_mouse_click(mouse.column, mouse.row)
+                }
+                MouseEventKind::ScrollUp => self.scroll_move_list(-1),
+                MouseEventKind::ScrollDown => self.scroll_move_list(1),
+                _ => {}
+            },
+            CrosstermEvent::Mouse(mouse) if self.screen == Screen::Editor => match mouse.kind {
+                MouseEventKind::Down(MouseButton::Left) => {
+                    self.handle_editor_click(mouse.column, mouse.row, false)
+                }
+                MouseEventKind::Down(MouseButton::Right) => {
+                    self.handle_editor_click(mouse.column, mouse.row, true)
+                }
+                _ => {}
+            },
+            CrosstermEvent::Resize(width, height) => self.resize(Rect::new(0, 0, width, height)),
+            _ => {}
+        }
+    }
+
+    // Picks up results from the background work that is checked on each tick
+    fn poll_background(&mut self) {
+        self.check_low_time();
+        if let Some(analysis) = self.analyzer.latest() {
+            self.record_eval(&analysis);
+            self.analysis = Some(analysis);
+        }
+        self.poll_puzzle_download();
+        self.poll_puzzle_pick();
+        self.poll_daily_download();
+        self.poll_review();
+        self.poll_coach();
+        self.poll_guess();
+        self.poll_import();
+        self.poll_broadcast();
+    }
+
+    // Follows a Lichess broadcast round, opening the list of its games
+    pub fn follow_broadcast(&mut self, link: &str) {
+        match Broadcast::follow(link) {
+            Ok(broadcast) => {
+                self.broadcast = Some(broadcast);
+                self.broadcast_index = 0;
+                self.screen = Screen::Broadcast;
+                self.notify("Downloading the broadcast...");
+            }
+            Err(e) => self.notify(format!("Could not follow the broadcast: {}", e)),
+        }
+    }
+
+    fn handle_broadcast_key(&mut self, key_code: KeyCode) {
+        let count = self.broadcast.as_ref().map_or(0, |b| b.games.len());
+        let actions = [
+            Action::Up,
+            Action::Down,
+            Action::Select,
+            Action::Quit,
+            Action::Menu,
+        ];
+        match self.keys.action(key_code, &actions) {
+            Some(Action::Up) => self.broadcast_index = self.broadcast_index.saturating_sub(1),
+            Some(Action::Down) if self.broadcast_index + 1 < count => self.broadcast_index += 1,
+            Some(Action::Select) if self.broadcast_index < count => {
+                self.watch_broadcast_game(self.broadcast_index);
+            }
+            // Leaving the list stops following the round
+            Some(Action::Quit | Action::Menu) => {
+                self.broadcast = None;
+                self.screen = Screen::MainMenu;
+            }
+            _ => {}
+        }
+    }
+
+    // Puts a game of the round on the board, to follow its moves as they come
+    fn watch_broadcast_game(&mut self, index: usize) {
+        self.vs_ai = false;
+        self.player_color = ColorChess::White;
+        self.new_game(false);
+        self.board.set_clock(None);
+        let Some(broadcast) = &mut self.broadcast else {
+            return;
+        };
+        broadcast.watching = Some(index);
+        let game = &broadcast.games[index];
+        let title = format!(
+            "Watching {} vs {}. Esc goes back to the round's games.",
+            game.tag("White").unwrap_or("?"),
+            game.tag("Black").unwrap_or("?")
+        );
+        self.sync_broadcast_board();
+        self.notify(title);
+    }
+
+    // Picks up the round's latest moves
+    fn poll_broadcast(&mut self) {
+        let Some(result) = self.broadcast.as_mut().and_then(Broadcast::poll) else {
+            return;
+        };
+        match result {
+            Ok(()) => self.sync_broadcast_board(),
+            Err(e) => self.notify(format!("Could not download the broadcast: {}", e)),
+        }
+    }
+
+    // Brings the watched game's board up to date; new moves are played on it
+    // so that the latest one is shown being made
+    fn sync_broadcast_board(&mut self) {
+        let Some(game) = self
+            .broadcast
+            .as_ref()
+            .and_then(|broadcast| broadcast.games.get(broadcast.watching?))
+        else {
+            return;
+        };
+        let (board, result) = (game.board.clone(), game.result());
+        let moves: Vec<Move> = board.get_history().iter().map(|record| record.mv).collect();
+        let history = self.board.get_history();
+        let extends = !history.is_empty()
+            && history.len() <= moves.len()
+            && history
+                .iter()
+                .zip(&moves)
+                .all(|(record, &mv)| record.mv == mv);
+        if extends {
+            for &mv in &moves[history.len()..] {
+                self.make_move(mv);
+            }
+        } else {
+            self.replace_board(board);
+            self.follow_turn();
+            self.request_analysis();
+        }
+        if let Some(result) = result
+            && !self.is_over()
+        {
+            self.end_game(result);
+        }
+    }
+
+    // Downloads a game from a Lichess or chess.com link, to be opened for replay
+    pub fn import_game(&mut self, link: &str) {
+        self.import = Some(import::fetch_game(link));
+        self.notify("Downloading the game...");
+    }
+
+    fn poll_import(&mut self) {
+        let Some(import) = &self.import else {
+            return;
+        };
+        let Ok(result) = import.try_recv() else {
+            return;
+        };
+        self.import = None;
+        match result {
+            Ok(game) => self.replay_game(game, "Imported"),
+            Err(e) => self.notify(format!("Could not import the game: {}", e)),
+        }
+    }
+
+    // Opens a downloaded or saved game at its first move, with the analysis
+    // panel, to be stepped through with ←/→
+    fn replay_game(&mut self, game: PgnGame, verb: &str) {
+        self.vs_ai = false;
+        self.player_color = ColorChess::White;
+        self.new_game(false);
+        let title = format!(
+            "{} {} vs {} ({}).",
+            verb,
+            game.tag("White").unwrap_or("?"),
+            game.tag("Black").unwrap_or("?"),
+            game.tag("Result").unwrap_or("*")
+        );
+        self.board = game.board;
+        self.tree = game.tree;
+        self.unsaved_analysis = false;
+        self.show_analysis = true;
+        self.follow_turn();
+        self.notify(title);
+        self.browse_to(ROOT);
+    }
+
+    // Opens a PGN file, or a position given as FEN, with the analysis panel
+    pub fn analyze(&mut self, target: &str) {
+        if Path::new(target).is_file() {
+            let game = std::fs::read_to_string(target)
+                .map_err(|e| e.to_string())
+                .and_then(|text| pgn::parse_pgn(&text).map_err(String::from));
+            return match game {
+                Ok(game) => self.replay_game(game, "Opened"),
+                Err(e) => self.notify(format!("Could not open {}: {}", target, e)),
+            };
+        }
+        let board = match Game::from_fen(target) {
+            Ok(board) => board,
+            Err(e) => {
+                return self.notify(format!(
+                    "{} is neither a PGN file nor a FEN position: {}",
+                    target, e
+                ));
+            }
+        };
+        self.analyze_board(board);
+    }
+
+    // Opens a position on its own with the analysis panel
+    fn analyze_board(&mut self, board: Game) {
+        self.vs_ai = false;
+        self.player_color = board.get_current_turn();
+        self.new_game(false);
+        self.board = board;
+        self.show_analysis = true;
+        self.follow_turn();
+        self.request_analysis();
+        self.notify("Analysing the position. Moves played are analysed in turn.");
+    }
+
+    // Puts another board in play in the game going on, which keeps its clocks
+    // and result
+    fn replace_board(&mut self, mut board: Game) {
+        board.set_clock(self.board.clock().cloned());
+        board.set_result(self.board.result());
+        self.board = board;
+    }
+
+    // Starts a new game with the moves of the board already played, as given
+    // on the command line
+    pub fn play_from(&mut self, board: Game) {
+        self.vs_ai = false;
+        self.player_color = self.preset_color.unwrap_or(ColorChess::White);
+        self.new_game(false);
+        let played = board.get_history().len();
+        self.replace_board(board);
+        self.follow_turn();
+        self.check_game_over();
+        self.sync_clock();
+        self.request_analysis();
+        self.notify(format!(
+            "Played {} moves; {:?} to move.",
+            played,
+            self.board.get_current_turn()
+        ));
+    }
+
+    // Opens the board editor on the game's position, unless that would give
+    // away what is in the fog
+    fn open_editor(&mut self) {
+        let board = match self.fog_viewer() {
+            Some(_) if self.is_game_in_progress() => Game::new(),
+            _ => self.board.clone(),
+        };
+        self.editor = Editor::from_board(&board);
+        self.editor.perspective = self.player_perspective;
+        self.screen = Screen::Editor;
+        self.notify("Click to put pieces down, right-click to take them off, '?' for the keys.");
+    }
+
+    // Letters pick the piece to put down as FEN writes them, upper case for
+    // White; Enter plays from the position, 'v' against the AI, 'a' analyses it
+    fn handle_editor_key(&mut self, key_code: KeyCode) {
+        let editor = &mut self.editor;
+        if let KeyCode::Char(letter) = key_code
+            && let Some(piece) = Piece::from_fen_char(letter)
+        {
+            editor.piece = piece;
+            return;
+        }
+        match key_code {
+            KeyCode::Tab => return editor.toggle_turn(),
+            KeyCode::Char(digit @ '1'..='4') => {
+                return editor.toggle_castling(digit as usize - '1' as usize);
+            }
+            KeyCode::Char('e') => {
+                editor.next_en_passant();
+                if editor.en_passant.is_none() && editor.en_passant_squares().is_empty() {
+                    self.notify("No pawn has just made a double step to take en passant.");
+                }
+                return;
+            }
+            KeyCode::Char('x') => return editor.clear(),
+            KeyCode::Char('s') => return editor.start_position(),
+            KeyCode::Char('f') => {
+                editor.perspective = editor.perspective.opposite();
+                return;
+            }
+            KeyCode::Char('v') => return self.play_edited(true),
+            KeyCode::Char('a') => {
+                if let Some(board) = self.edited_board() {
+                    self.analyze_board(board);
+                }
+                return;
+            }
+            KeyCode::Char('?') => {
+                return self.notify(
+                    "KQRBNP/kqrbnp piece, Tab side to move, 1-4 castling KQkq, e en passant, \
+                     x clear, s start, f flip, Enter play, v vs AI, a analyse, Esc menu",
+                );
+            }
+            _ => {}
+        }
+        match self.keys.action(key_code, &[Action::Select, Action::Menu]) {
+            Some(Action::Select) => self.play_edited(false),
+            Some(Action::Menu) => self.screen = Screen::MainMenu,
+            _ => {}
+        }
+    }
+
+    fn handle_editor_click(&mut self, mouse_x: u16, mouse_y: u16, remove: bool) {
+        let Some(display) = self
+            .layout
+            .and_then(|layout| layout.display_square_at(mouse_x, mouse_y))
+        else {
+            return;
+        };
+        let square = display_to_square(display, self.editor.perspective);
+        if remove {
+            self.editor.remove(square);
+        } else {
+            self.editor.click(square);
+        }
+    }
+
+    // The editor's position, or None with the first thing wrong with it shown
+    fn edited_board(&mut self) -> Option<Game> {
+        match self.editor.board() {
+            Ok(board) => Some(board),
+            Err(e) => {
+                self.notify(format!("The position cannot be played: {}", e));
+                None
+            }
+        }
+    }
+
+    // Starts a game from the editor's position, hot-seat or against the AI
+    // with the player on move
+    fn play_edited(&mut self, vs_ai: bool) {
+        let Some(board) = self.edited_board() else {
+            return;
+        };
+        // The engine's side is only taken once the position is set up
+        self.vs_ai = false;
+        self.player_color = board.get_current_turn();
+        self.new_game(false);
+        self.vs_ai = vs_ai;
+        self.replace_board(board);
+        self.follow_turn();
+        self.check_game_over();
+        self.sync_clock();
+        self.request_analysis();
+        self.notify(format!(
+            "Playing from the position set up; {:?} to move.",
+            self.board.get_current_turn()
+        ));
+    }
+
+    // Puzzles of every motif, as "Solve puzzles" in the menu plays them
+    pub fn start_puzzles(&mut self) {
+        self.puzzle_motif = None;
+        self.next_puzzle();
+    }
+
+    // Plays the puzzles in a file, in order, before going back to Lichess
+    pub fn load_puzzle_file(&mut self, path: &str) {
+        match puzzle::load_file(Path::new(path)) {
+            Ok(puzzles) => {
+                self.notify(format!("Loaded {} puzzles from {}.", puzzles.len(), path));
+                self.puzzle_file = puzzles;
+                // The puzzle on the board, if any, is skipped for the first one
+                self.next_puzzle();
+            }
+            Err(e) => self.notify(format!("Could not load the puzzles: {}", e)),
+        }
+    }
+
+    // Moves on to the next puzzle, from the loaded file, the local database or
+    // else Lichess; leaving a puzzle unsolved counts as failing it
+    fn next_puzzle(&mut self) {
+        if self.puzzle.as_ref().is_some_and(PuzzleRun::counts) {
+            let rating = self.score_puzzle(false);
+            self.notify(format!("Puzzle skipped.{}", rating));
+        }
+        let motif = self.puzzle_motif;
+        if let Some(index) = self
+            .puzzle_file
+            .iter()
+            .position(|puzzle| motif.is_none_or(|motif| puzzle.has_motif(motif)))
+        {
+            let puzzle = self.puzzle_file.remove(index);
+            return self.start_puzzle(puzzle);
+        }
+        if puzzle_db::exists() {
+            let mut filter = PuzzleFilter::new(&self.settings.puzzles, self.puzzles.rating);
+            if let Some(motif) = motif {
+                filter.themes.push(motif.theme().to_string());
+            }
+            self.puzzle_pick = Some(puzzle_db::pick(filter));
+            return;
+        }
+        self.next_lichess_puzzle();
+    }
+
+    // Starts a downloaded puzzle, or waits for one to arrive
+    fn next_lichess_puzzle(&mut self) {
+        match self.puzzles.take_downloaded(self.puzzle_motif) {
+            Some(puzzle) => {
+                self.save_puzzles();
+                self.start_puzzle(puzzle);
+            }
+            None => {
+                self.awaiting_puzzle = true;
+                self.notify("Downloading a puzzle from Lichess...");
+            }
+        }
+        // Keep a few puzzles in reserve; the reserve may have none of the
+        // motif being drilled
+        if self.puzzle_download.is_none()
+            && (self.awaiting_puzzle || self.puzzles.pool_len() < puzzle::POOL_SIZE)
+        {
+            self.download_puzzle();
+        }
+    }
+
+    fn download_puzzle(&mut self) {
+        let theme = self.puzzle_motif.map(Motif::theme);
+        self.puzzle_download = Some(lichess::fetch_puzzle(
+            self.settings.lichess.token.clone(),
+            theme,
+        ));
+    }
+
+    // Plays puzzles of one motif from here on; None mixes them all again
+    fn drill_motif(&mut self, motif: Option<Motif>) {
+        self.puzzle_motif = motif;
+        if let Some(motif) = motif {
+            self.notify(format!(
+                "Drilling {} puzzles.",
+                motif.label().to_lowercase()
+            ));
+        }
+        self.next_puzzle();
+    }
+
+    // Starts the puzzle picked from the local database; without a match,
+    // Lichess has to do
+    fn poll_puzzle_pick(&mut self) {
+        let Some(pick) = &self.puzzle_pick else {
+            return;
+        };
+        let Ok(result) = pick.try_recv() else {
+            return;
+        };
+        self.puzzle_pick = None;
+        match result {
+            Ok(puzzle) => self.start_puzzle(puzzle),
+            Err(e) => {
+                self.notify(format!("Could not pick a puzzle: {}", e));
+                self.next_lichess_puzzle();
+            }
+        }
+    }
+
+    // Adds a downloaded puzzle to the reserve, starting it if the player is
+    // waiting; without a connection a built-in puzzle is played instead
+    fn poll_puzzle_download(&mut self) {
+        let Some(download) = &self.puzzle_download else {
+            return;
+        };
+        let Ok(result) = download.try_recv() else {
+            return;
+        };
+        self.puzzle_download = None;
+        match result {
+            Ok(puzzle) => {
+                self.puzzles.add(puzzle);
+                self.save_puzzles();
+                if std::mem::take(&mut self.awaiting_puzzle) {
+                    self.next_puzzle();
+                } else if self.puzzles.pool_len() < puzzle::POOL_SIZE {
+                    self.download_puzzle();
+                }
+            }
+            Err(e) if std::mem::take(&mut self.awaiting_puzzle) => {
+                self.notify(format!(
+                    "Could not download a puzzle ({}). Here is a built-in one.",
+                    e
+                ));
+                let puzzle = self.puzzles.take_builtin();
+                self.save_puzzles();
+                self.start_puzzle(puzzle);
+            }
+            Err(_) => {}
+        }
+    }
+
+    // Plays today's puzzle, fetching it first unless that was done earlier
+    // in the day
+    pub fn start_daily_puzzle(&mut self) {
+        if let Some(puzzle) = self.puzzles.daily.puzzle() {
+            return self.play_daily_puzzle(puzzle.clone());
+        }
+        if self.daily_download.is_none() {
+            let filter = PuzzleFilter::new(&self.settings.puzzles, self.puzzles.rating);
+            self.daily_download = Some(puzzle::fetch_daily(filter));
+        }
+        self.notify("Fetching the daily puzzle...");
+    }
+
+    fn poll_daily_download(&mut self) {
+        let Some(download) = &self.daily_download else {
+            return;
+        };
+        let Ok(puzzle) = download.try_recv() else {
+            return;
+        };
+        self.daily_download = None;
+        self.puzzles.daily.set_puzzle(puzzle.clone());
+        self.save_puzzles();
+        self.play_daily_puzzle(puzzle);
+    }
+
+    // Once today's puzzle has been played, trying it again does not change
+    // the streak
+    fn play_daily_puzzle(&mut self, puzzle: Puzzle) {
+        self.start_puzzle(puzzle);
+        let played = self.puzzles.daily.played_today();
+        if let Some(run) = &mut self.puzzle {
+            run.daily = true;
+            run.retry = played;
+        }
+        let streak = self.puzzles.daily.current_streak();
+        if played {
+            self.notify("Today's puzzle again: it no longer counts.");
+        } else if streak > 0 {
+            self.notify(format!(
+                "Daily puzzle: solve it to make your streak {} days.",
+                streak + 1
+            ));
+        }
+    }
+
+    // Sets up a puzzle's position with the player on move
+    fn start_puzzle(&mut self, puzzle: Puzzle) {
+        let board = match Game::from_fen(&puzzle.fen) {
+            Ok(board) => board,
+            Err(e) => return self.notify(format!("Puzzle {} is broken: {}", puzzle.id, e)),
+        };
+        self.vs_ai = false;
+        self.player_color = board.get_current_turn();
+        self.new_game(false);
+        self.board = board;
+        self.follow_turn();
+        self.request_analysis();
+        let rated = puzzle
+            .rating
+            .map(|rating| format!(" (rated {})", rating))
+            .unwrap_or_default();
+        self.notify(format!(
+            "Puzzle {}{}: find the best move for {:?}.",
+            puzzle.id, rated, self.player_color
+        ));
+        self.puzzle = Some(PuzzleRun::new(puzzle));
+    }
+
+    // Sets the puzzle up again from the start; once it has been scored, it
+    // no longer counts
+    fn retry_puzzle(&mut self) {
+        let Some(run) = self.puzzle.take() else {
+            return;
+        };
+        let retry = !run.counts();
+        let daily = run.daily;
+        self.start_puzzle(run.puzzle);
+        if let Some(run) = &mut self.puzzle {
+            run.retry = retry;
+            run.daily = daily;
+        }
+    }
+
+    // Scores the puzzle on the board, returning what became of the rating
+    // and, for the daily puzzle, the streak; unrated puzzles leave the
+    // rating alone
+    fn score_puzzle(&mut self, solved: bool) -> String {
+        let Some(run) = &mut self.puzzle else {
+            return String::new();
+        };
+        if solved {
+            run.done = true;
+        } else {
+            run.failed = true;
+        }
+        self.puzzles.count(&run.puzzle, solved);
+        let mut message = String::new();
+        if let Some(rating) = run.puzzle.rating {
+            let change = self.puzzles.record(rating, solved);
+            message = format!(" Rating {} ({:+}).", self.puzzles.rating, change);
+        }
+        if run.daily {
+            let daily = &mut self.puzzles.daily;
+            daily.record(solved);
+            message.push_str(&match daily.streak {
+                0 => " The streak is over.".to_string(),
+                streak => format!(" Streak {} (best {}).", streak, daily.best),
+            });
+        }
+        self.save_puzzles();
+        message
+    }
+
+    // Checks the player's move against the puzzle's solution and plays the
+    // reply; a wrong move is taken back for another try
+    fn play_puzzle_move(&mut self, mv: Move) {
+        let Some(run) = &self.puzzle else {
+            return;
+        };
+        let expected = run.puzzle.solution.get(run.next).cloned();
+        self.make_move(mv);
+        // Any mate is as good as the one in the solution
+        let correct =
+            expected == Some(mv.to_uci()) || self.board.is_checkmate(self.board.get_current_turn());
+        if !correct {
+            self.board.undo();
+            self.animation = None;
+            self.move_timer = Stopwatch::default();
+            self.finish_message = None;
+            self.board.set_result(None);
+            let rating = if self.puzzle.as_ref().is_some_and(PuzzleRun::counts) {
+                self.score_puzzle(false)
+            } else {
+                String::new()
+            };
+            self.notify(format!(
+                "That's not it.{} Try again, or press n for the next puzzle.",
+                rating
+            ));
+            self.follow_turn();
+            self.request_analysis();
+            return;
+        }
+        let over = self.is_over();
+        let Some(run) = &mut self.puzzle else {
+            return;
+        };
+
+        run.next += 1;
+        let reply = run.puzzle.solution.get(run.next).cloned();
+        match reply {
+            Some(reply) if !over => {
+                run.next += 1;
+                match self.board.parse_uci_move(&reply) {
+                    Ok(reply) => {
+                        self.make_move(reply);
+                        self.notify("Correct! Keep going.");
+                    }
+                    Err(_) => self.notify(format!("The puzzle's reply {} is not legal.", reply)),
+                }
+            }
+            _ => self.complete_puzzle(),
+        }
+    }
+
+    fn complete_puzzle(&mut self) {
+        let Some(run) = &mut self.puzzle else {
+            return;
+        };
+        if run.done {
+            return;
+        }
+        let message = if run.counts() {
+            format!("Puzzle solved!{}", self.score_puzzle(true))
+        } else {
+            run.done = true;
+            if run.retry {
+                "Puzzle solved again.".to_string()
+            } else {
+                "Puzzle solved, though it counts as missed.".to_string()
+            }
+        };
+        self.finish_message = Some(message.clone());
+        self.notify(format!("{} Press n for the next puzzle.", message));
+    }
+
+    // Goes through the games of a PGN file guessing the winner's moves
+    pub fn start_guessing(&mut self, path: &str) {
+        match GuessRun::load(path) {
+            Ok(run) => {
+                self.guess = Some(run);
+                self.next_guess_game();
+            }
+            Err(e) => self.notify(format!("Could not load the games: {}", e)),
+        }
+    }
+
+    fn next_guess_game(&mut self) {
+        let Some(mut run) = self.guess.take() else {
+            return;
+        };
+        let Some(board) = run.next_game() else {
+            self.guess = Some(run);
+            return self.notify("That was the last game in the file.");
+        };
+        self.vs_ai = false;
+        self.player_color = run.side;
+        self.new_game(false);
+        self.board = board;
+        self.notify(format!(
+            "{} vs {}: guess {:?}'s moves.",
+            run.white, run.black, run.side
+        ));
+        self.guess = Some(run);
+        self.play_until_guess();
+    }
+
+    // Plays the game's moves up to the next one to be guessed
+    fn play_until_guess(&mut self) {
+        loop {
+            let Some(run) = &mut self.guess else {
+                return;
+            };
+            let Some(played) = run.played() else {
+                return self.finish_guess_game();
+            };
+            if self.board.get_current_turn() == run.side {
+                return self.follow_turn();
+            }
+            run.next += 1;
+            self.make_move(played);
+        }
+    }
+
+    fn finish_guess_game(&mut self) {
+        let Some(run) = &self.guess else {
+            return;
+        };
+        let message = format!(
+            "Game over: {} of {} points, {} moves found.",
+            run.points, run.possible, run.exact
+        );
+        self.finish_message = Some(message.clone());
+        self.request_analysis();
+        self.notify(format!("{} Press n for the next game.", message));
+    }
+
+    // Checks a guess against the move played; any other move is scored by
+    // the engine before the game goes on
+    fn play_guess(&mut self, mv: Move) {
+        let Some(run) = &mut self.guess else {
+            return;
+        };
+        if run.scoring.is_some() {
+            return self.notify("Still scoring your last guess...");
+        }
+        let Some(played) = run.played() else {
+            return;
+        };
+        if mv.to_uci() == played.to_uci() {
+            let points = run.score(None);
+            let message = format!(
+                "{} was played! +{} ({} of {}).",
+                self.board.move_to_san(played),
+                points,
+                run.points,
+                run.possible
+            );
+            return self.reveal_played(message);
+        }
+        run.scoring = Some((mv, guess::score_guess(&self.board, mv, played)));
+        let guessed = self.board.move_to_san(mv);
+        self.clear_selection();
+        self.notify(format!("Scoring {} against the move played...", guessed));
+    }
+
+    // The coach looks at the player's moves in local games, except where
+    // seeing the whole board would be unfair or the drill is the point
+    fn coach_watches(&self) -> bool {
+        self.settings.coach
+            && self.online.is_none()
+            && self.endgame.is_none()
+            && self.fog_viewer().is_none()
+    }
+
+    // Has the coach look the move over before it is played
+    fn check_move(&mut self, mv: Move) {
+        if self.coach_check.is_some() {
+            return self.notify("The coach is still looking at your last move.");
+        }
+        // Trying another move answers an earlier warning
+        if matches!(self.pending_action, Some(PendingAction::ConfirmMove(_))) {
+            self.pending_action = None;
+        }
+        self.clear_selection();
+        self.coach_check = Some((mv, coach::check_move(&self.board, mv)));
+    }
+
+    fn poll_coach(&mut self) {
+        let Some((mv, check)) = &self.coach_check else {
+            return;
+        };
+        let mv = *mv;
+        let Ok(warning) = check.try_recv() else {
+            return;
+        };
+        self.coach_check = None;
+        match warning {
+            None => self.commit_move(mv),
+            Some(warning) => {
+                self.pending_action = Some(PendingAction::ConfirmMove(mv));
+                self.notify(format!(
+                    "Coach: {} Play it anyway? Press '{}' to play it or '{}' to take it back.",
+                    warning,
+                    self.keys.describe(Action::Yes),
+                    self.keys.describe(Action::No)
+                ));
+            }
+        }
+    }
+
+    fn poll_guess(&mut self) {
+        let Some(run) = &mut self.guess else {
+            return;
+        };
+        let Some((mv, scoring)) = &run.scoring else {
+            return;
+        };
+        let Ok(loss) = scoring.try_recv() else {
+            return;
+        };
+        let guessed = self.board.move_to_san(*mv);
+        run.scoring = None;
+        let points = run.score(Some(loss));
+        let played = run.played().map(|played| self.board.move_to_san(played));
+        let message = format!(
+            "{} was played; your {} is {:.2} worse. +{} ({} of {}).",
+            played.unwrap_or_default(),
+            guessed,
+            f64::from(loss) / 100.0,
+            points,
+            run.points,
+            run.possible
+        );
+        self.reveal_played(message);
+    }
+
+    // Plays the move that was played and goes on to the next guess
+    fn reveal_played(&mut self, message: String) {
+        let Some(run) = &mut self.guess else {
+            return;
+        };
+        let Some(played) = run.played() else {
+            return;
+        };
+        run.next += 1;
+        self.make_move(played);
+        self.notify(message);
+        self.play_until_guess();
+    }
+
+    // Drills the lines of a repertoire PGN, as White or Black; without a
+    // side, the one the lines suggest
+    pub fn start_drill(&mut self, path: &str, color: Option<ColorChess>) {
+        let drill = RepertoireStore::load().and_then(|store| {
+            Repertoire::load(path, color).map(|repertoire| Drill::new(repertoire, store))
+        });
+        match drill {
+            Ok(drill) => {
+                self.drill = Some(drill);
+                self.next_drill_line();
+            }
+            Err(e) => self.notify(format!("Could not load the repertoire: {}", e)),
+        }
+    }
+
+    fn next_drill_line(&mut self) {
+        let Some(mut drill) = self.drill.take() else {
+            return;
+        };
+        drill.next_line();
+        self.vs_ai = false;
+        self.player_color = drill.repertoire.color;
+        self.new_game(false);
+        self.board = drill.repertoire.start.clone();
+        let due = drill.due();
+        self.notify(if due == 0 {
+            "No lines are due; drilling ahead.".to_string()
+        } else {
+            format!(
+                "{} of {} lines due. Play your repertoire as {:?}.",
+                due,
+                drill.repertoire.lines.len(),
+                drill.repertoire.color
+            )
+        });
+        self.drill = Some(drill);
+        self.play_until_drill_turn();
+    }
+
+    // Plays the opponent's moves of the line up to the player's next one
+    fn play_until_drill_turn(&mut self) {
+        loop {
+            let Some(drill) = &mut self.drill else {
+                return;
+            };
+            let Some(mv) = drill.expected() else {
+                return self.complete_drill_line();
+            };
+            if self.board.get_current_turn() == drill.repertoire.color {
+                return self.follow_turn();
+            }
+            drill.next += 1;
+            self.make_move(mv);
+        }
+    }
+
+    // Checks the player's move against the line; a move outside the
+    // repertoire is not played and puts the line back in the queue
+    fn play_drill_move(&mut self, mv: Move) {
+        let Some(drill) = &mut self.drill else {
+            return;
+        };
+        let Some(expected) = drill.expected() else {
+            return;
+        };
+        if mv != expected {
+            let san = self.board.move_to_san(mv);
+            let expected = self.board.move_to_san(expected);
+            let elsewhere = drill.repertoire.moves_after(drill.played()).contains(&mv);
+            drill.missed |= !elsewhere;
+            self.clear_selection();
+            if elsewhere {
+                return self.notify(format!(
+                    "{} is in your repertoire too, but this line goes {}.",
+                    san, expected
+                ));
+            }
+            return self.notify(format!(
+                "{} is not in your repertoire. Play {}.",
+                san, expected
+            ));
+        }
+        drill.next += 1;
+        self.make_move(mv);
+        self.play_until_drill_turn();
+    }
+
+    fn complete_drill_line(&mut self) {
+        let Some(drill) = &mut self.drill else {
+            return;
+        };
+        let days = drill.finish();
+        let message = if drill.missed {
+            "Line finished with a mistake; it comes up again soon.".to_string()
+        } else {
+            format!(
+                "Line played correctly; it comes up again in {} day{}.",
+                days,
+                if days == 1 { "" } else { "s" }
+            )
+        };
+        if let Err(e) = drill.store.save() {
+            self.notify(format!("Could not save the repertoire schedule: {}", e));
+        }
+        self.finish_message = Some(message.clone());
+        self.request_analysis();
+        self.notify(format!("{} Press n for the next line.", message));
+    }
+
+    // Sets up a lesson of the rules tutorial, played from the side to move
+    fn start_lesson(&mut self, index: usize) {
+        let lesson = &LESSONS[index];
+        let board = match lesson.board() {
+            Ok(board) => board,
+            Err(e) => return self.notify(format!("{} is broken: {}", lesson.title, e)),
+        };
+        self.vs_ai = false;
+        self.player_color = board.get_current_turn();
+        self.new_game(false);
+        self.board = board;
+        self.lesson = Some(index);
+        self.follow_turn();
+        self.request_analysis();
+        self.notify(format!("Lesson {}: {}", index + 1, lesson.title));
+    }
+
+    // Moves `step` lessons on from the current one; 0 starts it over
+    fn next_lesson(&mut self, step: usize) {
+        if let Some(index) = self.lesson {
+            self.start_lesson((index + step) % LESSONS.len());
+        }
+    }
+
+    // Plays the move if it does what the lesson asks; any other move is
+    // left unplayed, with a hint
+    fn play_lesson_move(&mut self, mv: Move) {
+        let Some(index) = self.lesson else {
+            return;
+        };
+        let lesson = &LESSONS[index];
+        let mut after = self.board.clone();
+        if after.make(mv).is_err() || !lesson.is_solved_by(mv, &after) {
+            self.clear_selection();
+            return self.notify(format!("Not quite. {}", lesson.hint));
+        }
+        self.make_move(mv);
+        let message = if index + 1 == LESSONS.len() {
+            "Well done! That was the last lesson."
+        } else {
+            "Well done!"
+        };
+        self.finish_message = Some(message.to_string());
+        self.request_analysis();
+        self.notify(if index + 1 == LESSONS.len() {
+            format!("{} Press n to start again.", message)
+        } else {
+            format!("{} Press n for the next lesson.", message)
+        });
+    }
+
+    // Sets up one of the preset endgames, with the engine on the other side
+    fn start_endgame(&mut self, index: usize) {
+        let endgame = &ENDGAMES[index];
+        let board = match endgame.board() {
+            Ok(board) => board,
+            Err(e) => return self.notify(format!("{} is broken: {}", endgame.title, e)),
+        };
+        // The engine's side is only taken once the position is set up
+        self.vs_ai = false;
+        self.player_color = board.get_current_turn();
+        self.new_game(false);
+        self.vs_ai = true;
+        self.board = board;
+        self.endgame = Some(index);
+        self.follow_turn();
+        self.request_analysis();
+        self.notify(format!(
+            "{}: {} as {:?}.",
+            endgame.title,
+            endgame.task(),
+            self.player_color
+        ));
+    }
+
+    // Moves `step` drills on from the current one; 0 starts it over
+    fn next_endgame(&mut self, step: usize) {
+        if let Some(index) = self.endgame {
+            self.start_endgame((index + step) % ENDGAMES.len());
+        }
+    }
+
+    // ":endgame kqk"; without a name, the first drill
+    pub fn start_endgame_by_name(&mut self, name: &str) {
+        if name.is_empty() {
+            return self.start_endgame(0);
+        }
+        match endgame::find(name) {
+            Some(index) => self.start_endgame(index),
+            None => {
+                let names: Vec<_> = ENDGAMES.iter().map(|endgame| endgame.name).collect();
+                self.notify(format!(
+                    "Unknown endgame drill: {} (try {})",
+                    name,
+                    names.join(", ")
+                ));
+            }
+        }
+    }
+
+    // Ends an endgame drill once its goal is reached or out of reach
+    fn check_endgame(&mut self) {
+        let Some(index) = self.endgame else {
+            return;
+        };
+        let verdict =
+            ENDGAMES[index].verdict(&self.board, self.player_color, self.tablebase.as_deref());
+        let message = match verdict {
+            Some(Verdict::Passed(reason)) => format!("Drill passed! {}", reason),
+            Some(Verdict::Failed(reason)) => format!("Drill failed. {}", reason),
+            None => return,
+        };
+        self.finish_message = Some(message.clone());
+        self.pending_action = None;
+        self.notify(format!(
+            "{} Press n for the next drill or m to try again.",
+            message
+        ));
+    }
+
+    fn save_puzzles(&mut self) {
+        if let Err(e) = self.puzzles.save() {
+            self.notify(format!("Could not save the puzzle rating: {}", e));
+        }
+    }
+
+    // Handles what Lichess has sent: challenges, games starting and the
+    // moves and clocks of the online game
+    fn handle_lichess_event(&mut self, event: LichessEvent) {
+        match event {
+            LichessEvent::Connected(username) => {
+                self.notify(format!("Connected to Lichess as {}.", username));
+                if let Some(lichess) = &mut self.lichess {
+                    lichess.username = Some(username);
+                }
+            }
+            LichessEvent::Challenge(challenge) => {
+                self.notify(format!(
+                    "{} challenges you to a {} game on Lichess. Accept it from the menu.",
+                    challenge.challenger, challenge.description
+                ));
+                self.challenge = Some(challenge);
+            }
+            LichessEvent::ChallengeGone(id) => {
+                if self.challenge.as_ref().is_some_and(|c| c.id == id) {
+                    self.challenge = None;
+                }
+            }
+            LichessEvent::GameStart(game) => self.start_online_game(game),
+            LichessEvent::Correspondence(game) => {
+                let opponent = game.opponent.clone();
+                if self.correspondence.update(game) {
+                    self.notify(format!(
+                        "Your move against {} in a correspondence game.",
+                        opponent
+                    ));
+                }
+                self.save_correspondence();
+            }
+            LichessEvent::Playing(games) => {
+                for opponent in self.correspondence.replace(games) {
+                    self.notify(format!(
+                        "{} has moved in your correspondence game.",
+                        opponent
+                    ));
+                }
+                self.save_correspondence();
+            }
+            LichessEvent::GameFinish(id) => {
+                if self.correspondence.remove(&id) {
+                    self.save_correspondence();
+                }
+            }
+            LichessEvent::GameState(state) => {
+                if self
+                    .online
+                    .as_ref()
+                    .is_some_and(|game| game.id == state.game_id)
+                {
+                    self.apply_online_state(state);
+                }
+            }
+            LichessEvent::Error(e) => self.notify(format!("Lichess: {}.", e)),
+        }
+    }
+
+    // Seeks an opponent on FICS at the new-game time control, logging in first
+    // if need be
+    fn seek_fics(&mut self) {
+        let control = self.time_control.unwrap_or(lichess::DEFAULT_CONTROL);
+        match &self.fics {
+            Some(fics) if fics.username.is_some() => {
+                fics.seek(control);
+                self.notify(format!("Looking for a {} game on FICS...", control));
+            }
+            Some(_) => self.fics_seek = true,
+            None => {
+                self.fics = Some(Fics::connect(&self.settings.fics, self.fics_events.clone()));
+                self.fics_seek = true;
+                self.notify("Connecting to FICS...");
+            }
+        }
+    }
+
+    // Handles what FICS sent: the login, and board updates and the ends of
+    // the player's games
+    fn handle_fics_event(&mut self, event: FicsEvent) {
+        match event {
+            FicsEvent::Connected(handle) => {
+                self.notify(format!("Logged in to FICS as {}.", handle));
+                if let Some(fics) = &mut self.fics {
+                    fics.username = Some(handle);
+                }
+                if std::mem::take(&mut self.fics_seek) {
+                    self.seek_fics();
+                }
+            }
+            FicsEvent::Game(board) if board.is_playing() => self.apply_fics_board(board),
+            FicsEvent::Game(_) => {}
+            FicsEvent::GameEnd {
+                game,
+                ending,
+                result,
+            } => {
+                let ours = self.online.as_ref().is_some_and(|online| {
+                    online.server == Server::Fics && online.id == game.to_string()
+                });
+                if ours && !self.is_over() {
+                    match result {
+                        Some(result) => self.end_game(result),
+                        None => {
+                            self.finish_message = Some(ending.clone());
+                            self.notify(ending);
+                        }
+                    }
+                }
+            }
+            FicsEvent::DrawOffer => {
+                let opponent = self.player_color.opposite();
+                if self.online.is_some() && self.pending_action.is_none() {
+                    self.pending_action = Some(PendingAction::DrawOffer(opponent));
+                    self.notify(format!(
+                        "{:?} offers a draw. Press 'y' to accept or 'n' to decline.",
+                        opponent
+                    ));
+                }
+            }
+            FicsEvent::Error(e) => {
+                // The next request connects again
+                self.fics = None;
+                self.fics_seek = false;
+                self.notify(format!("FICS: {}.", e));
+            }
+        }
+    }
+
+    // Brings the board and clocks in line with a FICS board update, starting
+    // the game on the first one. FICS sends positions rather than moves, so
+    // the update is matched to a move to keep the game's moves; when none
+    // fits the position is taken as it is
+    fn apply_fics_board(&mut self, update: Style12) {
+        let id = update.game.to_string();
+        if !self
+            .online
+            .as_ref()
+            .is_some_and(|game| game.server == Server::Fics && game.id == id)
+        {
+            let color = update.color();
+            let opponent = match color {
+                ColorChess::White => update.black.clone(),
+                ColorChess::Black => update.white.clone(),
+            };
+            self.vs_ai = false;
+            self.player_color = color;
+            self.start_online_game(OnlineGame {
+                server: Server::Fics,
+                id,
+                color,
+                opponent,
+                correspondence: false,
+            });
+            self.board.set_clock(Some(Clock::new(update.control)));
+        }
+
+        // Positions are compared by their pieces and side to move
+        let position = |fen: &str| fen.split(' ').take(2).collect::<Vec<_>>().join(" ");
+        let wanted = position(&update.fen);
+        if position(&self.board.to_fen()) != wanted {
+            let mv = update
+                .last_move
+                .as_deref()
+                .and_then(|san| self.board.parse_san(san).ok())
+                .filter(|&mv| {
+                    let mut board = self.board.clone();
+                    board.make(mv).is_ok() && position(&board.to_fen()) == wanted
+                });
+            match mv {
+                Some(mv) => {
+                    self.make_move(mv);
+                }
+                None => match Game::from_fen(&update.fen) {
+                    Ok(board) => self.replace_board(board),
+                    Err(e) => self.notify(format!("FICS sent a bad position: {}", e)),
+                },
+            }
+        }
+        if let Some(clock) = self.board.clock_mut() {
+            clock.set_remaining(update.times);
+        }
+        self.follow_turn();
+        self.request_analysis();
+    }
+
+    // Joins the game on a fog-of-war server, which starts once a second
+    // player is in
+    pub fn join_fog_game(&mut self, address: &str) {
+        let fog = FogClient::connect(address, self.fog_events.clone());
+        self.notify(format!("Connecting to {}...", fog.address));
+        self.fog_server = Some(fog);
+    }
+
+    // Handles what the fog-of-war server sent: the start and end of the game,
+    // and the player's view of it after each move
+    fn handle_fog_event(&mut self, event: FogEvent) {
+        match event {
+            FogEvent::Connected => self.notify("Connected. Waiting for an opponent..."),
+            FogEvent::Message(ServerMessage::Start { color }) => {
+                // Starting the game drops the connection of any game before
+                let fog = self.fog_server.take();
+                let id = fog
+                    .as_ref()
+                    .map_or_else(String::new, |fog| fog.address.clone());
+                self.start_online_game(OnlineGame {
+                    server: Server::Fog,
+                    id,
+                    color,
+                    opponent: "Opponent".to_string(),
+                    correspondence: false,
+                });
+                self.fog_server = fog;
+            }
+            FogEvent::Message(ServerMessage::View(view)) => {
+                let board = match view.board() {
+                    Ok(board) => board,
+                    Err(e) => return self.notify(format!("The server sent a bad view: {}", e)),
+                };
+                let to_move = board.get_current_turn() == self.player_color;
+                self.replace_board(board);
+                self.fog_view = Some(view);
+                self.turn_moves_hash = None;
+                self.clear_selection();
+                self.follow_turn();
+                if to_move {
+                    self.notify("Your move.");
+                }
+            }
+            FogEvent::Message(ServerMessage::Illegal { uci }) => {
+                self.notify(format!("The server refused {}.", uci));
+            }
+            FogEvent::Message(ServerMessage::End {
+                result,
+                reason,
+                fen,
+            }) => {
+                // The whole board is shown once the game is over
+                self.fog_view = None;
+                self.fog_server = None;
+                if let Ok(board) = Game::from_fen(&fen) {
+                    self.replace_board(board);
+                }
+                match GameResult::from_pgn_tag(&result, reason) {
+                    Some(result) => self.end_game(result),
+                    None => self.finish_message = Some(result),
+                }
+            }
+            // The server closes the connection after the end of the game
+            FogEvent::Error(_) if self.fog_server.is_none() => {}
+            FogEvent::Error(e) => {
+                if self.fog_view.take().is_some() && !self.is_over() {
+                    self.finish_message = Some("The game was cut off.".to_string());
+                }
+                self.fog_server = None;
+                self.notify(format!("Fog-of-war server: {}.", e));
+            }
+        }
+    }
+
+    // Seeks an opponent on Lichess for a game of a few days per move
+    fn seek_correspondence(&mut self) {
+        let Some(lichess) = &self.lichess else {
+            return self.notify_no_lichess();
+        };
+        lichess.seek_correspondence(correspondence::DAYS_PER_MOVE);
+        self.notify(format!(
+            "Looking for a correspondence game ({} days per move) on Lichess...",
+            correspondence::DAYS_PER_MOVE
+        ));
+    }
+
+    // Opens a correspondence game from the list, following its moves
+    fn open_correspondence(&mut self, game: CorrespondenceGame) {
+        if self.lichess.is_none() {
+            return self.notify_no_lichess();
+        }
+        self.start_online_game(OnlineGame {
+            server: Server::Lichess,
+            id: game.id,
+            color: game.color,
+            opponent: game.opponent,
+            correspondence: true,
+        });
+        // Already on the board
+        self.screen = Screen::Game;
+    }
+
+    fn save_correspondence(&mut self) {
+        if let Err(e) = self.correspondence.save() {
+            self.notify(format!("Could not save the correspondence games: {}", e));
+        }
+    }
+
+    // Seeks an opponent on Lichess at the new-game time control
+    fn seek_online(&mut self) {
+        let Some(lichess) = &self.lichess else {
+            return self.notify_no_lichess();
+        };
+        let control = self.time_control.unwrap_or(lichess::DEFAULT_CONTROL);
+        match lichess.seek(control) {
+            Ok(()) => self.notify(format!("Looking for a {} game on Lichess...", control)),
+            Err(e) => self.notify(format!("{}.", e)),
+        }
+    }
+
+    // Challenges a Lichess player at the new-game time control
+    pub fn challenge_player(&mut self, username: &str) {
+        let Some(lichess) = &self.lichess else {
+            return self.notify_no_lichess();
+        };
+        let control = self.time_control.unwrap_or(lichess::DEFAULT_CONTROL);
+        match lichess.challenge(username, control) {
+            Ok(()) => self.notify(format!(
+                "Challenged {} to a {} game on Lichess.",
+                username, control
+            )),
+            Err(e) => self.notify(format!("{}.", e)),
+        }
+    }
+
+    fn accept_challenge(&mut self) {
+        let (Some(lichess), Some(challenge)) = (&self.lichess, self.challenge.take()) else {
+            return;
+        };
+        lichess.accept(&challenge.id);
+        self.notify(format!(
+            "Accepted {}'s challenge. Waiting for the game...",
+            challenge.challenger
+        ));
+    }
+
+    fn notify_no_lichess(&mut self) {
+        self.notify(
+            "Add a Lichess API token to the [lichess] section of config.toml to play online.",
+        );
+    }
+
+    // Sets the board up for a Lichess game and follows its moves
+    fn start_online_game(&mut self, game: OnlineGame) {
+        if self
+            .online
+            .as_ref()
+            .is_some_and(|online| online.id == game.id)
+        {
+            return;
+        }
+        self.vs_ai = false;
+        self.player_color = game.color;
+        self.new_game(false);
+        // The clock comes with the game's first state
+        self.board.set_clock(None);
+        if game.server == Server::Lichess
+            && let Some(lichess) = &self.lichess
+        {
+            lichess.follow_game(&game.id);
+        }
+        self.notify(if game.correspondence {
+            format!(
+                "Correspondence game against {}. You play {:?}.",
+                game.opponent, game.color
+            )
+        } else {
+            format!(
+                "{} game against {} started. You play {:?}.",
+                game.server.name(),
+                game.opponent,
+                game.color
+            )
+        });
+        self.online = Some(game);
+    }
+
+    // Brings the board, clocks and result of the online game in line with
+    // what Lichess says
+    fn apply_online_state(&mut self, state: GameState) {
+        if let Some(control) = state.control {
+            self.board.set_clock(Some(Clock::new(control)));
+        }
+        if let Some(fen) = &state.initial_fen {
+            match Game::from_fen(fen) {
+                Ok(board) => self.replace_board(board),
+                Err(e) => self.notify(format!("Lichess sent a bad position: {}", e)),
+            }
+        }
+        // Moves already on the board stay; after anything else, such as a
+        // takeback, the game is played through again from the start
+        let played: Vec<String> = self
+            .board
+            .get_history()
+            .iter()
+            .map(|record| record.mv.to_uci())
+            .collect();
+        let mut known = played.len();
+        if !state.moves.starts_with(&played) {
+            self.replace_board(self.board.get_start_position());
+            self.move_times.clear();
+            known = 0;
+        }
+        let new_moves = state.moves.len().saturating_sub(known);
+        for (i, uci) in state.moves[known..].iter().enumerate() {
+            let Ok(mv) = self.board.parse_uci_move(uci) else {
+                self.notify(format!(
+                    "Lichess sent a move that is not legal here: {}",
+                    uci
+                ));
+                break;
+            };
+            // Only the latest move is shown being played
+            if i + 1 == new_moves {
+                self.make_move(mv);
+            } else if self.board.make(mv).is_err() {
+                break;
+            }
+        }
+
+        if let (Some(clock), Some(times)) = (self.board.clock_mut(), state.times) {
+            clock.set_remaining(times);
+        }
+        let opponent = self.player_color.opposite();
+        if state.draw_offer == Some(opponent) && self.pending_action.is_none() && !state.is_over() {
+            self.pending_action = Some(PendingAction::DrawOffer(opponent));
+            self.notify(format!(
+                "{:?} offers a draw. Press 'y' to accept or 'n' to decline.",
+                opponent
+            ));
+        }
+        if state.is_over() && !self.is_over() {
+            match state.result() {
+                Some(result) => self.end_game(result),
+                // An aborted game has no result
+                None => {
+                    let ending = state.ending();
+                    self.finish_message = Some(ending.clone());
+                    self.notify(ending);
+                }
+            }
+        }
+        self.update_correspondence(&state);
+        self.follow_turn();
+        self.request_analysis();
+    }
+
+    // Keeps the saved copy of a correspondence game in step with Lichess
+    fn update_correspondence(&mut self, state: &GameState) {
+        if !self.online.as_ref().is_some_and(|game| game.correspondence) {
+            return;
+        }
+        if state.is_over() {
+            self.correspondence.remove(&state.game_id);
+        } else if let Some(game) = self.correspondence.get_mut(&state.game_id) {
+            game.fen = self.board.to_fen();
+            game.my_turn = self.board.get_current_turn() == self.player_color;
+            game.last_move = state.moves.last().cloned();
+        }
+        self.save_correspondence();
+    }
+
+    // Pauses or resumes a game in progress
+    fn toggle_pause(&mut self) {
+        if self.is_watching() {
+            self.notify("You are watching a broadcast game.");
+        } else if self.online.is_some() {
+            self.notify("Online games cannot be paused.");
+        } else if self.paused {
+            self.paused = false;
+            self.notify("Game resumed.");
+        } else if self.is_over() {
+            self.notify("The game is over.");
+        } else {
+            self.paused = true;
+            self.clear_selection();
+            self.notify(format!(
+                "Game paused. Press {} to resume.",
+                self.keys.describe(Action::Pause)
+            ));
+        }
+    }
+
+    // Plays the AI's finished searches, drops searches for positions no longer
+    // on the board and starts thinking when the AI is on move
+    fn poll_ai(&mut self) {
+        for reply in std::mem::take(&mut self.ai_replies) {
+            if let Some(thinking) = &self.thinking
+                && reply.is_for(thinking)
+            {
+                let pondered = thinking.pondered;
+                self.thinking = None;
+                self.play_ai_reply(reply, pondered);
+            } else if let Some(ponder) = &mut self.pondering
+                && reply.is_for(&ponder.search)
+            {
+                ponder.reply = Some(reply);
+            }
+        }
+
+        let hash = self.board.hash();
+        if self
+            .thinking
+            .as_ref()
+            .is_some_and(|thinking| !self.is_ai_turn() || thinking.hash != hash)
+        {
+            self.thinking = None;
+        }
+        if self.is_ai_turn() {
+            if self.thinking.is_none() {
+                self.start_ai_move();
+            }
+        } else if !(self.settings.ponder
+            && self.vs_ai
+            && self.screen == Screen::Game
+            && !self.is_over()
+            && self
+                .pondering
+                .as_ref()
+                .is_some_and(|ponder| ponder.from_hash == hash))
+        {
+            self.pondering = None;
+        }
+    }
+
+    // Turns the board around; clicks are mapped through the same perspective
+    fn flip_board(&mut self) {
+        self.player_perspective = self.player_perspective.opposite();
+        self.notify(format!(
+            "Board flipped. {:?} is at the bottom.",
+            self.player_perspective
+        ));
+    }
+
+    // Keeps the settings for next time, unless config.toml could not be read
+    fn save_settings(&self) -> Result<(), String> {
+        match &self.settings_error {
+            Some(e) => Err(format!(
+                "config.toml was not read ({}), so it is left as it is",
+                e
+            )),
+            None => config::save_settings(&self.settings),
+        }
+    }
+
+    // Switches to the next color theme and keeps it for next time
+    fn next_theme(&mut self) {
+        self.settings.theme = self.settings.theme.next();
+        self.notify(match self.save_settings() {
+            Ok(()) => format!("Theme: {}.", self.settings.theme.label()),
+            Err(e) => format!(
+                "Theme: {}. Could not save settings: {}",
+                self.settings.theme.label(),
+                e
+            ),
+        });
+    }
+
+    // In hot-seat mode the board always faces the side to move
+    fn follow_turn(&mut self) {
+        if self.settings.rotate_board && self.is_hot_seat() {
+            self.player_perspective = self.board.get_current_turn();
+        }
+    }
+
+    // The squares the fog leaves in view, as the server sent them in a game
+    // on one
+    pub fn visible_squares(&self) -> Option<[[bool; 8]; 8]> {
+        let color = self.fog_viewer()?;
+        Some(match &self.fog_view {
+            Some(view) => view.visible(),
+            None => self.shown_board().visible_squares(color),
+        })
+    }
+
+    // Whether both sides are played at this keyboard
+    pub fn is_hot_seat(&self) -> bool {
+        !self.vs_ai
+            && self.online.is_none()
+            && self.puzzle.is_none()
+            && self.guess.is_none()
+            && self.drill.is_none()
+            && self.lesson.is_none()
+            && !self.is_watching()
+    }
+
+    // The side whose view of the board is shown in fog-of-war chess: the
+    // player against the AI or on a fog-of-war server, or the side to move in
+    // hot-seat games. None when the whole board is shown, as it is once the
+    // game is over
+    pub fn fog_viewer(&self) -> Option<ColorChess> {
+        if self.is_over() {
+            return None;
+        }
+        if self.fog_view.is_some() {
+            return Some(self.player_color);
+        }
+        if !self.settings.fog_of_war {
+            return None;
+        }
+        if self.vs_ai {
+            Some(self.player_color)
+        } else if self.is_hot_seat() {
+            Some(self.board.get_current_turn())
+        } else {
+            None
+        }
+    }
+
+    // Whether the board shows a game of the broadcast being followed
+    pub fn is_watching(&self) -> bool {
+        self.broadcast
+            .as_ref()
+            .is_some_and(|broadcast| broadcast.watching.is_some())
+    }
+
+    // True when the engine should move next
+    fn is_ai_turn(&self) -> bool {
+        self.vs_ai
+            && self.screen == Screen::Game
+            && !self.is_over()
+            && self.board.get_current_turn() != self.player_color
+    }
+
+    // Plays a book move or starts the search for the AI's move; after a correctly
+    // predicted player move the pondering search carries on instead
+    fn start_ai_move(&mut self) {
+        if let Some(mv) = self.book_move() {
+            self.pondering = None;
+            self.make_move(mv);
+            if !self.is_over() {
+                self.notify(format!(
+                    "AI played {}-{} from the opening book{}. Your move.",
+                    square_name(mv.from),
+                    square_name(mv.to),
+                    self.check_note()
+                ));
+            }
+            return;
+        }
+
+        let hash = self.board.hash();
+        match self.pondering.take() {
+            Some(ponder) if ponder.search.hash == hash => match ponder.reply {
+                Some(reply) => self.play_ai_reply(reply, true),
+                None => {
+                    ponder.search.ponder_hit();
+                    self.thinking = Some(ponder.search);
+                }
+            },
+            // On a ponder miss the dropped search stops by itself
+            _ => {
+                let limits = self.ai_difficulty().limits();
+                self.thinking = Some(self.ai.think(&self.board, limits, false));
+            }
+        }
+    }
+
+    // Plays the move found by the AI's search
+    fn play_ai_reply(&mut self, reply: AiReply, pondered: bool) {
+        let result = reply.result;
+        let Some(mv) = result.best_move else {
+            return;
+        };
+        // Scores are shown from White's point of view
+        let white_score = match self.board.get_current_turn() {
+            ColorChess::White => result.score,
+            ColorChess::Black => -result.score,
+        };
+        self.make_move(mv);
+        if !self.is_over() && self.fog_viewer().is_some() {
+            self.notify(format!("The AI has moved{}. Your move.", self.check_note()));
+            self.start_pondering(&result);
+        } else if !self.is_over() {
+            let opponent = match self.ai.engine_name() {
+                Some(name) => name.to_string(),
+                None => self.ai_difficulty().label().to_string(),
+            };
+            self.notify(format!(
+                "AI ({}) played {}-{} (depth {}, eval {}{}){}. Your move.",
+                opponent,
+                square_name(mv.from),
+                square_name(mv.to),
+                result.depth,
+                format_score(white_score),
+                if pondered { ", ponder hit" } else { "" },
+                self.check_note()
+            ));
+            self.start_pondering(&result);
+        }
+        if let Some(e) = reply.engine_error {
+            self.notify(format!("Engine failed ({}), the built-in AI took over.", e));
+        }
+    }
+
+    // Searches the position after the player's expected reply, the second move
+    // of the AI's principal variation, while the player thinks
+    fn start_pondering(&mut self, result: &SearchResult) {
+        if !self.settings.ponder || !self.ai.can_ponder() {
+            return;
+        }
+        let Some(&expected) = result.pv.get(1) else {
+            return;
+        };
+        if !self.turn_moves().contains(&expected) {
+            return;
+        }
+        let mut board = self.board.clone();
+        if board.play_move(expected).is_err() {
+            return;
+        }
+        let limits = self.ai_difficulty().limits();
+        self.pondering = Some(Ponder {
+            search: self.ai.think(&board, limits, true),
+            from_hash: self.board.hash(),
+            reply: None,
+        });
+    }
+
+    // How strongly the AI plays; in endgame drills it defends as well as it
+    // can, whatever the setting
+    fn ai_difficulty(&self) -> Difficulty {
+        if self.endgame.is_some() {
+            Difficulty::Strong
+        } else {
+            self.settings.difficulty
+        }
+    }
+
+    fn book_move(&self) -> Option<Move> {
+        let book = self.book.as_ref()?;
+        if self.board.get_history().len() >= self.settings.book.max_plies {
+            return None;
+        }
+        book.choose_move(&self.board, self.settings.book.variety)
+    }
+
+    // Posts a notification for the player
+    pub fn notify(&mut self, text: impl Into<String>) {
+        self.toasts.push(text);
+    }
+
+    // Added to move messages when the move gives check
+    fn check_note(&self) -> &'static str {
+        if self.board.is_in_check(self.board.get_current_turn()) {
+            ", check"
+        } else {
+            ""
+        }
+    }
+
+    // After a move, check for checkmate/stalemate of the side now to move
+    fn check_game_over(&mut self) {
+        // The server of a fog-of-war game, which sees the whole board, says
+        // when it is over
+        if self.fog_view.is_some() {
+            return;
+        }
+        if let Some(result) = self.board.get_result() {
+            self.end_game(result);
+        }
+    }
+
+    fn end_game(&mut self, result: GameResult) {
+        self.board.set_result(Some(result));
+        self.pending_action = None;
+        self.notify(format!("{} ({})", result, result.pgn_tag()));
+        // Games played out are reviewed by the engine, unlike the trainers'
+        let training = self.puzzle.is_some()
+            || self.guess.is_some()
+            || self.drill.is_some()
+            || self.endgame.is_some()
+            || self.lesson.is_some();
+        if !training && !self.is_watching() && self.board.can_undo() {
+            self.save_to_database();
+            self.rate_game(result);
+            self.start_review();
+        }
+    }
+
+    // Keeps the finished game for the "Load game" list
+    fn save_to_database(&mut self) {
+        let side = match &self.online {
+            Some(game) => Some(game.color),
+            None if self.vs_ai => Some(self.player_color),
+            None => None,
+        };
+        let pgn = self.game_pgn(self.board.get_history().len());
+        if let Err(e) = self.games.add(&pgn, side) {
+            self.notify(format!("Could not save the game to the database: {}", e));
+        }
+        self.opening_tree = None;
+    }
+
+    // Rates the player on a game against the built-in AI, or the named
+    // players on a hot-seat game
+    fn rate_game(&mut self, result: GameResult) {
+        let score = |side| match result.winner {
+            Some(winner) if winner == side => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        };
+        if self.vs_ai && self.online.is_none() && self.ai.engine_name().is_none() {
+            let difficulty = self.settings.difficulty;
+            let (rating, change) = self.ratings.record_ai(difficulty, score(self.player_color));
+            self.notify(format!(
+                "Your rating against {}: {} ({:+}).",
+                difficulty.label(),
+                rating,
+                change
+            ));
+        } else if self.is_hot_seat()
+            && let (Some(white), Some(black)) = (
+                self.settings.players.white.clone(),
+                self.settings.players.black.clone(),
+            )
+            && white != black
+        {
+            let (white_change, black_change) =
+                self.ratings
+                    .record_players(&white, &black, score(ColorChess::White));
+            self.notify(format!(
+                "Ratings: {} {:+}, {} {:+}.",
+                white, white_change, black, black_change
+            ));
+        } else {
+            return;
+        }
+        if let Err(e) = self.ratings.save() {
+            self.notify(format!("Could not save the ratings: {}", e));
+        }
+    }
+
+    // Runs the engine over every move of the game in the background
+    fn start_review(&mut self) {
+        let plies = self.board.get_history().len();
+        if plies == 0 {
+            return self.notify("There are no moves to review.");
+        }
+        self.review = None;
+        self.review_index = 0;
+        self.review_progress = Some((0, plies + 1));
+        self.reviewing = Some(review::review_game(&self.board));
+    }
+
+    // Opens the review of the game, starting it if there is none yet
+    fn open_review(&mut self) {
+        let hash = self.board.hash();
+        if self
+            .review
+            .as_ref()
+            .is_some_and(|review| review.hash == hash)
+        {
+            self.stop_browsing();
+            self.screen = Screen::Review;
+            return;
+        }
+        if let Some((done, total)) = self.review_progress {
+            return self.notify(format!(
+                "Reviewing the game: {} of {} positions searched.",
+                done, total
+            ));
+        }
+        self.start_review();
+        if self.reviewing.is_some() {
+            self.notify("Reviewing the game...");
+        }
+    }
+
+    fn poll_review(&mut self) {
+        let Some(reviewing) = &self.reviewing else {
+            return;
+        };
+        let mut finished = None;
+        for update in reviewing.try_iter() {
+            match update {
+                ReviewUpdate::Progress(done, total) => self.review_progress = Some((done, total)),
+                ReviewUpdate::Done(review) => finished = Some(review),
+            }
+        }
+        let Some(review) = finished else {
+            return;
+        };
+        self.reviewing = None;
+        self.review_progress = None;
+        // Moves taken back since make it a review of another game
+        if review.hash != self.board.hash() {
+            return;
+        }
+        // The verdicts and evals go into the game tree, for the move list and
+        // exports, without overriding annotations of the player's own
+        self.sync_tree();
+        let line = self.tree.main_line();
+        for reviewed in &review.moves {
+            let Some(&node) = line.get(reviewed.ply) else {
+                continue;
+            };
+            if let Some(nag) = reviewed.verdict.nag()
+                && self.tree.nags(node).is_empty()
+            {
+                self.tree.annotate(node, nag);
+            }
+            let eval = Eval {
+                white_score: reviewed.white_score,
+                depth: reviewed.depth,
+            };
+            self.tree.record_eval(node, eval);
+        }
+        let accuracy = |color| {
+            review
+                .accuracy(color)
+                .map_or("-".to_string(), |accuracy| format!("{:.0}%", accuracy))
+        };
+        self.notify(format!(
+            "Review done: accuracy White {}, Black {}. Press {} to see it.",
+            accuracy(ColorChess::White),
+            accuracy(ColorChess::Black),
+            self.keys.describe(Action::Review)
+        ));
+        self.review = Some(review);
+    }
+
+    // Shows the position after the mistake picked on the review screen on
+    // the analysis board, with what was better
+    fn show_key_move(&mut self) {
+        let Some(review) = &self.review else {
+            return;
+        };
+        let Some(key_move) = review.key_moves().get(self.review_index).copied() else {
+            return;
+        };
+        let ply = key_move.ply;
+        let mut message = format!("{} was a {}", key_move.label, key_move.verdict.label());
+        if let Some(best) = &key_move.best {
+            message.push_str(&format!("; {} was better", best));
+        }
+        message.push('.');
+        self.screen = Screen::Game;
+        self.sync_tree();
+        if let Some(&node) = self.tree.main_line().get(ply) {
+            self.show_node(node);
+        }
+        self.notify(message);
+    }
+
+    fn resign(&mut self) {
+        if self.is_over() {
+            return;
+        }
+        if self.is_watching() {
+            return self.notify("You are watching a broadcast game.");
+        }
+        if self.puzzle.is_some() {
+            return self.notify("Press n to skip to the next puzzle.");
+        }
+        if self.guess.is_some() {
+            return self.notify("Press n to skip to the next game.");
+        }
+        if self.drill.is_some() {
+            return self.notify("Press n to skip to the next line.");
+        }
+        if self.endgame.is_some() {
+            return self.notify("Press n to skip to the next drill, or m to start over.");
+        }
+        if self.lesson.is_some() {
+            return self.notify("Press n to skip to the next lesson.");
+        }
+        // The AI or the online opponent may be on move, but it is the player who resigns
+        let color = if !self.is_hot_seat() {
+            self.player_color
+        } else {
+            self.board.get_current_turn()
+        };
+        self.pending_action = Some(PendingAction::Resign(color));
+        self.notify(format!(
+            "Resign as {:?}? Press 'y' to confirm or 'n' to cancel.",
+            color
+        ));
+    }
+
+    fn offer_draw(&mut self) {
+        if self.is_over() {
+            return;
+        }
+        if self.is_watching() {
+            return self.notify("You are watching a broadcast game.");
+        }
+        if self.puzzle.is_some() {
+            return self.notify("Press n to skip to the next puzzle.");
+        }
+        if self.guess.is_some() {
+            return self.notify("Press n to skip to the next game.");
+        }
+        if self.drill.is_some() {
+            return self.notify("Press n to skip to the next line.");
+        }
+        if self.endgame.is_some() {
+            return self.notify("Press n to skip to the next drill, or m to start over.");
+        }
+        if self.lesson.is_some() {
+            return self.notify("Press n to skip to the next lesson.");
+        }
+        if let Some(game) = &self.online {
+            if game.server == Server::Fog {
+                return self.notify("Draws are not offered in fog-of-war games.");
+            }
+            let message = format!("Offered {} a draw.", game.opponent);
+            self.send_draw(true);
+            self.notify(message);
+            return;
+        }
+        let color = self.board.get_current_turn();
+        if self.is_ai_turn() {
+            self.notify("Wait for the AI's move before offering a draw.");
+            return;
+        }
+        if self.vs_ai {
+            // The engine only takes a draw when it is clearly worse off
+            let ai_score = -evaluate(&self.board);
+            if ai_score < -150 {
+                self.end_game(GameResult::draw(Termination::Agreement));
+            } else {
+                self.notify("The AI declined the draw offer.");
+            }
+            return;
+        }
+        self.pending_action = Some(PendingAction::DrawOffer(color));
+        self.notify(format!(
+            "{:?} offers a draw. {:?}: press 'y' to accept or 'n' to decline.",
+            color,
+            color.opposite()
+        ));
+    }
+
+    fn send_resign(&self) {
+        match (&self.online, &self.lichess, &self.fics) {
+            (Some(game), Some(lichess), _) if game.server == Server::Lichess => {
+                lichess.resign(&game.id);
+            }
+            (Some(game), _, Some(fics)) if game.server == Server::Fics => fics.resign(),
+            (Some(game), _, _) if game.server == Server::Fog => {
+                if let Some(fog) = &self.fog_server {
+                    fog.resign();
+                }
+            }
+            _ => {}
+        }
+    }
+
+    // Offers or accepts a draw (true), or declines one (false), online
+    fn send_draw(&self, accept: bool) {
+        match (&self.online, &self.lichess, &self.fics) {
+            (Some(game), Some(lichess), _) if game.server == Server::Lichess => {
+                lichess.draw(&game.id, accept);
+            }
+            (Some(game), _, Some(fics)) if game.server == Server::Fics => fics.draw(accept),
+            _ => {}
+        }
+    }
+
+    // Answers the pending resignation/draw offer with yes (true) or no (false)
+    fn answer_pending_action(&mut self, accepted: bool) {
+        let Some(action) = self.pending_action.take() else {
+            return;
+        };
+        if let PendingAction::ConfirmMove(mv) = action {
+            if accepted {
+                return self.commit_move(mv);
+            }
+            return self.notify("Move taken back.");
+        }
+        // The server ends an online game, which then comes back as its new state
+        if self.online.is_some() {
+            let message = match (action, accepted) {
+                (PendingAction::Resign(_), true) => {
+                    self.send_resign();
+                    "Resigning..."
+                }
+                (PendingAction::Resign(_), false) => "Resignation cancelled.",
+                (PendingAction::DrawOffer(_), true) => {
+                    self.send_draw(true);
+                    "Draw accepted."
+                }
+                (PendingAction::DrawOffer(_), false) => {
+                    self.send_draw(false);
+                    "Draw offer declined."
+                }
+                (PendingAction::ConfirmMove(_), _) => return,
+            };
+            return self.notify(message);
+        }
+        match (action, accepted) {
+            (PendingAction::Resign(color), true) => self.end_game(GameResult::win_for(
+                color.opposite(),
+                Termination::Resignation,
+            )),
+            (PendingAction::Resign(_), false) => {
+                self.notify("Resignation cancelled.");
+            }
+            (PendingAction::DrawOffer(_), true) => {
+                self.end_game(GameResult::draw(Termination::Agreement));
+            }
+            (PendingAction::DrawOffer(color), false) => {
+                self.notify(format!("{:?} declined the draw offer.", color.opposite()));
+            }
+            (PendingAction::ConfirmMove(_), _) => {}
+        }
+    }
+
+    fn clear_selection(&mut self) {
+        self.selected_square = None; // Reset selection
+        self.possible_moves.clear(); // Clear highlights
+        self.pending_promotion = None;
+    }
+
+    fn undo_move(&mut self) {
+        if self.is_watching() {
+            return self.notify("You are watching a broadcast game.");
+        }
+        if self.puzzle.is_some() {
+            return self.notify("Takebacks are not available in puzzles.");
+        }
+        if self.guess.is_some() {
+            return self.notify("Takebacks are not available when guessing moves.");
+        }
+        if self.drill.is_some() {
+            return self.notify("Takebacks are not available in repertoire drills.");
+        }
+        if self.endgame.is_some() {
+            return self.notify("Takebacks are not available in endgame drills.");
+        }
+        if self.lesson.is_some() {
+            return self.notify("Press m to try the lesson again.");
+        }
+        if self.online.is_some() {
+            return self.notify("Takebacks are not available in online games.");
+        }
+        self.clear_selection();
+        self.browse = None;
+        self.animation = None;
+        let Some(mut mv) = self.board.undo() else {
+            self.notify("Nothing to undo.");
+            return;
+        };
+        self.move_timer = Stopwatch::default();
+        // Taking back the final move reopens a finished game
+        self.finish_message = None;
+        self.board.set_result(None);
+        self.pending_action = None;
+        // Against the engine, take back the player's own move as well as its reply
+        if self.is_ai_turn()
+            && let Some(player_move) = self.board.undo()
+        {
+            mv = player_move;
+        }
+        self.notify(format!(
+            "Took back {}-{}. {:?} to move.",
+            square_name(mv.from),
+            square_name(mv.to),
+            self.board.get_current_turn()
+        ));
+        self.follow_turn();
+        self.request_analysis();
+    }
+
+    fn redo_move(&mut self) {
+        if self.is_watching() {
+            return self.notify("You are watching a broadcast game.");
+        }
+        if self.puzzle.is_some() {
+            return self.notify("Takebacks are not available in puzzles.");
+        }
+        if self.guess.is_some() {
+            return self.notify("Takebacks are not available when guessing moves.");
+        }
+        if self.drill.is_some() {
+            return self.notify("Takebacks are not available in repertoire drills.");
+        }
+        if self.endgame.is_some() {
+            return self.notify("Takebacks are not available in endgame drills.");
+        }
+        if self.lesson.is_some() {
+            return self.notify("Press m to try the lesson again.");
+        }
+        if self.online.is_some() {
+            return self.notify("Takebacks are not available in online games.");
+        }
+        self.clear_selection();
+        self.browse = None;
+        self.animation = None;
+        let Some(mv) = self.board.redo() else {
+            self.notify("Nothing to redo.");
+            return;
+        };
+        self.move_timer = Stopwatch::default();
+        self.check_game_over();
+        // Replay the engine's reply too, so it does not search the position again
+        if self.is_ai_turn() && self.board.redo().is_some() {
+            self.check_game_over();
+        }
+        if !self.is_over() {
+            self.notify(format!(
+                "Replayed {}-{}. {:?} to move.",
+                square_name(mv.from),
+                square_name(mv.to),
+                self.board.get_current_turn()
+            ));
+        }
+        self.follow_turn();
+        self.request_analysis();
+    }
+}
+
+// Maps a displayed (row, col) position, counted from the top-left corner of the
+// drawn board, to the board square shown there from the given perspective
+pub fn display_to_square(display: (usize, usize), perspective: ColorChess) -> (usize, usize) {
+    match perspective {
+        ColorChess::White => (7 - display.0, display.1),
+        ColorChess::Black => (display.0, 7 - display.1),
+    }
+}
+
+// The cursor starts on the king pawn of the side at the bottom
+fn start_cursor(color: ColorChess) -> (usize, usize) {
+    match color {
+        ColorChess::White => (1, 4),
+        ColorChess::Black => (6, 4),
+    }
+}
+
+// Engine score for display from White's point of view, e.g. "+0.35" or "White mates in 3"
+pub fn format_score(score: i32) -> String {
+    if score.abs() >= MATE_THRESHOLD {
+        let moves = (MATE_SCORE - score.abs() + 1) / 2;
+        if score > 0 {
+            format!("White mates in {}", moves)
+        } else {
+            format!("Black mates in {}", moves)
+        }
+    } else if score >= TB_WIN_THRESHOLD {
+        "White wins".to_string()
+    } else if score <= -TB_WIN_THRESHOLD {
+        "Black wins".to_string()
+    } else {
+        format!("{:+.2}", score as f64 / 100.0)
+    }
+}
+
+// Starts the configured UCI engine for one role, noting whether it came up
+fn start_engine(config: &EngineConfig, wanted: bool, toasts: &mut Toasts) -> Option<UciEngine> {
+    match config.start(wanted)? {
+        Ok(engine) => {
+            toasts.push(format!("Engine ready: {}.", engine.get_name()));
+            Some(engine)
+        }
+        Err(e) => {
+            toasts.push(format!(
+                "Could not start the engine ({}), using the built-in one.",
+                e
+            ));
+            None
+        }
+    }
+}
+
+// Key used to pick each piece in the promotion dialog
+pub fn promotion_key(piece_type: PieceType) -> char {
+    piece_type.letter()
+}
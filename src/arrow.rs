@@ -0,0 +1,62 @@
+use std::f64::consts::FRAC_PI_4;
+
+use tui::{buffer::Buffer, layout::Rect, style::Color, widgets::Widget};
+
+// Bit of each dot of a braille character, by its (column, row) in the cell
+const BRAILLE_DOTS: [[u32; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+
+// Heads pointing along each eighth of a turn, counterclockwise from the right
+const HEADS: [char; 8] = ['→', '↗', '↑', '↖', '←', '↙', '↓', '↘'];
+
+// A braille line from one terminal cell to another with a head at its end,
+// drawn only over blank cells so that pieces and labels stay on top of it
+pub struct Arrow {
+    pub from: (u16, u16),
+    pub to: (u16, u16),
+    pub color: Color,
+}
+
+impl Widget for Arrow {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        // Braille cells are two dots wide and four high; the line runs between
+        // the middles of the two cells
+        let dot = |(x, y): (u16, u16)| (f64::from(x) * 2.0 + 0.5, f64::from(y) * 4.0 + 1.5);
+        let (start, end) = (dot(self.from), dot(self.to));
+        let steps = (end.0 - start.0).abs().max((end.1 - start.1).abs()).ceil() as usize;
+
+        // The cells the line passes through, in order, with their dots
+        let mut cells: Vec<((u16, u16), u32)> = Vec::new();
+        for step in 0..=steps {
+            let t = if steps == 0 {
+                0.0
+            } else {
+                step as f64 / steps as f64
+            };
+            let x = (start.0 + (end.0 - start.0) * t).round() as u16;
+            let y = (start.1 + (end.1 - start.1) * t).round() as u16;
+            let cell = (x / 2, y / 4);
+            let bit = BRAILLE_DOTS[(x % 2) as usize][(y % 4) as usize];
+            match cells.last_mut() {
+                Some((last, dots)) if *last == cell => *dots |= bit,
+                _ => cells.push((cell, bit)),
+            }
+        }
+        let blank = |buf: &Buffer, (x, y): (u16, u16)| {
+            area.intersects(Rect::new(x, y, 1, 1)) && buf.get(x, y).symbol == " "
+        };
+        cells.retain(|&(cell, _)| blank(buf, cell));
+
+        // Cells are about twice as high as they are wide, so dots are square
+        let angle = (start.1 - end.1).atan2(end.0 - start.0);
+        let head = HEADS[(angle / FRAC_PI_4).round().rem_euclid(8.0) as usize];
+        let count = cells.len();
+        for (index, ((x, y), dots)) in cells.into_iter().enumerate() {
+            let symbol = if index + 1 == count {
+                head
+            } else {
+                char::from_u32(0x2800 + dots).unwrap_or(' ')
+            };
+            buf.get_mut(x, y).set_char(symbol).set_fg(self.color);
+        }
+    }
+}
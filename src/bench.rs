@@ -0,0 +1,73 @@
+use std::time::{Duration, Instant};
+
+use chess_rs::{Game, SearchLimits, Searcher};
+
+// Positions the benchmark runs through: openings, middlegames with tactics
+// and castling rights, and endgames with promotions
+const POSITIONS: [&str; 8] = [
+    "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+    "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+    "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+    "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+    "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+    "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+    "2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4RK1 w - - 0 1",
+    "8/8/1p6/3b4/1P1k1p2/8/3KBP2/8 w - - 0 1",
+];
+
+// Counts each position's moves `perft_depth` plies down, then searches it
+// to `depth` with a cleared searcher, printing the nodes, time and speed of
+// both. The search node count only changes when the search does, so it
+// tells a change in behaviour from one in speed
+pub fn run(depth: u32, perft_depth: u32) -> Result<(), String> {
+    let mut searcher = Searcher::new();
+    let (mut perft_nodes, mut perft_time) = (0, Duration::ZERO);
+    let (mut search_nodes, mut search_time) = (0, Duration::ZERO);
+    println!(
+        "{:<3} {:>12} {:>9} {:>12} {:>9}  best",
+        "#", "perft", "ms", "search", "ms"
+    );
+    for (i, fen) in POSITIONS.iter().enumerate() {
+        let mut board = Game::from_fen(fen)?;
+        let start = Instant::now();
+        let nodes = board.perft(perft_depth);
+        let perft_elapsed = start.elapsed();
+        perft_nodes += nodes;
+        perft_time += perft_elapsed;
+
+        searcher.clear();
+        let result = searcher.search(&board, SearchLimits::depth(depth));
+        search_nodes += result.nodes;
+        search_time += result.elapsed;
+        println!(
+            "{:<3} {:>12} {:>9} {:>12} {:>9}  {}",
+            i + 1,
+            nodes,
+            perft_elapsed.as_millis(),
+            result.nodes,
+            result.elapsed.as_millis(),
+            result
+                .best_move
+                .map_or("-".to_string(), |mv| board.move_to_san(mv))
+        );
+    }
+    println!();
+    println!(
+        "Move generation: {} nodes in {} ms, {} nodes a second",
+        perft_nodes,
+        perft_time.as_millis(),
+        per_second(perft_nodes, perft_time)
+    );
+    println!(
+        "Search to depth {}: {} nodes in {} ms, {} nodes a second",
+        depth,
+        search_nodes,
+        search_time.as_millis(),
+        per_second(search_nodes, search_time)
+    );
+    Ok(())
+}
+
+fn per_second(nodes: u64, time: Duration) -> u64 {
+    (nodes as f64 / time.as_secs_f64().max(1e-9)) as u64
+}
@@ -0,0 +1,366 @@
+use std::sync::OnceLock;
+
+use crate::board::ColorChess;
+
+// A set of squares with one bit per square, bit `row * 8 + col` (a1 is bit 0, h8 bit 63)
+pub(crate) type Bitboard = u64;
+
+pub(crate) fn square_index((row, col): (usize, usize)) -> usize {
+    row * 8 + col
+}
+
+pub(crate) fn square_bit(square: (usize, usize)) -> Bitboard {
+    1 << square_index(square)
+}
+
+// The squares in a bitboard, from a1 up to h8
+pub(crate) fn squares(mut bitboard: Bitboard) -> impl Iterator<Item = (usize, usize)> {
+    std::iter::from_fn(move || {
+        if bitboard == 0 {
+            return None;
+        }
+        let index = bitboard.trailing_zeros() as usize;
+        bitboard &= bitboard - 1;
+        Some((index / 8, index % 8))
+    })
+}
+
+// Squares a rook on `square` attacks, each ray ending at the first piece in `occupied`
+pub(crate) fn rook_attacks(square: (usize, usize), occupied: Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.attacks[tables.rook[square_index(square)].index(occupied)]
+}
+
+// Squares a bishop on `square` attacks, each ray ending at the first piece in `occupied`
+pub(crate) fn bishop_attacks(square: (usize, usize), occupied: Bitboard) -> Bitboard {
+    let tables = tables();
+    tables.attacks[tables.bishop[square_index(square)].index(occupied)]
+}
+
+// Squares strictly between two squares sharing a rank, file or diagonal, and
+// no squares for any other pair
+pub(crate) fn between(a: (usize, usize), b: (usize, usize)) -> Bitboard {
+    let (bit_a, bit_b) = (square_bit(a), square_bit(b));
+    if rook_attacks(a, 0) & bit_b != 0 {
+        rook_attacks(a, bit_b) & rook_attacks(b, bit_a)
+    } else if bishop_attacks(a, 0) & bit_b != 0 {
+        bishop_attacks(a, bit_b) & bishop_attacks(b, bit_a)
+    } else {
+        0
+    }
+}
+
+// Squares a knight on `square` attacks
+pub(crate) fn knight_attacks(square: (usize, usize)) -> Bitboard {
+    KNIGHT_ATTACKS[square_index(square)]
+}
+
+// Squares a king on `square` attacks
+pub(crate) fn king_attacks(square: (usize, usize)) -> Bitboard {
+    KING_ATTACKS[square_index(square)]
+}
+
+// Squares a pawn of the given color on `square` attacks
+pub(crate) fn pawn_attacks(square: (usize, usize), color: ColorChess) -> Bitboard {
+    PAWN_ATTACKS[color.index()][square_index(square)]
+}
+
+const KNIGHT_OFFSETS: [(isize, isize); 8] = [
+    (1, 2),
+    (2, 1),
+    (2, -1),
+    (1, -2),
+    (-1, -2),
+    (-2, -1),
+    (-2, 1),
+    (-1, 2),
+];
+const KING_OFFSETS: [(isize, isize); 8] = [
+    (1, 0),
+    (1, 1),
+    (0, 1),
+    (-1, 1),
+    (-1, 0),
+    (-1, -1),
+    (0, -1),
+    (1, -1),
+];
+
+static KNIGHT_ATTACKS: [Bitboard; 64] = leaper_attacks(&KNIGHT_OFFSETS);
+static KING_ATTACKS: [Bitboard; 64] = leaper_attacks(&KING_OFFSETS);
+// White pawns capture towards rank 8, Black ones towards rank 1
+static PAWN_ATTACKS: [[Bitboard; 64]; 2] = [
+    leaper_attacks(&[(1, -1), (1, 1)]),
+    leaper_attacks(&[(-1, -1), (-1, 1)]),
+];
+
+// Attack sets of a piece that jumps by fixed offsets, for every square
+const fn leaper_attacks(offsets: &[(isize, isize)]) -> [Bitboard; 64] {
+    let mut attacks = [0; 64];
+    let mut index = 0;
+    while index < 64 {
+        let mut i = 0;
+        while i < offsets.len() {
+            let row = (index / 8) as isize + offsets[i].0;
+            let col = (index % 8) as isize + offsets[i].1;
+            if row >= 0 && row < 8 && col >= 0 && col < 8 {
+                attacks[index] |= 1 << (row * 8 + col);
+            }
+            i += 1;
+        }
+        index += 1;
+    }
+    attacks
+}
+
+const ROOK_DIRECTIONS: [(isize, isize); 4] = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+const BISHOP_DIRECTIONS: [(isize, isize); 4] = [(1, 1), (1, -1), (-1, 1), (-1, -1)];
+
+// Multipliers that map every arrangement of blockers on a square's rays to its own
+// slot without harmful collisions, found once with a splitmix64 trial search
+const ROOK_MAGICS: [u64; 64] = [
+    0x02800022C0005084,
+    0x0840001000402005,
+    0x0100084020001104,
+    0x0480100080040801,
+    0x0200040200102008,
+    0x0500084100020400,
+    0x1080010000800200,
+    0x008003450000B080,
+    0x00A0802040008000,
+    0x4400400050002000,
+    0x002080100080200A,
+    0x0029002010010008,
+    0x0050800400800801,
+    0x4104808004000200,
+    0x3842000812004184,
+    0x0801001080610002,
+    0x2240048000804020,
+    0x0400404010002000,
+    0x0000808020001002,
+    0xC000808010000800,
+    0xA021030010080024,
+    0x4801808002010400,
+    0x0800EC00080ACB10,
+    0x1001020000843049,
+    0x4001004200220081,
+    0x2200200040005000,
+    0x0210419100200300,
+    0x0010100080080080,
+    0x4204000480080080,
+    0x1204010040020040,
+    0x041C080400B10230,
+    0x000020920005044C,
+    0x0022304001800080,
+    0x0000201000404000,
+    0x8000801000802000,
+    0x0000801000800800,
+    0x0000800800800400,
+    0x4000800400800200,
+    0x0006000102000408,
+    0x820500A042000904,
+    0x0200400080008029,
+    0x4600500020034000,
+    0x0030040028002002,
+    0x0010010800818010,
+    0x0841003008030004,
+    0x0081004400090002,
+    0x1500881081040002,
+    0x1004004084020001,
+    0x4009002048800100,
+    0x0000410082002200,
+    0x0010804602201200,
+    0x0106484200D12200,
+    0x0204000608008080,
+    0x0441041020400801,
+    0x1610080142100400,
+    0x2888004100941200,
+    0x4901001820800041,
+    0x8082010018402082,
+    0x20009020000B0141,
+    0x8210201001000905,
+    0x2002016004504802,
+    0x041A000410080102,
+    0x0000900088010204,
+    0x4020102084010052,
+];
+const BISHOP_MAGICS: [u64; 64] = [
+    0x4140010801010020,
+    0x2104081805023000,
+    0x0212141042000110,
+    0x0811040681000010,
+    0x8004042004A01000,
+    0x41220A5024A20000,
+    0x0011208220200040,
+    0x0000804842202020,
+    0x0000408202040100,
+    0x0400041004444988,
+    0x000842008101000A,
+    0x0400482080200000,
+    0x40402C0520004040,
+    0x0100231118400094,
+    0x0421510C10424800,
+    0x8008204410980881,
+    0x0020A01020020080,
+    0x80083B2088090062,
+    0x000801500050C048,
+    0x0004040804220800,
+    0x0104040280A00428,
+    0x0022010020900802,
+    0x0001104201012000,
+    0x2024201080841008,
+    0x2018288040108D2E,
+    0x9012200010248080,
+    0x0000900012042200,
+    0x000A808828020020,
+    0x0001001081004000,
+    0x08101C8080480400,
+    0x0021040200440490,
+    0xE20A042000840908,
+    0x1082104002050800,
+    0x4200822003114420,
+    0x0002014900901100,
+    0x2000020080080080,
+    0x0081100400208020,
+    0x0000AA0880141000,
+    0x2024040041188806,
+    0x0002006A40120200,
+    0x4001042084002100,
+    0x00A10082D0022010,
+    0x0010D05488101001,
+    0x0040002028000421,
+    0x001C020204110200,
+    0x48402A0411000448,
+    0x0020211240820601,
+    0x0002009202002898,
+    0x20004108A0104430,
+    0x00010C882108400C,
+    0x3001084044100000,
+    0x000002488404100C,
+    0x4010020590440044,
+    0x0000049002420180,
+    0x0005103208012020,
+    0x000424A802102001,
+    0x0000410080904005,
+    0x020000420210420A,
+    0x5104C00104010400,
+    0x0108012180420E04,
+    0x02000008A09A0489,
+    0x0002102084500081,
+    0x01D8200801012408,
+    0x30A1021010490042,
+];
+
+// Where the attack sets for one square start in the shared table, and how the
+// blockers on its rays are hashed into an offset from there
+struct Magic {
+    mask: Bitboard,
+    magic: u64,
+    shift: u32,
+    offset: usize,
+}
+
+impl Magic {
+    fn index(&self, occupied: Bitboard) -> usize {
+        self.offset + ((occupied & self.mask).wrapping_mul(self.magic) >> self.shift) as usize
+    }
+}
+
+struct MagicTables {
+    rook: [Magic; 64],
+    bishop: [Magic; 64],
+    attacks: Vec<Bitboard>,
+}
+
+fn tables() -> &'static MagicTables {
+    static TABLES: OnceLock<MagicTables> = OnceLock::new();
+    TABLES.get_or_init(MagicTables::new)
+}
+
+impl MagicTables {
+    fn new() -> MagicTables {
+        let mut attacks = Vec::new();
+        let rook = std::array::from_fn(|index| {
+            fill_attacks(index, &ROOK_DIRECTIONS, ROOK_MAGICS[index], &mut attacks)
+        });
+        let bishop = std::array::from_fn(|index| {
+            fill_attacks(
+                index,
+                &BISHOP_DIRECTIONS,
+                BISHOP_MAGICS[index],
+                &mut attacks,
+            )
+        });
+        MagicTables {
+            rook,
+            bishop,
+            attacks,
+        }
+    }
+}
+
+// Appends the attack sets of a slider on square `index` for every arrangement of
+// blockers. Pieces on the edge of the board never block anything further, so
+// they are left out of the mask.
+fn fill_attacks(
+    index: usize,
+    directions: &[(isize, isize)],
+    magic: u64,
+    attacks: &mut Vec<Bitboard>,
+) -> Magic {
+    let mut mask: Bitboard = 0;
+    for &(dr, dc) in directions {
+        let mut square = step(index, dr, dc);
+        while let Some(current) = square {
+            square = step(current, dr, dc);
+            if square.is_some() {
+                mask |= 1 << current;
+            }
+        }
+    }
+
+    let bits = mask.count_ones();
+    let magic = Magic {
+        mask,
+        magic,
+        shift: 64 - bits,
+        offset: attacks.len(),
+    };
+    attacks.resize(attacks.len() + (1 << bits), 0);
+
+    // Walk through every subset of the mask
+    let mut blockers: Bitboard = 0;
+    loop {
+        let slot = magic.index(blockers);
+        let reached = slide(index, blockers, directions);
+        debug_assert!(attacks[slot] == 0 || attacks[slot] == reached);
+        attacks[slot] = reached;
+        blockers = blockers.wrapping_sub(mask) & mask;
+        if blockers == 0 {
+            break;
+        }
+    }
+    magic
+}
+
+// Attacks found by walking each ray, used to fill the tables
+fn slide(index: usize, occupied: Bitboard, directions: &[(isize, isize)]) -> Bitboard {
+    let mut attacks = 0;
+    for &(dr, dc) in directions {
+        let mut square = step(index, dr, dc);
+        while let Some(current) = square {
+            attacks |= 1 << current;
+            if occupied & (1 << current) != 0 {
+                break;
+            }
+            square = step(current, dr, dc);
+        }
+    }
+    attacks
+}
+
+fn step(index: usize, dr: isize, dc: isize) -> Option<usize> {
+    let row = (index / 8).checked_add_signed(dr).filter(|&row| row < 8)?;
+    let col = (index % 8).checked_add_signed(dc).filter(|&col| col < 8)?;
+    Some(row * 8 + col)
+}
@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PieceType {
+    King,
+    Queen,
+    Rook,
+    Bishop,
+    Knight,
+    Pawn,
+}
+
+impl PieceType {
+    // Upper-case letter used for the piece in algebraic notation
+    pub fn letter(self) -> char {
+        match self {
+            PieceType::King => 'K',
+            PieceType::Queen => 'Q',
+            PieceType::Rook => 'R',
+            PieceType::Bishop => 'B',
+            PieceType::Knight => 'N',
+            PieceType::Pawn => 'P',
+        }
+    }
+
+    // Index of the type in Position::pieces
+    pub(crate) fn index(self) -> usize {
+        match self {
+            PieceType::Pawn => 0,
+            PieceType::Knight => 1,
+            PieceType::Bishop => 2,
+            PieceType::Rook => 3,
+            PieceType::Queen => 4,
+            PieceType::King => 5,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorChess {
+    White,
+    Black,
+}
+
+impl ColorChess {
+    pub fn opposite(self) -> ColorChess {
+        match self {
+            ColorChess::White => ColorChess::Black,
+            ColorChess::Black => ColorChess::White,
+        }
+    }
+
+    // Index of the color in Position::pieces and Position::occupancy
+    pub(crate) fn index(self) -> usize {
+        match self {
+            ColorChess::White => 0,
+            ColorChess::Black => 1,
+        }
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Piece(u8);
+
+// Piece type constants (bits 0-2)
+const PAWN: u8 = 0b000;
+const KNIGHT: u8 = 0b001;
+const BISHOP: u8 = 0b010;
+const ROOK: u8 = 0b011;
+const QUEEN: u8 = 0b100;
+const KING: u8 = 0b101;
+
+// Color flag (bit 3)
+const WHITE_FLAG: u8 = 0b0000;
+const BLACK_FLAG: u8 = 0b1000;
+
+impl Piece {
+    // Constructor
+    pub fn new(piece_type: PieceType, color: ColorChess) -> Self {
+        let type_bits = match piece_type {
+            PieceType::Pawn => PAWN,
+            PieceType::Knight => KNIGHT,
+            PieceType::Bishop => BISHOP,
+            PieceType::Rook => ROOK,
+            PieceType::Queen => QUEEN,
+            PieceType::King => KING,
+        };
+
+        let color_bit = match color {
+            ColorChess::White => WHITE_FLAG,
+            ColorChess::Black => BLACK_FLAG,
+        };
+
+        Piece(type_bits | color_bit)
+    }
+
+    // Getters
+    pub fn piece_type(&self) -> PieceType {
+        match self.0 & 0b0111 {
+            PAWN => PieceType::Pawn,
+            KNIGHT => PieceType::Knight,
+            BISHOP => PieceType::Bishop,
+            ROOK => PieceType::Rook,
+            QUEEN => PieceType::Queen,
+            KING => PieceType::King,
+            _ => unreachable!("Invalid piece type bits"),
+        }
+    }
+
+    pub fn color(&self) -> ColorChess {
+        if (self.0 & BLACK_FLAG) != 0 {
+            ColorChess::Black
+        } else {
+            ColorChess::White
+        }
+    }
+
+    pub fn is_color(&self, color: ColorChess) -> bool {
+        self.color() == color
+    }
+
+    pub fn is_type(&self, piece_type: PieceType) -> bool {
+        self.piece_type() == piece_type
+    }
+
+    pub fn to_char(self) -> char {
+        match self.piece_type() {
+            PieceType::King => '♚',
+            PieceType::Queen => '♛',
+            PieceType::Rook => '♜',
+            PieceType::Bishop => '♝',
+            PieceType::Knight => '♞',
+            PieceType::Pawn => '♟',
+        }
+    }
+
+    pub fn points(&self) -> u32 {
+        match self.piece_type() {
+            PieceType::Pawn => 1,
+            PieceType::Knight | PieceType::Bishop => 3,
+            PieceType::Rook => 5,
+            PieceType::Queen => 9,
+            PieceType::King => 0, // King's value is infinite in terms of game points
+        }
+    }
+}
+
+// Whether a (row, col) square is one of the board's 64
+pub(crate) fn on_board((row, col): (usize, usize)) -> bool {
+    row < 8 && col < 8
+}
+
+// Algebraic name of a (row, col) square, e.g. "e4"
+pub fn square_name(square: (usize, usize)) -> String {
+    format!("{}{}", (b'a' + square.1 as u8) as char, square.0 + 1)
+}
@@ -0,0 +1,330 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use crate::board::PieceType;
+use crate::error::ChessError;
+use crate::game::Game;
+use crate::moves::Move;
+use crate::pgn::{self, PgnGame};
+use crate::search::splitmix;
+
+// Size of one entry in a Polyglot .bin file
+const ENTRY_SIZE: usize = 16;
+
+// Polyglot promotion codes 1..=4
+const PROMOTIONS: [PieceType; 4] = [
+    PieceType::Knight,
+    PieceType::Bishop,
+    PieceType::Rook,
+    PieceType::Queen,
+];
+
+#[derive(Clone, Copy, Debug)]
+struct BookEntry {
+    key: u64,
+    raw_move: u16,
+    weight: u16,
+}
+
+// A Polyglot opening book, held in memory and sorted by position key
+pub struct OpeningBook {
+    entries: Vec<BookEntry>,
+}
+
+impl OpeningBook {
+    pub fn open(path: impl AsRef<Path>) -> Result<OpeningBook, ChessError> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(|e| ChessError::io(path.display(), e))?;
+        if bytes.len() % ENTRY_SIZE != 0 {
+            return Err(ChessError::BadBook(format!(
+                "{}: not a Polyglot book",
+                path.display()
+            )));
+        }
+        Ok(OpeningBook::from_bytes(&bytes))
+    }
+
+    // Entries are big-endian: key (8 bytes), move (2), weight (2), learn (4)
+    pub fn from_bytes(bytes: &[u8]) -> OpeningBook {
+        let mut entries: Vec<BookEntry> = bytes
+            .chunks_exact(ENTRY_SIZE)
+            .map(|chunk| BookEntry {
+                key: u64::from_be_bytes(chunk[0..8].try_into().unwrap()),
+                raw_move: u16::from_be_bytes([chunk[8], chunk[9]]),
+                weight: u16::from_be_bytes([chunk[10], chunk[11]]),
+            })
+            .collect();
+        // Books are written sorted, but lookups must not depend on it
+        entries.sort_by_key(|entry| entry.key);
+        OpeningBook { entries }
+    }
+
+    // The book as a Polyglot .bin file, with nothing learned
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(self.entries.len() * ENTRY_SIZE);
+        for entry in &self.entries {
+            bytes.extend_from_slice(&entry.key.to_be_bytes());
+            bytes.extend_from_slice(&entry.raw_move.to_be_bytes());
+            bytes.extend_from_slice(&entry.weight.to_be_bytes());
+            bytes.extend_from_slice(&[0; 4]);
+        }
+        bytes
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), ChessError> {
+        let path = path.as_ref();
+        fs::write(path, self.to_bytes()).map_err(|e| ChessError::io(path.display(), e))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    // Legal book moves for the position with their weights, heaviest first
+    pub fn moves(&self, board: &Game) -> Vec<(Move, u16)> {
+        let key = board.hash();
+        let start = self.entries.partition_point(|entry| entry.key < key);
+        let legal_moves = board.get_all_legal_moves(board.get_current_turn());
+        let mut moves: Vec<(Move, u16)> = self.entries[start..]
+            .iter()
+            .take_while(|entry| entry.key == key)
+            .filter_map(|entry| {
+                let mv = decode_move(board, entry.raw_move, &legal_moves)?;
+                Some((mv, entry.weight))
+            })
+            .collect();
+        moves.sort_by_key(|&(_, weight)| std::cmp::Reverse(weight));
+        moves
+    }
+
+    // Picks a book move. With variety 0 it is always the heaviest; higher values
+    // let in moves down to (1 - variety) of the top weight, chosen in proportion
+    // to their weights, so 1.0 plays every book move now and then
+    pub fn choose_move(&self, board: &Game, variety: f64) -> Option<Move> {
+        let moves = self.moves(board);
+        let &(best, top_weight) = moves.first()?;
+        let variety = variety.clamp(0.0, 1.0);
+        if variety == 0.0 {
+            return Some(best);
+        }
+        let threshold = (1.0 - variety) * top_weight as f64;
+        let candidates: Vec<(Move, u64)> = moves
+            .into_iter()
+            .filter(|&(_, weight)| weight as f64 >= threshold)
+            .map(|(mv, weight)| (mv, weight.max(1) as u64))
+            .collect();
+        let total: u64 = candidates.iter().map(|&(_, weight)| weight).sum();
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_nanos() as u64);
+        let mut roll = splitmix(seed ^ board.hash()) % total;
+        for (mv, weight) in candidates {
+            if roll < weight {
+                return Some(mv);
+            }
+            roll -= weight;
+        }
+        Some(best)
+    }
+}
+
+// How often a move was played from a position, and how it scored for the
+// side that played it
+#[derive(Default)]
+struct MoveTally {
+    games: u32,
+    // Two for a win and one for a draw, as Polyglot weighs moves
+    half_points: u32,
+}
+
+// Gathers the opening moves of a collection of games into a Polyglot book
+pub struct BookBuilder {
+    max_plies: usize,
+    moves: HashMap<(u64, u16), MoveTally>,
+    pub games: usize,
+}
+
+impl BookBuilder {
+    // Only the first `max_plies` moves of each game go into the book
+    pub fn new(max_plies: usize) -> BookBuilder {
+        BookBuilder {
+            max_plies,
+            moves: HashMap::new(),
+            games: 0,
+        }
+    }
+
+    // Adds the main line of a game; unfinished games are left out, as they
+    // have no result to weigh the moves by
+    pub fn add_game(&mut self, game: &PgnGame) -> bool {
+        let Some(result) = game.result() else {
+            return false;
+        };
+        let mut board = game.board.get_start_position();
+        for record in game.board.get_history().iter().take(self.max_plies) {
+            let half_points = match result.winner {
+                Some(winner) if winner == board.get_current_turn() => 2,
+                Some(_) => 0,
+                None => 1,
+            };
+            let tally = self
+                .moves
+                .entry((board.hash(), encode_move(record.mv)))
+                .or_default();
+            tally.games += 1;
+            tally.half_points += half_points;
+            if board.make(record.mv).is_err() {
+                break;
+            }
+        }
+        self.games += 1;
+        true
+    }
+
+    // Adds every game of PGN text, returning how many were left out as
+    // unreadable or unfinished
+    pub fn add_pgn(&mut self, text: &str) -> usize {
+        pgn::split_pgn_games(text)
+            .iter()
+            .filter(|game| {
+                !pgn::parse_pgn(game)
+                    .map(|game| self.add_game(&game))
+                    .unwrap_or(false)
+            })
+            .count()
+    }
+
+    // The book of the moves played in at least `min_games` games that scored
+    // with them. Each weighs the half points it scored, scaled down when the
+    // most played would not fit in a weight
+    pub fn build(&self, min_games: u32) -> OpeningBook {
+        let kept: Vec<_> = self
+            .moves
+            .iter()
+            .filter(|(_, tally)| tally.games >= min_games && tally.half_points > 0)
+            .collect();
+        let top = kept
+            .iter()
+            .map(|(_, tally)| tally.half_points)
+            .max()
+            .unwrap_or(0);
+        let scale = (f64::from(u16::MAX) / f64::from(top.max(1))).min(1.0);
+        let mut entries: Vec<BookEntry> = kept
+            .into_iter()
+            .map(|(&(key, raw_move), tally)| BookEntry {
+                key,
+                raw_move,
+                weight: ((f64::from(tally.half_points) * scale) as u16).max(1),
+            })
+            .collect();
+        // As Polyglot writes them: by key, then heaviest first
+        entries.sort_by_key(|entry| (entry.key, std::cmp::Reverse(entry.weight), entry.raw_move));
+        OpeningBook { entries }
+    }
+}
+
+// The Polyglot form of a move, as decode_move reads it
+fn encode_move(mv: Move) -> u16 {
+    let mut to = mv.to;
+    if mv.is_castling() {
+        to.1 = if mv.to.1 > mv.from.1 { 7 } else { 0 };
+    }
+    let promotion = mv
+        .promotion
+        .and_then(|piece_type| PROMOTIONS.iter().position(|&p| p == piece_type))
+        .map_or(0, |index| index + 1);
+    (promotion << 12 | mv.from.0 << 9 | mv.from.1 << 6 | to.0 << 3 | to.1) as u16
+}
+
+// Polyglot moves pack to file, to rank, from file, from rank and promotion into
+// 3 bits each; castling is written as the king taking its own rook
+fn decode_move(board: &Game, raw_move: u16, legal_moves: &[Move]) -> Option<Move> {
+    let field = |shift: u16| ((raw_move >> shift) & 7) as usize;
+    let mut to = (field(3), field(0));
+    let from = (field(9), field(6));
+    let promotion = match field(12) {
+        0 => None,
+        code => Some(*PROMOTIONS.get(code - 1)?),
+    };
+    if let (Some(king), Some(rook)) = (board.get_piece(from), board.get_piece(to))
+        && king.is_type(PieceType::King)
+        && rook.is_type(PieceType::Rook)
+        && king.color() == rook.color()
+    {
+        to.1 = if to.1 > from.1 { 6 } else { 2 };
+    }
+    legal_moves
+        .iter()
+        .copied()
+        .find(|mv| mv.from == from && mv.to == to && mv.promotion == promotion)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GAMES: &str = "[Result \"1-0\"]\n\n1. e4 e5 2. Nf3 Nc6 3. Bc4 Bc5 4. O-O Nf6 1-0\n\n\
+                         [Result \"0-1\"]\n\n1. d4 d5 2. Nc3 Nc6 3. Bf4 Bf5 4. Qd2 Qd7 5. O-O-O O-O-O 0-1\n\n\
+                         [Result \"1/2-1/2\"]\n[SetUp \"1\"]\n[FEN \"8/P6k/8/8/8/8/p6K/8 w - - 0 1\"]\n\n\
+                         1. a8=N a1=Q 1/2-1/2\n\n\
+                         [Result \"1-0\"]\n[SetUp \"1\"]\n[FEN \"8/P6k/8/8/8/8/p6K/8 w - - 0 1\"]\n\n\
+                         1. a8=Q a1=R 1-0\n";
+
+    #[test]
+    fn books_round_trip() {
+        let mut builder = BookBuilder::new(20);
+        assert_eq!(builder.add_pgn(GAMES), 0);
+        let book = builder.build(1);
+        let bytes = book.to_bytes();
+        assert_eq!(bytes.len(), book.len() * ENTRY_SIZE);
+        let read = OpeningBook::from_bytes(&bytes);
+        assert_eq!(read.to_bytes(), bytes);
+
+        // The moves that scored come back from the book read from bytes, and
+        // the losers' moves are left out
+        for text in pgn::split_pgn_games(GAMES) {
+            let game = pgn::parse_pgn(&text).unwrap();
+            let winner = game.result().unwrap().winner;
+            let mut board = game.board.get_start_position();
+            for record in game.board.get_history() {
+                let moves = read.moves(&board);
+                let lost = winner.is_some_and(|winner| winner != board.get_current_turn());
+                assert_eq!(
+                    moves.iter().any(|&(mv, _)| mv == record.mv),
+                    !lost,
+                    "{} at {}",
+                    record.mv.to_uci(),
+                    board.to_fen()
+                );
+                board.make(record.mv).unwrap();
+            }
+        }
+    }
+
+    #[test]
+    fn polyglot_move_codes() {
+        let board = Game::from_fen("1r2k2r/P6p/8/8/8/8/p6P/R3K2R w KQk - 0 1").unwrap();
+        let legal = board.get_all_legal_moves(board.get_current_turn());
+        let find = |uci: &str| *legal.iter().find(|mv| mv.to_uci() == uci).unwrap();
+        // Castling is the king taking its own rook: e1h1 and e1a1
+        let short = find("e1g1");
+        assert_eq!(encode_move(short), 7 | 4 << 6);
+        assert_eq!(encode_move(find("e1c1")), 4 << 6);
+        // Promotions count knight 1 to queen 4
+        let knight = find("a7b8n");
+        assert_eq!(encode_move(knight), 1 << 12 | 6 << 9 | 7 << 3 | 1);
+        assert_eq!(encode_move(find("a7b8q")) >> 12, 4);
+        for mv in &legal {
+            assert_eq!(decode_move(&board, encode_move(*mv), &legal), Some(*mv));
+        }
+        // A king move that is not castling is not read as one
+        let board = Game::from_fen("4k3/8/8/8/8/8/8/4K2R w - - 0 1").unwrap();
+        let legal = board.get_all_legal_moves(board.get_current_turn());
+        assert_eq!(decode_move(&board, 7 | 4 << 6, &legal), None);
+    }
+}
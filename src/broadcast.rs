@@ -0,0 +1,84 @@
+use std::{
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{Duration, Instant},
+};
+
+use chess_rs::pgn::{PgnGame, parse_pgn_games};
+
+use crate::lichess;
+
+// How often the round's games are downloaded again for new moves
+const REFRESH_INTERVAL: Duration = Duration::from_secs(10);
+
+// Length of a Lichess broadcast round id
+const ROUND_ID_LEN: usize = 8;
+
+// A Lichess broadcast round being followed: its games, refreshed every few
+// seconds while any of them is still being played
+pub struct Broadcast {
+    pub round_id: String,
+    pub games: Vec<PgnGame>,
+    // The game on the board, by its place in `games`
+    pub watching: Option<usize>,
+    download: Option<Receiver<Result<Vec<PgnGame>, String>>>,
+    last_download: Option<Instant>,
+}
+
+impl Broadcast {
+    // Follows a round from its link, such as
+    // "https://lichess.org/broadcast/<event>/<round>/<round id>", or its id
+    pub fn follow(link: &str) -> Result<Broadcast, String> {
+        let link = link.trim();
+        let mut parts = link.split(['/', '?', '#']).filter(|part| !part.is_empty());
+        let round_id = if link.contains('/') {
+            // The round id comes third after "broadcast"; a game id may follow
+            parts
+                .by_ref()
+                .find(|part| *part == "broadcast")
+                .and_then(|_| parts.nth(2))
+        } else {
+            Some(link)
+        };
+        let round_id = round_id
+            .filter(|id| id.len() == ROUND_ID_LEN && id.chars().all(|c| c.is_ascii_alphanumeric()))
+            .ok_or_else(|| format!("not a Lichess broadcast round: {}", link))?;
+        Ok(Broadcast {
+            round_id: round_id.to_string(),
+            games: Vec::new(),
+            watching: None,
+            download: None,
+            last_download: None,
+        })
+    }
+
+    // Whether every game has a result, so there is nothing more to download
+    pub fn is_finished(&self) -> bool {
+        !self.games.is_empty() && self.games.iter().all(|game| game.result().is_some())
+    }
+
+    // Starts a download when one is due, and reports one that has finished:
+    // Some(Ok) when the games were brought up to date
+    pub fn poll(&mut self) -> Option<Result<(), String>> {
+        if let Some(download) = &self.download {
+            let result = download.try_recv().ok()?;
+            self.download = None;
+            return Some(result.map(|games| self.games = games));
+        }
+        let due = self
+            .last_download
+            .is_none_or(|last| last.elapsed() >= REFRESH_INTERVAL);
+        if due && !self.is_finished() {
+            let (sender, receiver) = mpsc::channel();
+            let round_id = self.round_id.clone();
+            thread::spawn(move || {
+                let games = lichess::round_pgn(&round_id)
+                    .and_then(|pgn| parse_pgn_games(&pgn).map_err(String::from));
+                let _ = sender.send(games);
+            });
+            self.download = Some(receiver);
+            self.last_download = Some(Instant::now());
+        }
+        None
+    }
+}
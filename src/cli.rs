@@ -0,0 +1,257 @@
+use std::path::PathBuf;
+
+use chess_rs::ColorChess;
+use chess_rs::clock::TimeControl;
+use chess_rs::fog;
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+// Command line options; without a command the board opens at the menu
+#[derive(Parser)]
+#[command(
+    name = "chess-rs",
+    version,
+    about = "Chess in the terminal, against the built-in engine, hot-seat or online"
+)]
+pub struct Cli {
+    #[arg(
+        long,
+        global = true,
+        value_name = "FILE",
+        help = "config.toml to use, with the other saved files next to it"
+    )]
+    pub config: Option<PathBuf>,
+    #[arg(long, global = true, help = "Variant to play for this run")]
+    pub variant: Option<Variant>,
+    #[arg(
+        long = "tc",
+        global = true,
+        value_name = "CONTROL",
+        value_parser = TimeControl::parse,
+        help = "Time control of new games, e.g. \"blitz\" or \"5+3\""
+    )]
+    pub time_control: Option<TimeControl>,
+    #[arg(long, global = true, help = "Side to play")]
+    pub color: Option<Side>,
+    #[arg(
+        long,
+        global = true,
+        value_name = "MOVES|FILE",
+        help = "Moves to play on start-up, in UCI or SAN, e.g. \"e2e4 e7e5 g1f3\", or a file of them"
+    )]
+    pub moves: Option<String>,
+    #[arg(
+        long,
+        global = true,
+        help = "Play on stdin/stdout instead of the board, moves in UCI or SAN"
+    )]
+    pub no_tui: bool,
+    #[arg(
+        long,
+        global = true,
+        requires = "no_tui",
+        help = "With --no-tui, print only the engine's moves and the result"
+    )]
+    pub quiet: bool,
+    #[arg(
+        long,
+        global = true,
+        requires = "no_tui",
+        conflicts_with = "quiet",
+        help = "With --no-tui, print every move, check, clock and result as a line of JSON"
+    )]
+    pub json: bool,
+    // `serve` under the names engine GUIs were set up with before it
+    #[arg(long, hide = true)]
+    pub uci: bool,
+    #[arg(long, hide = true)]
+    pub xboard: bool,
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    #[command(about = "Play on the board (the default)")]
+    Play(PlayArgs),
+    #[command(about = "Open a PGN file or a FEN position with the analysis panel")]
+    Analyze {
+        #[arg(value_name = "PGN|FEN", help = "A PGN file, or a position in quotes")]
+        target: String,
+    },
+    #[command(about = "Count the positions a number of plies down, to check the move generator")]
+    Perft {
+        #[arg(help = "Plies to count down")]
+        depth: u32,
+        #[arg(
+            long,
+            help = "Position to count from; the starting position by default"
+        )]
+        fen: Option<String>,
+        #[arg(long, help = "Count after each first move on its own")]
+        divide: bool,
+    },
+    #[command(about = "Solve puzzles")]
+    Puzzle {
+        #[arg(help = "Puzzles to play first, one FEN and solution a line")]
+        file: Option<String>,
+        #[arg(long, conflicts_with = "file", help = "Play the puzzle of the day")]
+        daily: bool,
+    },
+    #[command(about = "Host a fog-of-war game for two players over the network")]
+    FogServer {
+        #[arg(
+            long,
+            default_value = "127.0.0.1",
+            help = "Address to listen on; 0.0.0.0 takes players from other machines"
+        )]
+        host: String,
+        #[arg(long, default_value_t = fog::DEFAULT_PORT, help = "Port to listen on")]
+        port: u16,
+    },
+    #[command(about = "Run the built-in engine on stdin/stdout for a chess GUI")]
+    Serve {
+        #[arg(long, default_value = "uci", help = "Protocol the GUI speaks")]
+        protocol: Protocol,
+    },
+    #[command(subcommand, about = "Make Polyglot opening books")]
+    Book(BookCommand),
+    #[command(about = "Run the engine over the best-move tests of an EPD file")]
+    TestSuite {
+        #[arg(help = "EPD file with bm or am operations")]
+        file: PathBuf,
+        #[arg(
+            long,
+            value_name = "MS",
+            default_value_t = 1000,
+            help = "Search time per position"
+        )]
+        movetime: u64,
+    },
+    #[command(about = "Time move generation and search over a fixed set of positions")]
+    Bench {
+        #[arg(long, default_value_t = 7, help = "Depth each position is searched to")]
+        depth: u32,
+        #[arg(
+            long,
+            default_value_t = 4,
+            help = "Plies each position's moves are counted down"
+        )]
+        perft_depth: u32,
+    },
+    #[command(about = "Check that a FEN describes a position that can arise in a game")]
+    Validate {
+        #[arg(help = "The position, in quotes")]
+        fen: String,
+    },
+    #[command(about = "Play engines against each other, as set up in a tournament file")]
+    Tournament {
+        #[arg(help = "TOML file with an [[engine]] section for each player")]
+        file: PathBuf,
+        #[arg(long, help = "Games each pair of engines plays, instead of the file's")]
+        games: Option<u32>,
+    },
+    #[command(about = "Fill the local puzzle database from the Lichess puzzle CSV")]
+    ImportPuzzles {
+        #[arg(help = "lichess_db_puzzle.csv, unpacked")]
+        csv: PathBuf,
+    },
+}
+
+// What to start with on the board
+#[derive(Args)]
+pub struct PlayArgs {
+    #[arg(long, value_name = "USER", help = "Challenge a Lichess player")]
+    pub challenge: Option<String>,
+    #[arg(long, value_name = "LINK", help = "Replay a Lichess or chess.com game")]
+    pub import: Option<String>,
+    #[arg(
+        long,
+        value_name = "LINK",
+        help = "Follow the games of a Lichess broadcast round"
+    )]
+    pub broadcast: Option<String>,
+    #[arg(
+        long,
+        value_name = "PGN",
+        help = "Guess the moves of the games in a PGN file"
+    )]
+    pub guess: Option<String>,
+    #[arg(
+        long,
+        value_name = "PGN",
+        help = "Drill the lines of a repertoire, from the side given with --color"
+    )]
+    pub repertoire: Option<String>,
+    #[arg(long, value_name = "NAME", help = "Drill one of the preset endgames")]
+    pub endgame: Option<String>,
+    #[arg(
+        long,
+        value_name = "HOST[:PORT]",
+        help = "Play fog of war on a server started with `chess-rs fog-server`"
+    )]
+    pub fog_server: Option<String>,
+}
+
+impl PlayArgs {
+    // Whether it asks for a plain game
+    pub fn is_empty(&self) -> bool {
+        self.challenge.is_none()
+            && self.import.is_none()
+            && self.broadcast.is_none()
+            && self.guess.is_none()
+            && self.repertoire.is_none()
+            && self.endgame.is_none()
+            && self.fog_server.is_none()
+    }
+}
+
+#[derive(Subcommand)]
+pub enum BookCommand {
+    #[command(about = "Write a book of the openings of the games in a PGN file")]
+    Build {
+        #[arg(help = "Games to read")]
+        pgn: PathBuf,
+        #[arg(help = "Book to write")]
+        bin: PathBuf,
+        #[arg(
+            long,
+            default_value_t = 30,
+            help = "Plies of each game that go into the book"
+        )]
+        plies: usize,
+        #[arg(
+            long,
+            default_value_t = 3,
+            help = "Games a move must have been played in to be kept"
+        )]
+        min_games: u32,
+    },
+}
+
+#[derive(Clone, Copy, PartialEq, ValueEnum)]
+pub enum Variant {
+    Standard,
+    // Fog of war, as the setting of that name plays it
+    Fog,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Side {
+    White,
+    Black,
+}
+
+impl Side {
+    pub fn color(self) -> ColorChess {
+        match self {
+            Side::White => ColorChess::White,
+            Side::Black => ColorChess::Black,
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum Protocol {
+    Uci,
+    Xboard,
+}
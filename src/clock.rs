@@ -0,0 +1,410 @@
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+
+use crate::board::ColorChess;
+
+// What a side's clock gets for each move besides its starting time
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum BonusMode {
+    // Fischer: the bonus is added after every move
+    Increment,
+    // US (simple) delay: the clock waits that long before it starts running
+    Delay,
+    // Bronstein: the time used is given back after the move, up to the bonus
+    Bronstein,
+}
+
+// Starting time and per-move bonus, written in config.toml like "10" (minutes),
+// "5+3" or "5|3" (plus 3 seconds a move), "3|2 delay" or "3|2 bronstein"
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct TimeControl {
+    pub base: Duration,
+    pub bonus: Duration,
+    pub mode: BonusMode,
+}
+
+// Longest starting time or bonus a time control may have
+const MAX_TIME: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Named time controls offered on the new-game and settings screens; the
+// names also work in config.toml and with --tc
+pub const PRESETS: [(&str, &str); 5] = [
+    ("Bullet", "1+0"),
+    ("Blitz", "3+2"),
+    ("Rapid", "10+0"),
+    ("Long rapid", "15+10"),
+    ("Classical", "90+30"),
+];
+
+impl TimeControl {
+    pub fn parse(text: &str) -> Result<TimeControl, String> {
+        let bad = || {
+            format!(
+                "bad time control {:?}, expected e.g. \"10\", \"5+3\" or \"3|2 delay\"",
+                text
+            )
+        };
+        if let Some(&(_, preset)) = PRESETS
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(text.trim()))
+        {
+            return TimeControl::parse(preset);
+        }
+        let mut words = text.split_whitespace();
+        let clock = words.next().ok_or_else(bad)?;
+        let mode = match words.next() {
+            None | Some("increment") => BonusMode::Increment,
+            Some("delay") => BonusMode::Delay,
+            Some("bronstein") => BonusMode::Bronstein,
+            Some(_) => return Err(bad()),
+        };
+        if words.next().is_some() {
+            return Err(bad());
+        }
+        let (minutes, seconds) = clock.split_once(['+', '|']).unwrap_or((clock, "0"));
+        let minutes: u64 = minutes.parse().map_err(|_| bad())?;
+        let seconds: u64 = seconds.parse().map_err(|_| bad())?;
+        if minutes == 0 {
+            return Err("a time control needs some time".to_string());
+        }
+        let base = Duration::from_secs(minutes.checked_mul(60).ok_or_else(bad)?);
+        let bonus = Duration::from_secs(seconds);
+        if base > MAX_TIME || bonus > MAX_TIME {
+            return Err(format!(
+                "bad time control {:?}, the time and the bonus can be 24 hours at most",
+                text
+            ));
+        }
+        Ok(TimeControl { base, bonus, mode })
+    }
+
+    // The choice `step` places after (or before) this one in the list of
+    // untimed followed by the presets; a control that is not a preset counts
+    // as untimed
+    pub fn cycle(control: Option<TimeControl>, step: isize) -> Option<TimeControl> {
+        let mut choices = vec![None];
+        choices.extend(PRESETS.map(|(_, text)| TimeControl::parse(text).ok()));
+        let index = choices.iter().position(|&c| c == control).unwrap_or(0);
+        let next = (index as isize + step).rem_euclid(choices.len() as isize);
+        choices[next as usize]
+    }
+
+    // "Blitz 3+2" for a preset, otherwise the control as written in config.toml
+    pub fn label(self) -> String {
+        let preset = PRESETS
+            .iter()
+            .find(|(_, text)| TimeControl::parse(text).ok() == Some(self));
+        match preset {
+            Some((name, _)) => format!("{} {}", name, self),
+            None => self.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for TimeControl {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let minutes = self.base.as_secs() / 60;
+        let seconds = self.bonus.as_secs();
+        match self.mode {
+            BonusMode::Increment => write!(f, "{}+{}", minutes, seconds),
+            BonusMode::Delay => write!(f, "{}|{} delay", minutes, seconds),
+            BonusMode::Bronstein => write!(f, "{}|{} bronstein", minutes, seconds),
+        }
+    }
+}
+
+impl TryFrom<String> for TimeControl {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<TimeControl, String> {
+        TimeControl::parse(&text)
+    }
+}
+
+impl From<TimeControl> for String {
+    fn from(control: TimeControl) -> String {
+        control.to_string()
+    }
+}
+
+// The two players' clocks. Only the side to move's clock runs, and only while
+// the clock is started; the app stops it whenever the game is not in view
+#[derive(Clone)]
+pub struct Clock {
+    control: TimeControl,
+    // Time left for White and Black before the move being played
+    remaining: [Duration; 2],
+    // Side whose move the clock is timing, and the time it has spent on it
+    // while the clock was stopped and started again
+    on_move: Option<(ColorChess, Duration)>,
+    // When the clock was last started, while it runs
+    running_since: Option<Instant>,
+    // Which sides have been warned that they are low on time
+    warned: [bool; 2],
+}
+
+impl Clock {
+    pub fn new(control: TimeControl) -> Clock {
+        Clock {
+            control,
+            remaining: [control.base; 2],
+            on_move: None,
+            running_since: None,
+            warned: [false; 2],
+        }
+    }
+
+    // Time a side has spent on the move it is playing
+    fn used(&self, color: ColorChess) -> Duration {
+        match self.on_move {
+            Some((side, banked)) if side == color => {
+                banked
+                    + self
+                        .running_since
+                        .map_or(Duration::ZERO, |since| since.elapsed())
+            }
+            _ => Duration::ZERO,
+        }
+    }
+
+    // The part of the time used that comes off the clock; with a delay the
+    // first seconds of each move are free
+    fn charged(&self, used: Duration) -> Duration {
+        match self.control.mode {
+            BonusMode::Delay => used.saturating_sub(self.control.bonus),
+            BonusMode::Increment | BonusMode::Bronstein => used,
+        }
+    }
+
+    // Time left for a side, counting the move it is playing
+    pub fn get_remaining(&self, color: ColorChess) -> Duration {
+        self.remaining[index(color)].saturating_sub(self.charged(self.used(color)))
+    }
+
+    // The side whose clock is running, if any
+    pub fn get_running(&self) -> Option<ColorChess> {
+        self.running_since.and(self.on_move).map(|(side, _)| side)
+    }
+
+    // When the running side's time runs out if it has not moved by then
+    pub fn flag_deadline(&self) -> Option<Instant> {
+        let (since, (side, banked)) = (self.running_since?, self.on_move?);
+        // A delay has to pass before the time starts coming off
+        let free = match self.control.mode {
+            BonusMode::Delay => self.control.bonus,
+            BonusMode::Increment | BonusMode::Bronstein => Duration::ZERO,
+        };
+        Some(since + (self.remaining[index(side)] + free).saturating_sub(banked))
+    }
+
+    // Runs a side's clock, stopping the other one
+    pub fn start(&mut self, color: ColorChess) {
+        if self.get_running() == Some(color) {
+            return;
+        }
+        self.stop();
+        match self.on_move {
+            Some((side, _)) if side == color => {}
+            // After a take-back the other side's move ends without a bonus
+            _ => {
+                if let Some((side, _)) = self.on_move {
+                    self.remaining[index(side)] = self.get_remaining(side);
+                }
+                self.on_move = Some((color, Duration::ZERO));
+            }
+        }
+        self.running_since = Some(Instant::now());
+    }
+
+    // Stops the clock that is running, keeping the time it used
+    pub fn stop(&mut self) {
+        if let (Some(since), Some((side, banked))) = (self.running_since.take(), self.on_move) {
+            self.on_move = Some((side, banked + since.elapsed()));
+        }
+    }
+
+    // Sets both sides' time to what a server reports; the move being played
+    // counts from now
+    pub fn set_remaining(&mut self, times: [Duration; 2]) {
+        self.remaining = times;
+        self.on_move = None;
+        self.running_since = None;
+    }
+
+    // Ends a side's move: its time used comes off its clock and the bonus
+    // for the move is added. Moves played before the clock started are free
+    pub fn complete_move(&mut self, color: ColorChess) {
+        if self.on_move.is_none_or(|(side, _)| side != color) {
+            return;
+        }
+        self.stop();
+        let used = self.used(color);
+        let bonus = match self.control.mode {
+            BonusMode::Increment => self.control.bonus,
+            BonusMode::Delay => Duration::ZERO,
+            BonusMode::Bronstein => used.min(self.control.bonus),
+        };
+        let left = self.get_remaining(color);
+        // A flag that has fallen stays down
+        self.remaining[index(color)] = if left.is_zero() { left } else { left + bonus };
+        self.on_move = None;
+    }
+
+    // The running side, the first time its time drops under the threshold;
+    // a bonus that lifts it back above allows another warning
+    pub fn warn_low_time(&mut self, threshold: Duration) -> Option<ColorChess> {
+        for color in [ColorChess::White, ColorChess::Black] {
+            if self.get_remaining(color) >= threshold {
+                self.warned[index(color)] = false;
+            }
+        }
+        let color = self.get_running()?;
+        if self.warned[index(color)] || self.get_remaining(color) >= threshold {
+            return None;
+        }
+        self.warned[index(color)] = true;
+        Some(color)
+    }
+}
+
+// Times the move being played, counting only while it runs, so that time in
+// the menu or paused is left out
+#[derive(Default)]
+pub struct Stopwatch {
+    banked: Duration,
+    running_since: Option<Instant>,
+}
+
+impl Stopwatch {
+    pub fn start(&mut self) {
+        self.running_since.get_or_insert_with(Instant::now);
+    }
+
+    pub fn stop(&mut self) {
+        if let Some(since) = self.running_since.take() {
+            self.banked += since.elapsed();
+        }
+    }
+
+    // The time on the watch, which then starts again from zero
+    pub fn lap(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = self.banked
+            + self
+                .running_since
+                .map_or(Duration::ZERO, |since| now - since);
+        self.banked = Duration::ZERO;
+        if self.running_since.is_some() {
+            self.running_since = Some(now);
+        }
+        elapsed
+    }
+}
+
+fn index(color: ColorChess) -> usize {
+    match color {
+        ColorChess::White => 0,
+        ColorChess::Black => 1,
+    }
+}
+
+// Time on a clock, as "1:05:00", "4:59", or "0:09.3" in the last ten seconds
+pub fn format_clock(time: Duration) -> String {
+    let seconds = time.as_secs();
+    if seconds >= 3600 {
+        format!(
+            "{}:{:02}:{:02}",
+            seconds / 3600,
+            seconds / 60 % 60,
+            seconds % 60
+        )
+    } else if seconds >= 10 {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    } else {
+        format!("0:{:02}.{}", seconds, time.subsec_millis() / 100)
+    }
+}
+
+// Time a move took, as "3.2s" or, from a minute on, "1m05s"
+pub fn format_move_time(time: Duration) -> String {
+    let seconds = time.as_secs();
+    if seconds >= 60 {
+        format!("{}m{:02}s", seconds / 60, seconds % 60)
+    } else {
+        format!("{:.1}s", time.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn control(base: u64, bonus: u64, mode: BonusMode) -> TimeControl {
+        TimeControl {
+            base: Duration::from_secs(base),
+            bonus: Duration::from_secs(bonus),
+            mode,
+        }
+    }
+
+    #[test]
+    fn parses_minutes_bonus_and_mode() {
+        let parse = |text| TimeControl::parse(text).unwrap();
+        assert_eq!(parse("10"), control(600, 0, BonusMode::Increment));
+        assert_eq!(parse("5+3"), control(300, 3, BonusMode::Increment));
+        assert_eq!(parse("5|3"), control(300, 3, BonusMode::Increment));
+        assert_eq!(
+            parse("5+3 increment"),
+            control(300, 3, BonusMode::Increment)
+        );
+        assert_eq!(parse("3|2 delay"), control(180, 2, BonusMode::Delay));
+        assert_eq!(
+            parse("3|2 bronstein"),
+            control(180, 2, BonusMode::Bronstein)
+        );
+        assert_eq!(parse(" Blitz "), control(180, 2, BonusMode::Increment));
+    }
+
+    #[test]
+    fn each_preset_is_found_by_its_name() {
+        for (name, preset) in PRESETS {
+            let control = TimeControl::parse(preset).unwrap();
+            assert_eq!(TimeControl::parse(&name.to_lowercase()), Ok(control));
+            assert_eq!(control.label(), format!("{} {}", name, preset));
+        }
+    }
+
+    #[test]
+    fn writes_what_it_reads() {
+        for text in ["10+0", "5+3", "3|2 delay", "3|2 bronstein"] {
+            assert_eq!(TimeControl::parse(text).unwrap().to_string(), text);
+        }
+        for (_, preset) in PRESETS {
+            let control = TimeControl::parse(preset).unwrap();
+            assert_eq!(TimeControl::parse(&control.to_string()), Ok(control));
+        }
+    }
+
+    #[test]
+    fn refuses_bad_controls() {
+        for text in [
+            "",
+            "0",
+            "0+5",
+            "x",
+            "5+x",
+            "-5",
+            "5+3 fischer",
+            "5+3 delay 2",
+            "999999999999999999",
+            "1441",
+            "5+86401",
+        ] {
+            assert!(TimeControl::parse(text).is_err(), "{:?}", text);
+        }
+        assert!(TimeControl::parse("1440+86400").is_ok());
+    }
+}
@@ -0,0 +1,60 @@
+use std::{
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use chess_rs::search::{MATE_SCORE, MATE_THRESHOLD};
+use chess_rs::{Game, Move, SearchLimits, Searcher};
+
+// The check is shallow and quick, so that a move is hardly held up
+const CHECK_DEPTH: u32 = 4;
+const CHECK_TIME: Duration = Duration::from_millis(150);
+
+// Centipawns a move may give away against the best one before the coach
+// speaks up, about two pawns
+const LOSS_THRESHOLD: i32 = 200;
+
+// Looks at a move on another thread before it is played, answering with
+// what is wrong with it: the mate it allows or the material it loses. None
+// when it looks fine
+pub fn check_move(board: &Game, mv: Move) -> Receiver<Option<String>> {
+    let (sender, receiver) = mpsc::channel();
+    let board = board.clone();
+    thread::spawn(move || {
+        let _ = sender.send(warning(&board, mv));
+    });
+    receiver
+}
+
+fn warning(board: &Game, mv: Move) -> Option<String> {
+    let mut searcher = Searcher::with_tt_size(1);
+    let limits = SearchLimits {
+        max_depth: CHECK_DEPTH,
+        ..SearchLimits::time(CHECK_TIME)
+    };
+    // Both scores are the mover's
+    let best = searcher.search(board, limits).score;
+    let mut after = board.clone();
+    after.make(mv).ok()?;
+    let to_move = after.get_current_turn();
+    let san = board.move_to_san(mv);
+    if after.get_all_legal_moves(to_move).is_empty() {
+        // Stalemating throws a win away
+        return (!after.is_in_check(to_move) && best >= LOSS_THRESHOLD)
+            .then(|| format!("{} is stalemate, a draw.", san));
+    }
+    let score = -searcher.search(&after, limits).score;
+    if score <= -MATE_THRESHOLD && best > -MATE_THRESHOLD {
+        let moves = (MATE_SCORE + score + 1) / 2;
+        return Some(format!("{} allows mate in {}.", san, moves));
+    }
+    let loss = best - score;
+    (loss >= LOSS_THRESHOLD).then(|| {
+        format!(
+            "{} gives away about {:.1} pawns.",
+            san,
+            f64::from(loss) / 100.0
+        )
+    })
+}
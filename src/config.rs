@@ -0,0 +1,302 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+};
+
+use chess_rs::{ChessError, OpeningBook, Tablebase, UciEngine};
+use serde::{Deserialize, Serialize};
+use toml_edit::{DocumentMut, Item, Table};
+
+use crate::app::Settings;
+
+// The [engine] section: an external UCI engine to use instead of the built-in one
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct EngineConfig {
+    // Path to the engine binary, e.g. "stockfish"; the built-in engine is used when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    pub args: Vec<String>,
+    // Sent with `setoption` after start-up, e.g. Threads = "4"
+    pub options: BTreeMap<String, String>,
+    // Whether the engine plays against you and whether it feeds the eval bar and analysis panel
+    pub opponent: bool,
+    pub analysis: bool,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        EngineConfig {
+            path: None,
+            args: Vec::new(),
+            options: BTreeMap::new(),
+            opponent: true,
+            analysis: true,
+        }
+    }
+}
+
+impl EngineConfig {
+    // Starts the configured engine if it is wanted for this role
+    pub fn start(&self, wanted: bool) -> Option<Result<UciEngine, ChessError>> {
+        let path = self.path.as_deref().filter(|_| wanted)?;
+        Some(UciEngine::spawn(path, &self.args).and_then(|mut engine| {
+            for (name, value) in &self.options {
+                engine.set_option(name, value)?;
+            }
+            Ok(engine)
+        }))
+    }
+}
+
+// The [clock] section: how a clock running low on time is pointed out
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct ClockConfig {
+    // Seconds left under which a clock counts as low; 0 turns the warning off
+    pub low_time: u64,
+    // Blink the running clock while it is low
+    pub blink: bool,
+    // Ring the terminal bell once when a clock gets low
+    pub bell: bool,
+}
+
+impl Default for ClockConfig {
+    fn default() -> Self {
+        ClockConfig {
+            low_time: 20,
+            blink: true,
+            bell: false,
+        }
+    }
+}
+
+// Set from --config, in place of the platform's config directory
+static CONFIG_PATH: OnceLock<PathBuf> = OnceLock::new();
+
+// Uses another config.toml, with the other saved files next to it; only the
+// first call counts, and it must come before anything is loaded
+pub fn set_config_path(path: PathBuf) {
+    let _ = CONFIG_PATH.set(path);
+}
+
+// config.toml in the platform's config directory, e.g. ~/.config/chess-rs/config.toml,
+// unless --config names another
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = CONFIG_PATH.get() {
+        return Some(path.clone());
+    }
+    dirs::config_dir().map(|dir| dir.join("chess-rs").join("config.toml"))
+}
+
+// The [book] section: a Polyglot opening book for the AI's first moves
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct BookConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+    // Book moves are only played this many plies into the game
+    pub max_plies: usize,
+    // 0.0 always plays the main line, 1.0 any book move in proportion to its weight
+    pub variety: f64,
+}
+
+impl Default for BookConfig {
+    fn default() -> Self {
+        BookConfig {
+            path: None,
+            max_plies: 16,
+            variety: 0.5,
+        }
+    }
+}
+
+impl BookConfig {
+    pub fn open(&self) -> Option<Result<OpeningBook, ChessError>> {
+        self.path.as_deref().map(OpeningBook::open)
+    }
+}
+
+// The [tablebase] section: Syzygy endgame tables for perfect play in small endings
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct TablebaseConfig {
+    // Directory with the .rtbw and .rtbz files; several are separated as in PATH
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+}
+
+impl TablebaseConfig {
+    pub fn open(&self) -> Option<Result<Tablebase, ChessError>> {
+        self.path.as_deref().map(Tablebase::open)
+    }
+}
+
+// The [lichess] section: the account online games are played with
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct LichessConfig {
+    // Personal API token with the board:play and challenge:write scopes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub token: Option<String>,
+}
+
+// The [fics] section: the Free Internet Chess Server account, a guest if unset
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct FicsConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub password: Option<String>,
+}
+
+// The [puzzles] section: which puzzles of the local database are played
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct PuzzleConfig {
+    // Rating range; within 200 points of the solver's rating when unset
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub min_rating: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_rating: Option<i32>,
+    // Lichess themes the puzzles must all have, e.g. ["fork", "endgame"]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub themes: Vec<String>,
+}
+
+// The [players] section: who plays hot-seat games, named in saved games and
+// rated against each other when both are set
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct PlayersConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub white: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub black: Option<String>,
+}
+
+// Reads the saved settings; a missing file just means the defaults
+pub fn load_settings() -> Result<Settings, String> {
+    let Some(path) = config_path() else {
+        return Ok(Settings::default());
+    };
+    match fs::read_to_string(&path) {
+        Ok(text) => {
+            toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e.message()))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Settings::default()),
+        Err(e) => Err(format!("{}: {}", path.display(), e)),
+    }
+}
+
+// Writes the settings into config.toml. Each one is changed where it stands,
+// so the comments and layout of the file are kept; a file that is not TOML
+// is left alone rather than written over
+pub fn save_settings(settings: &Settings) -> Result<(), String> {
+    let path = config_path().ok_or("no config directory on this system")?;
+    let text = toml::to_string_pretty(settings).map_err(|e| e.to_string())?;
+    let saved: DocumentMut = text.parse().map_err(|e| format!("{}", e))?;
+    let document = match fs::read_to_string(&path) {
+        Ok(old) => {
+            let mut document: DocumentMut = old
+                .parse()
+                .map_err(|e| format!("{} is not valid TOML: {}", path.display(), e))?;
+            merge(document.as_table_mut(), saved.as_table());
+            document
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => saved,
+        Err(e) => return Err(format!("{}: {}", path.display(), e)),
+    };
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    write_private(&path, &document.to_string()).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Writes a file only its owner can read, as config.toml may hold the Lichess
+// token and the FICS password
+fn write_private(path: &Path, text: &str) -> std::io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    // The mode only applies to a new file
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(text.as_bytes())
+}
+
+// Brings a table of the file in line with the saved settings: values are
+// replaced keeping their comments, and keys the settings leave out, such as
+// unset options, are dropped
+fn merge(table: &mut Table, settings: &Table) {
+    table.retain(|key, _| settings.contains_key(key));
+    for (key, item) in settings.iter() {
+        match (table.get_mut(key), item) {
+            (Some(Item::Table(old)), Item::Table(new)) => merge(old, new),
+            (Some(Item::Value(old)), Item::Value(new)) => {
+                let decor = old.decor().clone();
+                *old = new.clone();
+                *old.decor_mut() = decor;
+            }
+            _ => {
+                table.insert(key, item.clone());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn saving_keeps_comments_and_the_other_settings() {
+        let old = "# my settings\n\
+                   vim_mode = false # for now\n\
+                   threads = 4\n\
+                   \n\
+                   [lichess]\n\
+                   # from lichess.org/account/oauth/token\n\
+                   token = \"lip_secret\"\n";
+        let mut settings: Settings = toml::from_str(old).unwrap();
+        settings.vim_mode = true;
+        let text = toml::to_string_pretty(&settings).unwrap();
+        let mut document: DocumentMut = old.parse().unwrap();
+        merge(
+            document.as_table_mut(),
+            text.parse::<DocumentMut>().unwrap().as_table(),
+        );
+        let saved = document.to_string();
+        assert!(
+            saved.starts_with("# my settings\nvim_mode = true # for now\n"),
+            "{}",
+            saved
+        );
+        assert!(saved.contains("# from lichess.org/account/oauth/token\ntoken = \"lip_secret\"\n"));
+        let reloaded: Settings = toml::from_str(&saved).unwrap();
+        assert!(reloaded.vim_mode);
+        assert_eq!(reloaded.threads, 4);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn the_file_is_private() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path =
+            std::env::temp_dir().join(format!("chess-rs-config-{}.toml", std::process::id()));
+        fs::write(&path, "threads = 1\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&path, "threads = 2\n").unwrap();
+        let mode = fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "threads = 2\n");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+}
@@ -0,0 +1,117 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use chess_rs::ColorChess;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+// Days per move of correspondence games sought from the app
+pub const DAYS_PER_MOVE: u32 = 3;
+
+// A correspondence game on Lichess, as last heard of
+#[derive(Clone, Serialize, Deserialize)]
+pub struct CorrespondenceGame {
+    pub id: String,
+    #[serde(with = "ColorName")]
+    pub color: ColorChess,
+    pub opponent: String,
+    // Current position, for the games list
+    pub fen: String,
+    pub my_turn: bool,
+    // The opponent's latest move in UCI notation, if there is one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub last_move: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(remote = "ColorChess", rename_all = "lowercase")]
+enum ColorName {
+    White,
+    Black,
+}
+
+// The player's correspondence games by id, saved in correspondence.toml next
+// to config.toml so that the list is there before Lichess has answered
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CorrespondenceStore {
+    games: BTreeMap<String, CorrespondenceGame>,
+}
+
+fn store_path() -> Option<PathBuf> {
+    Some(config::config_path()?.with_file_name("correspondence.toml"))
+}
+
+impl CorrespondenceStore {
+    // Reads the saved games; a missing file means there are none
+    pub fn load() -> Result<CorrespondenceStore, String> {
+        let Some(path) = store_path() else {
+            return Ok(CorrespondenceStore::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e.message()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                Ok(CorrespondenceStore::default())
+            }
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = store_path().ok_or("no config directory on this system")?;
+        let text = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // Games where the player is to move first, then the rest, each by id
+    pub fn games(&self) -> Vec<&CorrespondenceGame> {
+        let mut games: Vec<_> = self.games.values().collect();
+        games.sort_by_key(|game| !game.my_turn);
+        games
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    pub fn waiting(&self) -> usize {
+        self.games.values().filter(|game| game.my_turn).count()
+    }
+
+    pub fn get_mut(&mut self, id: &str) -> Option<&mut CorrespondenceGame> {
+        self.games.get_mut(id)
+    }
+
+    // Adds a game or brings it up to date, returning whether it has just
+    // become the player's move
+    pub fn update(&mut self, game: CorrespondenceGame) -> bool {
+        let was_my_turn = self.games.get(&game.id).is_some_and(|old| old.my_turn);
+        let my_turn = game.my_turn;
+        self.games.insert(game.id.clone(), game);
+        my_turn && !was_my_turn
+    }
+
+    // Replaces the list with the games Lichess says are still running,
+    // returning the opponents who have moved since the last update
+    pub fn replace(&mut self, games: Vec<CorrespondenceGame>) -> Vec<String> {
+        let mut moved = Vec::new();
+        let mut updated = BTreeMap::new();
+        for game in games {
+            if game.my_turn && !self.games.get(&game.id).is_some_and(|old| old.my_turn) {
+                moved.push(game.opponent.clone());
+            }
+            updated.insert(game.id.clone(), game);
+        }
+        self.games = updated;
+        moved
+    }
+
+    pub fn remove(&mut self, id: &str) -> bool {
+        self.games.remove(id).is_some()
+    }
+}
@@ -0,0 +1,57 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::search::{MAX_DEPTH, SearchLimits};
+
+// Engine strength presets
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Difficulty {
+    Beginner,
+    #[default]
+    Casual,
+    Club,
+    Strong,
+}
+
+pub const DIFFICULTIES: [Difficulty; 4] = [
+    Difficulty::Beginner,
+    Difficulty::Casual,
+    Difficulty::Club,
+    Difficulty::Strong,
+];
+
+impl Difficulty {
+    pub fn label(self) -> &'static str {
+        match self {
+            Difficulty::Beginner => "Beginner",
+            Difficulty::Casual => "Casual",
+            Difficulty::Club => "Club",
+            Difficulty::Strong => "Strong",
+        }
+    }
+
+    // The next preset, wrapping around after Strong
+    pub fn next(self) -> Difficulty {
+        let index = DIFFICULTIES.iter().position(|&d| d == self).unwrap_or(0);
+        DIFFICULTIES[(index + 1) % DIFFICULTIES.len()]
+    }
+
+    // Depth, thinking time, move randomness and eval noise of the preset
+    pub fn limits(self) -> SearchLimits {
+        let (max_depth, millis, random_margin, eval_noise) = match self {
+            Difficulty::Beginner => (1, 500, 150, 100),
+            Difficulty::Casual => (2, 1000, 60, 40),
+            Difficulty::Club => (4, 2000, 15, 10),
+            Difficulty::Strong => (MAX_DEPTH, 3000, 0, 0),
+        };
+        SearchLimits {
+            max_depth,
+            time_limit: Some(Duration::from_millis(millis)),
+            random_margin,
+            eval_noise,
+            multi_pv: 1,
+        }
+    }
+}
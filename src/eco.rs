@@ -0,0 +1,296 @@
+use crate::fen::START_FEN;
+use crate::game::Game;
+use crate::moves::Move;
+use crate::position::Position;
+
+// An opening by its code in the Encyclopaedia of Chess Openings and its name
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Opening {
+    pub eco: &'static str,
+    pub name: &'static str,
+}
+
+// The best-known lines of each ECO volume, by their moves in SAN without
+// check marks; a game takes the code and name of the longest one it starts with
+const ECO_TABLE: [(&str, &str, &str); 120] = [
+    ("A00", "b4", "Polish Opening"),
+    ("A00", "g4", "Grob Opening"),
+    ("A00", "Nc3", "Van Geet Opening"),
+    ("A01", "b3", "Nimzo-Larsen Attack"),
+    ("A02", "f4", "Bird's Opening"),
+    ("A03", "f4 d5", "Bird's Opening, Dutch Variation"),
+    ("A04", "Nf3", "Réti Opening"),
+    ("A05", "Nf3 Nf6", "Réti Opening"),
+    ("A06", "Nf3 d5", "Réti Opening"),
+    ("A07", "Nf3 d5 g3", "King's Indian Attack"),
+    ("A09", "Nf3 d5 c4", "Réti Opening, Réti Gambit"),
+    ("A10", "c4", "English Opening"),
+    ("A13", "c4 e6", "English Opening, Agincourt Defense"),
+    ("A15", "c4 Nf6", "English Opening, Anglo-Indian Defense"),
+    ("A20", "c4 e5", "English Opening, King's English Variation"),
+    ("A30", "c4 c5", "English Opening, Symmetrical Variation"),
+    ("A40", "d4", "Queen's Pawn Game"),
+    ("A40", "d4 e6", "Queen's Pawn Game, Horwitz Defense"),
+    ("A41", "d4 d6", "Queen's Pawn Game, Old Indian"),
+    ("A43", "d4 c5", "Old Benoni Defense"),
+    ("A45", "d4 Nf6", "Indian Defense"),
+    ("A45", "d4 Nf6 Bg5", "Trompowsky Attack"),
+    ("A45", "d4 Nf6 Bf4", "Indian Defense, London System"),
+    ("A46", "d4 Nf6 Nf3", "Indian Defense, Knights Variation"),
+    ("A50", "d4 Nf6 c4", "Indian Defense, Normal Variation"),
+    ("A51", "d4 Nf6 c4 e5", "Budapest Gambit"),
+    ("A56", "d4 Nf6 c4 c5", "Benoni Defense"),
+    ("A57", "d4 Nf6 c4 c5 d5 b5", "Benko Gambit"),
+    ("A60", "d4 Nf6 c4 c5 d5 e6", "Modern Benoni"),
+    ("A80", "d4 f5", "Dutch Defense"),
+    ("A82", "d4 f5 e4", "Dutch Defense, Staunton Gambit"),
+    ("A84", "d4 f5 c4", "Dutch Defense"),
+    ("B00", "e4", "King's Pawn Opening"),
+    ("B00", "e4 Nc6", "Nimzowitsch Defense"),
+    ("B00", "e4 b6", "Owen Defense"),
+    ("B01", "e4 d5", "Scandinavian Defense"),
+    (
+        "B01",
+        "e4 d5 exd5 Qxd5",
+        "Scandinavian Defense, Mieses-Kotroc Variation",
+    ),
+    ("B02", "e4 Nf6", "Alekhine Defense"),
+    ("B03", "e4 Nf6 e5 Nd5 d4", "Alekhine Defense"),
+    ("B06", "e4 g6", "Modern Defense"),
+    ("B07", "e4 d6", "Pirc Defense"),
+    ("B07", "e4 d6 d4 Nf6", "Pirc Defense"),
+    ("B10", "e4 c6", "Caro-Kann Defense"),
+    ("B12", "e4 c6 d4 d5", "Caro-Kann Defense"),
+    (
+        "B12",
+        "e4 c6 d4 d5 e5",
+        "Caro-Kann Defense, Advance Variation",
+    ),
+    (
+        "B13",
+        "e4 c6 d4 d5 exd5",
+        "Caro-Kann Defense, Exchange Variation",
+    ),
+    ("B15", "e4 c6 d4 d5 Nc3", "Caro-Kann Defense"),
+    (
+        "B18",
+        "e4 c6 d4 d5 Nc3 dxe4 Nxe4 Bf5",
+        "Caro-Kann Defense, Classical Variation",
+    ),
+    ("B20", "e4 c5", "Sicilian Defense"),
+    (
+        "B21",
+        "e4 c5 d4 cxd4 c3",
+        "Sicilian Defense, Smith-Morra Gambit",
+    ),
+    ("B22", "e4 c5 c3", "Sicilian Defense, Alapin Variation"),
+    ("B23", "e4 c5 Nc3", "Sicilian Defense, Closed"),
+    ("B27", "e4 c5 Nf3", "Sicilian Defense"),
+    ("B30", "e4 c5 Nf3 Nc6", "Sicilian Defense, Old Sicilian"),
+    (
+        "B30",
+        "e4 c5 Nf3 Nc6 Bb5",
+        "Sicilian Defense, Rossolimo Variation",
+    ),
+    (
+        "B32",
+        "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4",
+        "Sicilian Defense, Open",
+    ),
+    (
+        "B33",
+        "e4 c5 Nf3 Nc6 d4 cxd4 Nxd4 Nf6 Nc3 e5",
+        "Sicilian Defense, Sveshnikov Variation",
+    ),
+    ("B40", "e4 c5 Nf3 e6", "Sicilian Defense, French Variation"),
+    ("B50", "e4 c5 Nf3 d6", "Sicilian Defense, Modern Variations"),
+    (
+        "B51",
+        "e4 c5 Nf3 d6 Bb5",
+        "Sicilian Defense, Moscow Variation",
+    ),
+    ("B54", "e4 c5 Nf3 d6 d4 cxd4 Nxd4", "Sicilian Defense, Open"),
+    (
+        "B56",
+        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3",
+        "Sicilian Defense, Classical",
+    ),
+    (
+        "B70",
+        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 g6",
+        "Sicilian Defense, Dragon Variation",
+    ),
+    (
+        "B80",
+        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 e6",
+        "Sicilian Defense, Scheveningen Variation",
+    ),
+    (
+        "B90",
+        "e4 c5 Nf3 d6 d4 cxd4 Nxd4 Nf6 Nc3 a6",
+        "Sicilian Defense, Najdorf Variation",
+    ),
+    ("C00", "e4 e6", "French Defense"),
+    (
+        "C01",
+        "e4 e6 d4 d5 exd5",
+        "French Defense, Exchange Variation",
+    ),
+    ("C02", "e4 e6 d4 d5 e5", "French Defense, Advance Variation"),
+    (
+        "C03",
+        "e4 e6 d4 d5 Nd2",
+        "French Defense, Tarrasch Variation",
+    ),
+    (
+        "C10",
+        "e4 e6 d4 d5 Nc3",
+        "French Defense, Paulsen Variation",
+    ),
+    (
+        "C11",
+        "e4 e6 d4 d5 Nc3 Nf6",
+        "French Defense, Classical Variation",
+    ),
+    (
+        "C15",
+        "e4 e6 d4 d5 Nc3 Bb4",
+        "French Defense, Winawer Variation",
+    ),
+    ("C20", "e4 e5", "King's Pawn Game"),
+    ("C21", "e4 e5 d4 exd4", "Center Game"),
+    ("C23", "e4 e5 Bc4", "Bishop's Opening"),
+    ("C25", "e4 e5 Nc3", "Vienna Game"),
+    ("C30", "e4 e5 f4", "King's Gambit"),
+    (
+        "C31",
+        "e4 e5 f4 d5",
+        "King's Gambit Declined, Falkbeer Countergambit",
+    ),
+    ("C33", "e4 e5 f4 exf4", "King's Gambit Accepted"),
+    ("C40", "e4 e5 Nf3", "King's Knight Opening"),
+    ("C40", "e4 e5 Nf3 f5", "Latvian Gambit"),
+    ("C41", "e4 e5 Nf3 d6", "Philidor Defense"),
+    ("C42", "e4 e5 Nf3 Nf6", "Petrov's Defense"),
+    ("C44", "e4 e5 Nf3 Nc6", "King's Pawn Game"),
+    ("C44", "e4 e5 Nf3 Nc6 d4", "Scotch Game"),
+    ("C45", "e4 e5 Nf3 Nc6 d4 exd4 Nxd4", "Scotch Game"),
+    ("C46", "e4 e5 Nf3 Nc6 Nc3", "Three Knights Opening"),
+    ("C47", "e4 e5 Nf3 Nc6 Nc3 Nf6", "Four Knights Game"),
+    ("C50", "e4 e5 Nf3 Nc6 Bc4", "Italian Game"),
+    ("C50", "e4 e5 Nf3 Nc6 Bc4 Bc5", "Italian Game, Giuoco Piano"),
+    (
+        "C51",
+        "e4 e5 Nf3 Nc6 Bc4 Bc5 b4",
+        "Italian Game, Evans Gambit",
+    ),
+    (
+        "C53",
+        "e4 e5 Nf3 Nc6 Bc4 Bc5 c3",
+        "Italian Game, Classical Variation",
+    ),
+    (
+        "C55",
+        "e4 e5 Nf3 Nc6 Bc4 Nf6",
+        "Italian Game, Two Knights Defense",
+    ),
+    (
+        "C57",
+        "e4 e5 Nf3 Nc6 Bc4 Nf6 Ng5",
+        "Italian Game, Two Knights Defense, Knight Attack",
+    ),
+    ("C60", "e4 e5 Nf3 Nc6 Bb5", "Ruy Lopez"),
+    ("C65", "e4 e5 Nf3 Nc6 Bb5 Nf6", "Ruy Lopez, Berlin Defense"),
+    (
+        "C68",
+        "e4 e5 Nf3 Nc6 Bb5 a6 Bxc6",
+        "Ruy Lopez, Exchange Variation",
+    ),
+    (
+        "C70",
+        "e4 e5 Nf3 Nc6 Bb5 a6 Ba4",
+        "Ruy Lopez, Morphy Defense",
+    ),
+    (
+        "C78",
+        "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O",
+        "Ruy Lopez, Morphy Defense",
+    ),
+    (
+        "C84",
+        "e4 e5 Nf3 Nc6 Bb5 a6 Ba4 Nf6 O-O Be7",
+        "Ruy Lopez, Closed",
+    ),
+    ("D00", "d4 d5", "Queen's Pawn Game"),
+    ("D00", "d4 d5 Bf4", "Queen's Pawn Game, London System"),
+    ("D02", "d4 d5 Nf3", "Queen's Pawn Game, Zukertort Variation"),
+    ("D06", "d4 d5 c4", "Queen's Gambit"),
+    (
+        "D07",
+        "d4 d5 c4 Nc6",
+        "Queen's Gambit Declined, Chigorin Defense",
+    ),
+    (
+        "D08",
+        "d4 d5 c4 e5",
+        "Queen's Gambit Declined, Albin Countergambit",
+    ),
+    ("D10", "d4 d5 c4 c6", "Slav Defense"),
+    ("D20", "d4 d5 c4 dxc4", "Queen's Gambit Accepted"),
+    ("D30", "d4 d5 c4 e6", "Queen's Gambit Declined"),
+    ("D43", "d4 d5 c4 c6 Nf3 Nf6 Nc3 e6", "Semi-Slav Defense"),
+    ("D80", "d4 Nf6 c4 g6 Nc3 d5", "Grünfeld Defense"),
+    ("E00", "d4 Nf6 c4 e6", "Indian Defense"),
+    ("E01", "d4 Nf6 c4 e6 g3", "Catalan Opening"),
+    ("E10", "d4 Nf6 c4 e6 Nf3", "Indian Defense"),
+    ("E11", "d4 Nf6 c4 e6 Nf3 Bb4", "Bogo-Indian Defense"),
+    ("E12", "d4 Nf6 c4 e6 Nf3 b6", "Queen's Indian Defense"),
+    ("E20", "d4 Nf6 c4 e6 Nc3 Bb4", "Nimzo-Indian Defense"),
+    ("E60", "d4 Nf6 c4 g6", "King's Indian Defense"),
+    ("E61", "d4 Nf6 c4 g6 Nc3 Bg7", "King's Indian Defense"),
+    (
+        "E70",
+        "d4 Nf6 c4 g6 Nc3 Bg7 e4 d6",
+        "King's Indian Defense, Normal Variation",
+    ),
+];
+
+// Moves past the longest line of the table need not be looked at
+const MAX_PLIES: usize = 10;
+
+impl Game {
+    // The opening of the game played on this board, if it started from the
+    // usual position and begins with a line of the table
+    pub fn opening(&self) -> Option<Opening> {
+        let moves: Vec<Move> = self.history.iter().map(|record| record.mv).collect();
+        classify(&self.get_start_position(), &moves)
+    }
+}
+
+// The opening of a game played from `start`, as above
+pub fn classify(start: &Position, moves: &[Move]) -> Option<Opening> {
+    if start.to_fen() != START_FEN {
+        return None;
+    }
+    let mut board = *start;
+    let mut played = Vec::new();
+    for &mv in moves.iter().take(MAX_PLIES) {
+        played.push(
+            board
+                .move_to_san(mv)
+                .trim_end_matches(['+', '#'])
+                .to_string(),
+        );
+        if board.make(mv).is_err() {
+            break;
+        }
+    }
+    ECO_TABLE
+        .iter()
+        .map(|&(eco, line, name)| (eco, line.split(' ').collect::<Vec<_>>(), name))
+        .filter(|(_, line, _)| {
+            line.len() <= played.len() && line.iter().zip(&played).all(|(a, b)| a == b)
+        })
+        .max_by_key(|(_, line, _)| line.len())
+        .map(|(eco, _, name)| Opening { eco, name })
+}
@@ -0,0 +1,179 @@
+use chess_rs::fen;
+use chess_rs::{ChessError, ColorChess, Game, Piece, PieceType, square_name};
+
+// Castling rights in the order FEN gives them
+const CASTLING: [char; 4] = ['K', 'Q', 'k', 'q'];
+
+// A position being set up square by square, to be played or analysed from
+pub struct Editor {
+    pub squares: [[Option<Piece>; 8]; 8],
+    pub turn: ColorChess,
+    // Rights in the order of CASTLING
+    pub castling: [bool; 4],
+    pub en_passant: Option<(usize, usize)>,
+    // What a click puts down
+    pub piece: Piece,
+    // Side shown at the bottom of the board
+    pub perspective: ColorChess,
+}
+
+impl Editor {
+    // Starts from a position, with its castling rights and en passant square
+    pub fn from_board(board: &Game) -> Editor {
+        let mut squares = [[None; 8]; 8];
+        for (row, rank) in squares.iter_mut().enumerate() {
+            for (col, square) in rank.iter_mut().enumerate() {
+                *square = board.get_piece((row, col));
+            }
+        }
+        let fen = board.to_fen();
+        let fields: Vec<&str> = fen.split_whitespace().collect();
+        Editor {
+            squares,
+            turn: board.get_current_turn(),
+            castling: CASTLING.map(|letter| fields[2].contains(letter)),
+            en_passant: board.parse_move(fields[3]).ok(),
+            piece: Piece::new(PieceType::Pawn, ColorChess::White),
+            perspective: ColorChess::White,
+        }
+    }
+
+    pub fn start_position(&mut self) {
+        let perspective = self.perspective;
+        let piece = self.piece;
+        *self = Editor {
+            piece,
+            perspective,
+            ..Editor::from_board(&Game::new())
+        };
+    }
+
+    // Takes every piece off, and with them the rights that needed them
+    pub fn clear(&mut self) {
+        self.squares = [[None; 8]; 8];
+        self.castling = [false; 4];
+        self.en_passant = None;
+    }
+
+    // Puts the chosen piece on the square, or takes it off if it is already there
+    pub fn click(&mut self, square: (usize, usize)) {
+        let (row, col) = square;
+        self.squares[row][col] = match self.squares[row][col] {
+            Some(piece) if piece == self.piece => None,
+            _ => Some(self.piece),
+        };
+        self.keep_en_passant();
+    }
+
+    pub fn remove(&mut self, square: (usize, usize)) {
+        self.squares[square.0][square.1] = None;
+        self.keep_en_passant();
+    }
+
+    pub fn toggle_turn(&mut self) {
+        self.turn = self.turn.opposite();
+        self.keep_en_passant();
+    }
+
+    pub fn toggle_castling(&mut self, index: usize) {
+        self.castling[index] = !self.castling[index];
+    }
+
+    // The castling rights as FEN writes them
+    pub fn castling_text(&self) -> String {
+        let text: String = CASTLING
+            .iter()
+            .zip(self.castling)
+            .filter(|&(_, on)| on)
+            .map(|(&letter, _)| letter)
+            .collect();
+        if text.is_empty() {
+            "-".to_string()
+        } else {
+            text
+        }
+    }
+
+    // Squares the side that just moved could have skipped with a pawn's
+    // double step, as FEN allows for the en passant square
+    pub fn en_passant_squares(&self) -> Vec<(usize, usize)> {
+        let mover = self.turn.opposite();
+        let (skipped, pawn, from) = match mover {
+            ColorChess::White => (2, 3, 1),
+            ColorChess::Black => (5, 4, 6),
+        };
+        (0..8)
+            .filter(|&col| {
+                self.squares[pawn][col] == Some(Piece::new(PieceType::Pawn, mover))
+                    && self.squares[skipped][col].is_none()
+                    && self.squares[from][col].is_none()
+            })
+            .map(|col| (skipped, col))
+            .collect()
+    }
+
+    // Steps to the next square en passant could be played on, then to none
+    pub fn next_en_passant(&mut self) {
+        let squares = self.en_passant_squares();
+        let next = match self.en_passant {
+            Some(square) => squares.iter().position(|&s| s == square).map(|i| i + 1),
+            None => Some(0),
+        };
+        self.en_passant = next.and_then(|i| squares.get(i).copied());
+    }
+
+    // An en passant square the pieces or the turn no longer allow is dropped
+    fn keep_en_passant(&mut self) {
+        if let Some(square) = self.en_passant
+            && !self.en_passant_squares().contains(&square)
+        {
+            self.en_passant = None;
+        }
+    }
+
+    // The position in FEN, as the first move of a game
+    pub fn fen(&self) -> String {
+        let mut placement = String::new();
+        for row in (0..8).rev() {
+            let mut empty = 0;
+            for square in self.squares[row] {
+                match square {
+                    Some(piece) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placement.push(piece.to_fen_char());
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if row > 0 {
+                placement.push('/');
+            }
+        }
+        let side = match self.turn {
+            ColorChess::White => "w",
+            ColorChess::Black => "b",
+        };
+        format!(
+            "{} {} {} {} 0 1",
+            placement,
+            side,
+            self.castling_text(),
+            self.en_passant.map_or("-".to_string(), square_name)
+        )
+    }
+
+    // What keeps the position from being played, if anything
+    pub fn problems(&self) -> Vec<String> {
+        fen::validate_fen(&self.fen())
+    }
+
+    pub fn board(&self) -> Result<Game, ChessError> {
+        Game::from_fen(&self.fen())
+    }
+}
@@ -0,0 +1,166 @@
+use chess_rs::{ChessError, ColorChess, Game, PieceType, Tablebase, Wdl};
+
+// Material a side has to be ahead by for a pawn ending to count as won: a
+// rook, as when the pawn queens or the defender gives the rook up for it
+const WINNING_EDGE: i32 = 5;
+
+// What the player has to do in an endgame drill
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Goal {
+    // Checkmate within the move budget
+    Mate,
+    // Queen the pawn, or win the defender's rook for it, within the budget
+    Promote,
+    // Hold the draw for the whole budget
+    Draw,
+}
+
+// A preset position to practise against the engine's best defence (or
+// attack); the player moves first
+pub struct Endgame {
+    // Name for ":endgame <name>"
+    pub name: &'static str,
+    pub title: &'static str,
+    pub fen: &'static str,
+    pub goal: Goal,
+    // Moves the player has for the goal
+    pub moves: u32,
+}
+
+pub const ENDGAMES: [Endgame; 4] = [
+    Endgame {
+        name: "kqk",
+        title: "King and queen against king",
+        fen: "8/8/8/4k3/8/8/8/3QK3 w - - 0 1",
+        goal: Goal::Mate,
+        moves: 15,
+    },
+    Endgame {
+        name: "krk",
+        title: "King and rook against king",
+        fen: "8/8/8/4k3/8/8/8/R3K3 w - - 0 1",
+        goal: Goal::Mate,
+        moves: 25,
+    },
+    Endgame {
+        name: "lucena",
+        title: "Lucena position",
+        fen: "1K1k4/1P6/8/8/8/8/r7/2R5 w - - 0 1",
+        goal: Goal::Promote,
+        moves: 15,
+    },
+    Endgame {
+        name: "philidor",
+        title: "Philidor position",
+        fen: "4k3/8/r7/4PK2/8/8/8/7R b - - 0 1",
+        goal: Goal::Draw,
+        moves: 25,
+    },
+];
+
+// The drill with the given name, as an index into ENDGAMES
+pub fn find(name: &str) -> Option<usize> {
+    ENDGAMES
+        .iter()
+        .position(|endgame| endgame.name.eq_ignore_ascii_case(name))
+}
+
+// How a drill ended
+pub enum Verdict {
+    Passed(String),
+    Failed(String),
+}
+
+impl Endgame {
+    pub fn board(&self) -> Result<Game, ChessError> {
+        Game::from_fen(self.fen)
+    }
+
+    // What the player is asked to do, for the message the drill starts with
+    pub fn task(&self) -> String {
+        match self.goal {
+            Goal::Mate => format!("checkmate within {} moves", self.moves),
+            Goal::Promote => format!("queen the pawn within {} moves", self.moves),
+            Goal::Draw => format!("hold the draw for {} moves", self.moves),
+        }
+    }
+
+    // Judges the position after a move of either side, for the player of
+    // `color`; None while the drill goes on. With tablebases a mistake is
+    // caught on the move it is made, without them only once it shows
+    pub fn verdict(
+        &self,
+        board: &Game,
+        color: ColorChess,
+        tablebase: Option<&Tablebase>,
+    ) -> Option<Verdict> {
+        let played = (board.get_history().len() as u32).div_ceil(2);
+        let player_moved = board.get_current_turn() != color;
+        match board.get_result().map(|result| result.winner) {
+            Some(Some(winner)) if winner == color => {
+                return Some(Verdict::Passed(format!("Checkmate in {} moves.", played)));
+            }
+            Some(None) if self.goal == Goal::Draw => {
+                return Some(Verdict::Passed(format!("Drawn after {} moves.", played)));
+            }
+            Some(None) => {
+                return Some(Verdict::Failed("Drawn: the win slipped away.".to_string()));
+            }
+            Some(_) => return Some(Verdict::Failed("You were checkmated.".to_string())),
+            None => {}
+        }
+
+        // The tablebases give the result from the side to move, the engine's
+        if player_moved && let Some(wdl) = tablebase.and_then(|tb| tb.probe_wdl(board)) {
+            match self.goal {
+                Goal::Draw if wdl == Wdl::Win => {
+                    return Some(Verdict::Failed(
+                        "That move loses, the tablebases say.".to_string(),
+                    ));
+                }
+                Goal::Mate | Goal::Promote if wdl != Wdl::Loss => {
+                    return Some(Verdict::Failed(
+                        "That move lets the win slip, the tablebases say.".to_string(),
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        let opponent = color.opposite();
+        let edge = board.get_material(color) as i32 - board.get_material(opponent) as i32;
+        let pawns = |side| board.count_pieces(side, PieceType::Pawn);
+        match self.goal {
+            Goal::Mate if !board.has_mating_material(color) => Some(Verdict::Failed(
+                "Without the material to mate, the win is gone.".to_string(),
+            )),
+            Goal::Mate if player_moved && played >= self.moves => Some(Verdict::Failed(format!(
+                "Out of moves: the mate had to come within {}.",
+                self.moves
+            ))),
+            // Judged on the player's turn, so that a queen the defender can
+            // take straight back does not count
+            Goal::Promote if !player_moved && edge >= WINNING_EDGE => Some(Verdict::Passed(
+                format!("Won the ending in {} moves.", played),
+            )),
+            Goal::Promote if pawns(color) == 0 && edge < WINNING_EDGE => Some(Verdict::Failed(
+                "The pawn is gone and the ending with it.".to_string(),
+            )),
+            Goal::Promote if !player_moved && played >= self.moves => Some(Verdict::Failed(
+                format!("Out of moves: the pawn had to queen within {}.", self.moves),
+            )),
+            // Judged after the player's move, which may take a new queen
+            Goal::Draw if player_moved && pawns(opponent) == 0 && edge >= 0 => Some(
+                Verdict::Passed("The pawn is gone, and with it the winning chances.".to_string()),
+            ),
+            Goal::Draw if player_moved && edge <= -WINNING_EDGE => Some(Verdict::Failed(
+                "Too much material is gone to hold the draw.".to_string(),
+            )),
+            Goal::Draw if player_moved && played >= self.moves => Some(Verdict::Passed(format!(
+                "Held the draw for {} moves.",
+                self.moves
+            ))),
+            _ => None,
+        }
+    }
+}
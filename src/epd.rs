@@ -0,0 +1,288 @@
+use crate::error::ChessError;
+use crate::game::Game;
+use crate::moves::Move;
+
+// One operation of an EPD record, e.g. `bm Qg6` or `id "WAC.001"`
+#[derive(Clone, Debug, PartialEq)]
+pub struct Operation {
+    pub opcode: String,
+    pub operands: Vec<String>,
+}
+
+// A position in Extended Position Description: the first four fields of its
+// FEN and the operations after them, as used by engine test suites
+#[derive(Clone)]
+pub struct Epd {
+    pub board: Game,
+    pub operations: Vec<Operation>,
+}
+
+impl Epd {
+    pub fn new(board: Game) -> Epd {
+        Epd {
+            board,
+            operations: Vec::new(),
+        }
+    }
+
+    pub fn operands(&self, opcode: &str) -> Option<&[String]> {
+        self.operations
+            .iter()
+            .find(|operation| operation.opcode == opcode)
+            .map(|operation| operation.operands.as_slice())
+    }
+
+    // Sets an operation, in place of any earlier one with the same opcode
+    pub fn set(&mut self, opcode: &str, operands: Vec<String>) {
+        match self
+            .operations
+            .iter_mut()
+            .find(|operation| operation.opcode == opcode)
+        {
+            Some(operation) => operation.operands = operands,
+            None => self.operations.push(Operation {
+                opcode: opcode.to_string(),
+                operands,
+            }),
+        }
+    }
+
+    // The record's name in its suite, from the `id` operation
+    pub fn id(&self) -> Option<&str> {
+        self.operands("id")?.first().map(String::as_str)
+    }
+
+    // The moves of the `bm` operation, one of which must be found
+    pub fn best_moves(&self) -> Result<Vec<Move>, ChessError> {
+        self.moves("bm")
+    }
+
+    // The moves of the `am` operation, none of which may be played
+    pub fn avoid_moves(&self) -> Result<Vec<Move>, ChessError> {
+        self.moves("am")
+    }
+
+    fn moves(&self, opcode: &str) -> Result<Vec<Move>, ChessError> {
+        self.operands(opcode)
+            .unwrap_or_default()
+            .iter()
+            .map(|san| {
+                self.board.parse_san(san).map_err(|_| {
+                    ChessError::BadEpd(format!("{} {} is not a legal move", opcode, san))
+                })
+            })
+            .collect()
+    }
+
+    // The record as a line of EPD; the move clocks are left to `hmvc` and
+    // `fmvn` operations, as EPD has no fields for them
+    pub fn to_epd(&self) -> String {
+        let fen = self.board.to_fen();
+        let mut line = fen.split_whitespace().take(4).collect::<Vec<_>>().join(" ");
+        for operation in &self.operations {
+            line.push(' ');
+            line.push_str(&operation.opcode);
+            for operand in &operation.operands {
+                line.push(' ');
+                if needs_quotes(&operation.opcode, operand) {
+                    line.push_str(&format!("\"{}\"", operand.replace('"', "")));
+                } else {
+                    line.push_str(operand);
+                }
+            }
+            line.push(';');
+        }
+        line
+    }
+}
+
+// Names and comments are written as strings, as is anything that would
+// otherwise not read back as one operand
+fn needs_quotes(opcode: &str, operand: &str) -> bool {
+    let comment = opcode.len() == 2
+        && opcode.starts_with('c')
+        && opcode.ends_with(|c: char| c.is_ascii_digit());
+    opcode == "id"
+        || comment
+        || operand.is_empty()
+        || operand.contains(|c: char| c.is_whitespace() || c == ';' || c == '"')
+}
+
+// Reads one line of EPD. `hmvc` and `fmvn` operations set the move clocks,
+// which otherwise start at 0 and 1
+pub fn parse_epd(line: &str) -> Result<Epd, ChessError> {
+    let line = line.trim();
+    let mut rest = line;
+    let mut fields = Vec::new();
+    for _ in 0..4 {
+        rest = rest.trim_start();
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        if end == 0 {
+            return Err(ChessError::BadEpd(format!(
+                "EPD needs 4 fields before its operations: {}",
+                line
+            )));
+        }
+        fields.push(&rest[..end]);
+        rest = &rest[end..];
+    }
+    let mut epd = Epd::new(Game::from_fen(&fields.join(" "))?);
+    epd.operations = parse_operations(rest).map_err(ChessError::BadEpd)?;
+    let clock = |opcode: &str| -> Result<Option<u32>, ChessError> {
+        match epd.operands(opcode).and_then(|operands| operands.first()) {
+            Some(text) => text
+                .parse()
+                .map(Some)
+                .map_err(|_| ChessError::BadEpd(format!("invalid {} '{}'", opcode, text))),
+            None => Ok(None),
+        }
+    };
+    let (halfmove, fullmove) = (clock("hmvc")?, clock("fmvn")?);
+    if halfmove.is_some() || fullmove.is_some() {
+        let fen = format!(
+            "{} {} {}",
+            fields.join(" "),
+            halfmove.unwrap_or(0),
+            fullmove.unwrap_or(1)
+        );
+        epd.board = Game::from_fen(&fen)?;
+    }
+    Ok(epd)
+}
+
+// Operations are an opcode and its operands, each ended by a semicolon;
+// operands in double quotes may hold spaces and semicolons
+fn parse_operations(text: &str) -> Result<Vec<Operation>, String> {
+    let mut operations = Vec::new();
+    let mut words: Vec<String> = Vec::new();
+    let mut chars = text.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        match chars.next() {
+            None => break,
+            Some(';') => {
+                if words.is_empty() {
+                    continue;
+                }
+                let opcode = words.remove(0);
+                operations.push(Operation {
+                    opcode,
+                    operands: std::mem::take(&mut words),
+                });
+            }
+            Some('"') if !words.is_empty() => {
+                let mut operand = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => operand.push(c),
+                        None => return Err(format!("unclosed string in EPD: {}", text.trim())),
+                    }
+                }
+                words.push(operand);
+            }
+            Some(c) => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|&c| !c.is_whitespace() && c != ';') {
+                    word.push(c);
+                }
+                words.push(word);
+            }
+        }
+    }
+    // The last semicolon is often left off
+    if !words.is_empty() {
+        let opcode = words.remove(0);
+        operations.push(Operation {
+            opcode,
+            operands: words,
+        });
+    }
+    Ok(operations)
+}
+
+// Reads every record of an EPD file, skipping blank lines and comments
+// starting with '#'
+pub fn parse_epd_file(text: &str) -> Result<Vec<Epd>, ChessError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+        .map(|(i, line)| {
+            parse_epd(line).map_err(|e| ChessError::BadEpd(format!("line {}: {}", i + 1, e)))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WAC_001: &str =
+        r#"2rr3k/pp3pp1/1nnqbN1p/3pN3/2pP4/2P3Q1/PPB4P/R4BK1 w - - bm Qg6; id "WAC.001";"#;
+
+    #[test]
+    fn reads_a_test_suite_record() {
+        let epd = parse_epd(WAC_001).unwrap();
+        assert_eq!(epd.id(), Some("WAC.001"));
+        let best = epd.best_moves().unwrap();
+        assert_eq!(best.len(), 1);
+        assert_eq!(best[0].to_uci(), "g3g6");
+        assert!(epd.avoid_moves().unwrap().is_empty());
+        assert_eq!(epd.to_epd(), WAC_001);
+    }
+
+    #[test]
+    fn reads_quoted_operands_and_move_clocks() {
+        let epd = parse_epd(
+            "4k3/8/8/8/8/8/8/4K2R w K - hmvc 12; fmvn 40; c0 \"mate; in time\"; am Rh8 Kd1",
+        )
+        .unwrap();
+        assert_eq!(epd.board.to_fen(), "4k3/8/8/8/8/8/8/4K2R w K - 12 40");
+        assert_eq!(
+            epd.operands("c0"),
+            Some(["mate; in time".to_string()].as_slice())
+        );
+        let avoid: Vec<String> = epd
+            .avoid_moves()
+            .unwrap()
+            .iter()
+            .map(|mv| mv.to_uci())
+            .collect();
+        assert_eq!(avoid, ["h1h8", "e1d1"]);
+        assert_eq!(
+            epd.to_epd(),
+            "4k3/8/8/8/8/8/8/4K2R w K - hmvc 12; fmvn 40; c0 \"mate; in time\"; am Rh8 Kd1;"
+        );
+    }
+
+    #[test]
+    fn sets_operations_in_place() {
+        let mut epd = Epd::new(Game::new());
+        epd.set("bm", vec!["e4".to_string()]);
+        epd.set("id", vec!["start".to_string()]);
+        epd.set("bm", vec!["d4".to_string()]);
+        assert_eq!(
+            epd.to_epd(),
+            r#"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - bm d4; id "start";"#
+        );
+    }
+
+    #[test]
+    fn refuses_bad_records() {
+        assert!(parse_epd("8/8/8/8 w").is_err());
+        assert!(parse_epd(r#"4k3/8/8/8/8/8/8/4K3 w - - id "open"#).is_err());
+        assert!(parse_epd("4k3/8/8/8/8/8/8/4K3 w - - hmvc x;").is_err());
+        let illegal = parse_epd("4k3/8/8/8/8/8/8/4K3 w - - bm Qh5;").unwrap();
+        assert!(illegal.best_moves().is_err());
+    }
+
+    #[test]
+    fn reads_a_file_skipping_comments() {
+        let text = format!("# Win at Chess\n\n{}\n{}\n", WAC_001, WAC_001);
+        assert_eq!(parse_epd_file(&text).unwrap().len(), 2);
+        let Err(error) = parse_epd_file(&format!("{}\nnot epd\n", WAC_001)) else {
+            panic!("read a line that is not EPD");
+        };
+        assert!(error.to_string().contains("line 2"), "{}", error);
+    }
+}
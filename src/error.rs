@@ -0,0 +1,63 @@
+use std::fmt;
+
+// Everything the library's fallible calls can fail with. Most carry the
+// message to show for it; the binary still passes errors around as strings,
+// which they turn into with `?`
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChessError {
+    // A move, in UCI, that is not legal in the position given as FEN
+    IllegalMove { mv: String, fen: String },
+    // A FEN that cannot be read, or is not a position of a real game
+    BadFen(String),
+    // Text naming no legal move in SAN
+    BadSan(String),
+    // Text that is not a move in UCI notation at all
+    BadUci(String),
+    // A square off the board, or a name that is not a square
+    OutOfBounds(String),
+    BadPgn(String),
+    BadEpd(String),
+    // A file that is not a Polyglot book
+    BadBook(String),
+    // Syzygy tables that are missing or do not agree with the known positions
+    BadTablebase(String),
+    // An external engine that could not be started or stopped answering
+    Engine(String),
+    // A file that could not be read or written, and why
+    Io { path: String, message: String },
+}
+
+impl ChessError {
+    pub fn io(path: impl fmt::Display, error: std::io::Error) -> ChessError {
+        ChessError::Io {
+            path: path.to_string(),
+            message: error.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ChessError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ChessError::IllegalMove { mv, fen } => write!(f, "{} is not legal in {}", mv, fen),
+            ChessError::BadSan(text) => write!(f, "{} is not a legal move", text),
+            ChessError::BadUci(text) => write!(f, "{} is not a move in UCI notation", text),
+            ChessError::OutOfBounds(square) => write!(f, "{} is not a square on the board", square),
+            ChessError::Io { path, message } => write!(f, "{}: {}", path, message),
+            ChessError::BadFen(message)
+            | ChessError::BadPgn(message)
+            | ChessError::BadEpd(message)
+            | ChessError::BadBook(message)
+            | ChessError::BadTablebase(message)
+            | ChessError::Engine(message) => f.write_str(message),
+        }
+    }
+}
+
+impl std::error::Error for ChessError {}
+
+impl From<ChessError> for String {
+    fn from(error: ChessError) -> String {
+        error.to_string()
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Receiver, RecvTimeoutError, Sender},
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use crossterm::event::{self, Event as CrosstermEvent};
+
+use crate::ai::AiReply;
+use crate::fics::FicsEvent;
+use crate::fog_client::FogEvent;
+use crate::lichess::LichessEvent;
+
+// Everything that wakes the main loop, each handled by App::update
+pub enum AppEvent {
+    // A key press, mouse event or resize from the terminal
+    Input(CrosstermEvent),
+    // Time to pick up background work and redraw, e.g. for the clocks
+    Tick,
+    // A search for the AI's move has finished
+    EngineMove(AiReply),
+    // Something a chess server sent
+    NetworkMsg(NetworkMsg),
+    // The running clock has reached zero
+    ClockExpired,
+}
+
+pub enum NetworkMsg {
+    Lichess(LichessEvent),
+    Fics(FicsEvent),
+    Fog(FogEvent),
+}
+
+// What background threads use to report back to the main loop: a sender of
+// one kind of message, which is wrapped up as its event
+pub struct EventSender<T> {
+    sender: Sender<AppEvent>,
+    wrap: fn(T) -> AppEvent,
+}
+
+impl<T> Clone for EventSender<T> {
+    fn clone(&self) -> Self {
+        EventSender {
+            sender: self.sender.clone(),
+            wrap: self.wrap,
+        }
+    }
+}
+
+impl<T> EventSender<T> {
+    pub fn new(sender: Sender<AppEvent>, wrap: fn(T) -> AppEvent) -> EventSender<T> {
+        EventSender { sender, wrap }
+    }
+
+    // Fails once the main loop has gone
+    pub fn send(&self, message: T) -> Result<(), String> {
+        self.sender
+            .send((self.wrap)(message))
+            .map_err(|_| "the app has closed".to_string())
+    }
+}
+
+// Changes for the timer thread
+enum Timer {
+    Rate(Duration),
+    Deadline(Option<Instant>),
+}
+
+// Terminal input, timer ticks and the reports of background work merged into
+// one channel. Input and ticks come from their own threads, so the clocks are
+// redrawn on time however busy the input is
+pub struct Events {
+    events: Receiver<AppEvent>,
+    sender: Sender<AppEvent>,
+    timer: Sender<Timer>,
+    tick_rate: Duration,
+    deadline: Option<Instant>,
+    // Set while a tick is waiting in the channel; a main loop that falls
+    // behind gets one tick rather than a backlog of them
+    tick_pending: Arc<AtomicBool>,
+}
+
+impl Events {
+    pub fn new(tick_rate: Duration) -> Events {
+        let (sender, events) = mpsc::channel();
+
+        let input = sender.clone();
+        thread::spawn(move || {
+            // Ends when the terminal cannot be read or the main loop is gone
+            while let Ok(event) = event::read() {
+                if input.send(AppEvent::Input(event)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let (timer, timer_rx) = mpsc::channel();
+        let tick_pending = Arc::new(AtomicBool::new(false));
+        let pending = Arc::clone(&tick_pending);
+        let ticks = sender.clone();
+        thread::spawn(move || {
+            let mut rate = tick_rate;
+            let mut next_tick = Instant::now() + rate;
+            let mut deadline: Option<Instant> = None;
+            loop {
+                let wake = deadline.map_or(next_tick, |deadline| deadline.min(next_tick));
+                match timer_rx.recv_timeout(wake.saturating_duration_since(Instant::now())) {
+                    // A new rate takes effect at once
+                    Ok(Timer::Rate(new_rate)) => {
+                        rate = new_rate;
+                        next_tick = Instant::now() + rate;
+                    }
+                    Ok(Timer::Deadline(new_deadline)) => deadline = new_deadline,
+                    Err(RecvTimeoutError::Timeout) => {
+                        let now = Instant::now();
+                        if deadline.is_some_and(|deadline| deadline <= now) {
+                            deadline = None;
+                            if ticks.send(AppEvent::ClockExpired).is_err() {
+                                break;
+                            }
+                        }
+                        if next_tick <= now {
+                            next_tick = now + rate;
+                            if !pending.swap(true, Ordering::Relaxed)
+                                && ticks.send(AppEvent::Tick).is_err()
+                            {
+                                break;
+                            }
+                        }
+                    }
+                    Err(RecvTimeoutError::Disconnected) => break,
+                }
+            }
+        });
+
+        Events {
+            events,
+            sender,
+            timer,
+            tick_rate,
+            deadline: None,
+            tick_pending,
+        }
+    }
+
+    // A sender for background work to report through as `wrap` makes of it
+    pub fn sender<T>(&self, wrap: fn(T) -> AppEvent) -> EventSender<T> {
+        EventSender::new(self.sender.clone(), wrap)
+    }
+
+    // Changes how often ticks come, e.g. faster while a piece slides
+    pub fn set_tick_rate(&mut self, tick_rate: Duration) {
+        if tick_rate != self.tick_rate {
+            self.tick_rate = tick_rate;
+            let _ = self.timer.send(Timer::Rate(tick_rate));
+        }
+    }
+
+    // Sets when ClockExpired comes, if the running clock has not stopped
+    // by then
+    pub fn set_deadline(&mut self, deadline: Option<Instant>) {
+        if deadline != self.deadline {
+            self.deadline = deadline;
+            let _ = self.timer.send(Timer::Deadline(deadline));
+        }
+    }
+
+    // Waits for the next event
+    pub fn next(&mut self) -> Result<AppEvent, String> {
+        let event = self
+            .events
+            .recv()
+            .map_err(|_| "the input and timer threads stopped".to_string())?;
+        match event {
+            AppEvent::Tick => self.tick_pending.store(false, Ordering::Relaxed),
+            // The timer is done with the deadline; the same one set again
+            // is a new one
+            AppEvent::ClockExpired => self.deadline = None,
+            _ => {}
+        }
+        Ok(event)
+    }
+}
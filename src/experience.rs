@@ -0,0 +1,144 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use chess_rs::Game;
+use serde::{Deserialize, Serialize};
+
+use crate::analysis::{Analysis, AnalysisLine};
+use crate::config;
+
+// Shallower analysis is quick enough to redo
+const MIN_DEPTH: u32 = 8;
+
+// Positions kept at most; the shallowest are forgotten first
+const MAX_POSITIONS: usize = 5000;
+
+// The deepest analysis of a position, as the panel showed it
+#[derive(Clone, Serialize, Deserialize)]
+struct Experience {
+    depth: u32,
+    white_score: i32,
+    // In UCI notation, for the arrow on the board
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    best_move: Option<String>,
+    lines: Vec<ExperienceLine>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+struct ExperienceLine {
+    white_score: i32,
+    pv: String,
+}
+
+// Deep analysis of the positions looked at, kept in experience.toml next to
+// config.toml when `experience` is on, so that analysing one again carries
+// on from the depth it reached
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExperienceStore {
+    // By the position's hash, in hex
+    positions: BTreeMap<String, Experience>,
+}
+
+fn store_path() -> Option<PathBuf> {
+    Some(config::config_path()?.with_file_name("experience.toml"))
+}
+
+fn position_key(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+impl ExperienceStore {
+    // Reads the saved analysis; a missing file means none yet
+    pub fn load() -> Result<ExperienceStore, String> {
+        let Some(path) = store_path() else {
+            return Ok(ExperienceStore::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e.message()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(ExperienceStore::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = store_path().ok_or("no config directory on this system")?;
+        let text = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // The saved analysis of a position, if it has at least `lines` lines
+    pub fn recall(&self, board: &Game, lines: usize) -> Option<Analysis> {
+        let experience = self.positions.get(&position_key(board.hash()))?;
+        if experience.lines.len() < lines {
+            return None;
+        }
+        Some(Analysis {
+            hash: board.hash(),
+            white_score: experience.white_score,
+            depth: experience.depth,
+            nodes: 0,
+            nodes_per_second: 0,
+            best_move: experience
+                .best_move
+                .as_deref()
+                .and_then(|text| board.parse_uci_move(text).ok()),
+            lines: experience
+                .lines
+                .iter()
+                .take(lines)
+                .map(|line| AnalysisLine {
+                    white_score: line.white_score,
+                    pv: line.pv.clone(),
+                })
+                .collect(),
+            exact: None,
+            saved: true,
+        })
+    }
+
+    // Keeps an analysis deeper than the one saved, or with more lines;
+    // returns whether it was kept
+    pub fn remember(&mut self, analysis: &Analysis) -> bool {
+        if analysis.depth < MIN_DEPTH {
+            return false;
+        }
+        let key = position_key(analysis.hash);
+        if let Some(saved) = self.positions.get(&key)
+            && saved.depth >= analysis.depth
+            && saved.lines.len() >= analysis.lines.len()
+        {
+            return false;
+        }
+        self.positions.insert(
+            key,
+            Experience {
+                depth: analysis.depth,
+                white_score: analysis.white_score,
+                best_move: analysis.best_move.map(|mv| mv.to_uci()),
+                lines: analysis
+                    .lines
+                    .iter()
+                    .map(|line| ExperienceLine {
+                        white_score: line.white_score,
+                        pv: line.pv.clone(),
+                    })
+                    .collect(),
+            },
+        );
+        if self.positions.len() > MAX_POSITIONS
+            && let Some(shallowest) = self
+                .positions
+                .iter()
+                .min_by_key(|(_, experience)| experience.depth)
+                .map(|(key, _)| key.clone())
+        {
+            self.positions.remove(&shallowest);
+        }
+        true
+    }
+}
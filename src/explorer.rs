@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use chess_rs::{Game, Move};
+
+use crate::game_db::{self, GameDatabase};
+
+// One move played from a position in the player's games, and how they went
+pub struct MoveStats {
+    pub mv: Move,
+    pub san: String,
+    pub games: u32,
+    // Half points the player scored in those games, so a draw counts as one
+    half_points: u32,
+}
+
+impl MoveStats {
+    // The player's score with the move, as a percentage
+    pub fn score(&self) -> u32 {
+        self.half_points * 50 / self.games.max(1)
+    }
+}
+
+// Every position of the player's saved games with the moves played from it,
+// by the position's hash, so that transpositions meet
+pub struct OpeningTree {
+    positions: HashMap<u64, Vec<MoveStats>>,
+    // Games left out: hot-seat ones, which have no side to score for, and
+    // any whose file could not be read
+    pub skipped: usize,
+}
+
+impl OpeningTree {
+    // Reads through the games in the database
+    pub fn build(database: &GameDatabase) -> OpeningTree {
+        let mut tree = OpeningTree {
+            positions: HashMap::new(),
+            skipped: 0,
+        };
+        for saved in database.games() {
+            let half_points = match saved.outcome.as_str() {
+                "Won" => 2,
+                "Drawn" => 1,
+                "Lost" => 0,
+                _ => {
+                    tree.skipped += 1;
+                    continue;
+                }
+            };
+            let Ok(game) = game_db::open(saved) else {
+                tree.skipped += 1;
+                continue;
+            };
+            let mut board = game.board.get_start_position();
+            for record in game.board.get_history() {
+                let moves = tree.positions.entry(board.hash()).or_default();
+                match moves.iter_mut().find(|stats| stats.mv == record.mv) {
+                    Some(stats) => {
+                        stats.games += 1;
+                        stats.half_points += half_points;
+                    }
+                    None => moves.push(MoveStats {
+                        mv: record.mv,
+                        san: board.move_to_san(record.mv),
+                        games: 1,
+                        half_points,
+                    }),
+                }
+                if board.make(record.mv).is_err() {
+                    break;
+                }
+            }
+        }
+        for moves in tree.positions.values_mut() {
+            moves.sort_by_key(|stats| std::cmp::Reverse(stats.games));
+        }
+        tree
+    }
+
+    // The moves played from a position, most often played first
+    pub fn moves(&self, board: &Game) -> &[MoveStats] {
+        self.positions
+            .get(&board.hash())
+            .map_or(&[], |moves| moves.as_slice())
+    }
+}
@@ -0,0 +1,451 @@
+use crate::board::{ColorChess, Piece, PieceType, square_name};
+use crate::error::ChessError;
+use crate::game::Game;
+use crate::position::Position;
+
+// The standard starting position
+pub const START_FEN: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1";
+
+impl Piece {
+    // Upper case for White, lower case for Black
+    pub fn to_fen_char(self) -> char {
+        let letter = self.piece_type().letter();
+        match self.color() {
+            ColorChess::White => letter,
+            ColorChess::Black => letter.to_ascii_lowercase(),
+        }
+    }
+
+    pub fn from_fen_char(letter: char) -> Option<Piece> {
+        let piece_type = match letter.to_ascii_uppercase() {
+            'K' => PieceType::King,
+            'Q' => PieceType::Queen,
+            'R' => PieceType::Rook,
+            'B' => PieceType::Bishop,
+            'N' => PieceType::Knight,
+            'P' => PieceType::Pawn,
+            _ => return None,
+        };
+        let color = if letter.is_ascii_uppercase() {
+            ColorChess::White
+        } else {
+            ColorChess::Black
+        };
+        Some(Piece::new(piece_type, color))
+    }
+}
+
+impl Position {
+    // Sets up a position from FEN. The move clocks may be left out, as in EPD.
+    // A position that could not come about in a game is refused with the
+    // first of its problems, as validate_fen lists them
+    pub fn from_fen(fen: &str) -> Result<Position, ChessError> {
+        let (board, problems) = parse_fen(fen).map_err(ChessError::BadFen)?;
+        match problems.into_iter().next() {
+            Some(problem) => Err(ChessError::BadFen(problem)),
+            None => Ok(board),
+        }
+    }
+
+    // Forsyth-Edwards Notation of the current position
+    pub fn to_fen(&self) -> String {
+        let mut placement = String::new();
+        for row in (0..8).rev() {
+            let mut empty = 0;
+            for col in 0..8 {
+                match self.squares[row][col] {
+                    Some(piece) => {
+                        if empty > 0 {
+                            placement.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        placement.push(piece.to_fen_char());
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                placement.push_str(&empty.to_string());
+            }
+            if row > 0 {
+                placement.push('/');
+            }
+        }
+
+        let side = match self.current_turn {
+            ColorChess::White => "w",
+            ColorChess::Black => "b",
+        };
+
+        format!(
+            "{} {} {} {} {} {}",
+            placement,
+            side,
+            self.fen_castling(),
+            self.en_passant_target.map_or("-".to_string(), square_name),
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+
+    // The flags only record whether a king or rook has moved, so a rook that was
+    // captured on its home square is checked for here
+    pub(crate) fn fen_castling(&self) -> String {
+        let rights = self.castling_rights();
+        let corners = [
+            (1, 'K', 0, 7, ColorChess::White),
+            (2, 'Q', 0, 0, ColorChess::White),
+            (4, 'k', 7, 7, ColorChess::Black),
+            (8, 'q', 7, 0, ColorChess::Black),
+        ];
+        let castling: String = corners
+            .iter()
+            .filter(|&&(bit, _, row, col, color)| {
+                rights & bit != 0
+                    && self.squares[row][col]
+                        .is_some_and(|p| p.is_type(PieceType::Rook) && p.is_color(color))
+            })
+            .map(|&(_, letter, ..)| letter)
+            .collect();
+        if castling.is_empty() {
+            "-".to_string()
+        } else {
+            castling
+        }
+    }
+}
+
+impl Game {
+    // A game from the position in FEN, with no moves (and so no captured
+    // pieces) before it
+    pub fn from_fen(fen: &str) -> Result<Game, ChessError> {
+        Position::from_fen(fen).map(Game::from)
+    }
+}
+
+// Everything that keeps a FEN from being a position of a real game: a king
+// short or too many, pawns on the first or last rank, more pieces than
+// promotions allow, the side not to move in check, castling rights without
+// the king and rook at home, and an en passant square no pawn just skipped.
+// A FEN that cannot be read at all gives the one reason why. Empty when the
+// position is legal
+pub fn validate_fen(fen: &str) -> Vec<String> {
+    match parse_fen(fen) {
+        Ok((_, problems)) => problems,
+        Err(e) => vec![e],
+    }
+}
+
+// Reads a FEN as far as its syntax allows, along with the problems of the
+// position it describes
+pub(crate) fn parse_fen(fen: &str) -> Result<(Position, Vec<String>), String> {
+    let fields: Vec<&str> = fen.split_whitespace().collect();
+    if fields.len() < 4 {
+        return Err(format!("FEN needs at least 4 fields, got {}", fields.len()));
+    }
+
+    let mut board = Position::new();
+    board.squares = [[None; 8]; 8];
+    let ranks: Vec<&str> = fields[0].split('/').collect();
+    if ranks.len() != 8 {
+        return Err(format!("FEN needs 8 ranks, got {}", ranks.len()));
+    }
+    for (i, rank) in ranks.iter().enumerate() {
+        let row = 7 - i;
+        let mut col = 0;
+        for letter in rank.chars() {
+            if let Some(empty) = letter.to_digit(10) {
+                col += empty as usize;
+            } else {
+                let piece = Piece::from_fen_char(letter)
+                    .ok_or_else(|| format!("unknown piece '{}' in FEN", letter))?;
+                if col >= 8 {
+                    return Err(format!("rank {} of the FEN has too many squares", row + 1));
+                }
+                board.squares[row][col] = Some(piece);
+                col += 1;
+            }
+        }
+        if col != 8 {
+            return Err(format!(
+                "rank {} of the FEN does not have 8 squares",
+                row + 1
+            ));
+        }
+    }
+
+    board.current_turn = match fields[1] {
+        "w" => ColorChess::White,
+        "b" => ColorChess::Black,
+        other => return Err(format!("side to move must be 'w' or 'b', got '{}'", other)),
+    };
+
+    let castling = fields[2];
+    if castling != "-" && !castling.chars().all(|c| "KQkq".contains(c)) {
+        return Err(format!("invalid castling rights '{}'", castling));
+    }
+
+    board.en_passant_target = match fields[3] {
+        "-" => None,
+        square => Some(
+            board
+                .parse_move(square)
+                .map_err(|_| format!("invalid en passant square '{}'", square))?,
+        ),
+    };
+
+    let number = |index: usize, default: u32| match fields.get(index) {
+        Some(text) => text
+            .parse()
+            .map_err(|_| format!("invalid move counter '{}'", text)),
+        None => Ok(default),
+    };
+    board.halfmove_clock = number(4, 0)?;
+    board.fullmove_number = number(5, 1)?.max(1);
+
+    let mut problems = piece_problems(&board);
+    problems.extend(castling_problems(&board, castling));
+    problems.extend(en_passant_problems(&board));
+
+    // Only the rights the pieces still allow are kept
+    let right = |letter: char, row: usize, col: usize, color: ColorChess| {
+        let home = |square: (usize, usize), piece_type| {
+            board.squares[square.0][square.1] == Some(Piece::new(piece_type, color))
+        };
+        castling.contains(letter)
+            && home((row, 4), PieceType::King)
+            && home((row, col), PieceType::Rook)
+    };
+    let white_king_side = right('K', 0, 7, ColorChess::White);
+    let white_queen_side = right('Q', 0, 0, ColorChess::White);
+    let black_king_side = right('k', 7, 7, ColorChess::Black);
+    let black_queen_side = right('q', 7, 0, ColorChess::Black);
+    board.white_rook_king_side_moved = !white_king_side;
+    board.white_rook_queen_side_moved = !white_queen_side;
+    board.black_rook_king_side_moved = !black_king_side;
+    board.black_rook_queen_side_moved = !black_queen_side;
+    board.white_king_moved = !(white_king_side || white_queen_side);
+    board.black_king_moved = !(black_king_side || black_queen_side);
+
+    board.compute_bitboards();
+    board.hash = board.compute_hash();
+    // Checks are only looked at with the kings in order
+    if problems.is_empty() {
+        problems.extend(check_problems(&board));
+    }
+    Ok((board, problems))
+}
+
+// Kings, pawns on the edge ranks and promoted pieces
+fn piece_problems(board: &Position) -> Vec<String> {
+    let mut problems = Vec::new();
+    let pieces = || {
+        (0..8)
+            .flat_map(|row| (0..8).map(move |col| (row, col)))
+            .filter_map(|square| board.squares[square.0][square.1].map(|piece| (square, piece)))
+    };
+    for color in [ColorChess::White, ColorChess::Black] {
+        let count = |piece_type| {
+            pieces()
+                .filter(|(_, p)| p.is_type(piece_type) && p.is_color(color))
+                .count()
+        };
+        let kings = count(PieceType::King);
+        if kings != 1 {
+            problems.push(format!(
+                "{:?} has {} kings, and must have exactly one",
+                color, kings
+            ));
+        }
+        // Every piece beyond the starting set must have been a pawn once,
+        // which also keeps the number of moves within MAX_MOVES
+        let promoted = count(PieceType::Queen).saturating_sub(1)
+            + count(PieceType::Rook).saturating_sub(2)
+            + count(PieceType::Bishop).saturating_sub(2)
+            + count(PieceType::Knight).saturating_sub(2);
+        if count(PieceType::Pawn) + promoted > 8 {
+            problems.push(format!("{:?} has more pieces than promotions allow", color));
+        }
+    }
+    for (square, piece) in pieces() {
+        if piece.is_type(PieceType::Pawn) && (square.0 == 0 || square.0 == 7) {
+            problems.push(format!(
+                "{:?} pawn on {}, but pawns cannot stand on the first or last rank",
+                piece.color(),
+                square_name(square)
+            ));
+        }
+    }
+    problems
+}
+
+// Each castling right needs its king and rook on their starting squares
+fn castling_problems(board: &Position, castling: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    for letter in "KQkq".chars().filter(|&c| castling.contains(c)) {
+        if castling.matches(letter).count() > 1 {
+            problems.push(format!("castling right {} is given twice", letter));
+        }
+        let (color, row) = if letter.is_ascii_uppercase() {
+            (ColorChess::White, 0)
+        } else {
+            (ColorChess::Black, 7)
+        };
+        let col = if letter.eq_ignore_ascii_case(&'K') {
+            7
+        } else {
+            0
+        };
+        let home =
+            |col: usize, piece_type| board.squares[row][col] == Some(Piece::new(piece_type, color));
+        if !home(4, PieceType::King) {
+            problems.push(format!(
+                "castling right {} needs the {:?} king on {}",
+                letter,
+                color,
+                square_name((row, 4))
+            ));
+        } else if !home(col, PieceType::Rook) {
+            problems.push(format!(
+                "castling right {} needs a {:?} rook on {}",
+                letter,
+                color,
+                square_name((row, col))
+            ));
+        }
+    }
+    problems
+}
+
+// An en passant square is the one a pawn of the side that just moved
+// skipped with its double step: behind that pawn, with nothing on it or on
+// the square the pawn came from
+fn en_passant_problems(board: &Position) -> Vec<String> {
+    let Some(target) = board.en_passant_target else {
+        return Vec::new();
+    };
+    let mover = board.current_turn.opposite();
+    // Rows of the skipped square, the pawn and the square it left
+    let (skipped, pawn, from) = match mover {
+        ColorChess::White => (2, 3, 1),
+        ColorChess::Black => (5, 4, 6),
+    };
+    let name = square_name(target);
+    if target.0 != skipped {
+        return vec![format!(
+            "en passant square {} must be on rank {} with {:?} to move",
+            name,
+            skipped + 1,
+            board.current_turn
+        )];
+    }
+    let mut problems = Vec::new();
+    if board.squares[pawn][target.1] != Some(Piece::new(PieceType::Pawn, mover)) {
+        problems.push(format!(
+            "en passant square {} needs a {:?} pawn on {}",
+            name,
+            mover,
+            square_name((pawn, target.1))
+        ));
+    }
+    for row in [skipped, from] {
+        if board.squares[row][target.1].is_some() {
+            problems.push(format!(
+                "en passant square {} needs {} empty, as the pawn just passed it",
+                name,
+                square_name((row, target.1))
+            ));
+        }
+    }
+    if board.halfmove_clock != 0 {
+        problems.push(format!(
+            "en passant square {} needs a halfmove clock of 0 after the pawn move, not {}",
+            name, board.halfmove_clock
+        ));
+    }
+    problems
+}
+
+// The side that just moved cannot have left its king in check, and no move
+// gives check with more than two pieces at once
+fn check_problems(board: &Position) -> Vec<String> {
+    let mover = board.current_turn.opposite();
+    if board.is_in_check(mover) {
+        return vec![format!(
+            "{:?} is in check but it is {:?} to move",
+            mover, board.current_turn
+        )];
+    }
+    let to_move = board.current_turn;
+    let checkers = board
+        .find_king(to_move)
+        .map_or(0, |king| board.attack_table().count(mover, king));
+    if checkers > 2 {
+        return vec![format!(
+            "{:?} is in check from {} pieces, but a move can give check with two at most",
+            to_move, checkers
+        )];
+    }
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The one problem found with a FEN
+    fn problem(fen: &str) -> String {
+        let problems = validate_fen(fen);
+        assert_eq!(problems.len(), 1, "{:?}", problems);
+        problems[0].clone()
+    }
+
+    #[test]
+    fn reads_and_writes_fen() {
+        let fens = [
+            START_FEN,
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 b - - 12 40",
+        ];
+        for fen in fens {
+            assert!(validate_fen(fen).is_empty(), "{}", fen);
+            assert_eq!(Position::from_fen(fen).unwrap().to_fen(), fen);
+        }
+        // The move counters may be left off
+        let short = Position::from_fen("8/8/8/8/8/8/8/K6k w - -").unwrap();
+        assert_eq!(short.to_fen(), "8/8/8/8/8/8/8/K6k w - - 0 1");
+    }
+
+    #[test]
+    fn refuses_fen_it_cannot_read() {
+        for fen in [
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w KQkq -",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -",
+            "rnbqkbnr/ppppxppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq -",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x KQkq -",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQxq -",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq z9",
+        ] {
+            assert_eq!(validate_fen(fen).len(), 1, "{}", fen);
+            assert!(Position::from_fen(fen).is_err(), "{}", fen);
+        }
+    }
+
+    #[test]
+    fn refuses_positions_no_game_reaches() {
+        assert!(problem("8/8/8/8/8/8/8/K7 w - - 0 1").contains("Black has 0 kings"));
+        assert!(problem("k7/8/8/8/8/8/8/K6P w - - 0 1").contains("pawn on h1"));
+        assert!(
+            problem("k7/8/8/8/8/8/PPPPPPPP/KQQ5 w - - 0 1").contains("more pieces than promotions")
+        );
+        assert!(problem("k7/8/8/8/8/8/8/K6R w K - 0 1").contains("needs the White king on e1"));
+        assert!(problem("k7/8/8/8/8/8/8/4K3 w K - 0 1").contains("needs a White rook on h1"));
+        assert!(problem("k7/8/8/8/8/8/4P3/K7 w - e3 0 1").contains("must be on rank 6 with White"));
+        assert!(problem("k7/8/8/8/4P3/8/8/K7 b - e3 5 1").contains("halfmove clock of 0"));
+        assert!(problem("k6R/8/8/8/8/8/8/K7 w - - 0 1").contains("Black is in check"));
+        assert!(problem("4k3/8/3N4/1B6/8/8/8/K3R3 b - - 0 1").contains("check from 3 pieces"));
+        assert!(Position::from_fen("k6R/8/8/8/8/8/8/K7 w - - 0 1").is_err());
+    }
+}
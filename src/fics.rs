@@ -0,0 +1,447 @@
+use std::{
+    io::{Read, Write},
+    net::TcpStream,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::Duration,
+};
+
+use chess_rs::clock::{BonusMode, TimeControl};
+use chess_rs::{ColorChess, GameResult, Move, Termination};
+
+use crate::config::FicsConfig;
+use crate::events::EventSender;
+
+const FICS_ADDRESS: &str = "freechess.org:5000";
+
+// Logs in as an anonymous guest, who can only play unrated games
+const GUEST: &str = "guest";
+
+// Prompt the server puts in front of its output once logged in
+const PROMPT: &str = "fics% ";
+
+// Telnet "interpret as command" byte; FICS sends a few telnet options, which
+// are skipped
+const IAC: u8 = 255;
+
+// Settings sent after logging in: style 12 board updates, no line wrapping,
+// and none of the chatter meant for interactive users
+const SETUP_COMMANDS: [&str; 6] = [
+    "set style 12",
+    "iset nowrap 1",
+    "set seek 0",
+    "set shout 0",
+    "set cshout 0",
+    "set bell 0",
+];
+
+// What the connection to FICS reports back to the app
+pub enum FicsEvent {
+    // Logged in; the handle the server gave the player
+    Connected(String),
+    // The position and clocks of a game after a move
+    Game(Style12),
+    // A game ended, by its number, with the server's reason and the result
+    // (none if aborted or adjourned)
+    GameEnd {
+        game: u32,
+        ending: String,
+        result: Option<GameResult>,
+    },
+    // The opponent offers a draw
+    DrawOffer,
+    Error(String),
+}
+
+// A "style 12" board update, FICS's one-line description of a game
+pub struct Style12 {
+    pub game: u32,
+    pub fen: String,
+    pub white: String,
+    pub black: String,
+    // 1 or -1 when the player is in the game and on move or not; other
+    // values are for watching and examining games
+    relation: i32,
+    pub control: TimeControl,
+    // White's and Black's time left
+    pub times: [Duration; 2],
+    // The latest move in SAN, e.g. "Nf3"
+    pub last_move: Option<String>,
+}
+
+impl Style12 {
+    // Reads a line such as "<12> rnbqkbnr pppppppp -------- ... W -1 1 1 1 1 0
+    // 7 GuestA GuestB 1 5 0 39 39 300 300 1 none (0:00) none 0 0 0"
+    pub fn parse(line: &str) -> Option<Style12> {
+        let fields: Vec<&str> = line.strip_prefix("<12> ")?.split_whitespace().collect();
+        if fields.len() < 29 {
+            return None;
+        }
+        let mut placement = Vec::new();
+        for rank in &fields[..8] {
+            let mut text = String::new();
+            let mut empty = 0;
+            for square in rank.chars() {
+                if square == '-' {
+                    empty += 1;
+                    continue;
+                }
+                if empty > 0 {
+                    text.push_str(&empty.to_string());
+                    empty = 0;
+                }
+                text.push(square);
+            }
+            if empty > 0 {
+                text.push_str(&empty.to_string());
+            }
+            placement.push(text);
+        }
+        let white_to_move = fields[8] == "W";
+        // Flags for castling short and long, White's then Black's
+        let mut castling: String = [(10, 'K'), (11, 'Q'), (12, 'k'), (13, 'q')]
+            .into_iter()
+            .filter(|&(field, _)| fields[field] == "1")
+            .map(|(_, letter)| letter)
+            .collect();
+        if castling.is_empty() {
+            castling.push('-');
+        }
+        // The file of a pawn that just moved two squares, or -1
+        let en_passant = match fields[9].parse::<u8>() {
+            Ok(file) if file < 8 => {
+                let rank = if white_to_move { '6' } else { '3' };
+                format!("{}{}", (b'a' + file) as char, rank)
+            }
+            _ => "-".to_string(),
+        };
+        let fen = format!(
+            "{} {} {} {} {} {}",
+            placement.join("/"),
+            if white_to_move { 'w' } else { 'b' },
+            castling,
+            en_passant,
+            fields[14],
+            fields[25]
+        );
+        let seconds =
+            |field: &str| Duration::from_secs(field.parse::<i64>().unwrap_or(0).max(0) as u64);
+        Some(Style12 {
+            game: fields[15].parse().ok()?,
+            fen,
+            white: fields[16].to_string(),
+            black: fields[17].to_string(),
+            relation: fields[18].parse().ok()?,
+            control: TimeControl {
+                base: seconds(fields[19]) * 60,
+                bonus: seconds(fields[20]),
+                mode: BonusMode::Increment,
+            },
+            times: [seconds(fields[23]), seconds(fields[24])],
+            last_move: Some(fields[28].to_string()).filter(|mv| mv != "none"),
+        })
+    }
+
+    // Whether the player is one of the sides, rather than watching
+    pub fn is_playing(&self) -> bool {
+        matches!(self.relation, 1 | -1)
+    }
+
+    // The player's side, when playing
+    pub fn color(&self) -> ColorChess {
+        let to_move = if self.fen.split(' ').nth(1) == Some("w") {
+            ColorChess::White
+        } else {
+            ColorChess::Black
+        };
+        if self.relation == 1 {
+            to_move
+        } else {
+            to_move.opposite()
+        }
+    }
+}
+
+// A telnet connection to the Free Internet Chess Server. The server is read
+// on its own thread, which reports back to the main loop as events
+pub struct Fics {
+    commands: Sender<String>,
+    // Only registered players can play rated games
+    registered: bool,
+    pub username: Option<String>,
+}
+
+impl Fics {
+    // Connects and logs in, as a guest unless the config has an account
+    pub fn connect(config: &FicsConfig, sender: EventSender<FicsEvent>) -> Fics {
+        let (commands, command_rx) = mpsc::channel();
+        let username = config.username.clone().unwrap_or_else(|| GUEST.to_string());
+        let registered = !username.eq_ignore_ascii_case(GUEST);
+        let password = config.password.clone().unwrap_or_default();
+        let replies = commands.clone();
+        thread::spawn(move || {
+            if let Err(e) = run(&username, &password, &replies, command_rx, &sender) {
+                let _ = sender.send(FicsEvent::Error(e));
+            }
+        });
+        Fics {
+            commands,
+            registered,
+            username: None,
+        }
+    }
+
+    // Posts a seek; a game starts with the first board update
+    pub fn seek(&self, control: TimeControl) {
+        let rated = if self.registered { "r" } else { "u" };
+        self.send(format!(
+            "seek {} {} {}",
+            control.base.as_secs() / 60,
+            control.bonus.as_secs(),
+            rated
+        ));
+    }
+
+    // Moves go in coordinates, with any promotion after '=', e.g. "e7e8=q"
+    pub fn play_move(&self, mv: Move) {
+        let uci = mv.to_uci();
+        let (squares, promotion) = uci.split_at(4);
+        self.send(if promotion.is_empty() {
+            uci.clone()
+        } else {
+            format!("{}={}", squares, promotion)
+        });
+    }
+
+    pub fn resign(&self) {
+        self.send("resign".to_string());
+    }
+
+    // Offers or accepts a draw (true), or declines one (false)
+    pub fn draw(&self, accept: bool) {
+        self.send(if accept { "draw" } else { "decline" }.to_string());
+    }
+
+    fn send(&self, command: String) {
+        let _ = self.commands.send(command);
+    }
+}
+
+// Logs in and turns the server's output into events until the connection drops
+fn run(
+    username: &str,
+    password: &str,
+    replies: &Sender<String>,
+    commands: Receiver<String>,
+    sender: &EventSender<FicsEvent>,
+) -> Result<(), String> {
+    let mut stream = TcpStream::connect(FICS_ADDRESS).map_err(|e| e.to_string())?;
+    let mut writer = stream.try_clone().map_err(|e| e.to_string())?;
+    thread::spawn(move || {
+        for command in commands {
+            if writer
+                .write_all(format!("{}\n", command).as_bytes())
+                .is_err()
+            {
+                break;
+            }
+        }
+    });
+
+    let mut pending = Vec::new();
+    let mut chunk = [0; 4096];
+    loop {
+        let read = stream.read(&mut chunk).map_err(|e| e.to_string())?;
+        if read == 0 {
+            return Err("the server closed the connection".to_string());
+        }
+        let mut bytes = chunk[..read].iter().copied();
+        while let Some(byte) = bytes.next() {
+            if byte == IAC {
+                // A telnet command and its option
+                bytes.nth(1);
+            } else {
+                pending.push(byte);
+            }
+        }
+
+        while let Some(end) = pending.iter().position(|&byte| byte == b'\n') {
+            let line: Vec<u8> = pending.drain(..=end).collect();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end().trim_start_matches(PROMPT);
+            if let Some(event) = server_event(line, replies)?
+                && sender.send(event).is_err()
+            {
+                return Ok(());
+            }
+        }
+
+        // Login prompts wait on the same line for an answer
+        let prompt = String::from_utf8_lossy(&pending).trim_end().to_string();
+        if prompt.ends_with("login:") {
+            let _ = replies.send(username.to_string());
+        } else if prompt.ends_with("password:") {
+            let _ = replies.send(password.to_string());
+        } else if prompt.starts_with("Press return to enter the server") {
+            let _ = replies.send(String::new());
+        } else {
+            continue;
+        }
+        pending.clear();
+    }
+}
+
+// Makes sense of a line from the server; most are chatter to be ignored
+fn server_event(line: &str, replies: &Sender<String>) -> Result<Option<FicsEvent>, String> {
+    if let Some(rest) = line.strip_prefix("**** Starting FICS session as ") {
+        // Guests come with "(U)" after their handle
+        let handle: String = rest
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect();
+        for command in SETUP_COMMANDS {
+            let _ = replies.send(command.to_string());
+        }
+        return Ok(Some(FicsEvent::Connected(handle)));
+    }
+    if line.contains("**** Invalid password! ****") {
+        return Err("the server rejected the password".to_string());
+    }
+    if line.starts_with("<12> ") {
+        return Ok(Style12::parse(line).map(FicsEvent::Game));
+    }
+    if line.contains("offers you a draw") {
+        return Ok(Some(FicsEvent::DrawOffer));
+    }
+    Ok(game_end(line))
+}
+
+// Reads "{Game 42 (GuestA vs GuestB) GuestA resigns} 0-1"; the same braces
+// without a result announce other things, such as a game being created
+fn game_end(line: &str) -> Option<FicsEvent> {
+    let rest = line.strip_prefix("{Game ")?;
+    let (number, rest) = rest.split_once(' ')?;
+    let (_, rest) = rest.split_once(") ")?;
+    let (ending, result) = rest.split_once("} ")?;
+    let result = match result.trim() {
+        "*" => None,
+        tag => Some(GameResult::from_pgn_tag(tag, termination(ending))?),
+    };
+    Some(FicsEvent::GameEnd {
+        game: number.parse().ok()?,
+        ending: format!("{}.", ending.trim_end_matches('.')),
+        result,
+    })
+}
+
+// The reason in the words FICS ends a game with, e.g. "GuestA forfeits on time"
+fn termination(ending: &str) -> Termination {
+    let ending = ending.to_ascii_lowercase();
+    let reasons = [
+        ("checkmated", Termination::Checkmate),
+        ("resigns", Termination::Resignation),
+        ("on time", Termination::Timeout),
+        ("out of time", Termination::Timeout),
+        ("agreement", Termination::Agreement),
+        ("repetition", Termination::Repetition),
+        ("50 move", Termination::FiftyMoves),
+        ("mating material", Termination::InsufficientMaterial),
+        ("stalemate", Termination::Stalemate),
+        ("disconnection", Termination::Abandoned),
+        ("adjudicat", Termination::Adjudication),
+    ];
+    reasons
+        .into_iter()
+        .find(|(words, _)| ending.contains(words))
+        .map_or(Termination::Unknown, |(_, reason)| reason)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // White has played 1. e4 in a 5+3 game; the player is White, waiting
+    const AFTER_E4: &str = "<12> rnbqkbnr pppppppp -------- -------- ----P--- -------- \
+        PPPP-PPP RNBQKBNR B 4 1 1 1 1 0 7 GuestA GuestB -1 5 3 39 39 297 300 1 \
+        P/e2-e4 (0:03) e4 0 0 0";
+
+    #[test]
+    fn reads_a_style12_board() {
+        let board = Style12::parse(AFTER_E4).unwrap();
+        assert_eq!(board.game, 7);
+        assert_eq!(
+            board.fen,
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1"
+        );
+        assert_eq!(
+            (board.white.as_str(), board.black.as_str()),
+            ("GuestA", "GuestB")
+        );
+        assert!(board.is_playing());
+        assert_eq!(board.color(), ColorChess::White);
+        assert_eq!(board.control.to_string(), "5+3");
+        assert_eq!(
+            board.times,
+            [Duration::from_secs(297), Duration::from_secs(300)]
+        );
+        assert_eq!(board.last_move.as_deref(), Some("e4"));
+    }
+
+    #[test]
+    fn reads_castling_rights_and_the_start_of_a_game() {
+        let line = "<12> rnbqkbnr pppppppp -------- -------- -------- -------- PPPPPPPP \
+            RNBQKBNR W -1 0 1 1 0 0 12 GuestA GuestB 1 3 0 39 39 180 180 1 none (0:00) none \
+            0 0 0";
+        let board = Style12::parse(line).unwrap();
+        assert_eq!(
+            board.fen,
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w Qk - 0 1"
+        );
+        assert_eq!(board.color(), ColorChess::White);
+        assert_eq!(board.last_move, None);
+        // Watching someone else's game
+        let watching = line.replace(" GuestB 1 ", " GuestB 0 ");
+        assert!(!Style12::parse(&watching).unwrap().is_playing());
+    }
+
+    #[test]
+    fn refuses_lines_that_are_not_boards() {
+        assert!(Style12::parse("fics% ").is_none());
+        assert!(Style12::parse("<12> rnbqkbnr pppppppp W").is_none());
+        let bad_number = AFTER_E4.replace(" 7 GuestA", " x GuestA");
+        assert!(Style12::parse(&bad_number).is_none());
+    }
+
+    #[test]
+    fn reads_how_games_end() {
+        let ending = |line| match game_end(line) {
+            Some(FicsEvent::GameEnd {
+                game,
+                ending,
+                result,
+            }) => (game, ending, result),
+            _ => panic!("no game end in {:?}", line),
+        };
+        assert_eq!(
+            ending("{Game 42 (GuestA vs GuestB) GuestA resigns} 0-1"),
+            (
+                42,
+                "GuestA resigns.".to_string(),
+                Some(GameResult::win_for(
+                    ColorChess::Black,
+                    Termination::Resignation
+                ))
+            )
+        );
+        let (_, _, result) = ending("{Game 3 (a vs b) b forfeits on time} 1-0");
+        assert_eq!(result.unwrap().reason, Termination::Timeout);
+        let (_, _, result) = ending("{Game 3 (a vs b) Game drawn by repetition} 1/2-1/2");
+        assert_eq!(result, Some(GameResult::draw(Termination::Repetition)));
+        let (_, ending, result) = ending("{Game 3 (a vs b) Game adjourned by mutual agreement} *");
+        assert_eq!(
+            (ending.as_str(), result),
+            ("Game adjourned by mutual agreement.", None)
+        );
+        assert!(game_end("{Game 3 (a vs b) Creating unrated blitz match.}").is_none());
+    }
+}
@@ -0,0 +1,287 @@
+use serde::{Deserialize, Serialize};
+
+use crate::board::{ColorChess, square_name};
+use crate::error::ChessError;
+use crate::fen::parse_fen;
+use crate::game::Game;
+use crate::moves::Move;
+use crate::position::Position;
+use crate::rules::{GameResult, Termination};
+
+// Port a fog-of-war game server listens on unless told otherwise
+pub const DEFAULT_PORT: u16 = 7878;
+
+// What one player of a fog-of-war game is shown. The server keeps the real
+// position and sends each player only this, so nothing in the fog ever
+// reaches the other side's program
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub struct FogView {
+    // The position with only the pieces the player can see, so it may lack
+    // the opponent's king, and only the player's own castling rights
+    pub fen: String,
+    // The squares in the fog, e.g. "e7"
+    pub hidden: Vec<String>,
+    // The player's moves in UCI when it is on move, as moves() finds them;
+    // with no check to answer, they depend only on what the player can see
+    pub moves: Vec<String>,
+}
+
+// A line from the server to a player, as JSON
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ServerMessage {
+    // Both players are in and the game begins, the player on `color`
+    Start {
+        color: ColorChess,
+    },
+    // The player's view, at the start and after every move
+    View(FogView),
+    // The move sent is not one of the player's legal moves
+    Illegal {
+        uci: String,
+    },
+    // The game is over: the result as in PGN, why, and the whole position
+    End {
+        result: String,
+        reason: Termination,
+        fen: String,
+    },
+}
+
+// A line from a player to the server, as JSON
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ClientMessage {
+    Move { uci: String },
+    Resign,
+}
+
+impl FogView {
+    // What `color` sees of the position
+    pub fn new(position: &Position, color: ColorChess) -> FogView {
+        let visible = position.visible_squares(color);
+        let mut ranks = Vec::new();
+        let mut hidden = Vec::new();
+        for row in (0..8).rev() {
+            let mut rank = String::new();
+            let mut empty = 0;
+            for (col, &seen) in visible[row].iter().enumerate() {
+                if !seen {
+                    hidden.push(square_name((row, col)));
+                }
+                match position.get_piece((row, col)).filter(|_| seen) {
+                    Some(piece) => {
+                        if empty > 0 {
+                            rank.push_str(&empty.to_string());
+                            empty = 0;
+                        }
+                        rank.push(piece.to_fen_char());
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                rank.push_str(&empty.to_string());
+            }
+            ranks.push(rank);
+        }
+        let fen = position.to_fen();
+        let mut fields: Vec<&str> = fen.split(' ').collect();
+        let placement = ranks.join("/");
+        fields[0] = &placement;
+        // The opponent's castling rights, and an en passant square its pawns
+        // could take on, give away where its pieces are
+        let own: String = fields[2]
+            .chars()
+            .filter(|&right| right.is_ascii_uppercase() == (color == ColorChess::White))
+            .collect();
+        fields[2] = if own.is_empty() { "-" } else { &own };
+        let on_move = position.get_current_turn() == color;
+        if !on_move {
+            fields[3] = "-";
+        }
+        let moves = if on_move {
+            moves(position).iter().map(Move::to_uci).collect()
+        } else {
+            Vec::new()
+        };
+        FogView {
+            fen: fields.join(" "),
+            hidden,
+            moves,
+        }
+    }
+
+    // The position as the player sees it. It need not be one that could come
+    // about in a game, so it is taken as it is
+    pub fn board(&self) -> Result<Game, ChessError> {
+        let (position, _) = parse_fen(&self.fen).map_err(ChessError::BadFen)?;
+        Ok(Game::from(position))
+    }
+
+    // The squares the player can see, by [row][col]
+    pub fn visible(&self) -> [[bool; 8]; 8] {
+        let mut visible = [[true; 8]; 8];
+        for (row, squares) in visible.iter_mut().enumerate() {
+            for (col, square) in squares.iter_mut().enumerate() {
+                *square = !self.hidden.contains(&square_name((row, col)));
+            }
+        }
+        visible
+    }
+
+    // The player's moves, as played on `board`, the position of the view
+    pub fn moves_on(&self, board: &Position) -> Vec<Move> {
+        self.moves
+            .iter()
+            .filter_map(|uci| board.build_uci_move(uci).ok())
+            .collect()
+    }
+}
+
+// The moves of the side to move in fog-of-war chess played over the network,
+// which follows dark chess: there is no check, so a king may step into an
+// attack or stay in one, and castling only needs the king and rook unmoved
+// and the squares between them empty
+pub fn moves(position: &Position) -> Vec<Move> {
+    let color = position.get_current_turn();
+    let mut moves: Vec<Move> = position
+        .get_all_moves(color)
+        .into_iter()
+        .filter(|mv| !mv.is_castling())
+        .collect();
+    if let Some(king) = position.find_king(color) {
+        for to in [(king.0, 6), (king.0, 2)] {
+            if position.castling_path(king, to, color).is_some() {
+                moves.push(position.classify_move(king, to));
+            }
+        }
+    }
+    moves
+}
+
+// How a fog-of-war game over the network stands after a move: won by taking
+// the king, and drawn when the side to move has no move, by threefold
+// repetition or by the fifty-move rule. With no check, any material can
+// still win, as a king may walk into a capture
+pub fn result(game: &Game) -> Option<GameResult> {
+    let to_move = game.get_current_turn();
+    if !game.has_king(to_move) {
+        Some(GameResult::win_for(
+            to_move.opposite(),
+            Termination::KingCaptured,
+        ))
+    } else if moves(game).is_empty() {
+        Some(GameResult::draw(Termination::Stalemate))
+    } else if game.is_threefold_repetition() {
+        Some(GameResult::draw(Termination::Repetition))
+    } else if game.get_halfmove_clock() >= 100 {
+        Some(GameResult::draw(Termination::FiftyMoves))
+    } else {
+        None
+    }
+}
+
+impl ServerMessage {
+    // The message that ends the game with its result
+    pub fn end(result: GameResult, position: &Position) -> ServerMessage {
+        ServerMessage::End {
+            result: result.pgn_tag().to_string(),
+            reason: result.reason,
+            fen: position.to_fen(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_view_leaves_out_what_the_fog_hides() {
+        let game = Game::new();
+        let view = FogView::new(&game, ColorChess::White);
+        // White sees its own two ranks and the two its pawns reach
+        assert_eq!(view.fen, "8/8/8/8/8/8/PPPPPPPP/RNBQKBNR w KQ - 0 1");
+        assert_eq!(view.hidden.len(), 32);
+        assert!(view.hidden.contains(&"e7".to_string()));
+        assert_eq!(view.moves.len(), 20);
+        // Black is not on move, so it gets no moves
+        let view = FogView::new(&game, ColorChess::Black);
+        assert_eq!(view.fen, "rnbqkbnr/pppppppp/8/8/8/8/8/8 w kq - 0 1");
+        assert!(view.moves.is_empty());
+    }
+
+    #[test]
+    fn the_views_position_plays_the_moves_sent() {
+        let game = Game::from_fen("4k3/8/8/8/8/8/4P3/R3K2R w KQ - 0 1").unwrap();
+        let view = FogView::new(&game, ColorChess::White);
+        let board = view.board().unwrap();
+        let moves = view.moves_on(&board);
+        assert_eq!(moves.len(), view.moves.len());
+        let castle = moves.iter().find(|mv| mv.to_uci() == "e1g1").unwrap();
+        assert!(castle.is_castling());
+        // The black king is out of sight
+        assert!(!view.visible()[7][4]);
+        assert_eq!(board.get_piece((7, 4)), None);
+    }
+
+    #[test]
+    fn there_is_no_check() {
+        // The white king is in check from the rook and castles through the
+        // bishop's diagonal; both are out of sight
+        let game = Game::from_fen("4k3/8/8/8/b7/8/8/r3K2R w K - 0 1").unwrap();
+        let moves: Vec<String> = moves(&game).iter().map(Move::to_uci).collect();
+        assert!(moves.contains(&"h1h2".to_string()));
+        assert!(moves.contains(&"e1g1".to_string()));
+        assert!(moves.contains(&"e1d1".to_string()));
+        let view = FogView::new(&game, ColorChess::White);
+        assert_eq!(view.moves.len(), moves.len());
+        assert_eq!(result(&game), None);
+    }
+
+    #[test]
+    fn taking_the_king_wins() {
+        let mut game = Game::from_fen("4k3/8/8/8/8/8/r7/4K3 w - - 0 1").unwrap();
+        // The king may walk into the rook's rank, and is taken there
+        for uci in ["e1e2", "a2e2"] {
+            let mv = moves(&game).into_iter().find(|mv| mv.to_uci() == uci);
+            game.make(mv.unwrap()).unwrap();
+        }
+        assert_eq!(
+            result(&game),
+            Some(GameResult::win_for(
+                ColorChess::Black,
+                Termination::KingCaptured
+            ))
+        );
+    }
+
+    #[test]
+    fn messages_are_read_back_as_sent() {
+        let view = FogView::new(&Game::new(), ColorChess::White);
+        for message in [
+            ServerMessage::Start {
+                color: ColorChess::Black,
+            },
+            ServerMessage::View(view),
+            ServerMessage::end(
+                GameResult::win_for(ColorChess::White, Termination::Resignation),
+                &Game::new(),
+            ),
+        ] {
+            let line = serde_json::to_string(&message).unwrap();
+            assert_eq!(
+                serde_json::from_str::<ServerMessage>(&line).unwrap(),
+                message
+            );
+        }
+        let line = r#"{"type":"move","uci":"e2e4"}"#;
+        assert_eq!(
+            serde_json::from_str::<ClientMessage>(line).unwrap(),
+            ClientMessage::Move {
+                uci: "e2e4".to_string()
+            }
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::TcpStream,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+};
+
+use chess_rs::Move;
+use chess_rs::fog::{ClientMessage, DEFAULT_PORT, ServerMessage};
+
+use crate::events::EventSender;
+
+// What the connection to a fog-of-war server reports back to the app
+pub enum FogEvent {
+    // Connected; the game starts once the other player is in as well
+    Connected,
+    Message(ServerMessage),
+    Error(String),
+}
+
+// A connection to a game started with `chess-rs fog-server`. The server is
+// read on its own thread, which reports back to the main loop as events
+pub struct FogClient {
+    commands: Sender<ClientMessage>,
+    pub address: String,
+}
+
+impl FogClient {
+    // Connects to "host:port", or to the default port when only a host is given
+    pub fn connect(address: &str, sender: EventSender<FogEvent>) -> FogClient {
+        let address = if address.contains(':') {
+            address.to_string()
+        } else {
+            format!("{}:{}", address, DEFAULT_PORT)
+        };
+        let (commands, command_rx) = mpsc::channel();
+        let server = address.clone();
+        thread::spawn(move || {
+            if let Err(e) = run(&server, command_rx, &sender) {
+                let _ = sender.send(FogEvent::Error(e));
+            }
+        });
+        FogClient { commands, address }
+    }
+
+    pub fn play_move(&self, mv: Move) {
+        let _ = self.commands.send(ClientMessage::Move { uci: mv.to_uci() });
+    }
+
+    pub fn resign(&self) {
+        let _ = self.commands.send(ClientMessage::Resign);
+    }
+}
+
+// Sends the player's messages and turns the server's lines into events until
+// the connection drops
+fn run(
+    address: &str,
+    commands: Receiver<ClientMessage>,
+    sender: &EventSender<FogEvent>,
+) -> Result<(), String> {
+    let stream = TcpStream::connect(address).map_err(|e| format!("{}: {}", address, e))?;
+    let mut writer = stream.try_clone().map_err(|e| e.to_string())?;
+    thread::spawn(move || {
+        for command in commands {
+            let Ok(line) = serde_json::to_string(&command) else {
+                continue;
+            };
+            if writeln!(writer, "{}", line).is_err() {
+                break;
+            }
+        }
+    });
+    if sender.send(FogEvent::Connected).is_err() {
+        return Ok(());
+    }
+    for line in BufReader::new(stream).lines() {
+        let line = line.map_err(|e| e.to_string())?;
+        let message = serde_json::from_str(&line)
+            .map_err(|e| format!("the server sent something unreadable: {}", e))?;
+        if sender.send(FogEvent::Message(message)).is_err() {
+            return Ok(());
+        }
+    }
+    Err("the server closed the connection".to_string())
+}
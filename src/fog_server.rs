@@ -0,0 +1,107 @@
+use std::{
+    io::{BufRead, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::mpsc::{self, Sender},
+    thread,
+};
+
+use chess_rs::fog::{self, ClientMessage, FogView, ServerMessage};
+use chess_rs::{ColorChess, Game, GameResult, Termination};
+
+// A message from a player, or None once its connection has closed
+type Received = (ColorChess, Option<ClientMessage>);
+
+// Hosts a fog-of-war game between the first two players to connect, White
+// then Black. The real position stays here and each player is only sent what
+// its pieces can see, so neither program has anything in the fog to give away.
+// The game is played by the rules of dark chess, in fog::moves and fog::result
+pub fn run(host: &str, port: u16) -> Result<(), String> {
+    let listener =
+        TcpListener::bind((host, port)).map_err(|e| format!("{}:{}: {}", host, port, e))?;
+    println!("Waiting for two players on {}:{}...", host, port);
+    let (sender, received) = mpsc::channel();
+    let mut players = Vec::new();
+    for color in [ColorChess::White, ColorChess::Black] {
+        let (stream, address) = listener.accept().map_err(|e| e.to_string())?;
+        println!("{} joined as {:?}.", address, color);
+        let reader = stream.try_clone().map_err(|e| e.to_string())?;
+        let sender = sender.clone();
+        thread::spawn(move || read_player(color, reader, &sender));
+        players.push(stream);
+    }
+
+    let mut game = Game::new();
+    for (color, player) in [ColorChess::White, ColorChess::Black]
+        .into_iter()
+        .zip(&mut players)
+    {
+        send(player, &ServerMessage::Start { color });
+        send(player, &ServerMessage::View(FogView::new(&game, color)));
+    }
+    let result = loop {
+        let (color, message) = received.recv().map_err(|e| e.to_string())?;
+        let player = &mut players[index(color)];
+        let uci = match message {
+            Some(ClientMessage::Move { uci }) => uci,
+            Some(ClientMessage::Resign) => {
+                break GameResult::win_for(color.opposite(), Termination::Resignation);
+            }
+            None => break GameResult::win_for(color.opposite(), Termination::Abandoned),
+        };
+        let mv = fog::moves(&game)
+            .into_iter()
+            .find(|mv| mv.to_uci() == uci)
+            .filter(|_| game.get_current_turn() == color);
+        if mv.is_none_or(|mv| game.make(mv).is_err()) {
+            send(player, &ServerMessage::Illegal { uci });
+            send(player, &ServerMessage::View(FogView::new(&game, color)));
+            continue;
+        }
+        if let Some(result) = fog::result(&game) {
+            break result;
+        }
+        for (color, player) in [ColorChess::White, ColorChess::Black]
+            .into_iter()
+            .zip(&mut players)
+        {
+            send(player, &ServerMessage::View(FogView::new(&game, color)));
+        }
+    };
+    let end = ServerMessage::end(result, &game);
+    for player in &mut players {
+        send(player, &end);
+    }
+    println!("{} ({})", result, result.pgn_tag());
+    Ok(())
+}
+
+// Passes on a player's messages until its connection closes; lines that are
+// not messages are left out
+fn read_player(color: ColorChess, stream: TcpStream, sender: &Sender<Received>) {
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+        if let Ok(message) = serde_json::from_str(&line)
+            && sender.send((color, Some(message))).is_err()
+        {
+            return;
+        }
+    }
+    let _ = sender.send((color, None));
+}
+
+// A player that has gone is noticed by its reader, so a failed write is
+// left to that
+fn send(player: &mut TcpStream, message: &ServerMessage) {
+    if let Ok(line) = serde_json::to_string(message) {
+        let _ = writeln!(player, "{}", line);
+    }
+}
+
+fn index(color: ColorChess) -> usize {
+    match color {
+        ColorChess::White => 0,
+        ColorChess::Black => 1,
+    }
+}
@@ -0,0 +1,214 @@
+use std::ops::{Deref, DerefMut};
+
+use crate::board::{ColorChess, Piece};
+use crate::clock::Clock;
+use crate::error::ChessError;
+use crate::moves::Move;
+use crate::position::{MoveRecord, Position, Undo};
+use crate::rules::GameResult;
+
+// A game: the current position and the moves that led to it, which undo,
+// redo, the captured pieces and the repetition rule are read from, with its
+// clocks and how it ended. It derefs to the position for everything the rules
+// and the board need.
+#[derive(Clone, Default)]
+pub struct Game {
+    pub(crate) position: Position,
+    // Played moves (for undo) and undone moves (for redo)
+    pub(crate) history: Vec<MoveRecord>,
+    pub(crate) redo_stack: Vec<Move>,
+    // Both players' clocks in a timed game
+    clock: Option<Clock>,
+    // How the game ended, once it has: by the rules, or by a flag, a
+    // resignation or an agreement the position does not show
+    result: Option<GameResult>,
+}
+
+impl Deref for Game {
+    type Target = Position;
+
+    fn deref(&self) -> &Position {
+        &self.position
+    }
+}
+
+impl DerefMut for Game {
+    fn deref_mut(&mut self) -> &mut Position {
+        &mut self.position
+    }
+}
+
+// A game starting from the position, with no moves played yet
+impl From<Position> for Game {
+    fn from(position: Position) -> Game {
+        Game {
+            position,
+            history: Vec::new(),
+            redo_stack: Vec::new(),
+            clock: None,
+            result: None,
+        }
+    }
+}
+
+impl Game {
+    pub fn new() -> Game {
+        Game::default()
+    }
+
+    pub fn position(&self) -> &Position {
+        &self.position
+    }
+
+    // Plays a move (a new move clears the redo stack). The turn is not switched
+    // and the move is not checked, so moves from outside go through play_move.
+    fn move_piece(&mut self, mv: Move) {
+        self.redo_stack.clear();
+        if let Some(record) = self.position.apply_move(mv) {
+            self.history.push(record);
+        }
+    }
+
+    // Plays a move given by its squares and promotion piece and passes the
+    // turn, once it is found to be on the board and legal; a move from
+    // elsewhere need not be classified with build_move first
+    pub fn play_move(&mut self, mv: Move) -> Result<(), ChessError> {
+        for square in [mv.from, mv.to] {
+            if square.0 >= 8 || square.1 >= 8 {
+                return Err(ChessError::OutOfBounds(format!("{:?}", square)));
+            }
+        }
+        let legal = self
+            .get_all_legal_moves(self.current_turn)
+            .into_iter()
+            .find(|legal| {
+                legal.from == mv.from && legal.to == mv.to && legal.promotion == mv.promotion
+            })
+            .ok_or_else(|| ChessError::IllegalMove {
+                mv: mv.to_uci(),
+                fen: self.to_fen(),
+            })?;
+        self.move_piece(legal);
+        self.switch_turn();
+        Ok(())
+    }
+
+    // Plays a move and passes the turn like Position::make, recording it in
+    // the history. Unlike play_move it leaves the redo stack alone.
+    pub fn make(&mut self, mv: Move) -> Result<Undo, ChessError> {
+        let undo = self.position.make(mv)?;
+        self.history.push(undo.record());
+        Ok(undo)
+    }
+
+    // Takes back a move played with make
+    pub fn unmake(&mut self, undo: Undo) {
+        self.history.pop();
+        self.position.unmake(undo);
+    }
+
+    // Takes back the last move, restoring the turn to the player who made it
+    pub fn undo(&mut self) -> Option<Move> {
+        let record = self.history.pop()?;
+        self.position.restore(record);
+        self.redo_stack.push(record.mv);
+        Some(record.mv)
+    }
+
+    // Replays the last undone move and passes the turn to the other player;
+    // None if there is none, or it no longer fits the position
+    pub fn redo(&mut self) -> Option<Move> {
+        let mv = self.redo_stack.pop()?;
+        let record = self.position.apply_move(mv)?;
+        self.history.push(record);
+        self.switch_turn();
+        Some(mv)
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn get_history(&self) -> &[MoveRecord] {
+        &self.history
+    }
+
+    // The position before the first move of the history, with nothing to undo
+    pub fn get_start_position(&self) -> Game {
+        let mut position = self.position;
+        for &record in self.history.iter().rev() {
+            position.restore(record);
+        }
+        Game::from(position)
+    }
+
+    pub fn clock(&self) -> Option<&Clock> {
+        self.clock.as_ref()
+    }
+
+    pub fn clock_mut(&mut self) -> Option<&mut Clock> {
+        self.clock.as_mut()
+    }
+
+    // Puts the game on a clock, or takes it off with None
+    pub fn set_clock(&mut self, clock: Option<Clock>) {
+        self.clock = clock;
+    }
+
+    // How the game ended, as recorded with set_result; get_result only tells
+    // what the rules say of the position
+    pub fn result(&self) -> Option<GameResult> {
+        self.result
+    }
+
+    // Records how the game ended, or with None that it goes on, e.g. after
+    // the last move is taken back
+    pub fn set_result(&mut self, result: Option<GameResult>) {
+        self.result = result;
+    }
+
+    // Pieces of the given color that have been captured so far
+    pub fn get_captured(&self, color: ColorChess) -> Vec<Piece> {
+        self.history
+            .iter()
+            .filter_map(|record| record.captured)
+            .filter(|piece| piece.is_color(color))
+            .collect()
+    }
+
+    // Points the given color has scored: the total value of the opponent's
+    // pieces it has captured
+    pub fn get_points(&self, color: ColorChess) -> u32 {
+        self.get_captured(color.opposite())
+            .iter()
+            .map(Piece::points)
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redo_keeps_the_turn_when_the_move_no_longer_fits() {
+        let mut game = Game::new();
+        let mv = game.parse_san("e4").unwrap();
+        game.play_move(mv).unwrap();
+        assert_eq!(game.undo(), Some(mv));
+        assert_eq!(game.redo(), Some(mv));
+        assert_eq!(game.get_current_turn(), ColorChess::Black);
+
+        game.undo();
+        let position: &mut Position = &mut game;
+        *position =
+            Position::from_fen("rnbqkbnr/pppppppp/8/8/8/8/PPPP1PPP/RNBQKBNR w KQkq - 0 1").unwrap();
+        assert_eq!(game.redo(), None);
+        assert_eq!(game.get_current_turn(), ColorChess::White);
+        assert!(game.get_history().is_empty());
+    }
+}
@@ -0,0 +1,115 @@
+use std::{fs, path::PathBuf};
+
+use chess_rs::ColorChess;
+use chess_rs::pgn::{self, PgnGame};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+// A finished game in the database, as listed in the browser; the game
+// itself is in its own PGN file
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SavedGame {
+    // The game is in games/<id>.pgn
+    pub id: u32,
+    // As in the PGN Date tag, e.g. "2024.05.17"
+    pub date: String,
+    pub white: String,
+    pub black: String,
+    // Who the player faced, or "Hot-seat" when both sides were played here
+    pub opponent: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub opening: Option<String>,
+    // "Won", "Lost" or "Drawn" for the player, or the PGN result of a
+    // hot-seat game
+    pub outcome: String,
+    // Full moves played
+    pub moves: u32,
+}
+
+// Every finished game, kept as PGN files in games/ next to config.toml with
+// an index.toml listing them, so that the browser need not read them all
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GameDatabase {
+    games: Vec<SavedGame>,
+}
+
+fn db_dir() -> Option<PathBuf> {
+    Some(config::config_path()?.with_file_name("games"))
+}
+
+impl GameDatabase {
+    // Reads the index; a missing one means no games have been saved
+    pub fn load() -> Result<GameDatabase, String> {
+        let Some(path) = db_dir().map(|dir| dir.join("index.toml")) else {
+            return Ok(GameDatabase::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e.message()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(GameDatabase::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    // The games, newest first
+    pub fn games(&self) -> impl Iterator<Item = &SavedGame> {
+        self.games.iter().rev()
+    }
+
+    pub fn len(&self) -> usize {
+        self.games.len()
+    }
+
+    // Writes a game's PGN to the database and lists it, with what its tags
+    // and moves say; `side` is the player's, None for a hot-seat game
+    pub fn add(&mut self, pgn: &str, side: Option<ColorChess>) -> Result<(), String> {
+        let dir = db_dir().ok_or("no config directory on this system")?;
+        fs::create_dir_all(&dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        let game = pgn::parse_pgn(pgn)?;
+        let tag = |name| game.tag(name).unwrap_or("?").to_string();
+        let opponent = match side {
+            Some(ColorChess::White) => tag("Black"),
+            Some(ColorChess::Black) => tag("White"),
+            None => "Hot-seat".to_string(),
+        };
+        let outcome = match (game.result(), side) {
+            (Some(result), Some(side)) => match result.winner {
+                Some(winner) if winner == side => "Won".to_string(),
+                Some(_) => "Lost".to_string(),
+                None => "Drawn".to_string(),
+            },
+            _ => tag("Result"),
+        };
+        let plies = game.board.get_history().len() as u32;
+        let id = self.games.iter().map(|game| game.id + 1).max().unwrap_or(1);
+        let path = dir.join(format!("{}.pgn", id));
+        fs::write(&path, pgn).map_err(|e| format!("{}: {}", path.display(), e))?;
+        self.games.push(SavedGame {
+            id,
+            date: tag("Date"),
+            white: tag("White"),
+            black: tag("Black"),
+            opponent,
+            opening: game
+                .board
+                .opening()
+                .map(|opening| format!("{} {}", opening.eco, opening.name)),
+            outcome,
+            moves: plies.div_ceil(2),
+        });
+        let path = dir.join("index.toml");
+        let text = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+}
+
+// A listed game itself, read back from its PGN file
+pub fn open(game: &SavedGame) -> Result<PgnGame, String> {
+    let dir = db_dir().ok_or("no config directory on this system")?;
+    let path = dir.join(format!("{}.pgn", game.id));
+    let text = fs::read_to_string(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    pgn::parse_pgn(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
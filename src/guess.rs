@@ -0,0 +1,137 @@
+use std::{
+    fs,
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use chess_rs::pgn::{PgnGame, parse_pgn_games};
+use chess_rs::search::MATE_SCORE;
+use chess_rs::{ColorChess, Game, Move, SearchLimits, Searcher};
+
+// Points for finding the move that was played; any other move earns a point
+// less than that for every quarter pawn it is worse by
+pub const EXACT_POINTS: u32 = 10;
+const CENTIPAWNS_PER_POINT: i32 = 25;
+
+// Search time for each of the two moves a wrong guess is scored with
+const SCORING_TIME: Duration = Duration::from_millis(300);
+
+// Guessing the moves of one side through the games of a PGN file, with a
+// running score over all of them
+pub struct GuessRun {
+    // Games still to come after this one
+    games: Vec<PgnGame>,
+    pub white: String,
+    pub black: String,
+    // The game's moves, and the index of the next one to be played
+    moves: Vec<Move>,
+    pub next: usize,
+    // The side whose moves are guessed
+    pub side: ColorChess,
+    pub points: u32,
+    pub possible: u32,
+    // Guesses that were the move played
+    pub exact: u32,
+    // A wrong guess, while it is scored against the move played
+    pub scoring: Option<(Move, Receiver<i32>)>,
+}
+
+impl GuessRun {
+    pub fn load(path: &str) -> Result<GuessRun, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let games = parse_pgn_games(&text)?;
+        if games.iter().all(|game| game.board.get_history().is_empty()) {
+            return Err(format!("{}: no games with moves", path));
+        }
+        Ok(GuessRun {
+            games,
+            white: String::new(),
+            black: String::new(),
+            moves: Vec::new(),
+            next: 0,
+            side: ColorChess::White,
+            points: 0,
+            possible: 0,
+            exact: 0,
+            scoring: None,
+        })
+    }
+
+    // Moves on to the next game with moves in it, returning where it starts
+    pub fn next_game(&mut self) -> Option<Game> {
+        while !self.games.is_empty() {
+            let game = self.games.remove(0);
+            let history = game.board.get_history();
+            if history.is_empty() {
+                continue;
+            }
+            self.moves = history.iter().map(|record| record.mv).collect();
+            self.next = 0;
+            self.white = game.tag("White").unwrap_or("?").to_string();
+            self.black = game.tag("Black").unwrap_or("?").to_string();
+            // The winner's moves are the ones worth guessing, White's in a draw
+            self.side = match game.result().and_then(|result| result.winner) {
+                Some(ColorChess::Black) => ColorChess::Black,
+                _ => ColorChess::White,
+            };
+            self.scoring = None;
+            return Some(game.board.get_start_position());
+        }
+        None
+    }
+
+    // The move played next in the game
+    pub fn played(&self) -> Option<Move> {
+        self.moves.get(self.next).copied()
+    }
+
+    // Scores a guess, the move played when `loss` is None, and returns its points
+    pub fn score(&mut self, loss: Option<i32>) -> u32 {
+        let points = match loss {
+            None => {
+                self.exact += 1;
+                EXACT_POINTS
+            }
+            Some(loss) => {
+                (EXACT_POINTS as i32 - 1 - loss.max(0) / CENTIPAWNS_PER_POINT).max(0) as u32
+            }
+        };
+        self.points += points;
+        self.possible += EXACT_POINTS;
+        points
+    }
+}
+
+// How many centipawns a guess is worse than the move played, found by
+// searching both on another thread
+pub fn score_guess(board: &Game, guess: Move, played: Move) -> Receiver<i32> {
+    let (sender, receiver) = mpsc::channel();
+    let board = board.clone();
+    thread::spawn(move || {
+        let mut searcher = Searcher::new();
+        let loss =
+            mover_score(&mut searcher, &board, played) - mover_score(&mut searcher, &board, guess);
+        let _ = sender.send(loss.max(0));
+    });
+    receiver
+}
+
+// The score of a move for the side making it
+fn mover_score(searcher: &mut Searcher, board: &Game, mv: Move) -> i32 {
+    let mut after = board.clone();
+    if after.make(mv).is_err() {
+        return -MATE_SCORE;
+    }
+    let to_move = after.get_current_turn();
+    if after.get_all_legal_moves(to_move).is_empty() {
+        return if after.is_in_check(to_move) {
+            MATE_SCORE
+        } else {
+            0
+        };
+    }
+    -searcher
+        .search(&after, SearchLimits::time(SCORING_TIME))
+        .score
+}
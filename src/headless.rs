@@ -0,0 +1,381 @@
+use std::{
+    io::{self, BufRead, StdoutLock, Write},
+    sync::mpsc::{self, RecvTimeoutError},
+    thread,
+    time::Duration,
+};
+
+use chess_rs::clock::{Clock, TimeControl, format_clock};
+use chess_rs::search::DEFAULT_MOVES_TO_GO;
+use chess_rs::{
+    ColorChess, Difficulty, Game, GameResult, Move, SearchLimits, Searcher, Termination,
+};
+use serde::Serialize;
+
+// How the game is printed
+#[derive(Clone, Copy, PartialEq)]
+pub enum Output {
+    // The board in letters, with a prompt for each move
+    Text,
+    // Only the engine's moves and the result
+    Quiet,
+    // A JSON object a line for every change, for programs driving the game
+    Json,
+}
+
+// Something that happened in the game; in JSON its kind is under "event"
+#[derive(Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Report {
+    Start {
+        fen: String,
+        // Side the engine plays, if any
+        engine: Option<&'static str>,
+    },
+    Move {
+        number: u32,
+        side: &'static str,
+        uci: String,
+        san: String,
+        // The position after the move
+        fen: String,
+        // Whether the engine played it rather than it being read
+        engine: bool,
+    },
+    Illegal {
+        input: String,
+    },
+    Check {
+        side: &'static str,
+    },
+    Clock {
+        white_ms: u64,
+        black_ms: u64,
+    },
+    GameOver {
+        result: &'static str,
+        reason: Termination,
+        message: String,
+    },
+    Undo {
+        fen: String,
+    },
+    Position {
+        fen: String,
+    },
+    Moves {
+        moves: String,
+    },
+    Help {
+        text: &'static str,
+    },
+    Error {
+        message: String,
+    },
+}
+
+const HELP: &str = "Enter a move like e2e4 or Nf3; undo takes back your last move, fen prints \
+                    the position, moves the game so far, quit stops.";
+
+struct Session {
+    board: Game,
+    searcher: Searcher,
+    difficulty: Difficulty,
+    time_control: Option<TimeControl>,
+    output: Output,
+    out: StdoutLock<'static>,
+}
+
+// Plays the game on from `board` on stdin/stdout instead of the board UI, for
+// scripts and dumb terminals. Moves are read a line each, in UCI or SAN; the
+// engine answers for `engine_side`, and without one both sides' moves are read
+pub fn run(
+    mut board: Game,
+    engine_side: Option<ColorChess>,
+    difficulty: Difficulty,
+    time_control: Option<TimeControl>,
+    output: Output,
+) -> io::Result<()> {
+    // Lines are read on their own thread, so a flag can fall while waiting
+    let (line_tx, lines) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line_tx.send(line).is_err() {
+                return;
+            }
+        }
+    });
+
+    board.set_clock(time_control.map(Clock::new));
+    let mut game = Session {
+        board,
+        searcher: Searcher::new(),
+        difficulty,
+        time_control,
+        output,
+        out: io::stdout().lock(),
+    };
+    game.report(Report::Start {
+        fen: game.board.to_fen(),
+        engine: engine_side.map(side_name),
+    })?;
+    if game.board.clock().is_some() {
+        game.report_clock()?;
+    }
+    // Whether the position changed since the board was last printed
+    let mut moved = true;
+    loop {
+        if let Some(report) = game.game_over() {
+            game.report(report)?;
+            break;
+        }
+        let to_move = game.board.get_current_turn();
+        if let Some(clock) = game.board.clock_mut() {
+            clock.start(to_move);
+        }
+        if engine_side == Some(to_move) {
+            let Some(mv) = game.searcher.search(&game.board, game.limits()).best_move else {
+                break;
+            };
+            game.play(mv, true)?;
+            moved = true;
+            continue;
+        }
+        if game.output == Output::Text {
+            if std::mem::take(&mut moved) {
+                write_board(
+                    &mut game.out,
+                    &game.board,
+                    engine_side == Some(ColorChess::White),
+                )?;
+            }
+            writeln!(game.out, "{:?} to move:", to_move)?;
+        }
+        game.out.flush()?;
+        let received = match game.board.clock() {
+            Some(clock) => lines.recv_timeout(clock.get_remaining(to_move)),
+            None => lines.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        let line = match received {
+            Ok(line) => line,
+            // The flag fell; the game ends at the top of the loop
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => break,
+        };
+        match line.trim() {
+            "" => {}
+            "quit" | "exit" => break,
+            "help" => game.report(Report::Help { text: HELP })?,
+            "undo" => {
+                if game.board.undo().is_none() {
+                    game.report(Report::Error {
+                        message: "No move to take back.".to_string(),
+                    })?;
+                    continue;
+                }
+                // Back to the last position where it was the player's turn
+                while engine_side == Some(game.board.get_current_turn())
+                    && game.board.undo().is_some()
+                {}
+                moved = true;
+                game.report(Report::Undo {
+                    fen: game.board.to_fen(),
+                })?;
+            }
+            "fen" => game.report(Report::Position {
+                fen: game.board.to_fen(),
+            })?,
+            "moves" => game.report(Report::Moves {
+                moves: movetext(&game.board),
+            })?,
+            input => match game
+                .board
+                .parse_uci_move(input)
+                .or_else(|_| game.board.parse_san(input))
+            {
+                Ok(mv) => {
+                    game.play(mv, false)?;
+                    moved = true;
+                }
+                Err(_) => game.report(Report::Illegal {
+                    input: input.to_string(),
+                })?,
+            },
+        }
+    }
+    Ok(())
+}
+
+impl Session {
+    fn play(&mut self, mv: Move, engine: bool) -> io::Result<()> {
+        let side = self.board.get_current_turn();
+        let number = self.board.get_fullmove_number();
+        let san = self.board.move_to_san(mv);
+        self.board.play_move(mv).map_err(io::Error::other)?;
+        if let Some(clock) = self.board.clock_mut() {
+            clock.complete_move(side);
+        }
+        self.report(Report::Move {
+            number,
+            side: side_name(side),
+            uci: mv.to_uci(),
+            san,
+            fen: self.board.to_fen(),
+            engine,
+        })?;
+        let to_move = self.board.get_current_turn();
+        if self.board.is_in_check(to_move) {
+            self.report(Report::Check {
+                side: side_name(to_move),
+            })?;
+        }
+        if self.board.clock().is_some() {
+            self.report_clock()?;
+        }
+        Ok(())
+    }
+
+    // The difficulty's search, cut short to what the clock allows
+    fn limits(&self) -> SearchLimits {
+        let mut limits = self.difficulty.limits();
+        if let (Some(clock), Some(control)) = (self.board.clock(), self.time_control) {
+            let remaining = clock.get_remaining(self.board.get_current_turn());
+            let budget = SearchLimits::clock(remaining, control.bonus, DEFAULT_MOVES_TO_GO);
+            limits.time_limit = limits.time_limit.into_iter().chain(budget.time_limit).min();
+        }
+        limits
+    }
+
+    fn report_clock(&mut self) -> io::Result<()> {
+        let Some(clock) = self.board.clock() else {
+            return Ok(());
+        };
+        let millis = |color| clock.get_remaining(color).as_millis() as u64;
+        let report = Report::Clock {
+            white_ms: millis(ColorChess::White),
+            black_ms: millis(ColorChess::Black),
+        };
+        self.report(report)
+    }
+
+    // How the game ended, if it has, in the words of the board UI
+    fn game_over(&self) -> Option<Report> {
+        let flagged = self.board.clock().and_then(|clock| {
+            [ColorChess::White, ColorChess::Black]
+                .into_iter()
+                .find(|&color| clock.get_remaining(color).is_zero())
+        });
+        let result = match flagged {
+            Some(color) if self.board.has_mating_material(color.opposite()) => {
+                GameResult::win_for(color.opposite(), Termination::Timeout)
+            }
+            Some(_) => GameResult::draw(Termination::Timeout),
+            None => self.board.get_result()?,
+        };
+        Some(Report::GameOver {
+            result: result.pgn_tag(),
+            reason: result.reason,
+            message: format!("{} ({})", result, result.pgn_tag()),
+        })
+    }
+
+    // Prints a change as the output asks: every one as JSON, or in text
+    // what the board does not already show
+    fn report(&mut self, report: Report) -> io::Result<()> {
+        let out = &mut self.out;
+        if self.output == Output::Json {
+            let line = serde_json::to_string(&report).map_err(io::Error::other)?;
+            return writeln!(out, "{}", line);
+        }
+        let quiet = self.output == Output::Quiet;
+        match report {
+            Report::Move {
+                number,
+                side,
+                san,
+                engine: true,
+                ..
+            } => {
+                if quiet {
+                    writeln!(out, "{}", san)
+                } else {
+                    let dots = if side == "white" { "." } else { "..." };
+                    writeln!(out, "{}{} {}", number, dots, san)
+                }
+            }
+            Report::Clock { white_ms, black_ms } if !quiet => {
+                let time = |ms| format_clock(Duration::from_millis(ms));
+                writeln!(out, "White {} | Black {}", time(white_ms), time(black_ms))
+            }
+            Report::GameOver { message, .. } => writeln!(out, "{}", message),
+            Report::Position { fen } => writeln!(out, "{}", fen),
+            Report::Moves { moves } => writeln!(out, "{}", moves),
+            Report::Help { text } => writeln!(out, "{}", text),
+            Report::Illegal { input } => {
+                eprintln!("Illegal move: {}", input);
+                Ok(())
+            }
+            Report::Error { message } => {
+                eprintln!("{}", message);
+                Ok(())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn side_name(color: ColorChess) -> &'static str {
+    match color {
+        ColorChess::White => "white",
+        ColorChess::Black => "black",
+    }
+}
+
+// The moves played, numbered as in PGN
+fn movetext(board: &Game) -> String {
+    let start = board.get_start_position();
+    let mut number = start.get_fullmove_number();
+    let mut white = start.get_current_turn() == ColorChess::White;
+    let mut text = Vec::new();
+    for (i, san) in board.history_san().into_iter().enumerate() {
+        if white {
+            text.push(format!("{}. {}", number, san));
+        } else {
+            if i == 0 {
+                text.push(format!("{}...", number));
+            }
+            text.push(san);
+            number += 1;
+        }
+        white = !white;
+    }
+    text.join(" ")
+}
+
+// The board in letters, upper case for White, with dots for empty squares
+fn write_board(out: &mut impl Write, board: &Game, flipped: bool) -> io::Result<()> {
+    let order = |i: usize| if flipped { i } else { 7 - i };
+    for i in 0..8 {
+        let row = order(i);
+        let squares: Vec<String> = (0..8)
+            .map(|j| {
+                let col = 7 - order(j);
+                match board.get_piece((row, col)) {
+                    Some(piece) if piece.color() == ColorChess::White => {
+                        piece.piece_type().letter().to_string()
+                    }
+                    Some(piece) => piece.piece_type().letter().to_ascii_lowercase().to_string(),
+                    None => ".".to_string(),
+                }
+            })
+            .collect();
+        writeln!(out, "{} {}", row + 1, squares.join(" "))?;
+    }
+    let files: Vec<String> = (0..8)
+        .map(|j| ((b'a' + (7 - order(j)) as u8) as char).to_string())
+        .collect();
+    writeln!(out, "  {}", files.join(" "))
+}
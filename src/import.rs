@@ -0,0 +1,153 @@
+use std::{
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use chess_rs::pgn::{PgnGame, parse_pgn};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::lichess;
+
+const CHESS_COM_URL: &str = "https://www.chess.com";
+const CHESS_COM_API_URL: &str = "https://api.chess.com/pub";
+
+// Length of a Lichess game id; longer ids in links add the player's side
+const LICHESS_ID_LEN: usize = 8;
+
+// Where a game to import is kept
+enum GameLink {
+    Lichess(String),
+    // Live games and daily (correspondence) games are numbered separately
+    ChessCom { daily: bool, id: String },
+}
+
+// Makes sense of a pasted link, such as "https://lichess.org/abcdEFGH/black"
+// or "https://www.chess.com/game/live/123456789", or of a bare game id:
+// eight letters and digits for Lichess, a number for chess.com
+fn parse_link(link: &str) -> Result<GameLink, String> {
+    let link = link.trim();
+    let path = link
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_start_matches("www.");
+    let mut parts = path.split(['/', '?', '#']).filter(|part| !part.is_empty());
+    let unknown = || format!("not a Lichess or chess.com game: {}", link);
+    match parts.next() {
+        Some("lichess.org") => {
+            let id = parts.next().ok_or_else(unknown)?;
+            lichess_id(id).ok_or_else(unknown)
+        }
+        Some("chess.com") => {
+            // Links look like /game/live/<id>, /game/daily/<id> or /live/game/<id>
+            let parts: Vec<_> = parts.collect();
+            let daily = parts.contains(&"daily");
+            let id = parts
+                .iter()
+                .find(|part| part.chars().all(|c| c.is_ascii_digit()))
+                .ok_or_else(unknown)?;
+            Ok(GameLink::ChessCom {
+                daily,
+                id: id.to_string(),
+            })
+        }
+        Some(id) if link == id && id.chars().all(|c| c.is_ascii_digit()) => {
+            Ok(GameLink::ChessCom {
+                daily: false,
+                id: id.to_string(),
+            })
+        }
+        Some(id) if link == id => lichess_id(id).ok_or_else(unknown),
+        _ => Err(unknown()),
+    }
+}
+
+fn lichess_id(id: &str) -> Option<GameLink> {
+    (id.len() >= LICHESS_ID_LEN && id.chars().all(|c| c.is_ascii_alphanumeric()))
+        .then(|| GameLink::Lichess(id[..LICHESS_ID_LEN].to_string()))
+}
+
+// Downloads a game from its link in the background and reads its PGN
+pub fn fetch_game(link: &str) -> Receiver<Result<PgnGame, String>> {
+    let (sender, receiver) = mpsc::channel();
+    let link = parse_link(link);
+    thread::spawn(move || {
+        let pgn = link.and_then(|link| match link {
+            GameLink::Lichess(id) => lichess::export_game(&id),
+            GameLink::ChessCom { daily, id } => chess_com_game(daily, &id),
+        });
+        let _ = sender.send(pgn.and_then(|pgn| parse_pgn(&pgn).map_err(String::from)));
+    });
+    receiver
+}
+
+// chess.com has no public export of a single game, so this looks the game up
+// to learn who played it and when, then finds it in White's monthly archive
+fn chess_com_game(daily: bool, id: &str) -> Result<String, String> {
+    let kind = if daily { "daily" } else { "live" };
+    let url = format!("{}/callback/{}/game/{}", CHESS_COM_URL, kind, id);
+    let headers = get_json::<CallbackGame>(&url)?.game.pgn_headers;
+    // Dates are written "2024.03.09"
+    let mut date = headers.date.split('.');
+    let (Some(year), Some(month)) = (date.next(), date.next()) else {
+        return Err(format!(
+            "chess.com gave an unexpected date {}",
+            headers.date
+        ));
+    };
+    let url = format!(
+        "{}/player/{}/games/{}/{}",
+        CHESS_COM_API_URL,
+        headers.white.to_lowercase(),
+        year,
+        month
+    );
+    let suffix = format!("/{}", id);
+    get_json::<Archive>(&url)?
+        .games
+        .into_iter()
+        .find(|game| game.url.ends_with(&suffix))
+        .map(|game| game.pgn)
+        .ok_or_else(|| format!("chess.com game {} is not in its players' archive", id))
+}
+
+fn get_json<T: DeserializeOwned>(url: &str) -> Result<T, String> {
+    ureq::get(url)
+        .call()
+        .map_err(|e| match e {
+            ureq::Error::Status(code, _) => format!("chess.com answered {}", code),
+            ureq::Error::Transport(e) => e.to_string(),
+        })?
+        .into_json()
+        .map_err(|e| e.to_string())
+}
+
+#[derive(Deserialize)]
+struct CallbackGame {
+    game: CallbackDetails,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CallbackDetails {
+    pgn_headers: PgnHeaders,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct PgnHeaders {
+    white: String,
+    date: String,
+}
+
+#[derive(Deserialize)]
+struct Archive {
+    games: Vec<ArchivedGame>,
+}
+
+#[derive(Deserialize)]
+struct ArchivedGame {
+    url: String,
+    #[serde(default)]
+    pgn: String,
+}
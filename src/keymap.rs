@@ -0,0 +1,223 @@
+use std::collections::BTreeMap;
+
+use crossterm::event::KeyCode;
+use serde::{Deserialize, Serialize};
+
+// Everything a key can be bound to
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Action {
+    Quit,
+    // Back to the main menu, or out of whatever is open
+    Menu,
+    Help,
+    Up,
+    Down,
+    Left,
+    Right,
+    // Pick the entry or square under the cursor
+    Select,
+    Flip,
+    Undo,
+    Redo,
+    Analysis,
+    // Shade the squares by who attacks them
+    AttackMap,
+    Theme,
+    // Freeze the clocks and the board until pressed again
+    Pause,
+    Resign,
+    OfferDraw,
+    Yes,
+    No,
+    NewGame,
+    Rematch,
+    ScrollUp,
+    ScrollDown,
+    // Type a move in SAN or UCI notation
+    TypeMove,
+    // Show the pieces for a moment in blindfold play
+    Peek,
+    // Leave the game for the analysis board, or come back to it
+    Explore,
+    // Move the variation on the analysis board up or down among the others
+    PromoteLine,
+    DemoteLine,
+    // Annotate the move on the analysis board with !, ? or a NAG
+    Annotate,
+    // Write a comment on the move on the analysis board
+    Comment,
+    // Open the engine's review of the game
+    Review,
+    // The moves played from the position in the player's saved games
+    Explorer,
+}
+
+// The keys each action has unless config.toml says otherwise
+const DEFAULT_KEYS: [(Action, &[&str]); 32] = [
+    (Action::Quit, &["q"]),
+    (Action::Menu, &["esc"]),
+    (Action::Help, &["?"]),
+    (Action::Up, &["up", "k"]),
+    (Action::Down, &["down", "j"]),
+    (Action::Left, &["left", "h"]),
+    (Action::Right, &["right", "l"]),
+    (Action::Select, &["enter", "space"]),
+    (Action::Flip, &["f"]),
+    (Action::Undo, &["u"]),
+    (Action::Redo, &["r"]),
+    (Action::Analysis, &["a"]),
+    (Action::AttackMap, &["i"]),
+    (Action::Theme, &["t"]),
+    (Action::Pause, &["p"]),
+    (Action::Resign, &["x"]),
+    (Action::OfferDraw, &["d"]),
+    (Action::Yes, &["y"]),
+    (Action::No, &["n"]),
+    (Action::NewGame, &["n"]),
+    (Action::Rematch, &["m"]),
+    (Action::ScrollUp, &["pageup"]),
+    (Action::ScrollDown, &["pagedown"]),
+    (Action::TypeMove, &["/"]),
+    (Action::Peek, &["b"]),
+    (Action::Explore, &["e"]),
+    (Action::PromoteLine, &["+"]),
+    (Action::DemoteLine, &["-"]),
+    (Action::Annotate, &["!"]),
+    (Action::Comment, &["c"]),
+    (Action::Review, &["g"]),
+    (Action::Explorer, &["o"]),
+];
+
+// Keys for one action in the [keys] section, either `undo = "z"` or
+// `undo = ["z", "backspace"]`
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KeyList {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl KeyList {
+    fn names(&self) -> &[String] {
+        match self {
+            KeyList::One(name) => std::slice::from_ref(name),
+            KeyList::Many(names) => names,
+        }
+    }
+}
+
+// Which keys trigger which actions; a key may serve several actions, and each
+// screen asks only about the actions it has
+pub struct KeyMap {
+    bindings: BTreeMap<Action, Vec<KeyCode>>,
+}
+
+impl Default for KeyMap {
+    fn default() -> Self {
+        let bindings = DEFAULT_KEYS
+            .iter()
+            .map(|&(action, names)| {
+                let keys = names.iter().filter_map(|name| parse_key(name)).collect();
+                (action, keys)
+            })
+            .collect();
+        KeyMap { bindings }
+    }
+}
+
+impl KeyMap {
+    // The default bindings with those from the [keys] section in their place
+    pub fn new(overrides: &BTreeMap<Action, KeyList>) -> Result<KeyMap, String> {
+        let mut keymap = KeyMap::default();
+        for (&action, list) in overrides {
+            let keys = list
+                .names()
+                .iter()
+                .map(|name| parse_key(name).ok_or(format!("unknown key {:?}", name)))
+                .collect::<Result<_, _>>()?;
+            keymap.bindings.insert(action, keys);
+        }
+        Ok(keymap)
+    }
+
+    // The first of `actions` that the key is bound to
+    pub fn action(&self, key: KeyCode, actions: &[Action]) -> Option<Action> {
+        actions.iter().copied().find(|action| {
+            self.bindings
+                .get(action)
+                .is_some_and(|keys| keys.contains(&key))
+        })
+    }
+
+    // The keys of an action for display, e.g. "up / k"
+    pub fn describe(&self, action: Action) -> String {
+        let names: Vec<String> = self
+            .bindings
+            .get(&action)
+            .map_or(&[][..], |keys| keys)
+            .iter()
+            .map(|&key| key_name(key))
+            .collect();
+        if names.is_empty() {
+            "(none)".to_string()
+        } else {
+            names.join(" / ")
+        }
+    }
+}
+
+// A key as written in config.toml: a single character, or a name such as
+// "enter", "esc", "pageup" or "f1"
+fn parse_key(name: &str) -> Option<KeyCode> {
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        return Some(KeyCode::Char(c));
+    }
+    let key = match name.to_ascii_lowercase().as_str() {
+        "enter" | "return" => KeyCode::Enter,
+        "esc" | "escape" => KeyCode::Esc,
+        "space" => KeyCode::Char(' '),
+        "tab" => KeyCode::Tab,
+        "backspace" => KeyCode::Backspace,
+        "delete" => KeyCode::Delete,
+        "insert" => KeyCode::Insert,
+        "up" => KeyCode::Up,
+        "down" => KeyCode::Down,
+        "left" => KeyCode::Left,
+        "right" => KeyCode::Right,
+        "home" => KeyCode::Home,
+        "end" => KeyCode::End,
+        "pageup" => KeyCode::PageUp,
+        "pagedown" => KeyCode::PageDown,
+        other => {
+            let number = other.strip_prefix('f')?.parse().ok()?;
+            KeyCode::F(number)
+        }
+    };
+    Some(key)
+}
+
+// The name parse_key reads back as the same key
+fn key_name(key: KeyCode) -> String {
+    match key {
+        KeyCode::Char(' ') => "space".to_string(),
+        KeyCode::Char(c) => c.to_string(),
+        KeyCode::Enter => "enter".to_string(),
+        KeyCode::Esc => "esc".to_string(),
+        KeyCode::Tab => "tab".to_string(),
+        KeyCode::Backspace => "backspace".to_string(),
+        KeyCode::Delete => "delete".to_string(),
+        KeyCode::Insert => "insert".to_string(),
+        KeyCode::Up => "up".to_string(),
+        KeyCode::Down => "down".to_string(),
+        KeyCode::Left => "left".to_string(),
+        KeyCode::Right => "right".to_string(),
+        KeyCode::Home => "home".to_string(),
+        KeyCode::End => "end".to_string(),
+        KeyCode::PageUp => "pageup".to_string(),
+        KeyCode::PageDown => "pagedown".to_string(),
+        KeyCode::F(number) => format!("f{}", number),
+        other => format!("{:?}", other),
+    }
+}
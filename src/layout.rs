@@ -0,0 +1,165 @@
+use tui::{
+    layout::{Constraint, Direction, Layout, Rect},
+    widgets::{Block, Borders},
+};
+
+// Rows of the "Game Info" block above the board and of the "Messages" block below it
+const INFO_HEIGHT: u16 = 8;
+const MESSAGES_HEIGHT: u16 = 3;
+
+// The status bar along the bottom of the game screen
+const STATUS_HEIGHT: u16 = 1;
+
+// Columns left of the squares: padding, the rank label and a space
+const RANK_LABEL_WIDTH: u16 = 3;
+
+// Columns right of the squares kept free for the eval bar and its label
+const EVAL_BAR_WIDTH: u16 = 8;
+
+// Widths of the side panels beside the eval bar
+const ANALYSIS_MIN_WIDTH: u16 = 20;
+const ANALYSIS_MAX_WIDTH: u16 = 36;
+const MOVES_MIN_WIDTH: u16 = 18;
+const MOVES_MAX_WIDTH: u16 = 24;
+
+// Square heights tried from the largest down when none is configured; squares
+// are twice as wide as they are high, so they look roughly square in a terminal
+pub const SQUARE_HEIGHTS: [u16; 4] = [4, 3, 2, 1];
+
+// Smallest terminal (width, height) the game screen fits in with squares of
+// the given height
+pub fn min_size(square_height: u16) -> (u16, u16) {
+    (
+        2 + RANK_LABEL_WIDTH + 8 * 2 * square_height + EVAL_BAR_WIDTH,
+        INFO_HEIGHT + MESSAGES_HEIGHT + STATUS_HEIGHT + 2 + 8 * square_height + 2,
+    )
+}
+
+// Where everything on the game screen goes for one terminal size; the drawing
+// code and the mouse handling both use it, so clicks land on the squares drawn
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct BoardLayout {
+    // The whole terminal the layout was computed for
+    pub area: Rect,
+    pub info: Rect,
+    // The "Chess Board" block, borders included
+    pub board_block: Rect,
+    // Inside of the board block
+    pub board_area: Rect,
+    // The 8x8 squares, without the labels
+    pub squares: Rect,
+    pub square_width: u16,
+    pub square_height: u16,
+    // Room right of the eval bar for the side panels, as high as the board
+    // with its file labels and a row above it
+    pub side: Rect,
+    pub messages: Rect,
+    pub status: Rect,
+}
+
+impl BoardLayout {
+    // The layout for a terminal of the given size, with squares of the given
+    // height or else the largest that fit; None when the board does not fit
+    pub fn new(area: Rect, square_height: Option<u16>) -> Option<BoardLayout> {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints(
+                [
+                    Constraint::Length(INFO_HEIGHT),     // Captured pieces and info
+                    Constraint::Min(0),                  // Chess board (takes remaining space)
+                    Constraint::Length(MESSAGES_HEIGHT), // Messages and input
+                    Constraint::Length(STATUS_HEIGHT),   // Status bar
+                ]
+                .as_ref(),
+            )
+            .split(area);
+        let board_block = chunks[1];
+        let board_area = Block::default().borders(Borders::ALL).inner(board_block);
+
+        // A blank row above the squares and the file labels below them
+        let heights = match square_height {
+            Some(height) => vec![height],
+            None => SQUARE_HEIGHTS.to_vec(),
+        };
+        let square_height = heights.into_iter().find(|&height| {
+            let (width, total_height) = min_size(height);
+            width <= area.width && total_height <= area.height
+        })?;
+        let square_width = 2 * square_height;
+        let squares = Rect::new(
+            board_area.x + RANK_LABEL_WIDTH,
+            board_area.y + 1,
+            8 * square_width,
+            8 * square_height,
+        );
+        let side_x = squares.x + squares.width + EVAL_BAR_WIDTH + 2;
+        let side = Rect::new(
+            side_x,
+            board_area.y,
+            (board_area.x + board_area.width).saturating_sub(side_x),
+            squares.height + 2,
+        );
+        Some(BoardLayout {
+            area,
+            info: chunks[0],
+            board_block,
+            board_area,
+            squares,
+            square_width,
+            square_height,
+            side,
+            messages: chunks[2],
+            status: chunks[3],
+        })
+    }
+
+    // Screen area of the square shown at a displayed (row, col), counted from
+    // the top-left corner of the board
+    pub fn square_rect(&self, display: (usize, usize)) -> Rect {
+        Rect::new(
+            self.squares.x + display.1 as u16 * self.square_width,
+            self.squares.y + display.0 as u16 * self.square_height,
+            self.square_width,
+            self.square_height,
+        )
+    }
+
+    // The displayed (row, col) of the square at a terminal cell, if there is one
+    pub fn display_square_at(&self, x: u16, y: u16) -> Option<(usize, usize)> {
+        let squares = self.squares;
+        if x < squares.x
+            || y < squares.y
+            || x >= squares.x + squares.width
+            || y >= squares.y + squares.height
+        {
+            return None;
+        }
+        Some((
+            ((y - squares.y) / self.square_height) as usize,
+            ((x - squares.x) / self.square_width) as usize,
+        ))
+    }
+
+    // Where the analysis panel, when open, and the move list go, left to right
+    // in the side room; a panel that does not fit is left out
+    pub fn side_panels(&self, show_analysis: bool) -> (Option<Rect>, Option<Rect>) {
+        let mut side = self.side;
+        let mut analysis = None;
+        if show_analysis && side.width >= ANALYSIS_MIN_WIDTH {
+            let width = side.width.min(ANALYSIS_MAX_WIDTH);
+            analysis = Some(Rect { width, ..side });
+            side.x += width;
+            side.width -= width;
+        }
+        let moves = (side.width >= MOVES_MIN_WIDTH).then(|| Rect {
+            width: side.width.min(MOVES_MAX_WIDTH),
+            ..side
+        });
+        (analysis, moves)
+    }
+
+    // Row inside a square that its piece is drawn on, and the rank label beside it
+    pub fn label_row(&self) -> u16 {
+        (self.square_height - 1) / 2
+    }
+}
@@ -0,0 +1,41 @@
+mod bitboard;
+pub mod board;
+pub mod book;
+pub mod clock;
+pub mod difficulty;
+pub mod eco;
+pub mod epd;
+pub mod error;
+pub mod eval;
+pub mod fen;
+pub mod fog;
+pub mod game;
+pub mod movegen;
+pub mod moves;
+mod ordering;
+pub mod pgn;
+mod polyglot_keys;
+pub mod position;
+pub mod rules;
+pub mod san;
+pub mod search;
+pub mod tablebase;
+pub mod tree;
+pub mod tt;
+pub mod uci;
+mod zobrist;
+
+pub use board::{ColorChess, Piece, PieceType, square_name};
+pub use book::OpeningBook;
+pub use clock::{Clock, TimeControl};
+pub use difficulty::Difficulty;
+pub use error::ChessError;
+pub use fen::START_FEN;
+pub use game::Game;
+pub use moves::{MAX_MOVES, Move, MoveFlags, MoveList, PROMOTION_PIECES};
+pub use position::{MoveRecord, Position, Undo};
+pub use rules::{AttackTable, GameResult, Termination};
+pub use search::{SearchLimits, SearchLine, SearchResult, Searcher};
+pub use tablebase::{Tablebase, Wdl};
+pub use tree::GameTree;
+pub use uci::UciEngine;
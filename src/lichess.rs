@@ -0,0 +1,840 @@
+use std::{
+    io::{BufRead, BufReader},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use chess_rs::clock::{BonusMode, TimeControl};
+use chess_rs::{ColorChess, Game, GameResult, Termination};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::correspondence::CorrespondenceGame;
+use crate::events::EventSender;
+use crate::puzzle::Puzzle;
+
+const LICHESS_URL: &str = "https://lichess.org";
+
+// How often the list of correspondence games is refreshed, since the event
+// stream says nothing when an opponent moves in one
+const PLAYING_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+// Time control of seeks and challenges when none is chosen: 10+0 rapid
+pub const DEFAULT_CONTROL: TimeControl = TimeControl {
+    base: Duration::from_secs(600),
+    bonus: Duration::ZERO,
+    mode: BonusMode::Increment,
+};
+
+// What the connection to Lichess reports back to the app
+pub enum LichessEvent {
+    // The token was accepted; the account's username
+    Connected(String),
+    // Another player challenged the player
+    Challenge(Challenge),
+    // A challenge was withdrawn, declined or accepted, by its id
+    ChallengeGone(String),
+    // One of the player's real-time games started, or was still running at connect
+    GameStart(OnlineGame),
+    // The same for a correspondence game, which is not opened straight away
+    Correspondence(CorrespondenceGame),
+    // The correspondence games still running, refreshed every so often
+    Playing(Vec<CorrespondenceGame>),
+    // One of the player's games ended, by its id
+    GameFinish(String),
+    // The moves, clocks and status of a followed game after a change
+    GameState(GameState),
+    // A request failed
+    Error(String),
+}
+
+// Where an online game is played
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Server {
+    Lichess,
+    Fics,
+    // A fog-of-war game server started with `chess-rs fog-server`
+    Fog,
+}
+
+impl Server {
+    pub fn name(self) -> &'static str {
+        match self {
+            Server::Lichess => "Lichess",
+            Server::Fics => "FICS",
+            Server::Fog => "Fog of war",
+        }
+    }
+}
+
+// A game being played on Lichess, on FICS or on a fog-of-war server
+#[derive(Clone)]
+pub struct OnlineGame {
+    pub server: Server,
+    pub id: String,
+    pub color: ColorChess,
+    pub opponent: String,
+    // Days rather than minutes per move; such games outlast the app
+    pub correspondence: bool,
+}
+
+pub struct Challenge {
+    pub id: String,
+    pub challenger: String,
+    // e.g. "5+3 rated"
+    pub description: String,
+}
+
+pub struct GameState {
+    pub game_id: String,
+    // Start position when it is not the usual one; only in a game's first state
+    pub initial_fen: Option<String>,
+    // Time control of a real-time game; only in a game's first state
+    pub control: Option<TimeControl>,
+    // Every move of the game in UCI notation
+    pub moves: Vec<String>,
+    // White's and Black's time left, in real-time games
+    pub times: Option<[Duration; 2]>,
+    // Lichess's game status, e.g. "started", "mate" or "resign"
+    pub status: String,
+    pub winner: Option<ColorChess>,
+    // Side offering a draw, if any
+    pub draw_offer: Option<ColorChess>,
+}
+
+impl GameState {
+    pub fn is_over(&self) -> bool {
+        !matches!(self.status.as_str(), "created" | "started")
+    }
+
+    // How a finished game came out, by Lichess's status; none for a game
+    // still going or one aborted before it started
+    pub fn result(&self) -> Option<GameResult> {
+        let reason = match self.status.as_str() {
+            "created" | "started" | "aborted" | "noStart" => return None,
+            "mate" => Termination::Checkmate,
+            "resign" => Termination::Resignation,
+            // Without a winner the flag fell against a side that could not
+            // be mated, which draws
+            "outoftime" => Termination::Timeout,
+            "timeout" => Termination::Abandoned,
+            "cheat" => Termination::Forfeit,
+            "stalemate" => return Some(GameResult::draw(Termination::Stalemate)),
+            // Agreed draws and those by the rules alike
+            "draw" => return Some(GameResult::draw(Termination::Unknown)),
+            _ => Termination::Unknown,
+        };
+        Some(GameResult {
+            winner: self.winner,
+            reason,
+        })
+    }
+
+    // Why a game without a result ended
+    pub fn ending(&self) -> String {
+        match self.status.as_str() {
+            "aborted" => "Game aborted.".to_string(),
+            "noStart" => "The game did not start in time.".to_string(),
+            status => format!("Game over ({}).", status),
+        }
+    }
+}
+
+// A connection to the Lichess Board API with a personal API token. Requests
+// run on their own threads and report back to the main loop as events
+pub struct Lichess {
+    token: String,
+    sender: EventSender<LichessEvent>,
+    pub username: Option<String>,
+}
+
+impl Lichess {
+    // Checks the token and starts following the account's events: challenges
+    // and games starting, including those in progress already
+    pub fn connect(token: &str, sender: EventSender<LichessEvent>) -> Lichess {
+        let lichess = Lichess {
+            token: token.to_string(),
+            sender,
+            username: None,
+        };
+        let (token, sender) = (lichess.token.clone(), lichess.sender.clone());
+        thread::spawn(move || {
+            let account: Account = match get_json(Some(&token), "/api/account") {
+                Ok(account) => account,
+                Err(e) => {
+                    let _ = sender.send(LichessEvent::Error(e));
+                    return;
+                }
+            };
+            let _ = sender.send(LichessEvent::Connected(account.username));
+            let (playing_token, playing_sender) = (token.clone(), sender.clone());
+            thread::spawn(move || poll_playing(&playing_token, &playing_sender));
+            stream(&token, "/api/stream/event", &sender, |line| {
+                account_event(line, &account.id)
+            });
+        });
+        lichess
+    }
+
+    // Looks for an opponent in the lobby; the game arrives as GameStart
+    pub fn seek(&self, control: TimeControl) -> Result<(), String> {
+        increment_only(control)?;
+        let time = (control.base.as_secs() / 60).to_string();
+        let increment = control.bonus.as_secs().to_string();
+        self.post(
+            "/api/board/seek".to_string(),
+            vec![
+                ("rated", "true".to_string()),
+                ("time", time),
+                ("increment", increment),
+            ],
+        );
+        Ok(())
+    }
+
+    // Looks for an opponent for a correspondence game with `days` per move
+    pub fn seek_correspondence(&self, days: u32) {
+        self.post(
+            "/api/board/seek".to_string(),
+            vec![("rated", "true".to_string()), ("days", days.to_string())],
+        );
+    }
+
+    // Challenges a player to a rated game
+    pub fn challenge(&self, username: &str, control: TimeControl) -> Result<(), String> {
+        increment_only(control)?;
+        let limit = control.base.as_secs().to_string();
+        let increment = control.bonus.as_secs().to_string();
+        self.post(
+            format!("/api/challenge/{}", percent_encode(username)),
+            vec![
+                ("rated", "true".to_string()),
+                ("clock.limit", limit),
+                ("clock.increment", increment),
+            ],
+        );
+        Ok(())
+    }
+
+    pub fn accept(&self, challenge_id: &str) {
+        self.post(format!("/api/challenge/{}/accept", challenge_id), vec![]);
+    }
+
+    // Starts following a game's moves and clocks as GameState events
+    pub fn follow_game(&self, game_id: &str) {
+        let (token, sender) = (self.token.clone(), self.sender.clone());
+        let path = format!("/api/board/game/stream/{}", game_id);
+        let game_id = game_id.to_string();
+        thread::spawn(move || {
+            stream(&token, &path, &sender, |line| game_event(line, &game_id));
+        });
+    }
+
+    pub fn play_move(&self, game_id: &str, uci: &str) {
+        self.post(format!("/api/board/game/{}/move/{}", game_id, uci), vec![]);
+    }
+
+    pub fn resign(&self, game_id: &str) {
+        self.post(format!("/api/board/game/{}/resign", game_id), vec![]);
+    }
+
+    // Offers or accepts a draw (true), or declines one (false)
+    pub fn draw(&self, game_id: &str, accept: bool) {
+        let answer = if accept { "yes" } else { "no" };
+        self.post(
+            format!("/api/board/game/{}/draw/{}", game_id, answer),
+            vec![],
+        );
+    }
+
+    // Sends a request in the background; only a failure is reported
+    fn post(&self, path: String, form: Vec<(&'static str, String)>) {
+        let (token, sender) = (self.token.clone(), self.sender.clone());
+        thread::spawn(move || {
+            let form: Vec<(&str, &str)> = form
+                .iter()
+                .map(|(name, value)| (*name, value.as_str()))
+                .collect();
+            let result = ureq::post(&format!("{}{}", LICHESS_URL, path))
+                .set("Authorization", &format!("Bearer {}", token))
+                .send_form(&form);
+            if let Err(e) = result {
+                let _ = sender.send(LichessEvent::Error(request_error(e)));
+            }
+        });
+    }
+}
+
+// Lichess clocks only add an increment, so delay and Bronstein controls are
+// refused rather than played as something else
+fn increment_only(control: TimeControl) -> Result<(), String> {
+    match control.mode {
+        BonusMode::Increment => Ok(()),
+        BonusMode::Delay | BonusMode::Bronstein => Err(format!(
+            "Lichess has no {} games, only increments such as {}+{}",
+            control,
+            control.base.as_secs() / 60,
+            control.bonus.as_secs()
+        )),
+    }
+}
+
+// Escapes everything but letters, digits and -._~ for a URL path
+fn percent_encode(text: &str) -> String {
+    text.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(byte).to_string()
+            }
+            _ => format!("%{:02X}", byte),
+        })
+        .collect()
+}
+
+// Downloads a puzzle in the background, of a Lichess theme if one is given;
+// with a token, one the account has not seen yet
+pub fn fetch_puzzle(
+    token: Option<String>,
+    theme: Option<&'static str>,
+) -> Receiver<Result<Puzzle, String>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let path = match theme {
+            Some(theme) => format!("/api/puzzle/next?angle={}", theme),
+            None => "/api/puzzle/next".to_string(),
+        };
+        let result = get_json(token.as_deref(), &path).and_then(puzzle_from);
+        let _ = sender.send(result);
+    });
+    receiver
+}
+
+// Today's puzzle, the same for everyone; waits for the answer
+pub fn daily_puzzle() -> Result<Puzzle, String> {
+    get_json(None, "/api/puzzle/daily").and_then(puzzle_from)
+}
+
+// A finished or ongoing game as PGN, without clock or evaluation comments
+pub fn export_game(id: &str) -> Result<String, String> {
+    ureq::get(&format!("{}/game/export/{}", LICHESS_URL, id))
+        .query("clocks", "false")
+        .query("evals", "false")
+        .set("Accept", "application/x-chess-pgn")
+        .call()
+        .map_err(request_error)?
+        .into_string()
+        .map_err(|e| e.to_string())
+}
+
+// Every game of a broadcast round as PGN, with the moves so far
+pub fn round_pgn(round_id: &str) -> Result<String, String> {
+    ureq::get(&format!(
+        "{}/api/broadcast/round/{}.pgn",
+        LICHESS_URL, round_id
+    ))
+    .call()
+    .map_err(request_error)?
+    .into_string()
+    .map_err(|e| e.to_string())
+}
+
+// The puzzle's position comes from playing through the game it was taken from
+fn puzzle_from(info: PuzzleInfo) -> Result<Puzzle, String> {
+    let mut board = Game::new();
+    for san in info.game.pgn.split_whitespace() {
+        let mv = board
+            .parse_san(san)
+            .map_err(|_| format!("puzzle {} has an unknown move {}", info.puzzle.id, san))?;
+        board.make(mv)?;
+    }
+    Ok(Puzzle {
+        id: info.puzzle.id,
+        fen: board.to_fen(),
+        solution: info.puzzle.solution,
+        rating: Some(info.puzzle.rating),
+        themes: info.puzzle.themes,
+    })
+}
+
+// Sends the account's correspondence games now and then, until the app has gone
+fn poll_playing(token: &str, sender: &EventSender<LichessEvent>) {
+    loop {
+        // A failed refresh is tried again next time round
+        if let Ok(playing) = get_json::<Playing>(Some(token), "/api/account/playing") {
+            let games = playing
+                .now_playing
+                .into_iter()
+                .filter_map(correspondence_game)
+                .collect();
+            if sender.send(LichessEvent::Playing(games)).is_err() {
+                return;
+            }
+        }
+        thread::sleep(PLAYING_POLL_INTERVAL);
+    }
+}
+
+fn correspondence_game(game: GameInfo) -> Option<CorrespondenceGame> {
+    if game.speed != "correspondence" {
+        return None;
+    }
+    Some(CorrespondenceGame {
+        id: game.game_id,
+        color: color(&game.color)?,
+        opponent: game.opponent.username,
+        fen: game.fen,
+        my_turn: game.is_my_turn,
+        last_move: Some(game.last_move).filter(|mv| !mv.is_empty()),
+    })
+}
+
+fn get_json<T: DeserializeOwned>(token: Option<&str>, path: &str) -> Result<T, String> {
+    let mut request = ureq::get(&format!("{}{}", LICHESS_URL, path));
+    if let Some(token) = token {
+        request = request.set("Authorization", &format!("Bearer {}", token));
+    }
+    request
+        .call()
+        .map_err(request_error)?
+        .into_json()
+        .map_err(|e| e.to_string())
+}
+
+// Reads a streamed response line by line, passing on the events `parse` makes
+// of them, until the stream ends or the app has gone
+fn stream(
+    token: &str,
+    path: &str,
+    sender: &EventSender<LichessEvent>,
+    parse: impl Fn(&str) -> Option<LichessEvent>,
+) {
+    let response = match ureq::get(&format!("{}{}", LICHESS_URL, path))
+        .set("Authorization", &format!("Bearer {}", token))
+        .call()
+    {
+        Ok(response) => response,
+        Err(e) => {
+            let _ = sender.send(LichessEvent::Error(request_error(e)));
+            return;
+        }
+    };
+    for line in BufReader::new(response.into_reader()).lines() {
+        let Ok(line) = line else {
+            let _ = sender.send(LichessEvent::Error("lost the connection".to_string()));
+            return;
+        };
+        // Empty lines only keep the connection alive
+        if let Some(event) = parse(&line)
+            && sender.send(event).is_err()
+        {
+            return;
+        }
+    }
+}
+
+// Lichess's own error message where it sent one
+fn request_error(error: ureq::Error) -> String {
+    match error {
+        ureq::Error::Status(code, response) => {
+            let message = response
+                .into_json::<ErrorBody>()
+                .map(|body| body.error)
+                .unwrap_or_default();
+            format!("Lichess answered {} {}", code, message)
+        }
+        ureq::Error::Transport(e) => e.to_string(),
+    }
+}
+
+fn account_event(line: &str, account_id: &str) -> Option<LichessEvent> {
+    match serde_json::from_str(line).ok()? {
+        AccountEvent::GameStart { game } if game.speed == "correspondence" => {
+            correspondence_game(game).map(LichessEvent::Correspondence)
+        }
+        AccountEvent::GameStart { game } => Some(LichessEvent::GameStart(OnlineGame {
+            server: Server::Lichess,
+            id: game.game_id,
+            color: color(&game.color)?,
+            opponent: game.opponent.username,
+            correspondence: false,
+        })),
+        AccountEvent::GameFinish { game } => Some(LichessEvent::GameFinish(game.game_id)),
+        // The player's own challenges come through here too
+        AccountEvent::Challenge { challenge } if challenge.challenger.id != account_id => {
+            let control = challenge.time_control.show.unwrap_or_default();
+            let rated = if challenge.rated { "rated" } else { "casual" };
+            Some(LichessEvent::Challenge(Challenge {
+                id: challenge.id,
+                challenger: challenge.challenger.name,
+                description: format!("{} {}", control, rated).trim().to_string(),
+            }))
+        }
+        AccountEvent::ChallengeCanceled { challenge }
+        | AccountEvent::ChallengeDeclined { challenge } => {
+            Some(LichessEvent::ChallengeGone(challenge.id))
+        }
+        AccountEvent::Challenge { .. } | AccountEvent::Other => None,
+    }
+}
+
+fn game_event(line: &str, game_id: &str) -> Option<LichessEvent> {
+    let (state, initial_fen, control) = match serde_json::from_str(line).ok()? {
+        GameEvent::GameFull {
+            initial_fen,
+            clock,
+            state,
+        } => {
+            let control = clock.map(|clock| TimeControl {
+                base: Duration::from_millis(clock.initial),
+                bonus: Duration::from_millis(clock.increment),
+                mode: BonusMode::Increment,
+            });
+            (
+                state,
+                Some(initial_fen).filter(|fen| fen != "startpos"),
+                control,
+            )
+        }
+        GameEvent::GameState(state) => (state, None, None),
+        GameEvent::Other => return None,
+    };
+    let times = match (state.wtime, state.btime) {
+        (Some(white), Some(black)) => {
+            Some([Duration::from_millis(white), Duration::from_millis(black)])
+        }
+        _ => None,
+    };
+    let draw_offer = if state.wdraw {
+        Some(ColorChess::White)
+    } else if state.bdraw {
+        Some(ColorChess::Black)
+    } else {
+        None
+    };
+    Some(LichessEvent::GameState(GameState {
+        game_id: game_id.to_string(),
+        initial_fen,
+        control,
+        moves: state.moves.split_whitespace().map(str::to_string).collect(),
+        times,
+        status: state.status,
+        winner: state.winner.as_deref().and_then(color),
+        draw_offer,
+    }))
+}
+
+fn color(name: &str) -> Option<ColorChess> {
+    match name {
+        "white" => Some(ColorChess::White),
+        "black" => Some(ColorChess::Black),
+        _ => None,
+    }
+}
+
+// The parts of Lichess's JSON that are used
+
+#[derive(Deserialize)]
+struct Account {
+    id: String,
+    username: String,
+}
+
+#[derive(Deserialize)]
+struct PuzzleInfo {
+    game: PuzzleGame,
+    puzzle: PuzzleDetails,
+}
+
+#[derive(Deserialize)]
+struct PuzzleGame {
+    pgn: String,
+}
+
+#[derive(Deserialize)]
+struct PuzzleDetails {
+    id: String,
+    rating: i32,
+    solution: Vec<String>,
+    #[serde(default)]
+    themes: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    error: String,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum AccountEvent {
+    GameStart {
+        game: GameInfo,
+    },
+    GameFinish {
+        game: GameInfo,
+    },
+    Challenge {
+        challenge: ChallengeInfo,
+    },
+    ChallengeCanceled {
+        challenge: ChallengeInfo,
+    },
+    ChallengeDeclined {
+        challenge: ChallengeInfo,
+    },
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GameInfo {
+    game_id: String,
+    color: String,
+    opponent: Opponent,
+    // "bullet", "blitz", ..., "correspondence"
+    #[serde(default)]
+    speed: String,
+    #[serde(default)]
+    fen: String,
+    #[serde(default)]
+    is_my_turn: bool,
+    #[serde(default)]
+    last_move: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Playing {
+    now_playing: Vec<GameInfo>,
+}
+
+#[derive(Deserialize)]
+struct Opponent {
+    username: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct ChallengeInfo {
+    id: String,
+    challenger: Challenger,
+    #[serde(default)]
+    rated: bool,
+    time_control: ChallengeTimeControl,
+}
+
+#[derive(Deserialize)]
+struct Challenger {
+    id: String,
+    name: String,
+}
+
+#[derive(Deserialize)]
+struct ChallengeTimeControl {
+    show: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase")]
+enum GameEvent {
+    #[serde(rename_all = "camelCase")]
+    GameFull {
+        initial_fen: String,
+        clock: Option<ClockInfo>,
+        state: StateInfo,
+    },
+    GameState(StateInfo),
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Deserialize)]
+struct ClockInfo {
+    initial: u64,
+    increment: u64,
+}
+
+#[derive(Deserialize)]
+struct StateInfo {
+    moves: String,
+    wtime: Option<u64>,
+    btime: Option<u64>,
+    status: String,
+    winner: Option<String>,
+    #[serde(default)]
+    wdraw: bool,
+    #[serde(default)]
+    bdraw: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(line: &str) -> GameState {
+        match game_event(line, "5IrD6Gzz") {
+            Some(LichessEvent::GameState(state)) => state,
+            _ => panic!("no game state in {}", line),
+        }
+    }
+
+    #[test]
+    fn game_events() {
+        let full = state(
+            r#"{"type":"gameFull","id":"5IrD6Gzz","rated":true,"clock":{"initial":1200000,"increment":10000},"speed":"classical","initialFen":"startpos","state":{"type":"gameState","moves":"e2e4 c7c5 f2f4 d7d6","wtime":7598040,"btime":8395220,"winc":10000,"binc":10000,"status":"started"}}"#,
+        );
+        assert_eq!(full.game_id, "5IrD6Gzz");
+        assert_eq!(full.initial_fen, None);
+        assert_eq!(
+            full.control.map(|control| control.to_string()).as_deref(),
+            Some("20+10")
+        );
+        assert_eq!(full.moves, ["e2e4", "c7c5", "f2f4", "d7d6"]);
+        assert_eq!(
+            full.times,
+            Some([
+                Duration::from_millis(7598040),
+                Duration::from_millis(8395220)
+            ])
+        );
+        assert!(!full.is_over());
+        assert!(full.result().is_none());
+
+        let correspondence = state(
+            r#"{"type":"gameFull","id":"5IrD6Gzz","clock":null,"initialFen":"8/8/8/8/8/2k5/8/K6R w - - 0 1","state":{"type":"gameState","moves":"","status":"started","bdraw":true}}"#,
+        );
+        assert_eq!(
+            correspondence.initial_fen.as_deref(),
+            Some("8/8/8/8/8/2k5/8/K6R w - - 0 1")
+        );
+        assert!(correspondence.control.is_none() && correspondence.times.is_none());
+        assert!(correspondence.moves.is_empty());
+        assert_eq!(correspondence.draw_offer, Some(ColorChess::Black));
+
+        let mate = state(
+            r#"{"type":"gameState","moves":"f2f3 e7e5 g2g4 d8h4","wtime":1000,"btime":2000,"status":"mate","winner":"black"}"#,
+        );
+        assert!(mate.is_over());
+        assert_eq!(
+            mate.result(),
+            Some(GameResult::win_for(
+                ColorChess::Black,
+                Termination::Checkmate
+            ))
+        );
+
+        assert!(game_event(r#"{"type":"chatLine","username":"x","text":"hi"}"#, "id").is_none());
+        assert!(game_event("", "id").is_none());
+    }
+
+    #[test]
+    fn results_by_status() {
+        let result = |status: &str, winner: Option<ColorChess>| {
+            GameState {
+                game_id: String::new(),
+                initial_fen: None,
+                control: None,
+                moves: Vec::new(),
+                times: None,
+                status: status.to_string(),
+                winner,
+                draw_offer: None,
+            }
+            .result()
+        };
+        let white = Some(ColorChess::White);
+        assert_eq!(
+            result("outoftime", white),
+            Some(GameResult::win_for(ColorChess::White, Termination::Timeout))
+        );
+        assert_eq!(
+            result("outoftime", None),
+            Some(GameResult::draw(Termination::Timeout))
+        );
+        assert_eq!(
+            result("timeout", white),
+            Some(GameResult::win_for(
+                ColorChess::White,
+                Termination::Abandoned
+            ))
+        );
+        assert_eq!(
+            result("resign", white),
+            Some(GameResult::win_for(
+                ColorChess::White,
+                Termination::Resignation
+            ))
+        );
+        assert_eq!(
+            result("stalemate", None),
+            Some(GameResult::draw(Termination::Stalemate))
+        );
+        assert_eq!(
+            result("draw", None),
+            Some(GameResult::draw(Termination::Unknown))
+        );
+        assert_eq!(result("aborted", None), None);
+        assert_eq!(result("noStart", white), None);
+    }
+
+    #[test]
+    fn account_events() {
+        let start = r#"{"type":"gameStart","game":{"gameId":"rCRw1AuO","fullId":"rCRw1AuOvonq","color":"black","fen":"rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1","isMyTurn":false,"lastMove":"","opponent":{"id":"philippe","rating":1790,"username":"Philippe"},"speed":"blitz"}}"#;
+        match account_event(start, "me") {
+            Some(LichessEvent::GameStart(game)) => {
+                assert_eq!(game.id, "rCRw1AuO");
+                assert_eq!(game.color, ColorChess::Black);
+                assert_eq!(game.opponent, "Philippe");
+                assert!(!game.correspondence);
+            }
+            _ => panic!("no game start"),
+        }
+        let correspondence = start.replace("blitz", "correspondence");
+        match account_event(&correspondence, "me") {
+            Some(LichessEvent::Correspondence(game)) => {
+                assert_eq!(game.id, "rCRw1AuO");
+                assert!(!game.my_turn);
+                assert_eq!(game.last_move, None);
+            }
+            _ => panic!("no correspondence game"),
+        }
+
+        let challenge = r#"{"type":"challenge","challenge":{"id":"7pGLxJ4F","status":"created","challenger":{"id":"lovlas","name":"Lovlas","rating":1506},"rated":true,"speed":"rapid","timeControl":{"type":"clock","limit":300,"increment":25,"show":"5+25"},"color":"random"}}"#;
+        match account_event(challenge, "me") {
+            Some(LichessEvent::Challenge(challenge)) => {
+                assert_eq!(challenge.id, "7pGLxJ4F");
+                assert_eq!(challenge.challenger, "Lovlas");
+                assert_eq!(challenge.description, "5+25 rated");
+            }
+            _ => panic!("no challenge"),
+        }
+        // The player's own challenge is not offered back to them
+        assert!(account_event(challenge, "lovlas").is_none());
+        let declined = challenge.replace(r#""type":"challenge""#, r#""type":"challengeDeclined""#);
+        assert!(matches!(
+            account_event(&declined, "me"),
+            Some(LichessEvent::ChallengeGone(id)) if id == "7pGLxJ4F"
+        ));
+        assert!(account_event(r#"{"type":"gameFinish","game":{"gameId":"rCRw1AuO","color":"black","opponent":{"username":"Philippe"}}}"#, "me").is_some());
+        assert!(account_event(r#"{"type":"somethingNew"}"#, "me").is_none());
+    }
+
+    #[test]
+    fn only_increments_go_to_lichess() {
+        assert!(increment_only(DEFAULT_CONTROL).is_ok());
+        let delay = TimeControl::parse("3|2 delay").unwrap();
+        assert_eq!(
+            increment_only(delay),
+            Err("Lichess has no 3|2 delay games, only increments such as 3+2".to_string())
+        );
+        assert!(increment_only(TimeControl::parse("3|2 bronstein").unwrap()).is_err());
+    }
+
+    #[test]
+    fn usernames_are_escaped() {
+        assert_eq!(percent_encode("Lovlas_1-x"), "Lovlas_1-x");
+        assert_eq!(percent_encode("a/../b?c d"), "a%2F..%2Fb%3Fc%20d");
+    }
+}
@@ -0,0 +1,116 @@
+use chess_rs::ColorChess;
+use chess_rs::tree::{GameTree, ROOT, Token};
+
+// Columns of a move in the main line
+const SAN_WIDTH: usize = 8;
+
+// How far variations are set in from the main line
+const INDENT: usize = 2;
+
+// One row of the move list, as the pieces of text it is made of
+pub struct MoveRow {
+    pub cells: Vec<Cell>,
+}
+
+pub struct Cell {
+    pub text: String,
+    // The node of the move in the game tree; None for move numbers and the
+    // space between moves
+    pub node: Option<usize>,
+    // Who played a move of the main line, so that fog of war can hide it
+    pub mover: Option<ColorChess>,
+}
+
+impl Cell {
+    fn plain(text: String) -> Cell {
+        Cell {
+            text,
+            node: None,
+            mover: None,
+        }
+    }
+}
+
+impl MoveRow {
+    // The node of the move at a column of the row
+    pub fn node_at(&self, column: usize) -> Option<usize> {
+        let mut end = 0;
+        for cell in &self.cells {
+            end += cell.text.chars().count();
+            if column < end {
+                return cell.node;
+            }
+        }
+        None
+    }
+}
+
+// The main line in rows of a White and a Black move under their number, each
+// move's alternatives following on rows of their own, set in and wrapped to
+// the width; the move after them starts a row again, as in "12. ... Nc6"
+pub fn layout(tree: &GameTree, width: usize, variations: bool) -> Vec<MoveRow> {
+    let mut rows = Vec::new();
+    let mut current: Option<MoveRow> = None;
+    let mut board = tree.start().clone();
+    let mut node = ROOT;
+    while let Some((&next, alternatives)) = tree.children(node).split_first() {
+        let Some(mv) = tree.mv(next) else {
+            break;
+        };
+        let mover = board.get_current_turn();
+        if mover == ColorChess::White || current.is_none() {
+            rows.extend(current.take());
+            let mut cells = vec![Cell::plain(format!("{:>3}. ", board.get_fullmove_number()))];
+            if mover == ColorChess::Black {
+                cells.push(Cell::plain(format!("{:<SAN_WIDTH$}", "...")));
+            }
+            current = Some(MoveRow { cells });
+        }
+        if let Some(row) = &mut current {
+            row.cells.push(Cell {
+                // Long annotated moves still keep a space before the next
+                text: format!("{:<1$} ", tree.move_text(&board, next), SAN_WIDTH - 1),
+                node: Some(next),
+                mover: Some(mover),
+            });
+        }
+        if variations && !alternatives.is_empty() {
+            rows.extend(current.take());
+            for &alternative in alternatives {
+                rows.extend(wrap(tree.variation(alternative), width));
+            }
+        }
+        if board.make(mv).is_err() {
+            break;
+        }
+        node = next;
+    }
+    rows.extend(current);
+    rows
+}
+
+// Movetext in rows no wider than `width`, set in from the main line
+fn wrap(tokens: Vec<Token>, width: usize) -> Vec<MoveRow> {
+    let indent = || Cell::plain(" ".repeat(INDENT));
+    let mut rows = Vec::new();
+    let mut cells = vec![indent()];
+    let mut length = INDENT;
+    for token in tokens {
+        let token_length = token.text.chars().count();
+        if length > INDENT && length + token_length > width {
+            rows.push(MoveRow {
+                cells: std::mem::replace(&mut cells, vec![indent()]),
+            });
+            length = INDENT;
+        }
+        length += token_length + 1;
+        cells.push(Cell {
+            text: token.text,
+            node: token.node,
+            mover: None,
+        });
+        cells.push(Cell::plain(" ".to_string()));
+    }
+    rows.push(MoveRow { cells });
+    rows
+}
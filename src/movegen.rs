@@ -0,0 +1,265 @@
+use crate::bitboard::{
+    Bitboard, between, bishop_attacks, king_attacks, knight_attacks, pawn_attacks, rook_attacks,
+    square_bit, square_index, squares,
+};
+use crate::board::{ColorChess, PieceType, on_board};
+use crate::moves::{Move, MoveList, push_with_promotions};
+use crate::position::Position;
+
+impl Position {
+    // Replaces the list's contents with the legal moves of the side to move
+    pub fn moves_into(&self, moves: &mut MoveList) {
+        moves.clear();
+        self.generate_legal(self.current_turn, !0, moves);
+    }
+
+    // The legal moves of the side to move
+    pub fn legal_moves(&self) -> MoveList {
+        let mut moves = MoveList::new();
+        self.generate_legal(self.current_turn, !0, &mut moves);
+        moves
+    }
+
+    // The legal moves of the piece on a square, if it belongs to the side to
+    // move; only that piece's moves are generated. None for a square off the
+    // board
+    pub fn legal_moves_from(&self, square: (usize, usize)) -> MoveList {
+        let mut moves = MoveList::new();
+        if on_board(square) {
+            self.generate_legal(self.current_turn, square_bit(square), &mut moves);
+        }
+        moves
+    }
+
+    // Counts the positions `depth` plies down, to check the move generator
+    // against known totals
+    pub fn perft(&mut self, depth: u32) -> u64 {
+        if depth == 0 {
+            return 1;
+        }
+        let moves = self.legal_moves();
+        if depth == 1 {
+            return moves.len() as u64;
+        }
+        let mut nodes = 0;
+        for mv in moves {
+            let Ok(undo) = self.make(mv) else {
+                continue;
+            };
+            nodes += self.perft(depth - 1);
+            self.unmake(undo);
+        }
+        nodes
+    }
+
+    // perft split by the first move, to find where a count goes wrong
+    pub fn perft_divide(&mut self, depth: u32) -> Vec<(Move, u64)> {
+        let mut counts = Vec::new();
+        for mv in self.legal_moves() {
+            let Ok(undo) = self.make(mv) else {
+                continue;
+            };
+            counts.push((mv, self.perft(depth.saturating_sub(1))));
+            self.unmake(undo);
+        }
+        counts
+    }
+
+    pub fn get_all_legal_moves(&self, color: ColorChess) -> Vec<Move> {
+        let mut moves = MoveList::new();
+        self.generate_legal(color, !0, &mut moves);
+        moves.to_vec()
+    }
+
+    // Appends the legal moves of the given color's pieces on the `origins` squares.
+    // Checks and pins are worked out first, so each piece only generates the
+    // moves that keep its own king safe.
+    fn generate_legal(&self, color: ColorChess, origins: Bitboard, moves: &mut MoveList) {
+        let Some(king) = self.find_king(color) else {
+            // Without a king nothing can be left in check
+            let pseudo_legal = self.get_all_moves(color).into_iter();
+            moves.extend(pseudo_legal.filter(|mv| origins & square_bit(mv.from) != 0));
+            return;
+        };
+        let enemy = color.opposite();
+        let occupied = self.occupied();
+        let own = self.occupancy[color.index()];
+        let checkers = self.attackers_to(king, enemy, occupied);
+
+        // The king may not step along a checking ray either, so it is lifted off
+        // the board while its destinations are tested
+        let without_king = occupied & !square_bit(king);
+        let king_moves = origins & square_bit(king) != 0;
+        let king_targets = if king_moves {
+            king_attacks(king) & !own
+        } else {
+            0
+        };
+        for to in squares(king_targets) {
+            if self.attackers_to(to, enemy, without_king) == 0 {
+                moves.push(self.classify_move(king, to));
+            }
+        }
+        // Only the king can answer a double check
+        if checkers.count_ones() > 1 {
+            return;
+        }
+        if checkers == 0 && king_moves {
+            for to in [(king.0, 6), (king.0, 2)] {
+                if self.is_valid_castling(king, to, color) {
+                    moves.push(self.classify_move(king, to));
+                }
+            }
+        }
+
+        // Other pieces must capture a lone checker or step in front of it, and a
+        // pinned piece may only move along its pin
+        let evasions = match squares(checkers).next() {
+            Some(checker) => square_bit(checker) | between(king, checker),
+            None => !0,
+        };
+        let pin_rays = self.pin_rays(king, color);
+
+        for from in squares(own & origins & !square_bit(king)) {
+            let Some(piece) = self.squares[from.0][from.1] else {
+                continue;
+            };
+            let reach = match piece.piece_type() {
+                PieceType::Pawn => self.pawn_targets(from, color),
+                PieceType::Knight => knight_attacks(from),
+                PieceType::Bishop => bishop_attacks(from, occupied),
+                PieceType::Rook => rook_attacks(from, occupied),
+                PieceType::Queen => bishop_attacks(from, occupied) | rook_attacks(from, occupied),
+                PieceType::King => 0,
+            };
+            let targets = reach & !own & evasions & pin_rays[square_index(from)];
+            for to in squares(targets) {
+                push_with_promotions(moves, self.classify_move(from, to));
+            }
+
+            if piece.is_type(PieceType::Pawn)
+                && let Some(to) = self.en_passant_target
+                && pawn_attacks(from, color) & square_bit(to) != 0
+                && self.is_safe_en_passant(king, from, to, color)
+            {
+                moves.push(self.classify_move(from, to));
+            }
+        }
+    }
+
+    // For each square, where a piece there may move without exposing the king:
+    // anywhere, or for a pinned piece the squares between the king and the
+    // pinning piece, including the capture of that piece
+    fn pin_rays(&self, king: (usize, usize), color: ColorChess) -> [Bitboard; 64] {
+        let mut rays = [!0; 64];
+        let enemy = color.opposite();
+        let queens = self.pieces_of(enemy, PieceType::Queen);
+        let snipers = bishop_attacks(king, 0) & (self.pieces_of(enemy, PieceType::Bishop) | queens)
+            | rook_attacks(king, 0) & (self.pieces_of(enemy, PieceType::Rook) | queens);
+        for sniper in squares(snipers) {
+            let ray = between(king, sniper);
+            let blockers = ray & self.occupied();
+            if blockers.count_ones() == 1 && blockers & self.occupancy[color.index()] != 0 {
+                rays[blockers.trailing_zeros() as usize] = ray | square_bit(sniper);
+            }
+        }
+        rays
+    }
+
+    // Pushes and ordinary captures of the pawn on `from`
+    fn pawn_targets(&self, from: (usize, usize), color: ColorChess) -> Bitboard {
+        let empty = !self.occupied();
+        let (forward, start_row) = match color {
+            ColorChess::White => (from.0 + 1, 1),
+            ColorChess::Black => (from.0.wrapping_sub(1), 6),
+        };
+        let mut targets = pawn_attacks(from, color) & self.occupancy[color.opposite().index()];
+        if forward < 8 && empty & square_bit((forward, from.1)) != 0 {
+            targets |= square_bit((forward, from.1));
+            if from.0 == start_row {
+                let double = square_bit((2 * forward - from.0, from.1));
+                targets |= double & empty;
+            }
+        }
+        targets
+    }
+
+    // En passant takes two pawns off one rank at once, which can uncover the
+    // king where no single pin shows it, so the position after it is tested
+    fn is_safe_en_passant(
+        &self,
+        king: (usize, usize),
+        from: (usize, usize),
+        to: (usize, usize),
+        color: ColorChess,
+    ) -> bool {
+        let enemy = color.opposite();
+        let captured = square_bit((from.0, to.1));
+        if self.pieces_of(enemy, PieceType::Pawn) & captured == 0 {
+            return false;
+        }
+        let after = (self.occupied() & !square_bit(from) & !captured) | square_bit(to);
+        self.attackers_to(king, enemy, after) & !captured == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::position::Position;
+
+    // The perft positions of the Chess Programming Wiki with their counts
+    // for depth 1 upwards, kept to depths a debug build runs quickly
+    const PERFT: [(&str, &[u64]); 6] = [
+        (
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            &[20, 400, 8902, 197281],
+        ),
+        (
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            &[48, 2039, 97862, 4085603],
+        ),
+        (
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            &[14, 191, 2812, 43238, 674624],
+        ),
+        (
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            &[6, 264, 9467, 422333],
+        ),
+        (
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+            &[44, 1486, 62379],
+        ),
+        (
+            "r4rk1/1pp1qppp/p1np1n2/2b1p1B1/2B1P1b1/P1NP1N2/1PP1QPPP/R4RK1 w - - 0 10",
+            &[46, 2079, 89890],
+        ),
+    ];
+
+    #[test]
+    fn perft_matches_reference_counts() {
+        for (fen, counts) in PERFT {
+            let mut position = Position::from_fen(fen).unwrap();
+            let start = position;
+            for (depth, &count) in (1..).zip(counts) {
+                assert_eq!(position.perft(depth), count, "{} at depth {}", fen, depth);
+            }
+            // make and unmake leave the position as it was
+            assert!(position == start, "{} changed by perft", fen);
+        }
+    }
+
+    #[test]
+    fn legal_moves_from_a_square_are_that_pieces_moves() {
+        let position = Position::from_fen(PERFT[1].0).unwrap();
+        let all = position.legal_moves();
+        for row in 0..8 {
+            for col in 0..8 {
+                let from = position.legal_moves_from((row, col));
+                let expected = all.iter().filter(|mv| mv.from == (row, col)).count();
+                assert_eq!(from.len(), expected);
+                assert!(from.iter().all(|mv| mv.from == (row, col)));
+            }
+        }
+    }
+}
@@ -0,0 +1,474 @@
+use crate::bitboard::{Bitboard, bishop_attacks, rook_attacks, square_bit, squares};
+use crate::board::{ColorChess, Piece, PieceType, square_name};
+use crate::position::Position;
+
+// Metadata describing what kind of move a Move is (one bit per property)
+#[derive(Copy, Clone, PartialEq, Eq, Debug, Default)]
+pub struct MoveFlags(u8);
+
+impl MoveFlags {
+    pub const QUIET: MoveFlags = MoveFlags(0b0000_0000);
+    pub const CAPTURE: MoveFlags = MoveFlags(0b0000_0001);
+    pub const DOUBLE_PAWN_PUSH: MoveFlags = MoveFlags(0b0000_0010);
+    pub const EN_PASSANT: MoveFlags = MoveFlags(0b0000_0100);
+    pub const CASTLE_KING_SIDE: MoveFlags = MoveFlags(0b0000_1000);
+    pub const CASTLE_QUEEN_SIDE: MoveFlags = MoveFlags(0b0001_0000);
+    pub const PROMOTION: MoveFlags = MoveFlags(0b0010_0000);
+
+    pub fn contains(self, other: MoveFlags) -> bool {
+        self.0 & other.0 == other.0
+    }
+}
+
+impl std::ops::BitOr for MoveFlags {
+    type Output = MoveFlags;
+
+    fn bitor(self, rhs: MoveFlags) -> MoveFlags {
+        MoveFlags(self.0 | rhs.0)
+    }
+}
+
+impl std::ops::BitOrAssign for MoveFlags {
+    fn bitor_assign(&mut self, rhs: MoveFlags) {
+        self.0 |= rhs.0;
+    }
+}
+
+// Pieces a pawn may promote to, in the order they are offered to the player
+pub const PROMOTION_PIECES: [PieceType; 4] = [
+    PieceType::Queen,
+    PieceType::Rook,
+    PieceType::Bishop,
+    PieceType::Knight,
+];
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Move {
+    pub from: (usize, usize),
+    pub to: (usize, usize),
+    pub promotion: Option<PieceType>,
+    pub flags: MoveFlags,
+}
+
+impl Move {
+    // Constructor for a quiet move; use Position::build_move to classify a move in a position
+    pub fn new(from: (usize, usize), to: (usize, usize)) -> Self {
+        Move {
+            from,
+            to,
+            promotion: None,
+            flags: MoveFlags::QUIET,
+        }
+    }
+
+    pub fn with_promotion(self, piece_type: PieceType) -> Self {
+        Move {
+            promotion: Some(piece_type),
+            ..self
+        }
+    }
+
+    // Long algebraic notation used by UCI, e.g. "e2e4" or "e7e8q"
+    pub fn to_uci(&self) -> String {
+        let mut text = square_name(self.from) + &square_name(self.to);
+        if let Some(promotion) = self.promotion {
+            text.push(promotion.letter().to_ascii_lowercase());
+        }
+        text
+    }
+
+    pub fn is_capture(&self) -> bool {
+        self.flags.contains(MoveFlags::CAPTURE)
+    }
+
+    pub fn is_en_passant(&self) -> bool {
+        self.flags.contains(MoveFlags::EN_PASSANT)
+    }
+
+    pub fn is_castling(&self) -> bool {
+        self.flags.contains(MoveFlags::CASTLE_KING_SIDE)
+            || self.flags.contains(MoveFlags::CASTLE_QUEEN_SIDE)
+    }
+
+    pub fn is_double_pawn_push(&self) -> bool {
+        self.flags.contains(MoveFlags::DOUBLE_PAWN_PUSH)
+    }
+
+    pub fn is_promotion(&self) -> bool {
+        self.flags.contains(MoveFlags::PROMOTION)
+    }
+}
+
+// Room for more moves than any legal position has (the most known is 218)
+pub const MAX_MOVES: usize = 256;
+
+// A list of moves kept on the stack, so generating moves does not allocate
+#[derive(Clone)]
+pub struct MoveList {
+    moves: [Move; MAX_MOVES],
+    len: usize,
+}
+
+impl MoveList {
+    pub fn new() -> Self {
+        MoveList {
+            moves: [Move::new((0, 0), (0, 0)); MAX_MOVES],
+            len: 0,
+        }
+    }
+
+    pub fn push(&mut self, mv: Move) {
+        self.moves[self.len] = mv;
+        self.len += 1;
+    }
+
+    pub fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Default for MoveList {
+    fn default() -> Self {
+        MoveList::new()
+    }
+}
+
+impl std::ops::Deref for MoveList {
+    type Target = [Move];
+
+    fn deref(&self) -> &[Move] {
+        &self.moves[..self.len]
+    }
+}
+
+impl std::ops::DerefMut for MoveList {
+    fn deref_mut(&mut self) -> &mut [Move] {
+        &mut self.moves[..self.len]
+    }
+}
+
+impl Extend<Move> for MoveList {
+    fn extend<I: IntoIterator<Item = Move>>(&mut self, moves: I) {
+        for mv in moves {
+            self.push(mv);
+        }
+    }
+}
+
+impl IntoIterator for MoveList {
+    type Item = Move;
+    type IntoIter = std::iter::Take<std::array::IntoIter<Move, MAX_MOVES>>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.moves.into_iter().take(self.len)
+    }
+}
+
+impl<'a> IntoIterator for &'a MoveList {
+    type Item = &'a Move;
+    type IntoIter = std::slice::Iter<'a, Move>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl Position {
+    pub fn is_valid_move(
+        &self,
+        start: (usize, usize),
+        end: (usize, usize),
+        color: ColorChess,
+    ) -> bool {
+        let (start_x, start_y) = start;
+        let (end_x, end_y) = end;
+
+        if start == end || start_x >= 8 || start_y >= 8 || end_x >= 8 || end_y >= 8 {
+            return false;
+        }
+        if let Some(piece) = &self.squares[start_x][start_y] {
+            if piece.color() != color {
+                return false;
+            }
+            match piece.piece_type() {
+                PieceType::Pawn => self.is_valid_pawn_move(start, end, color),
+                PieceType::Knight => self.is_valid_knight_move(start, end, color),
+                PieceType::Bishop => self.is_valid_bishop_move(start, end, color),
+                PieceType::Rook => self.is_valid_rook_move(start, end, color),
+                PieceType::Queen => self.is_valid_queen_move(start, end, color),
+                PieceType::King => self.is_valid_king_move(start, end, color),
+            }
+        } else {
+            false
+        }
+    }
+
+    pub fn get_all_moves(&self, color: ColorChess) -> Vec<Move> {
+        let mut moves = Vec::new();
+        for start in squares(self.occupancy[color.index()]) {
+            let Some(piece) = self.squares[start.0][start.1] else {
+                continue;
+            };
+            for end in squares(self.candidate_targets(start, piece)) {
+                if self.is_valid_move(start, end, color) {
+                    push_with_promotions(&mut moves, self.classify_move(start, end));
+                }
+            }
+        }
+        moves
+    }
+
+    // Destinations worth trying for a piece: sliders are limited to the squares
+    // their attack tables reach, other pieces are tried on every square not
+    // holding a piece of their own
+    fn candidate_targets(&self, start: (usize, usize), piece: Piece) -> Bitboard {
+        let occupied = self.occupied();
+        let reach = match piece.piece_type() {
+            PieceType::Bishop => bishop_attacks(start, occupied),
+            PieceType::Rook => rook_attacks(start, occupied),
+            PieceType::Queen => bishop_attacks(start, occupied) | rook_attacks(start, occupied),
+            _ => !0,
+        };
+        reach & !self.occupancy[piece.color().index()]
+    }
+
+    fn is_valid_pawn_move(
+        &self,
+        start: (usize, usize),
+        end: (usize, usize),
+        color: ColorChess,
+    ) -> bool {
+        let (start_x, start_y) = start;
+        let (end_x, end_y) = end;
+
+        // Standard pawn moves
+        if color == ColorChess::White {
+            // One step forward
+            if start_x + 1 == end_x && start_y == end_y && self.squares[end_x][end_y].is_none() {
+                return true;
+            }
+            // Two steps forward from starting position
+            if start_x == 1
+                && end_x == 3
+                && start_y == end_y
+                && self.squares[2][end_y].is_none()
+                && self.squares[end_x][end_y].is_none()
+            {
+                return true;
+            }
+            // Capturing diagonally
+            if start_x + 1 == end_x
+                && (start_y as isize - end_y as isize).abs() == 1
+                && let Some(piece) = &self.squares[end_x][end_y]
+                && piece.color() == ColorChess::Black
+            {
+                return true;
+            }
+        } else {
+            // Black pawn
+            // One step forward
+            if start_x > 0
+                && start_x - 1 == end_x
+                && start_y == end_y
+                && self.squares[end_x][end_y].is_none()
+            {
+                return true;
+            }
+            // Two steps forward from starting position
+            if start_x == 6
+                && end_x == 4
+                && start_y == end_y
+                && self.squares[5][end_y].is_none()
+                && self.squares[end_x][end_y].is_none()
+            {
+                return true;
+            }
+            // Capturing diagonally
+            if start_x > 0
+                && start_x - 1 == end_x
+                && (start_y as isize - end_y as isize).abs() == 1
+                && let Some(piece) = &self.squares[end_x][end_y]
+                && piece.color() == ColorChess::White
+            {
+                return true;
+            }
+        }
+
+        // En passant
+        if (start_y as isize - end_y as isize).abs() == 1
+            && let Some(target) = self.en_passant_target
+        {
+            if color == ColorChess::White {
+                if start_x == 4 && end_x == 5 && end == target {
+                    // Check if the pawn to be captured is actually there
+                    if let Some(pawn_to_capture) = &self.squares[start_x][end_y]
+                        && pawn_to_capture.is_type(PieceType::Pawn)
+                        && pawn_to_capture.is_color(ColorChess::Black)
+                    {
+                        return true;
+                    }
+                }
+            } else {
+                // Black pawn
+                if start_x == 3 && end_x == 2 && end == target {
+                    // Check if the pawn to be captured is actually there
+                    if let Some(pawn_to_capture) = &self.squares[start_x][end_y]
+                        && pawn_to_capture.is_type(PieceType::Pawn)
+                        && pawn_to_capture.is_color(ColorChess::White)
+                    {
+                        return true;
+                    }
+                }
+            }
+        }
+
+        false
+    }
+
+    fn is_valid_bishop_move(
+        &self,
+        start: (usize, usize),
+        end: (usize, usize),
+        color: ColorChess,
+    ) -> bool {
+        let targets = bishop_attacks(start, self.occupied()) & !self.occupancy[color.index()];
+        targets & square_bit(end) != 0
+    }
+
+    fn is_valid_rook_move(
+        &self,
+        start: (usize, usize),
+        end: (usize, usize),
+        color: ColorChess,
+    ) -> bool {
+        let targets = rook_attacks(start, self.occupied()) & !self.occupancy[color.index()];
+        targets & square_bit(end) != 0
+    }
+
+    fn is_valid_knight_move(
+        &self,
+        start: (usize, usize),
+        end: (usize, usize),
+        color: ColorChess,
+    ) -> bool {
+        let (start_x, start_y) = start;
+        let (end_x, end_y) = end;
+
+        let dx = (end_x as isize - start_x as isize).abs();
+        let dy = (end_y as isize - start_y as isize).abs();
+
+        if (dx == 2 && dy == 1) || (dx == 1 && dy == 2) {
+            return self.squares[end_x][end_y].is_none()
+                || self.squares[end_x][end_y].is_some_and(|p| p.color() != color);
+        }
+        false
+    }
+
+    fn is_valid_queen_move(
+        &self,
+        start: (usize, usize),
+        end: (usize, usize),
+        color: ColorChess,
+    ) -> bool {
+        self.is_valid_rook_move(start, end, color) || self.is_valid_bishop_move(start, end, color)
+    }
+
+    fn is_valid_king_move(
+        &self,
+        start: (usize, usize),
+        end: (usize, usize),
+        color: ColorChess,
+    ) -> bool {
+        let (start_x, start_y) = start;
+        let (end_x, end_y) = end;
+
+        // Check for castling first
+        if self.is_valid_castling(start, end, color) {
+            return true;
+        }
+
+        let dx = (end_x as isize - start_x as isize).abs();
+        let dy = (end_y as isize - start_y as isize).abs();
+
+        if dx <= 1 && dy <= 1 {
+            if let Some(piece) = &self.squares[end_x][end_y] {
+                piece.color() != color
+            } else {
+                true
+            }
+        } else {
+            false
+        }
+    }
+
+    // Whether the king may castle from `start` to `end`: castling_path allows
+    // it, the king is not in check, and it neither passes through nor lands
+    // on an attacked square
+    pub(crate) fn is_valid_castling(
+        &self,
+        start: (usize, usize),
+        end: (usize, usize),
+        color: ColorChess,
+    ) -> bool {
+        self.castling_path(start, end, color).is_some_and(|path| {
+            !self.is_in_check(color)
+                && path
+                    .iter()
+                    .all(|&square| !self.is_square_attacked(square, color.opposite()))
+        })
+    }
+
+    // The squares the king passes through and lands on when castling from
+    // `start` to `end`, if the king and that rook have not moved and the
+    // squares between them are empty. Attacks on them are left to the caller
+    pub(crate) fn castling_path(
+        &self,
+        start: (usize, usize),
+        end: (usize, usize),
+        color: ColorChess,
+    ) -> Option<[(usize, usize); 2]> {
+        let (row, king_moved, king_side_moved, queen_side_moved) = match color {
+            ColorChess::White => (
+                0,
+                self.white_king_moved,
+                self.white_rook_king_side_moved,
+                self.white_rook_queen_side_moved,
+            ),
+            ColorChess::Black => (
+                7,
+                self.black_king_moved,
+                self.black_rook_king_side_moved,
+                self.black_rook_queen_side_moved,
+            ),
+        };
+        if start != (row, 4) || king_moved {
+            return None;
+        }
+        let (rook_moved, between, path) = match end.1 {
+            6 if end.0 == row => (king_side_moved, 5..7, [(row, 5), (row, 6)]),
+            2 if end.0 == row => (queen_side_moved, 1..4, [(row, 3), (row, 2)]),
+            _ => return None,
+        };
+        if rook_moved
+            || between
+                .into_iter()
+                .any(|col| self.squares[row][col].is_some())
+        {
+            return None;
+        }
+        Some(path)
+    }
+}
+
+// Adds a move to the list, expanding a promotion into one move per promotion piece
+pub(crate) fn push_with_promotions(moves: &mut impl Extend<Move>, mv: Move) {
+    if mv.is_promotion() {
+        moves.extend(
+            PROMOTION_PIECES
+                .iter()
+                .map(|&piece_type| mv.with_promotion(piece_type)),
+        );
+    } else {
+        moves.extend([mv]);
+    }
+}
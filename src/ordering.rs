@@ -0,0 +1,127 @@
+use crate::board::PieceType;
+use crate::eval::piece_value;
+use crate::moves::{MAX_MOVES, Move};
+use crate::position::Position;
+
+// Deepest ply that keeps its own killer moves
+const MAX_PLY: usize = 128;
+
+const HASH_MOVE_SCORE: i32 = 1_000_000;
+const CAPTURE_SCORE: i32 = 100_000;
+const PROMOTION_SCORE: i32 = 90_000;
+const KILLER_SCORE: i32 = 80_000;
+
+// Orders moves so that alpha-beta sees the likely best ones first: the
+// transposition table move, then captures by MVV-LVA, then killer moves, then
+// quiet moves by history score
+pub(crate) struct MoveOrdering {
+    // Two quiet moves per ply that recently caused a beta cutoff
+    killers: Vec<[Option<Move>; 2]>,
+    // Cutoff counts for quiet moves, indexed by [from square][to square]
+    history: Vec<[i32; 64]>,
+}
+
+impl Default for MoveOrdering {
+    fn default() -> Self {
+        MoveOrdering {
+            killers: vec![[None; 2]; MAX_PLY],
+            history: vec![[0; 64]; 64],
+        }
+    }
+}
+
+fn square_index((row, col): (usize, usize)) -> usize {
+    row * 8 + col
+}
+
+impl MoveOrdering {
+    // Killers only make sense within one search; history is aged instead of dropped
+    pub(crate) fn new_search(&mut self) {
+        self.killers.fill([None; 2]);
+        for row in self.history.iter_mut() {
+            for score in row.iter_mut() {
+                *score /= 8;
+            }
+        }
+    }
+
+    pub(crate) fn clear(&mut self) {
+        *self = MoveOrdering::default();
+    }
+
+    pub(crate) fn order(
+        &self,
+        board: &Position,
+        moves: &mut [Move],
+        hash_move: Option<Move>,
+        ply: usize,
+    ) {
+        let mut scores = [0; MAX_MOVES];
+        for (score, &mv) in scores.iter_mut().zip(moves.iter()) {
+            *score = self.score(board, mv, hash_move, ply);
+        }
+        // Insertion sort: stable, allocation-free and quick on lists this short
+        for i in 1..moves.len() {
+            let (mv, score) = (moves[i], scores[i]);
+            let mut j = i;
+            while j > 0 && scores[j - 1] < score {
+                moves[j] = moves[j - 1];
+                scores[j] = scores[j - 1];
+                j -= 1;
+            }
+            moves[j] = mv;
+            scores[j] = score;
+        }
+    }
+
+    fn score(&self, board: &Position, mv: Move, hash_move: Option<Move>, ply: usize) -> i32 {
+        if Some(mv) == hash_move {
+            return HASH_MOVE_SCORE;
+        }
+        if mv.is_capture() {
+            return CAPTURE_SCORE + mvv_lva(board, mv);
+        }
+        if let Some(promotion) = mv.promotion {
+            return PROMOTION_SCORE + piece_value(promotion);
+        }
+        if let Some(killers) = self.killers.get(ply) {
+            if killers[0] == Some(mv) {
+                return KILLER_SCORE;
+            }
+            if killers[1] == Some(mv) {
+                return KILLER_SCORE - 1;
+            }
+        }
+        self.history[square_index(mv.from)][square_index(mv.to)]
+    }
+
+    // Remembers a quiet move that refuted the previous move
+    pub(crate) fn record_cutoff(&mut self, mv: Move, ply: usize, depth: u32) {
+        if mv.is_capture() || mv.is_promotion() {
+            return;
+        }
+        if let Some(killers) = self.killers.get_mut(ply)
+            && killers[0] != Some(mv)
+        {
+            killers[1] = killers[0];
+            killers[0] = Some(mv);
+        }
+        let score = &mut self.history[square_index(mv.from)][square_index(mv.to)];
+        *score = (*score + (depth * depth) as i32).min(KILLER_SCORE - 2);
+    }
+}
+
+// Most valuable victim, least valuable attacker: PxQ comes first, QxP last
+fn mvv_lva(board: &Position, mv: Move) -> i32 {
+    let victim = if mv.is_en_passant() {
+        PieceType::Pawn
+    } else {
+        board
+            .get_piece(mv.to)
+            .map_or(PieceType::Pawn, |piece| piece.piece_type())
+    };
+    let attacker = board
+        .get_piece(mv.from)
+        .map_or(PieceType::Pawn, |piece| piece.piece_type());
+    10 * piece_value(victim) - piece_value(attacker)
+}
@@ -0,0 +1,597 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::eco;
+use crate::error::ChessError;
+use crate::fen::START_FEN;
+use crate::game::Game;
+use crate::moves::Move;
+use crate::rules::{GameResult, Termination};
+use crate::search::{MATE_SCORE, MATE_THRESHOLD};
+use crate::tree::{Eval, GameTree, ROOT, parse_nag};
+
+// Longest line of movetext, as the PGN standard asks for export format
+const PGN_LINE_WIDTH: usize = 79;
+
+// How long a move took and, in a timed game, what was left on the mover's
+// clock after it; exported as [%emt] and [%clk] comments
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct MoveTiming {
+    pub spent: Duration,
+    pub clock: Option<Duration>,
+}
+
+impl Game {
+    // The game played so far as PGN, with the seven standard tags, Termination
+    // when the result's reason is known, ECO and Opening when it began with a
+    // known opening and, for games that did not start from the usual
+    // position, SetUp and FEN.
+    // `timings` holds each move's timing by ply, where it is known
+    pub fn to_pgn(
+        &self,
+        white: &str,
+        black: &str,
+        result: Option<GameResult>,
+        timings: &[Option<MoveTiming>],
+    ) -> String {
+        GameTree::from_board(self).to_pgn(self.history.len(), white, black, result, timings)
+    }
+}
+
+impl GameTree {
+    // The tree as PGN, as Game::to_pgn writes a game, with the variations in
+    // parentheses; the main line is cut short after `plies` moves
+    pub fn to_pgn(
+        &self,
+        plies: usize,
+        white: &str,
+        black: &str,
+        result: Option<GameResult>,
+        timings: &[Option<MoveTiming>],
+    ) -> String {
+        let start = self.start();
+        let result_tag = result.map_or("*", |result| result.pgn_tag());
+
+        let mut tags = vec![
+            ("Event", "Casual game".to_string()),
+            ("Site", "chess-rs".to_string()),
+            ("Date", pgn_date(SystemTime::now())),
+            ("Round", "-".to_string()),
+            ("White", white.to_string()),
+            ("Black", black.to_string()),
+            ("Result", result_tag.to_string()),
+        ];
+        if let Some(termination) = result.and_then(|result| result.reason.pgn_tag()) {
+            tags.push(("Termination", termination.to_string()));
+        }
+        // The opening the main line starts with, as far as it is exported
+        let moves: Vec<Move> = self
+            .main_line()
+            .into_iter()
+            .take(plies)
+            .filter_map(|node| self.mv(node))
+            .collect();
+        if let Some(opening) = eco::classify(start, &moves) {
+            tags.push(("ECO", opening.eco.to_string()));
+            tags.push(("Opening", opening.name.to_string()));
+        }
+        let start_fen = start.to_fen();
+        if start_fen != START_FEN {
+            tags.push(("SetUp", "1".to_string()));
+            tags.push(("FEN", start_fen));
+        }
+
+        let mut pgn = String::new();
+        for (name, value) in tags {
+            let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+            pgn.push_str(&format!("[{} \"{}\"]\n", name, value));
+        }
+        pgn.push('\n');
+
+        // Moves carry the engine's eval, and those of the game line their
+        // timing, as commands ahead of the comment, as in
+        // "1. e4 {[%eval 0.3] [%clk 0:03:00] [%emt 0:00:02] Best by test}"
+        let comment = |node: usize| {
+            let mut parts = Vec::new();
+            if let Some(eval) = self.eval(node) {
+                parts.push(format!("[%eval {}]", pgn_eval(eval.white_score)));
+            }
+            let timing = match self.depth(node) {
+                0 => None,
+                _ if !self.is_main_line(node) => None,
+                depth => timings.get(depth - 1).copied().flatten(),
+            };
+            if let Some(timing) = timing {
+                if let Some(clock) = timing.clock {
+                    parts.push(format!("[%clk {}]", pgn_time(clock)));
+                }
+                parts.push(format!("[%emt {}]", pgn_time(timing.spent)));
+            }
+            parts.extend(self.comment(node).map(|text| text.replace('}', ")")));
+            (!parts.is_empty()).then(|| parts.join(" "))
+        };
+        let mut tokens: Vec<String> = self
+            .movetext(plies, comment)
+            .into_iter()
+            .map(|token| token.text)
+            .collect();
+        tokens.push(result_tag.to_string());
+
+        let mut line = String::new();
+        for token in &tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > PGN_LINE_WIDTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+        pgn
+    }
+}
+
+// A game read from PGN: its tags, a board with the moves of its main line
+// played on it, and the moves with their variations
+pub struct PgnGame {
+    pub tags: Vec<(String, String)>,
+    pub board: Game,
+    pub tree: GameTree,
+}
+
+impl PgnGame {
+    pub fn tag(&self, name: &str) -> Option<&str> {
+        self.tags
+            .iter()
+            .find(|(tag, _)| tag == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    // The "Result" tag, unless the game is unfinished. The reason comes from
+    // the Termination tag where it names one, else from the final position
+    // when that decided the game
+    pub fn result(&self) -> Option<GameResult> {
+        let reason = match self.tag("Termination").and_then(Termination::from_pgn_tag) {
+            Some(reason) => reason,
+            None => self
+                .board
+                .get_result()
+                .filter(|result| Some(result.pgn_tag()) == self.tag("Result"))
+                .map_or(Termination::Unknown, |result| result.reason),
+        };
+        GameResult::from_pgn_tag(self.tag("Result")?, reason)
+    }
+}
+
+// Reads the first game in PGN text, with its variations, NAGs, comments and
+// any engine evals in them; a FEN tag sets the starting position
+pub fn parse_pgn(text: &str) -> Result<PgnGame, ChessError> {
+    let (tags, start, movetext) = split_pgn(text).map_err(ChessError::BadPgn)?;
+    let tree = read_tree(start, &movetext).map_err(ChessError::BadPgn)?;
+    let board = tree.board_at(tree.line_end(ROOT));
+    Ok(PgnGame { tags, board, tree })
+}
+
+// Reads the first game in PGN text as every line of play through it, the
+// main line and each variation followed to its end, along with the position
+// they start from; used for opening repertoires
+pub fn parse_pgn_lines(text: &str) -> Result<(Game, Vec<Vec<Move>>), ChessError> {
+    let (_, start, movetext) = split_pgn(text).map_err(ChessError::BadPgn)?;
+    let tree = read_tree(start, &movetext).map_err(ChessError::BadPgn)?;
+    // Each line ends where the tree does, the main line first
+    let mut lines = Vec::new();
+    let mut open = vec![ROOT];
+    while let Some(node) = open.pop() {
+        let children = tree.children(node);
+        if children.is_empty() && node != ROOT {
+            lines.push(tree.moves_to(node));
+        }
+        open.extend(children.iter().rev());
+    }
+    Ok((tree.start().clone(), lines))
+}
+
+// The moves of movetext as a tree, a variation in parentheses replacing the
+// move before it
+fn read_tree(start: Game, movetext: &str) -> Result<GameTree, String> {
+    let mut tree = GameTree::new(start);
+    let mut board = tree.start().clone();
+    let mut node = ROOT;
+    // The node each open variation goes back to once it closes
+    let mut branches = Vec::new();
+    for token in movetext_tokens(movetext) {
+        let token = token.as_str();
+        match token {
+            _ if token.starts_with('{') => {
+                let (text, eval) = read_comment(token);
+                if let Some(eval) = eval {
+                    tree.record_eval(node, eval);
+                }
+                if !text.is_empty() {
+                    tree.add_comment(node, &text);
+                }
+            }
+            "(" => {
+                branches.push(node);
+                node = tree
+                    .parent(node)
+                    .ok_or("a variation before the first move")?;
+                board = tree.board_at(node);
+            }
+            ")" => {
+                node = branches.pop().ok_or("unbalanced parentheses")?;
+                board = tree.board_at(node);
+            }
+            _ if is_result(token) => break,
+            _ if token.starts_with('$') => {
+                if let Some(nag) = parse_nag(token) {
+                    tree.annotate(node, nag);
+                }
+            }
+            _ => {
+                let Some(token) = move_token(token) else {
+                    continue;
+                };
+                // Assessments follow the move, as in "Nf3!?", or stand apart
+                let (san, symbol) = token.split_at(token.find(['!', '?']).unwrap_or(token.len()));
+                if !san.is_empty() {
+                    let mv = parse_move(&board, san)?;
+                    board.make(mv)?;
+                    node = tree.add(node, mv);
+                }
+                if let Some(nag) = parse_nag(symbol) {
+                    tree.annotate(node, nag);
+                }
+            }
+        }
+    }
+    if !branches.is_empty() {
+        return Err("unbalanced parentheses".to_string());
+    }
+    Ok(tree)
+}
+
+// Name and value of each tag pair, such as ("White", "Carlsen, Magnus")
+type Tags = Vec<(String, String)>;
+
+// Movetext split into moves, move numbers, NAGs, parentheses and comments,
+// each comment one token with its braces
+fn movetext_tokens(movetext: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut in_comment = false;
+    for letter in movetext.chars() {
+        if in_comment {
+            token.push(letter);
+            if letter == '}' {
+                tokens.push(std::mem::take(&mut token));
+                in_comment = false;
+            }
+            continue;
+        }
+        let apart = letter.is_whitespace() || matches!(letter, '{' | '(' | ')');
+        if apart && !token.is_empty() {
+            tokens.push(std::mem::take(&mut token));
+        }
+        match letter {
+            '{' => {
+                token.push(letter);
+                in_comment = true;
+            }
+            '(' | ')' => tokens.push(letter.to_string()),
+            _ if letter.is_whitespace() => {}
+            _ => token.push(letter),
+        }
+    }
+    if !token.is_empty() {
+        tokens.push(token);
+    }
+    tokens
+}
+
+// The text of a comment without its commands, such as "[%clk 0:03:00]",
+// and the eval of an "[%eval 0.35]" or "[%eval #-3]" command if it has one
+fn read_comment(comment: &str) -> (String, Option<Eval>) {
+    let mut rest = comment.trim_start_matches('{').trim_end_matches('}');
+    let mut text = Vec::new();
+    let mut eval = None;
+    while let Some(start) = rest.find("[%") {
+        text.push(&rest[..start]);
+        let close = rest[start..]
+            .find(']')
+            .map_or(rest.len(), |close| start + close);
+        let command = &rest[start + 2..close];
+        if let Some(value) = command.strip_prefix("eval ") {
+            eval = eval.or(parse_eval(value));
+        }
+        rest = &rest[(close + 1).min(rest.len())..];
+    }
+    text.push(rest);
+    let text = text
+        .join(" ")
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ");
+    (text, eval)
+}
+
+// An eval as "[%eval]" writes it: pawns, or "#n" for mate in n moves, "#-n"
+// when Black mates
+fn pgn_eval(white_score: i32) -> String {
+    if white_score.abs() >= MATE_THRESHOLD {
+        let moves = (MATE_SCORE - white_score.abs() + 1) / 2;
+        format!("#{}", moves * white_score.signum())
+    } else {
+        format!("{:.2}", white_score as f64 / 100.0)
+    }
+}
+
+// An "[%eval]" value, which may give the depth after a comma, as in "0.35,24"
+fn parse_eval(value: &str) -> Option<Eval> {
+    let (score, depth) = value.trim().split_once(',').unwrap_or((value.trim(), ""));
+    let white_score = match score.strip_prefix('#') {
+        Some(moves) => {
+            let moves: i32 = moves.parse().ok()?;
+            (MATE_SCORE - (2 * moves.abs() - 1)) * moves.signum()
+        }
+        None => (score.parse::<f64>().ok()? * 100.0).round() as i32,
+    };
+    Some(Eval {
+        white_score,
+        depth: depth.parse().unwrap_or(0),
+    })
+}
+
+// A game's tag pairs and starting position, and its movetext
+fn split_pgn(text: &str) -> Result<(Tags, Game, String), String> {
+    let mut tags = Vec::new();
+    let mut lines = text.lines().map(str::trim).peekable();
+    // Tag pairs, such as [White "Carlsen, Magnus"], come before the moves
+    while let Some(line) = lines.next_if(|line| line.is_empty() || line.starts_with('[')) {
+        let Some(pair) = line
+            .strip_prefix('[')
+            .and_then(|pair| pair.strip_suffix(']'))
+        else {
+            continue;
+        };
+        let (name, value) = pair
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("bad tag: {}", line))?;
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(value);
+        tags.push((
+            name.to_string(),
+            value.replace("\\\"", "\"").replace("\\\\", "\\"),
+        ));
+    }
+
+    let board = match tags.iter().find(|(name, _)| name == "FEN") {
+        Some((_, fen)) => Game::from_fen(fen)?,
+        None => Game::new(),
+    };
+
+    // Movetext up to the result, leaving out everything that is not a move
+    let mut movetext = String::new();
+    for line in lines {
+        // A line starting with '%' is an escape, and ';' comments to the end of the line
+        if line.starts_with('%') {
+            continue;
+        }
+        movetext.push_str(line.split(';').next().unwrap_or_default());
+        movetext.push(' ');
+    }
+    Ok((tags, board, movetext))
+}
+
+fn is_result(token: &str) -> bool {
+    matches!(token, "1-0" | "0-1" | "1/2-1/2" | "*")
+}
+
+// The move in a token of movetext, or None for move numbers and NAGs; move
+// numbers may be run into the move, as in "1.e4" or "12...Nf6"
+fn move_token(token: &str) -> Option<&str> {
+    let san = token.rsplit('.').next().unwrap_or_default();
+    if san.is_empty() || san.starts_with('$') || san.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    Some(san)
+}
+
+fn parse_move(board: &Game, san: &str) -> Result<Move, String> {
+    board.parse_san(san).map_err(|_| {
+        format!(
+            "illegal move {} at move {}",
+            san,
+            board.get_fullmove_number()
+        )
+    })
+}
+
+// Reads every game in PGN text, such as all the games of a tournament round
+pub fn parse_pgn_games(text: &str) -> Result<Vec<PgnGame>, ChessError> {
+    split_pgn_games(text)
+        .iter()
+        .map(|game| parse_pgn(game))
+        .collect()
+}
+
+// The text of each game in PGN text, to be read one at a time
+pub fn split_pgn_games(text: &str) -> Vec<String> {
+    // A game ends where the tags of the next one begin
+    let mut games = Vec::new();
+    let mut game = String::new();
+    let mut in_moves = false;
+    for line in text.lines() {
+        let is_tag = line.trim_start().starts_with('[');
+        if is_tag && in_moves {
+            games.push(std::mem::take(&mut game));
+            in_moves = false;
+        }
+        in_moves |= !is_tag && !line.trim().is_empty();
+        game.push_str(line);
+        game.push('\n');
+    }
+    if !game.trim().is_empty() {
+        games.push(game);
+    }
+    games
+}
+
+// A duration as the clock comments write it, "h:mm:ss" to the nearest second
+fn pgn_time(time: Duration) -> String {
+    let seconds = (time.as_millis() as u64 + 500) / 1000;
+    format!(
+        "{}:{:02}:{:02}",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+// A PGN "Date" value such as "2024.03.09", in UTC
+fn pgn_date(time: SystemTime) -> String {
+    let Ok(elapsed) = time.duration_since(UNIX_EPOCH) else {
+        return "????.??.??".to_string();
+    };
+    // Days since 1970-01-01 to a civil date, counting in 400-year eras that
+    // start on 1 March so that leap days fall at the end of a year
+    let days = (elapsed.as_secs() / 86_400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+    format!("{:04}.{:02}.{:02}", year, month, day)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::board::ColorChess;
+
+    const ANNOTATED: &str = r#"[Event "Club championship"]
+[White "Player, A"]
+[Black "Player, B"]
+[Result "1-0"]
+
+1. e4 e5 (1... c5 2. Nf3 (2. c3 d5) d6) 2. Nf3!? {[%eval 0.35,20] Main line}
+Nc6 $2 3. Bb5 a6?! ; a comment to the end of the line
+4. Ba4 $14 1-0
+"#;
+
+    // The movetext of exported PGN, on one line
+    fn movetext(pgn: &str) -> String {
+        let (_, moves) = pgn.split_once("\n\n").unwrap();
+        moves.split_whitespace().collect::<Vec<_>>().join(" ")
+    }
+
+    #[test]
+    fn reads_tags_variations_nags_and_comments() {
+        let game = parse_pgn(ANNOTATED).unwrap();
+        assert_eq!(game.tag("White"), Some("Player, A"));
+        assert_eq!(game.tag("Site"), None);
+        assert_eq!(
+            game.board.history_san(),
+            ["e4", "e5", "Nf3", "Nc6", "Bb5", "a6", "Ba4"]
+        );
+        assert_eq!(
+            game.result(),
+            Some(GameResult::win_for(ColorChess::White, Termination::Unknown))
+        );
+
+        let tree = &game.tree;
+        let main = tree.main_line();
+        // 1... c5 is the alternative to 1... e5
+        let e4 = main[0];
+        assert_eq!(tree.children(e4).len(), 2);
+        let nf3 = main[2];
+        assert_eq!(tree.nags(nf3), [5]);
+        assert_eq!(tree.comment(nf3), Some("Main line"));
+        let eval = tree.eval(nf3).unwrap();
+        assert_eq!((eval.white_score, eval.depth), (35, 20));
+        assert_eq!(tree.nags(main[3]), [2]);
+        assert_eq!(tree.nags(main[5]), [6]);
+        assert_eq!(tree.nags(main[6]), [14]);
+    }
+
+    #[test]
+    fn reads_every_line_of_play() {
+        let (start, lines) = parse_pgn_lines(ANNOTATED).unwrap();
+        let sans: Vec<String> = lines.iter().map(|line| start.line_to_san(line)).collect();
+        assert_eq!(
+            sans,
+            [
+                "1. e4 e5 2. Nf3 Nc6 3. Bb5 a6 4. Ba4",
+                "1. e4 c5 2. Nf3 d6",
+                "1. e4 c5 2. c3 d5",
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_variations_nags_and_comments_back() {
+        let game = parse_pgn(ANNOTATED).unwrap();
+        let pgn = game
+            .tree
+            .to_pgn(usize::MAX, "Player, A", "Player, B", game.result(), &[]);
+        assert!(pgn.contains("[White \"Player, A\"]\n"));
+        assert!(pgn.contains("[Result \"1-0\"]\n"));
+        assert_eq!(
+            movetext(&pgn),
+            "1. e4 e5 (1... c5 2. Nf3 (2. c3 d5) 2... d6) 2. Nf3!? {[%eval 0.35] Main line} \
+             2... Nc6? 3. Bb5 a6?! 4. Ba4 $14 1-0"
+        );
+        assert!(pgn.lines().all(|line| line.len() <= PGN_LINE_WIDTH));
+        // What is written reads back the same
+        let again = parse_pgn(&pgn).unwrap();
+        assert_eq!(again.board.history_san(), game.board.history_san());
+        assert_eq!(
+            movetext(&again.tree.to_pgn(usize::MAX, "", "", None, &[])),
+            movetext(&pgn).replace(" 1-0", " *")
+        );
+    }
+
+    #[test]
+    fn starts_from_a_fen_tag_and_writes_it_back() {
+        let fen = "4k3/8/8/8/8/8/4P3/4K3 w - - 0 1";
+        let text = format!("[SetUp \"1\"]\n[FEN \"{}\"]\n\n1. e4 Kd7 *\n", fen);
+        let game = parse_pgn(&text).unwrap();
+        assert_eq!(game.tree.start().to_fen(), fen);
+        assert_eq!(game.result(), None);
+        let pgn = game.board.to_pgn("A", "B", None, &[]);
+        assert!(pgn.contains(&format!("[FEN \"{}\"]", fen)));
+        assert_eq!(movetext(&pgn), "1. e4 Kd7 *");
+    }
+
+    #[test]
+    fn refuses_broken_movetext() {
+        for text in [
+            "1. e4 (1. d4 d5",
+            "1. e4 e5)",
+            "( 1. e4 ) 1. d4",
+            "1. e4 e5 2. Ke3",
+        ] {
+            assert!(parse_pgn(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn splits_a_file_into_games() {
+        let text = format!("{}\n{}", ANNOTATED, ANNOTATED);
+        assert_eq!(parse_pgn_games(&text).unwrap().len(), 2);
+    }
+}
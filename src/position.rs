@@ -0,0 +1,529 @@
+use std::hash::{Hash, Hasher};
+
+use crate::bitboard::{Bitboard, square_bit, squares};
+use crate::board::{ColorChess, Piece, PieceType, on_board};
+use crate::error::ChessError;
+use crate::moves::{Move, MoveFlags};
+use crate::zobrist::{piece_key, side_key};
+
+// The pieces on the board and what the rules need to know besides: the side
+// to move, the castling rights, the en passant square and the move clocks.
+// It holds no history, so it is copied freely; the engine searches on it,
+// and Game adds the moves that led to it.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub(crate) squares: [[Option<Piece>; 8]; 8],
+    pub(crate) current_turn: ColorChess,
+    // fields for castling and en passant
+    pub(crate) white_king_moved: bool,
+    pub(crate) black_king_moved: bool,
+    pub(crate) white_rook_king_side_moved: bool,
+    pub(crate) white_rook_queen_side_moved: bool,
+    pub(crate) black_rook_king_side_moved: bool,
+    pub(crate) black_rook_queen_side_moved: bool,
+    pub(crate) en_passant_target: Option<(usize, usize)>,
+    // Plies since the last capture or pawn move, and the move number starting at 1
+    pub(crate) halfmove_clock: u32,
+    pub(crate) fullmove_number: u32,
+    // Zobrist key of the position (its Polyglot key), kept up to date by
+    // make/unmake and switch_turn
+    pub(crate) hash: u64,
+    // Bitboards of each color's pieces by type, and of all its pieces, mirroring
+    // `squares`; change squares through put_piece/take_piece to keep them in step
+    pub(crate) pieces: [[Bitboard; 6]; 2],
+    pub(crate) occupancy: [Bitboard; 2],
+}
+
+// Everything make changes that cannot be re-derived from the move itself,
+// so that the move can be taken back
+#[derive(Clone, Copy, Debug)]
+pub struct MoveRecord {
+    pub mv: Move,
+    pub moved: Piece,
+    pub captured: Option<Piece>,
+    pub turn: ColorChess,
+    castling: [bool; 6],
+    en_passant_target: Option<(usize, usize)>,
+    halfmove_clock: u32,
+    fullmove_number: u32,
+    pub(crate) hash: u64,
+}
+
+// What Position::make hands back so that Position::unmake can take the move back
+#[derive(Clone, Copy, Debug)]
+pub struct Undo(MoveRecord);
+
+impl Undo {
+    pub(crate) fn record(&self) -> MoveRecord {
+        self.0
+    }
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position::new()
+    }
+}
+
+// The key already sums up the position, so it is all a hash map needs
+impl Hash for Position {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        state.write_u64(self.hash);
+    }
+}
+
+impl Position {
+    pub fn new() -> Position {
+        let mut squares = [[None; 8]; 8];
+        squares[1] = [Some(Piece::new(PieceType::Pawn, ColorChess::White)); 8];
+        squares[6] = [Some(Piece::new(PieceType::Pawn, ColorChess::Black)); 8];
+
+        let back_rank = [
+            PieceType::Rook,
+            PieceType::Knight,
+            PieceType::Bishop,
+            PieceType::Queen,
+            PieceType::King,
+            PieceType::Bishop,
+            PieceType::Knight,
+            PieceType::Rook,
+        ];
+
+        for (i, &piece_type) in back_rank.iter().enumerate() {
+            squares[0][i] = Some(Piece::new(piece_type, ColorChess::White));
+            squares[7][i] = Some(Piece::new(piece_type, ColorChess::Black));
+        }
+
+        let mut position = Position {
+            squares,
+            current_turn: ColorChess::White,
+            white_king_moved: false,
+            black_king_moved: false,
+            white_rook_king_side_moved: false,
+            white_rook_queen_side_moved: false,
+            black_rook_king_side_moved: false,
+            black_rook_queen_side_moved: false,
+            en_passant_target: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            hash: 0,
+            pieces: [[0; 6]; 2],
+            occupancy: [0; 2],
+        };
+        position.compute_bitboards();
+        position.hash = position.compute_hash();
+        position
+    }
+
+    // Rebuilds the bitboards from `squares`, after the squares were set directly
+    pub(crate) fn compute_bitboards(&mut self) {
+        self.pieces = [[0; 6]; 2];
+        self.occupancy = [0; 2];
+        for row in 0..8 {
+            for col in 0..8 {
+                if let Some(piece) = self.squares[row][col] {
+                    let bit = square_bit((row, col));
+                    self.pieces[piece.color().index()][piece.piece_type().index()] |= bit;
+                    self.occupancy[piece.color().index()] |= bit;
+                }
+            }
+        }
+    }
+
+    // Puts a piece on an empty square
+    pub(crate) fn put_piece(&mut self, square: (usize, usize), piece: Piece) {
+        let bit = square_bit(square);
+        self.pieces[piece.color().index()][piece.piece_type().index()] |= bit;
+        self.occupancy[piece.color().index()] |= bit;
+        self.squares[square.0][square.1] = Some(piece);
+    }
+
+    // Lifts the piece, if any, off a square
+    pub(crate) fn take_piece(&mut self, square: (usize, usize)) -> Option<Piece> {
+        let piece = self.squares[square.0][square.1].take()?;
+        let bit = square_bit(square);
+        self.pieces[piece.color().index()][piece.piece_type().index()] &= !bit;
+        self.occupancy[piece.color().index()] &= !bit;
+        Some(piece)
+    }
+
+    // Every occupied square
+    pub(crate) fn occupied(&self) -> Bitboard {
+        self.occupancy[0] | self.occupancy[1]
+    }
+
+    // The squares holding the given color's pieces of the given type
+    pub(crate) fn pieces_of(&self, color: ColorChess, piece_type: PieceType) -> Bitboard {
+        self.pieces[color.index()][piece_type.index()]
+    }
+
+    // Builds a Move for the given coordinates, classifying it from the current position.
+    // The move itself is not validated; use is_valid_move for that. Squares
+    // off the board are refused.
+    pub fn build_move(
+        &self,
+        start: (usize, usize),
+        end: (usize, usize),
+    ) -> Result<Move, ChessError> {
+        for square in [start, end] {
+            if !on_board(square) {
+                return Err(ChessError::OutOfBounds(format!("{:?}", square)));
+            }
+        }
+        Ok(self.classify_move(start, end))
+    }
+
+    // build_move for squares known to be on the board, as the move generator's are
+    pub(crate) fn classify_move(&self, start: (usize, usize), end: (usize, usize)) -> Move {
+        let mut mv = Move::new(start, end);
+
+        if self.squares[end.0][end.1].is_some() {
+            mv.flags |= MoveFlags::CAPTURE;
+        }
+
+        if let Some(piece) = self.squares[start.0][start.1] {
+            match piece.piece_type() {
+                PieceType::Pawn => {
+                    if start.0.abs_diff(end.0) == 2 {
+                        mv.flags |= MoveFlags::DOUBLE_PAWN_PUSH;
+                    }
+                    // A diagonal pawn move to an empty square can only be en passant
+                    if start.1 != end.1 && self.squares[end.0][end.1].is_none() {
+                        mv.flags |= MoveFlags::EN_PASSANT | MoveFlags::CAPTURE;
+                    }
+                    if end.0 == 0 || end.0 == 7 {
+                        // The promotion piece is chosen by the caller (see Move::with_promotion)
+                        mv.flags |= MoveFlags::PROMOTION;
+                    }
+                }
+                PieceType::King if start.1.abs_diff(end.1) == 2 => {
+                    if end.1 == 6 {
+                        mv.flags |= MoveFlags::CASTLE_KING_SIDE;
+                    } else if end.1 == 2 {
+                        mv.flags |= MoveFlags::CASTLE_QUEEN_SIDE;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        mv
+    }
+
+    fn castling_state(&self) -> [bool; 6] {
+        [
+            self.white_king_moved,
+            self.black_king_moved,
+            self.white_rook_king_side_moved,
+            self.white_rook_queen_side_moved,
+            self.black_rook_king_side_moved,
+            self.black_rook_queen_side_moved,
+        ]
+    }
+
+    fn restore_castling_state(&mut self, castling: [bool; 6]) {
+        [
+            self.white_king_moved,
+            self.black_king_moved,
+            self.white_rook_king_side_moved,
+            self.white_rook_queen_side_moved,
+            self.black_rook_king_side_moved,
+            self.black_rook_queen_side_moved,
+        ] = castling;
+    }
+
+    // Moves a castling rook along its rank, keeping the hash in step
+    fn move_rook(&mut self, row: usize, from_col: usize, to_col: usize) {
+        if let Some(rook) = self.take_piece((row, from_col)) {
+            self.hash ^= piece_key(rook, (row, from_col)) ^ piece_key(rook, (row, to_col));
+            self.put_piece((row, to_col), rook);
+        }
+    }
+
+    // Plays a move and passes the turn, returning what unmake needs to take it
+    // back. The move is not checked for legality, only refused when it leaves
+    // the board or has no piece to move
+    pub fn make(&mut self, mv: Move) -> Result<Undo, ChessError> {
+        for square in [mv.from, mv.to] {
+            if !on_board(square) {
+                return Err(ChessError::OutOfBounds(format!("{:?}", square)));
+            }
+        }
+        let record = self.apply_move(mv).ok_or_else(|| ChessError::IllegalMove {
+            mv: mv.to_uci(),
+            fen: self.to_fen(),
+        })?;
+        self.switch_turn();
+        Ok(Undo(record))
+    }
+
+    // Takes back a move played with make
+    pub fn unmake(&mut self, undo: Undo) {
+        self.restore(undo.0);
+    }
+
+    // Plays a move without passing the turn. Returns the record to take it
+    // back with, or None (changing nothing) when there is no piece to move or
+    // the move leaves the board.
+    pub(crate) fn apply_move(&mut self, mv: Move) -> Option<MoveRecord> {
+        let (start, end) = (mv.from, mv.to);
+        if !on_board(end) {
+            return None;
+        }
+        let piece_moving = self.get_piece(start)?;
+
+        let mut record = MoveRecord {
+            mv,
+            moved: piece_moving,
+            captured: None,
+            turn: self.current_turn,
+            castling: self.castling_state(),
+            en_passant_target: self.en_passant_target,
+            halfmove_clock: self.halfmove_clock,
+            fullmove_number: self.fullmove_number,
+            hash: self.hash,
+        };
+        // Castling rights and the en passant square are hashed back in at the end
+        let mover = piece_moving.color();
+        self.hash ^= self.castling_key() ^ self.en_passant_key(mover);
+        self.en_passant_target = None;
+
+        // Track king and rook movements for castling validity
+        if piece_moving.is_type(PieceType::King) {
+            if piece_moving.color() == ColorChess::White {
+                self.white_king_moved = true;
+            } else {
+                self.black_king_moved = true;
+            }
+        } else if piece_moving.is_type(PieceType::Rook) {
+            if piece_moving.color() == ColorChess::White {
+                if start == (0, 0) {
+                    self.white_rook_queen_side_moved = true;
+                } else if start == (0, 7) {
+                    self.white_rook_king_side_moved = true;
+                }
+            } else {
+                // Black rook
+                if start == (7, 0) {
+                    self.black_rook_queen_side_moved = true;
+                } else if start == (7, 7) {
+                    self.black_rook_king_side_moved = true;
+                }
+            }
+        }
+
+        // Set en_passant_target to the square behind a pawn that moved two squares
+        if mv.is_double_pawn_push() {
+            self.en_passant_target = Some(((start.0 + end.0) / 2, start.1));
+        }
+
+        // Move the rook alongside the king when castling
+        if mv.flags.contains(MoveFlags::CASTLE_KING_SIDE) {
+            self.move_rook(start.0, 7, 5);
+        } else if mv.flags.contains(MoveFlags::CASTLE_QUEEN_SIDE) {
+            self.move_rook(start.0, 0, 3);
+        }
+
+        // Handle en passant capture: the captured pawn sits beside the start square
+        if mv.is_en_passant()
+            && let Some(captured) = self.take_piece((start.0, end.1))
+        {
+            self.hash ^= piece_key(captured, (start.0, end.1));
+            record.captured = Some(captured);
+        }
+
+        // Capture logic for regular moves
+        if let Some(captured) = self.take_piece(end) {
+            self.hash ^= piece_key(captured, end);
+            record.captured = Some(captured);
+
+            // A rook captured on its starting square can no longer castle, nor
+            // can a side whose king is taken, as in fog-of-war chess
+            match end {
+                (0, 0) => self.white_rook_queen_side_moved = true,
+                (0, 7) => self.white_rook_king_side_moved = true,
+                (7, 0) => self.black_rook_queen_side_moved = true,
+                (7, 7) => self.black_rook_king_side_moved = true,
+                _ if captured.is_type(PieceType::King) => match captured.color() {
+                    ColorChess::White => self.white_king_moved = true,
+                    ColorChess::Black => self.black_king_moved = true,
+                },
+                _ => {}
+            }
+        }
+
+        // Move the piece
+        if let Some(piece) = self.take_piece(start) {
+            self.hash ^= piece_key(piece, start) ^ piece_key(piece, end);
+            self.put_piece(end, piece);
+        }
+
+        // Pawn promotion, defaulting to a queen when no piece was chosen
+        if mv.is_promotion()
+            && let Some(piece) = self.take_piece(end)
+        {
+            let promotion = Piece::new(mv.promotion.unwrap_or(PieceType::Queen), piece.color());
+            self.hash ^= piece_key(piece, end) ^ piece_key(promotion, end);
+            self.put_piece(end, promotion);
+        }
+
+        self.hash ^= self.castling_key() ^ self.en_passant_key(mover.opposite());
+
+        // Update the clocks
+        if piece_moving.is_type(PieceType::Pawn) || record.captured.is_some() {
+            self.halfmove_clock = 0;
+        } else {
+            self.halfmove_clock += 1;
+        }
+        if piece_moving.is_color(ColorChess::Black) {
+            self.fullmove_number += 1;
+        }
+
+        Some(record)
+    }
+
+    // Puts back the position from before the recorded move, turn included
+    pub(crate) fn restore(&mut self, record: MoveRecord) {
+        let mv = record.mv;
+        let (start, end) = (mv.from, mv.to);
+
+        // Put the moving piece back (this also reverts a promotion)
+        self.take_piece(end);
+        self.put_piece(start, record.moved);
+
+        // Move the castling rook back (the hash is restored from the record below)
+        if mv.flags.contains(MoveFlags::CASTLE_KING_SIDE) {
+            self.move_rook(start.0, 5, 7);
+        } else if mv.flags.contains(MoveFlags::CASTLE_QUEEN_SIDE) {
+            self.move_rook(start.0, 3, 0);
+        }
+
+        if let Some(captured) = record.captured {
+            let captured_square = if mv.is_en_passant() {
+                (start.0, end.1)
+            } else {
+                end
+            };
+            self.put_piece(captured_square, captured);
+        }
+
+        self.restore_castling_state(record.castling);
+        self.en_passant_target = record.en_passant_target;
+        self.halfmove_clock = record.halfmove_clock;
+        self.fullmove_number = record.fullmove_number;
+        self.current_turn = record.turn;
+        self.hash = record.hash;
+    }
+
+    pub fn get_halfmove_clock(&self) -> u32 {
+        self.halfmove_clock
+    }
+
+    pub fn get_fullmove_number(&self) -> u32 {
+        self.fullmove_number
+    }
+
+    pub fn find_king(&self, color: ColorChess) -> Option<(usize, usize)> {
+        squares(self.pieces_of(color, PieceType::King)).next()
+    }
+
+    // This method is for text input, will be less used with mouse input
+    pub fn parse_move(&self, move_str: &str) -> Result<(usize, usize), ChessError> {
+        let off_board = || ChessError::OutOfBounds(move_str.to_string());
+        let chars: Vec<char> = move_str.chars().collect();
+        let &[col, row] = chars.as_slice() else {
+            return Err(off_board());
+        };
+        let col = col.to_ascii_lowercase();
+        if !('a'..='h').contains(&col) || !('1'..='8').contains(&row) {
+            return Err(off_board());
+        }
+
+        let col_index = (col as usize) - ('a' as usize);
+        let row_index = row as usize - '1' as usize;
+
+        Ok((row_index, col_index))
+    }
+
+    pub fn switch_turn(&mut self) {
+        self.current_turn = self.current_turn.opposite();
+        self.hash ^= side_key();
+    }
+
+    pub fn get_current_turn(&self) -> ColorChess {
+        self.current_turn
+    }
+
+    // Empty for squares off the board
+    pub fn get_piece(&self, square: (usize, usize)) -> Option<Piece> {
+        self.squares
+            .get(square.0)
+            .and_then(|rank| rank.get(square.1))
+            .copied()
+            .flatten()
+    }
+
+    // How many pieces of a type the given color has on the board
+    pub fn count_pieces(&self, color: ColorChess, piece_type: PieceType) -> u32 {
+        self.pieces_of(color, piece_type).count_ones()
+    }
+
+    // Total value of the given color's pieces on the board, promoted ones included
+    pub fn get_material(&self, color: ColorChess) -> u32 {
+        [
+            PieceType::Queen,
+            PieceType::Rook,
+            PieceType::Bishop,
+            PieceType::Knight,
+            PieceType::Pawn,
+        ]
+        .into_iter()
+        .map(|piece_type| {
+            let count = self.pieces_of(color, piece_type).count_ones();
+            count * Piece::new(piece_type, color).points()
+        })
+        .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bad_squares_and_empty_squares_are_refused() {
+        let mut position = Position::new();
+        let start = position;
+        assert!(matches!(
+            position.make(Move::new((3, 4), (4, 4))),
+            Err(ChessError::IllegalMove { .. })
+        ));
+        assert!(matches!(
+            position.make(Move::new((1, 4), (8, 4))),
+            Err(ChessError::OutOfBounds(_))
+        ));
+        assert!(matches!(
+            position.build_move((9, 0), (0, 0)),
+            Err(ChessError::OutOfBounds(_))
+        ));
+        assert!(position == start);
+
+        assert!(position.legal_moves_from((8, 0)).is_empty());
+        assert!(!position.is_square_attacked((9, 9), ColorChess::White));
+        assert!(!position.attacks((0, 1), (9, 9)));
+        assert_eq!(position.attack_table().count(ColorChess::White, (8, 8)), 0);
+        assert_eq!(position.get_piece((8, 0)), None);
+    }
+
+    #[test]
+    fn make_and_unmake_restore_the_position() {
+        let mut position = Position::new();
+        let start = position;
+        let mv = position.build_move((1, 4), (3, 4)).unwrap();
+        let undo = position.make(mv).unwrap();
+        assert_eq!(position.get_current_turn(), ColorChess::Black);
+        assert_eq!(position.en_passant_target, Some((2, 4)));
+        position.unmake(undo);
+        assert!(position == start);
+    }
+}
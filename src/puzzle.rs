@@ -0,0 +1,549 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chess_rs::{ColorChess, Game, PieceType};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+use crate::lichess;
+use crate::puzzle_db::{self, PuzzleFilter};
+
+// Rating a new solver starts from, as on Lichess
+const START_RATING: i32 = 1500;
+
+// Most a single puzzle moves the rating
+const RATING_K: f64 = 32.0;
+
+// Downloaded puzzles kept in reserve, so that some can be solved offline
+pub const POOL_SIZE: usize = 5;
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Puzzle {
+    pub id: String,
+    // Position the solver moves from
+    pub fen: String,
+    // The expected line in UCI notation: the solver's moves and the replies
+    pub solution: Vec<String>,
+    // Puzzles from a file of one's own may come without a rating, and then
+    // leave the solver's rating alone
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rating: Option<i32>,
+    #[serde(default)]
+    pub themes: Vec<String>,
+}
+
+// Tactical motifs puzzles can be drilled by, under their Lichess theme names
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Motif {
+    Fork,
+    Pin,
+    Skewer,
+    BackRankMate,
+    Deflection,
+}
+
+pub const MOTIFS: [Motif; 5] = [
+    Motif::Fork,
+    Motif::Pin,
+    Motif::Skewer,
+    Motif::BackRankMate,
+    Motif::Deflection,
+];
+
+impl Motif {
+    pub fn label(self) -> &'static str {
+        match self {
+            Motif::Fork => "Fork",
+            Motif::Pin => "Pin",
+            Motif::Skewer => "Skewer",
+            Motif::BackRankMate => "Back-rank mate",
+            Motif::Deflection => "Deflection",
+        }
+    }
+
+    // The theme as Lichess tags it, in the database and in its API
+    pub fn theme(self) -> &'static str {
+        match self {
+            Motif::Fork => "fork",
+            Motif::Pin => "pin",
+            Motif::Skewer => "skewer",
+            Motif::BackRankMate => "backRankMate",
+            Motif::Deflection => "deflection",
+        }
+    }
+
+    // A motif from what was typed, such as "fork", "back-rank" or "backRankMate"
+    pub fn parse(name: &str) -> Option<Motif> {
+        let simple = |text: &str| {
+            text.chars()
+                .filter(char::is_ascii_alphanumeric)
+                .collect::<String>()
+                .to_ascii_lowercase()
+        };
+        let name = simple(name);
+        if name.is_empty() {
+            return None;
+        }
+        MOTIFS
+            .into_iter()
+            .find(|motif| simple(motif.theme()).starts_with(&name))
+    }
+}
+
+impl Puzzle {
+    pub fn has_motif(&self, motif: Motif) -> bool {
+        self.themes
+            .iter()
+            .any(|theme| theme.eq_ignore_ascii_case(motif.theme()))
+    }
+}
+
+// Puzzles to fall back on when none could be downloaded
+fn builtin_puzzles() -> [Puzzle; 3] {
+    let puzzle = |id: &str, fen: &str, solution: &[&str], rating, themes: &[&str]| Puzzle {
+        id: id.to_string(),
+        fen: fen.to_string(),
+        solution: solution.iter().map(|mv| mv.to_string()).collect(),
+        rating: Some(rating),
+        themes: themes.iter().map(|theme| theme.to_string()).collect(),
+    };
+    [
+        puzzle(
+            "builtin-1",
+            "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1",
+            &["a1a8"],
+            800,
+            &["mate", "backRankMate"],
+        ),
+        puzzle(
+            "builtin-2",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p2Q/2B1P3/8/PPPP1PPP/RNB1K1NR w KQkq - 4 4",
+            &["h5f7"],
+            700,
+            &["mate"],
+        ),
+        puzzle(
+            "builtin-3",
+            "r5k1/5ppp/8/8/8/8/3R1PPP/3R2K1 w - - 0 1",
+            &["d2d8", "a8d8", "d1d8"],
+            1000,
+            &["mate", "backRankMate"],
+        ),
+    ]
+}
+
+// A puzzle being solved
+pub struct PuzzleRun {
+    pub puzzle: Puzzle,
+    // Index in the solution of the next move to be played
+    pub next: usize,
+    // Whether a wrong move was tried; the puzzle then counts as failed
+    pub failed: bool,
+    pub done: bool,
+    // Tried again after it was scored, so it no longer counts
+    pub retry: bool,
+    // Today's daily puzzle, whose result keeps the streak going
+    pub daily: bool,
+}
+
+impl PuzzleRun {
+    pub fn new(puzzle: Puzzle) -> PuzzleRun {
+        PuzzleRun {
+            puzzle,
+            next: 0,
+            failed: false,
+            done: false,
+            retry: false,
+            daily: false,
+        }
+    }
+
+    // Whether the result of the puzzle is still to be scored
+    pub fn counts(&self) -> bool {
+        !self.failed && !self.done && !self.retry
+    }
+}
+
+// Reads puzzles from a file, one a line: a FEN and, after a semicolon, the
+// solution in SAN or UCI, starting with the solver's move, e.g.
+// "6k1/5ppp/8/8/8/8/5PPP/R5K1 w - - 0 1; Ra8#". Lichess themes may follow
+// after another semicolon; without them, forks and back-rank mates are found
+// in the solution. Lines of the Lichess puzzle database CSV work as well, and
+// '#' starts a comment
+pub fn load_file(path: &Path) -> Result<Vec<Puzzle>, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let name = path
+        .file_stem()
+        .map_or("puzzle".into(), |stem| stem.to_string_lossy());
+    let mut puzzles = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with("PuzzleId,") {
+            continue;
+        }
+        let id = format!("{} #{}", name, puzzles.len() + 1);
+        let puzzle = parse_line(line, id)
+            .map_err(|e| format!("{} line {}: {}", path.display(), i + 1, e))?;
+        puzzles.push(puzzle);
+    }
+    if puzzles.is_empty() {
+        return Err(format!("{}: no puzzles", path.display()));
+    }
+    Ok(puzzles)
+}
+
+fn parse_line(line: &str, id: String) -> Result<Puzzle, String> {
+    let mut fields = line.split(';');
+    let (Some(fen), Some(moves)) = (fields.next(), fields.next()) else {
+        return from_lichess_csv(line);
+    };
+    let board = Game::from_fen(fen.trim())?;
+    let solution = solution_line(&board, moves)?;
+    let themes = match fields.next() {
+        Some(themes) => themes.split_whitespace().map(str::to_string).collect(),
+        None => find_themes(&board, &solution),
+    };
+    Ok(Puzzle {
+        id,
+        fen: board.to_fen(),
+        solution,
+        rating: None,
+        themes,
+    })
+}
+
+// The motifs that can be told from the moves alone: a fork, where a move of
+// the solver's attacks two pieces that are each the king, worth more than
+// the attacker or left undefended, and a mate on the back rank by a rook or
+// queen along it
+fn find_themes(board: &Game, solution: &[String]) -> Vec<String> {
+    let solver = board.get_current_turn();
+    let mut board = board.clone();
+    let mut fork = false;
+    for uci in solution {
+        let Ok(mv) = board.parse_uci_move(uci) else {
+            break;
+        };
+        if board.make(mv).is_err() {
+            break;
+        }
+        fork |= board.get_current_turn() != solver && is_fork(&board, mv.to);
+    }
+    let mut themes = Vec::new();
+    if fork {
+        themes.push(Motif::Fork.theme().to_string());
+    }
+    if is_back_rank_mate(&board) {
+        themes.push("mate".to_string());
+        themes.push(Motif::BackRankMate.theme().to_string());
+    }
+    themes
+}
+
+fn is_fork(board: &Game, square: (usize, usize)) -> bool {
+    let Some(attacker) = board.get_piece(square) else {
+        return false;
+    };
+    let defender = attacker.color().opposite();
+    let mut targets = 0;
+    for row in 0..8 {
+        for col in 0..8 {
+            let Some(piece) = board.get_piece((row, col)) else {
+                continue;
+            };
+            if !piece.is_color(defender)
+                || piece.is_type(PieceType::Pawn)
+                || !board.attacks(square, (row, col))
+            {
+                continue;
+            }
+            if piece.is_type(PieceType::King)
+                || piece.points() > attacker.points()
+                || !board.is_square_attacked((row, col), defender)
+            {
+                targets += 1;
+            }
+        }
+    }
+    targets >= 2
+}
+
+fn is_back_rank_mate(board: &Game) -> bool {
+    let mated = board.get_current_turn();
+    if !board.is_checkmate(mated) {
+        return false;
+    }
+    let Some(king) = board.find_king(mated) else {
+        return false;
+    };
+    let back_rank = match mated {
+        ColorChess::White => 0,
+        ColorChess::Black => 7,
+    };
+    king.0 == back_rank
+        && (0..8).any(|col| {
+            board.get_piece((back_rank, col)).is_some_and(|piece| {
+                !piece.is_color(mated)
+                    && (piece.is_type(PieceType::Rook) || piece.is_type(PieceType::Queen))
+                    && board.attacks((back_rank, col), king)
+            })
+        })
+}
+
+// A line of the Lichess puzzle database, "PuzzleId,FEN,Moves,Rating,
+// RatingDeviation,Popularity,NbPlays,Themes,GameUrl,OpeningTags"
+pub fn from_lichess_csv(line: &str) -> Result<Puzzle, String> {
+    let fields: Vec<&str> = line.split(',').collect();
+    if fields.len() < 3 {
+        return Err("expected \"FEN; moves\" or a Lichess puzzle CSV line".to_string());
+    }
+    // The database starts from the opponent's move that sets the puzzle up
+    let mut board = Game::from_fen(fields[1])?;
+    let (setup, moves) = fields[2]
+        .trim()
+        .split_once(' ')
+        .ok_or("the puzzle has no solution")?;
+    let mv = board.parse_uci_move(setup)?;
+    board.make(mv)?;
+    Ok(Puzzle {
+        id: fields[0].to_string(),
+        fen: board.to_fen(),
+        solution: solution_line(&board, moves)?,
+        rating: fields.get(3).and_then(|rating| rating.parse().ok()),
+        themes: fields
+            .get(7)
+            .map(|themes| themes.split_whitespace().map(str::to_string).collect())
+            .unwrap_or_default(),
+    })
+}
+
+// The moves of a solution in UCI notation, checked by playing them out; move
+// numbers such as "1." or "1..." are skipped
+fn solution_line(board: &Game, moves: &str) -> Result<Vec<String>, String> {
+    let mut board = board.clone();
+    let mut solution = Vec::new();
+    for text in moves.split_whitespace() {
+        if text.ends_with('.') {
+            continue;
+        }
+        let mv = board
+            .parse_uci_move(text)
+            .or_else(|_| board.parse_san(text))?;
+        solution.push(mv.to_uci());
+        board.make(mv)?;
+    }
+    if solution.is_empty() {
+        return Err("the puzzle has no solution".to_string());
+    }
+    Ok(solution)
+}
+
+// Days since 1970 in UTC, the date the daily puzzle goes by
+pub fn today() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() / 86_400)
+}
+
+// Today's puzzle: the Lichess daily puzzle or, without a connection, one
+// picked by the date from the local database or the built-in puzzles, so
+// that it is the same all day
+pub fn fetch_daily(filter: PuzzleFilter) -> Receiver<Puzzle> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let day = today();
+        let puzzle = lichess::daily_puzzle()
+            .or_else(|_| puzzle_db::pick_daily(&filter, day))
+            .unwrap_or_else(|_| {
+                let builtin = builtin_puzzles();
+                builtin[day as usize % builtin.len()].clone()
+            });
+        let _ = sender.send(puzzle);
+    });
+    receiver
+}
+
+// The daily puzzle and the run of days it has been solved
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Daily {
+    // Days are counted as today() does
+    pub last_played: Option<u64>,
+    pub last_solved: Option<u64>,
+    pub streak: u32,
+    pub best: u32,
+    // Today's puzzle once fetched, and its day
+    day: u64,
+    puzzle: Option<Puzzle>,
+}
+
+impl Daily {
+    // Today's puzzle, if it was fetched already
+    pub fn puzzle(&self) -> Option<&Puzzle> {
+        self.puzzle.as_ref().filter(|_| self.day == today())
+    }
+
+    pub fn set_puzzle(&mut self, puzzle: Puzzle) {
+        self.day = today();
+        self.puzzle = Some(puzzle);
+    }
+
+    pub fn played_today(&self) -> bool {
+        self.last_played == Some(today())
+    }
+
+    pub fn solved_today(&self) -> bool {
+        self.last_solved == Some(today())
+    }
+
+    // The streak as it stands; a day without a solve ends it
+    pub fn current_streak(&self) -> u32 {
+        match self.last_solved {
+            Some(day) if day + 1 >= today() => self.streak,
+            _ => 0,
+        }
+    }
+
+    // A miss ends the streak, a solve the day after the last one adds to it
+    pub fn record(&mut self, solved: bool) {
+        let day = today();
+        self.last_played = Some(day);
+        if !solved {
+            self.streak = 0;
+            return;
+        }
+        self.streak = if self.last_solved.is_some_and(|last| last + 1 == day) {
+            self.streak + 1
+        } else {
+            1
+        };
+        self.last_solved = Some(day);
+        self.best = self.best.max(self.streak);
+    }
+}
+
+// Puzzles of one motif solved and missed
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct MotifStats {
+    pub solved: u32,
+    pub failed: u32,
+}
+
+// The solver's rating and the downloaded puzzles not played yet, saved in
+// puzzles.toml next to config.toml
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct PuzzleStore {
+    pub rating: i32,
+    pub solved: u32,
+    pub failed: u32,
+    // Which built-in puzzle comes next, so they take turns
+    next_builtin: usize,
+    pub daily: Daily,
+    // Results by motif, keyed by Lichess theme
+    motifs: BTreeMap<String, MotifStats>,
+    pool: Vec<Puzzle>,
+}
+
+impl Default for PuzzleStore {
+    fn default() -> Self {
+        PuzzleStore {
+            rating: START_RATING,
+            solved: 0,
+            failed: 0,
+            next_builtin: 0,
+            daily: Daily::default(),
+            motifs: BTreeMap::new(),
+            pool: Vec::new(),
+        }
+    }
+}
+
+fn store_path() -> Option<PathBuf> {
+    Some(config::config_path()?.with_file_name("puzzles.toml"))
+}
+
+impl PuzzleStore {
+    // Reads the saved store; a missing file is a new solver
+    pub fn load() -> Result<PuzzleStore, String> {
+        let Some(path) = store_path() else {
+            return Ok(PuzzleStore::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e.message()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(PuzzleStore::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = store_path().ok_or("no config directory on this system")?;
+        let text = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn pool_len(&self) -> usize {
+        self.pool.len()
+    }
+
+    pub fn add(&mut self, puzzle: Puzzle) {
+        self.pool.push(puzzle);
+    }
+
+    // The oldest downloaded puzzle, of the motif being drilled if there is one
+    pub fn take_downloaded(&mut self, motif: Option<Motif>) -> Option<Puzzle> {
+        let index = self
+            .pool
+            .iter()
+            .position(|puzzle| motif.is_none_or(|motif| puzzle.has_motif(motif)))?;
+        Some(self.pool.remove(index))
+    }
+
+    pub fn take_builtin(&mut self) -> Puzzle {
+        let builtin = builtin_puzzles();
+        let puzzle = builtin[self.next_builtin % builtin.len()].clone();
+        self.next_builtin = (self.next_builtin + 1) % builtin.len();
+        puzzle
+    }
+
+    pub fn motif_stats(&self, motif: Motif) -> MotifStats {
+        self.motifs.get(motif.theme()).copied().unwrap_or_default()
+    }
+
+    // Counts a puzzle's result, overall and for each motif it is tagged with
+    pub fn count(&mut self, puzzle: &Puzzle, solved: bool) {
+        if solved {
+            self.solved += 1;
+        } else {
+            self.failed += 1;
+        }
+        for motif in MOTIFS.into_iter().filter(|&motif| puzzle.has_motif(motif)) {
+            let stats = self.motifs.entry(motif.theme().to_string()).or_default();
+            if solved {
+                stats.solved += 1;
+            } else {
+                stats.failed += 1;
+            }
+        }
+    }
+
+    // Scores a puzzle against the solver's rating, Elo style, returning the change
+    pub fn record(&mut self, puzzle_rating: i32, solved: bool) -> i32 {
+        let expected = 1.0 / (1.0 + 10f64.powf(f64::from(puzzle_rating - self.rating) / 400.0));
+        let score = if solved { 1.0 } else { 0.0 };
+        let change = (RATING_K * (score - expected)).round() as i32;
+        self.rating += change;
+        change
+    }
+}
@@ -0,0 +1,233 @@
+use std::{
+    collections::{BTreeMap, btree_map::Entry},
+    fs::{self, File},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver},
+    thread,
+};
+
+use chess_rs::search::splitmix;
+
+use crate::config::{self, PuzzleConfig};
+use crate::puzzle::{self, Puzzle};
+
+// Puzzles are kept in a file for every hundred rating points, so that only
+// the files in the wanted range are read
+const BUCKET: i32 = 100;
+
+// Ratings either side of the solver's that puzzles are picked from, unless
+// the [puzzles] section sets a range
+const RATING_WINDOW: i32 = 200;
+
+// The puzzles the local database is asked for
+pub struct PuzzleFilter {
+    pub min_rating: i32,
+    pub max_rating: i32,
+    // Lichess themes a puzzle must all have, e.g. "fork" or "endgame"
+    pub themes: Vec<String>,
+}
+
+impl PuzzleFilter {
+    // The configured range and themes, around the solver's rating by default
+    pub fn new(config: &PuzzleConfig, rating: i32) -> PuzzleFilter {
+        PuzzleFilter {
+            min_rating: config.min_rating.unwrap_or(rating - RATING_WINDOW),
+            max_rating: config.max_rating.unwrap_or(rating + RATING_WINDOW),
+            themes: config.themes.clone(),
+        }
+    }
+
+    fn matches(&self, rating: i32, themes: &str) -> bool {
+        (self.min_rating..=self.max_rating).contains(&rating)
+            && self.themes.iter().all(|wanted| {
+                themes
+                    .split_whitespace()
+                    .any(|theme| theme.eq_ignore_ascii_case(wanted))
+            })
+    }
+}
+
+// The database lives in puzzle-db/ next to config.toml
+fn db_dir() -> Option<PathBuf> {
+    Some(config::config_path()?.with_file_name("puzzle-db"))
+}
+
+pub fn exists() -> bool {
+    db_dir().is_some_and(|dir| dir.is_dir())
+}
+
+fn bucket_path(dir: &Path, bucket: i32) -> PathBuf {
+    dir.join(format!("{}.csv", bucket))
+}
+
+// Splits the Lichess puzzle database (lichess_db_puzzle.csv, unpacked) into
+// the local one, replacing what was there, and returns how many puzzles it got
+pub fn import(path: &Path) -> Result<usize, String> {
+    let dir = db_dir().ok_or("no config directory on this system")?;
+    import_to(path, &dir)
+}
+
+// import into `dir`. The new database is built next to it and only takes its
+// place once the whole file has been read, so a file that fails part of the
+// way leaves the old database as it was
+fn import_to(path: &Path, dir: &Path) -> Result<usize, String> {
+    let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let new = dir.with_extension("new");
+    let old = dir.with_extension("old");
+    // Left over from an import that was cut off
+    for stale in [&new, &old] {
+        if stale.exists() {
+            fs::remove_dir_all(stale).map_err(|e| format!("{}: {}", stale.display(), e))?;
+        }
+    }
+    fs::create_dir_all(&new).map_err(|e| format!("{}: {}", new.display(), e))?;
+    let count = match split(file, path, &new) {
+        Ok(count) => count,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&new);
+            return Err(e);
+        }
+    };
+    if dir.exists() {
+        fs::rename(dir, &old).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+    if let Err(e) = fs::rename(&new, dir) {
+        let _ = fs::rename(&old, dir);
+        return Err(format!("{}: {}", dir.display(), e));
+    }
+    let _ = fs::remove_dir_all(&old);
+    Ok(count)
+}
+
+// Writes the puzzles of the database file into a bucket file each in `dir`
+fn split(file: File, path: &Path, dir: &Path) -> Result<usize, String> {
+    let mut buckets: BTreeMap<i32, BufWriter<File>> = BTreeMap::new();
+    let mut count = 0;
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("{}: {}", path.display(), e))?;
+        if line.trim().is_empty() || line.starts_with("PuzzleId,") {
+            continue;
+        }
+        let rating = csv_rating(&line).ok_or_else(|| {
+            format!(
+                "{} line {}: not a line of the Lichess puzzle database",
+                path.display(),
+                i + 1
+            )
+        })?;
+        let bucket = rating.div_euclid(BUCKET) * BUCKET;
+        let writer = match buckets.entry(bucket) {
+            Entry::Occupied(entry) => entry.into_mut(),
+            Entry::Vacant(entry) => {
+                let path = bucket_path(dir, bucket);
+                let file = File::create(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+                entry.insert(BufWriter::new(file))
+            }
+        };
+        writeln!(writer, "{}", line).map_err(|e| e.to_string())?;
+        count += 1;
+    }
+    for writer in buckets.values_mut() {
+        writer.flush().map_err(|e| e.to_string())?;
+    }
+    Ok(count)
+}
+
+// The rating, the fourth field of a database line
+fn csv_rating(line: &str) -> Option<i32> {
+    line.split(',').nth(3)?.parse().ok()
+}
+
+// Picks a random puzzle that passes the filter, on its own thread as
+// millions of puzzles take a moment to go through
+pub fn pick(filter: PuzzleFilter) -> Receiver<Result<Puzzle, String>> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(1, |d| d.as_nanos() as u64);
+        let _ = sender.send(pick_seeded(&filter, seed));
+    });
+    receiver
+}
+
+// The puzzle of the day: the same one all day, as long as the database and
+// the filter stay the same
+pub fn pick_daily(filter: &PuzzleFilter, day: u64) -> Result<Puzzle, String> {
+    pick_seeded(filter, splitmix(day))
+}
+
+fn pick_seeded(filter: &PuzzleFilter, seed: u64) -> Result<Puzzle, String> {
+    let dir = db_dir().ok_or("no config directory on this system")?;
+    let mut rng = seed;
+    let mut chosen = None;
+    let mut matching = 0;
+    let buckets = filter.min_rating.div_euclid(BUCKET)..=filter.max_rating.div_euclid(BUCKET);
+    for bucket in buckets {
+        let path = bucket_path(&dir, bucket * BUCKET);
+        let Ok(file) = File::open(&path) else {
+            continue;
+        };
+        for line in BufReader::new(file).lines() {
+            let line = line.map_err(|e| format!("{}: {}", path.display(), e))?;
+            let themes = line.split(',').nth(7).unwrap_or("");
+            if !csv_rating(&line).is_some_and(|rating| filter.matches(rating, themes)) {
+                continue;
+            }
+            // Reservoir sampling: every match ends up with the same chance
+            matching += 1;
+            rng = splitmix(rng);
+            if rng.is_multiple_of(matching) {
+                chosen = Some(line);
+            }
+        }
+    }
+    let line = chosen.ok_or_else(|| {
+        format!(
+            "no puzzles rated {} to {}{} in the database",
+            filter.min_rating,
+            filter.max_rating,
+            if filter.themes.is_empty() {
+                String::new()
+            } else {
+                format!(" with the themes {}", filter.themes.join(", "))
+            }
+        )
+    })?;
+    puzzle::from_lichess_csv(&line)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUZZLE: &str = "00sHx,q3k1nr/1pp1nQpp/3p4/1P2p3/4P3/B1PP1b2/B5PP/5K2 b k - 0 17,e8d7 a2e6 d7d8 f7f8,1760,80,83,72,mate mateIn2 middlegame short,https://lichess.org/yyznGmXs/black#34,Italian_Game";
+
+    #[test]
+    fn a_failed_import_keeps_the_old_database() {
+        let root = std::env::temp_dir().join(format!("chess-rs-puzzle-db-{}", std::process::id()));
+        let dir = root.join("puzzle-db");
+        let good = root.join("good.csv");
+        let bad = root.join("bad.csv");
+        fs::create_dir_all(&root).unwrap();
+        fs::write(&good, format!("PuzzleId,FEN,Moves,Rating\n{}\n", PUZZLE)).unwrap();
+        fs::write(&bad, format!("{}\nnot a puzzle\n", PUZZLE)).unwrap();
+
+        assert_eq!(import_to(&good, &dir), Ok(1));
+        let bucket = bucket_path(&dir, 1700);
+        assert_eq!(
+            fs::read_to_string(&bucket).unwrap(),
+            format!("{}\n", PUZZLE)
+        );
+
+        let error = import_to(&bad, &dir).unwrap_err();
+        assert!(error.ends_with("line 2: not a line of the Lichess puzzle database"));
+        assert_eq!(
+            fs::read_to_string(&bucket).unwrap(),
+            format!("{}\n", PUZZLE)
+        );
+        assert!(!dir.with_extension("new").exists());
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
@@ -0,0 +1,147 @@
+use std::{collections::BTreeMap, fs, path::PathBuf};
+
+use chess_rs::Difficulty;
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+// Rating every new player starts from, as for puzzles
+const START_RATING: i32 = 1500;
+
+// Most a single game moves a rating
+const RATING_K: f64 = 32.0;
+
+// What the AI at each difficulty is rated as, roughly as it plays
+pub fn difficulty_rating(difficulty: Difficulty) -> i32 {
+    match difficulty {
+        Difficulty::Beginner => 800,
+        Difficulty::Casual => 1200,
+        Difficulty::Club => 1600,
+        Difficulty::Strong => 2000,
+    }
+}
+
+// An Elo rating with the games it was made from
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rating {
+    pub rating: i32,
+    pub won: u32,
+    pub drawn: u32,
+    pub lost: u32,
+}
+
+impl Default for Rating {
+    fn default() -> Self {
+        Rating {
+            rating: START_RATING,
+            won: 0,
+            drawn: 0,
+            lost: 0,
+        }
+    }
+}
+
+impl Rating {
+    pub fn games(&self) -> u32 {
+        self.won + self.drawn + self.lost
+    }
+
+    // Scores a game against an opponent of the given rating, 1 for a win and
+    // 0.5 for a draw, returning the change
+    fn record(&mut self, opponent: i32, score: f64) -> i32 {
+        let expected = 1.0 / (1.0 + 10f64.powf(f64::from(opponent - self.rating) / 400.0));
+        let change = (RATING_K * (score - expected)).round() as i32;
+        self.rating += change;
+        if score >= 1.0 {
+            self.won += 1;
+        } else if score <= 0.0 {
+            self.lost += 1;
+        } else {
+            self.drawn += 1;
+        }
+        change
+    }
+}
+
+// The player's rating against each AI difficulty, and those of the named
+// hot-seat players, saved in ratings.toml next to config.toml
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RatingStore {
+    // By difficulty, as it is written in config.toml
+    ai: BTreeMap<String, Rating>,
+    players: BTreeMap<String, Rating>,
+}
+
+fn store_path() -> Option<PathBuf> {
+    Some(config::config_path()?.with_file_name("ratings.toml"))
+}
+
+fn difficulty_key(difficulty: Difficulty) -> String {
+    difficulty.label().to_lowercase()
+}
+
+impl RatingStore {
+    // Reads the saved ratings; a missing file means no rated games yet
+    pub fn load() -> Result<RatingStore, String> {
+        let Some(path) = store_path() else {
+            return Ok(RatingStore::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e.message()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RatingStore::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = store_path().ok_or("no config directory on this system")?;
+        let text = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    pub fn against_ai(&self, difficulty: Difficulty) -> Rating {
+        self.ai
+            .get(&difficulty_key(difficulty))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    // Scores a game against the AI, returning the new rating and the change
+    pub fn record_ai(&mut self, difficulty: Difficulty, score: f64) -> (i32, i32) {
+        let rating = self.ai.entry(difficulty_key(difficulty)).or_default();
+        let change = rating.record(difficulty_rating(difficulty), score);
+        (rating.rating, change)
+    }
+
+    // The named hot-seat players, by name
+    pub fn players(&self) -> impl Iterator<Item = (&str, &Rating)> {
+        self.players
+            .iter()
+            .map(|(name, rating)| (name.as_str(), rating))
+    }
+
+    // Scores a hot-seat game between two named players, each against the
+    // other's rating before it, returning their changes
+    pub fn record_players(&mut self, white: &str, black: &str, white_score: f64) -> (i32, i32) {
+        let before = |name: &str| self.players.get(name).copied().unwrap_or_default().rating;
+        let (white_before, black_before) = (before(white), before(black));
+        let white_change = self
+            .players
+            .entry(white.to_string())
+            .or_default()
+            .record(black_before, white_score);
+        let black_change = self
+            .players
+            .entry(black.to_string())
+            .or_default()
+            .record(white_before, 1.0 - white_score);
+        (white_change, black_change)
+    }
+}
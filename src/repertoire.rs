@@ -0,0 +1,221 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs,
+    path::PathBuf,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use chess_rs::pgn::parse_pgn_lines;
+use chess_rs::{ColorChess, Game, Move};
+use serde::{Deserialize, Serialize};
+
+use crate::config;
+
+// Days until a line comes up again, by how many times in a row it has been
+// played without a mistake; a missed line is due again straight away
+const INTERVALS: [u64; 7] = [0, 1, 3, 7, 14, 30, 60];
+
+const DAY: u64 = 24 * 60 * 60;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+// An opening repertoire: every line of a PGN with variations, played from
+// one side
+pub struct Repertoire {
+    pub start: Game,
+    pub lines: Vec<Vec<Move>>,
+    pub color: ColorChess,
+}
+
+impl Repertoire {
+    // Reads the first game of a PGN file; without a color, the side is the one
+    // the lines branch for less
+    pub fn load(path: &str, color: Option<ColorChess>) -> Result<Repertoire, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path, e))?;
+        let (start, lines) = parse_pgn_lines(&text).map_err(|e| format!("{}: {}", path, e))?;
+        if lines.is_empty() {
+            return Err(format!("{}: no moves", path));
+        }
+        let mut repertoire = Repertoire {
+            start,
+            lines,
+            color: ColorChess::White,
+        };
+        repertoire.color = color.unwrap_or_else(|| repertoire.guess_color());
+        Ok(repertoire)
+    }
+
+    // A repertoire has one answer to each of the opponent's tries, so the
+    // side with fewer choices is the one it is for
+    fn guess_color(&self) -> ColorChess {
+        // The moves after each sequence of moves, in UCI notation
+        let mut next_moves: BTreeMap<Vec<String>, BTreeSet<String>> = BTreeMap::new();
+        for line in &self.lines {
+            let line: Vec<String> = line.iter().map(|mv| mv.to_uci()).collect();
+            for ply in 0..line.len() {
+                next_moves
+                    .entry(line[..ply].to_vec())
+                    .or_default()
+                    .insert(line[ply].clone());
+            }
+        }
+        let mut choices = [0, 0];
+        for (played, moves) in next_moves {
+            let white =
+                (self.start.get_current_turn() == ColorChess::White) == (played.len() % 2 == 0);
+            choices[usize::from(!white)] += moves.len() - 1;
+        }
+        if choices[1] < choices[0] {
+            ColorChess::Black
+        } else {
+            ColorChess::White
+        }
+    }
+
+    // The repertoire's moves after the moves played so far
+    pub fn moves_after(&self, played: &[Move]) -> Vec<Move> {
+        let mut moves = Vec::new();
+        for line in &self.lines {
+            if line.starts_with(played)
+                && let Some(&mv) = line.get(played.len())
+                && !moves.contains(&mv)
+            {
+                moves.push(mv);
+            }
+        }
+        moves
+    }
+
+    // What a line's schedule is saved under: the side and the moves in UCI
+    // notation
+    fn key(&self, line: usize) -> String {
+        let side = match self.color {
+            ColorChess::White => "white",
+            ColorChess::Black => "black",
+        };
+        let moves: Vec<String> = self.lines[line].iter().map(|mv| mv.to_uci()).collect();
+        format!("{} {}", side, moves.join(" "))
+    }
+}
+
+// When a line comes up again
+#[derive(Clone, Copy, Default, Serialize, Deserialize)]
+pub struct LineSchedule {
+    // Times in a row the line was played without a mistake
+    pub streak: usize,
+    // Seconds since 1970
+    pub due: u64,
+}
+
+// The schedules of the lines drilled so far, saved in repertoire.toml next
+// to config.toml
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct RepertoireStore {
+    lines: BTreeMap<String, LineSchedule>,
+}
+
+fn store_path() -> Option<PathBuf> {
+    Some(config::config_path()?.with_file_name("repertoire.toml"))
+}
+
+impl RepertoireStore {
+    // Reads the saved schedules; a missing file means no line was drilled yet
+    pub fn load() -> Result<RepertoireStore, String> {
+        let Some(path) = store_path() else {
+            return Ok(RepertoireStore::default());
+        };
+        match fs::read_to_string(&path) {
+            Ok(text) => {
+                toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e.message()))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(RepertoireStore::default()),
+            Err(e) => Err(format!("{}: {}", path.display(), e)),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), String> {
+        let path = store_path().ok_or("no config directory on this system")?;
+        let text = toml::to_string_pretty(self).map_err(|e| e.to_string())?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+        }
+        fs::write(&path, text).map_err(|e| format!("{}: {}", path.display(), e))
+    }
+
+    // A line never drilled is due at once
+    fn schedule(&self, repertoire: &Repertoire, line: usize) -> LineSchedule {
+        self.lines
+            .get(&repertoire.key(line))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+// Drilling a repertoire one line at a time, the lines most overdue first
+pub struct Drill {
+    pub repertoire: Repertoire,
+    pub store: RepertoireStore,
+    // The line being played, and the index of its next move
+    pub line: usize,
+    pub next: usize,
+    // Whether a move outside the repertoire was tried in this line
+    pub missed: bool,
+}
+
+impl Drill {
+    pub fn new(repertoire: Repertoire, store: RepertoireStore) -> Drill {
+        Drill {
+            repertoire,
+            store,
+            line: 0,
+            next: 0,
+            missed: false,
+        }
+    }
+
+    // Lines due now
+    pub fn due(&self) -> usize {
+        let now = now();
+        (0..self.repertoire.lines.len())
+            .filter(|&line| self.store.schedule(&self.repertoire, line).due <= now)
+            .count()
+    }
+
+    // Sets up the line due the longest, or the one due soonest if none is
+    pub fn next_line(&mut self) {
+        self.line = (0..self.repertoire.lines.len())
+            .min_by_key(|&line| self.store.schedule(&self.repertoire, line).due)
+            .unwrap_or(0);
+        self.next = 0;
+        self.missed = false;
+    }
+
+    pub fn played(&self) -> &[Move] {
+        &self.repertoire.lines[self.line][..self.next]
+    }
+
+    // The line's next move
+    pub fn expected(&self) -> Option<Move> {
+        self.repertoire.lines[self.line].get(self.next).copied()
+    }
+
+    // Schedules the line again once it has been played through, returning
+    // the days until it is due
+    pub fn finish(&mut self) -> u64 {
+        let key = self.repertoire.key(self.line);
+        let schedule = self.store.lines.entry(key).or_default();
+        schedule.streak = if self.missed {
+            0
+        } else {
+            (schedule.streak + 1).min(INTERVALS.len() - 1)
+        };
+        let days = INTERVALS[schedule.streak];
+        schedule.due = now() + days * DAY;
+        days
+    }
+}
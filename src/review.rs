@@ -0,0 +1,229 @@
+use std::{
+    sync::mpsc::{self, Receiver},
+    thread,
+    time::Duration,
+};
+
+use chess_rs::search::MATE_SCORE;
+use chess_rs::{ColorChess, Game, Move, SearchLimits, Searcher};
+
+// Search time for each position of the game
+const REVIEW_TIME: Duration = Duration::from_millis(300);
+
+// Scores beyond this many centipawns count as this much, so that a won
+// position that stays won loses nothing, whatever the engine makes of it
+const SCORE_CAP: i32 = 1_000;
+
+// How good a move was, by how many centipawns it gave away
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Verdict {
+    Best,
+    Good,
+    Inaccuracy,
+    Mistake,
+    Blunder,
+}
+
+pub const VERDICTS: [Verdict; 5] = [
+    Verdict::Best,
+    Verdict::Good,
+    Verdict::Inaccuracy,
+    Verdict::Mistake,
+    Verdict::Blunder,
+];
+
+impl Verdict {
+    fn from_loss(loss: i32) -> Verdict {
+        match loss {
+            i32::MIN..=10 => Verdict::Best,
+            11..=50 => Verdict::Good,
+            51..=100 => Verdict::Inaccuracy,
+            101..=300 => Verdict::Mistake,
+            _ => Verdict::Blunder,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            Verdict::Best => "best",
+            Verdict::Good => "good",
+            Verdict::Inaccuracy => "inaccuracy",
+            Verdict::Mistake => "mistake",
+            Verdict::Blunder => "blunder",
+        }
+    }
+
+    // The NAG the move gets in the game tree: "?!", "?" or "??"
+    pub fn nag(self) -> Option<u8> {
+        match self {
+            Verdict::Inaccuracy => Some(6),
+            Verdict::Mistake => Some(2),
+            Verdict::Blunder => Some(4),
+            Verdict::Best | Verdict::Good => None,
+        }
+    }
+}
+
+pub struct MoveReview {
+    // Moves before this one in the game
+    pub ply: usize,
+    pub mover: ColorChess,
+    // The move with its number, as in "12... Qxb2"
+    pub label: String,
+    // The engine's choice, when it is not the move played
+    pub best: Option<String>,
+    // The position after the move, in centipawns from White's point of view
+    pub white_score: i32,
+    pub depth: u32,
+    // Centipawns the move gave away against the engine's choice
+    pub loss: i32,
+    pub verdict: Verdict,
+    // From 0 to 100, by how far the move lowered the mover's chances
+    accuracy: f64,
+}
+
+// The engine's verdict on every move of a game
+pub struct Review {
+    // The final position of the game reviewed
+    pub hash: u64,
+    pub moves: Vec<MoveReview>,
+}
+
+impl Review {
+    // The mean accuracy of a side's moves, as a percentage
+    pub fn accuracy(&self, color: ColorChess) -> Option<f64> {
+        let moves: Vec<f64> = self
+            .moves
+            .iter()
+            .filter(|review| review.mover == color)
+            .map(|review| review.accuracy)
+            .collect();
+        (!moves.is_empty()).then(|| moves.iter().sum::<f64>() / moves.len() as f64)
+    }
+
+    pub fn count(&self, color: ColorChess, verdict: Verdict) -> usize {
+        self.moves
+            .iter()
+            .filter(|review| review.mover == color && review.verdict == verdict)
+            .count()
+    }
+
+    // The mistakes and blunders, in the order they were played
+    pub fn key_moves(&self) -> Vec<&MoveReview> {
+        self.moves
+            .iter()
+            .filter(|review| matches!(review.verdict, Verdict::Mistake | Verdict::Blunder))
+            .collect()
+    }
+}
+
+pub enum ReviewUpdate {
+    // Positions searched so far, out of how many
+    Progress(usize, usize),
+    Done(Review),
+}
+
+// Searches every position of a game on another thread, reporting after each,
+// and judges each move by how much worse it left the mover than before
+pub fn review_game(board: &Game) -> Receiver<ReviewUpdate> {
+    let (sender, receiver) = mpsc::channel();
+    let hash = board.hash();
+    let moves: Vec<Move> = board.get_history().iter().map(|record| record.mv).collect();
+    let mut position = board.get_start_position();
+    thread::spawn(move || {
+        let mut searcher = Searcher::new();
+        let total = moves.len() + 1;
+        let mut searched = vec![search(&mut searcher, &position)];
+        let mut reviews = Vec::new();
+        for (ply, &mv) in moves.iter().enumerate() {
+            let before = position.clone();
+            if position.make(mv).is_err() {
+                break;
+            }
+            let after = search(&mut searcher, &position);
+            let _ = sender.send(ReviewUpdate::Progress(ply + 2, total));
+            reviews.push(judge(&before, mv, ply, searched[ply], after));
+            searched.push(after);
+        }
+        let _ = sender.send(ReviewUpdate::Done(Review {
+            hash,
+            moves: reviews,
+        }));
+    });
+    receiver
+}
+
+// The score of a position from White's point of view, the engine's move
+// there and how deep it looked; a finished game scores as its result
+type Searched = (i32, Option<Move>, u32);
+
+fn search(searcher: &mut Searcher, board: &Game) -> Searched {
+    let to_move = board.get_current_turn();
+    let for_white = |score: i32| match to_move {
+        ColorChess::White => score,
+        ColorChess::Black => -score,
+    };
+    if board.get_all_legal_moves(to_move).is_empty() {
+        let score = if board.is_in_check(to_move) {
+            -MATE_SCORE
+        } else {
+            0
+        };
+        return (for_white(score), None, 0);
+    }
+    let result = searcher.search(board, SearchLimits::time(REVIEW_TIME));
+    (for_white(result.score), result.best_move, result.depth)
+}
+
+// A move by what the engine made of the positions before and after it
+fn judge(
+    before: &Game,
+    mv: Move,
+    ply: usize,
+    (white_before, best, _): Searched,
+    (white_after, _, depth): Searched,
+) -> MoveReview {
+    let mover = before.get_current_turn();
+    let for_mover = |white_score: i32| {
+        match mover {
+            ColorChess::White => white_score,
+            ColorChess::Black => -white_score,
+        }
+        .clamp(-SCORE_CAP, SCORE_CAP)
+    };
+    let (score_before, score_after) = (for_mover(white_before), for_mover(white_after));
+    // The engine's own move loses nothing, whatever a deeper look after it says
+    let loss = if best == Some(mv) {
+        0
+    } else {
+        (score_before - score_after).max(0)
+    };
+    let number = match mover {
+        ColorChess::White => format!("{}.", before.get_fullmove_number()),
+        ColorChess::Black => format!("{}...", before.get_fullmove_number()),
+    };
+    let lost = (win_chance(score_before) - win_chance(score_after)).max(0.0);
+    MoveReview {
+        ply,
+        mover,
+        label: format!("{} {}", number, before.move_to_san(mv)),
+        best: best
+            .filter(|&best| best != mv)
+            .map(|best| before.move_to_san(best)),
+        white_score: white_after,
+        depth,
+        loss,
+        verdict: Verdict::from_loss(loss),
+        accuracy: if loss == 0 {
+            100.0
+        } else {
+            (103.1668 * (-0.04354 * lost).exp() - 3.1669).clamp(0.0, 100.0)
+        },
+    }
+}
+
+// The mover's chances in percent, from a score in centipawns, on the curve
+// Lichess fits to its games
+fn win_chance(score: i32) -> f64 {
+    50.0 + 50.0 * (2.0 / (1.0 + (-0.003_682_08 * f64::from(score)).exp()) - 1.0)
+}
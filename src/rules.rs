@@ -0,0 +1,389 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+use crate::bitboard::{
+    Bitboard, bishop_attacks, king_attacks, knight_attacks, pawn_attacks, rook_attacks, square_bit,
+    squares,
+};
+use crate::board::{ColorChess, PieceType, on_board};
+use crate::game::Game;
+use crate::position::Position;
+
+// Why a game ended. In JSON it is written in snake case, with a loss on
+// time as "time"
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Termination {
+    Checkmate,
+    Stalemate,
+    Resignation,
+    #[serde(rename = "time")]
+    Timeout,
+    Agreement,
+    Repetition,
+    FiftyMoves,
+    InsufficientMaterial,
+    // Fog-of-war chess over the network, which has no check, is won by
+    // taking the king
+    KingCaptured,
+    // A player left the game, or it never got going
+    Abandoned,
+    // Called by whoever runs the game, e.g. at a tournament's move limit
+    Adjudication,
+    // Lost for breaking the rules, e.g. by an engine that crashed
+    Forfeit,
+    // A result known without its reason, as from a PGN Result tag
+    Unknown,
+}
+
+impl Termination {
+    // Reads a PGN Termination tag; "normal" and the like leave the reason to
+    // the moves
+    pub fn from_pgn_tag(tag: &str) -> Option<Termination> {
+        match tag.to_ascii_lowercase().as_str() {
+            "time forfeit" => Some(Termination::Timeout),
+            "abandoned" => Some(Termination::Abandoned),
+            "adjudication" => Some(Termination::Adjudication),
+            "rules infraction" => Some(Termination::Forfeit),
+            _ => None,
+        }
+    }
+
+    // Value of the PGN Termination tag, if the reason is known
+    pub fn pgn_tag(self) -> Option<&'static str> {
+        match self {
+            Termination::Timeout => Some("time forfeit"),
+            Termination::Abandoned => Some("abandoned"),
+            Termination::Adjudication => Some("adjudication"),
+            Termination::Forfeit => Some("rules infraction"),
+            Termination::Unknown => None,
+            _ => Some("normal"),
+        }
+    }
+}
+
+// Final outcome of a game: the winner, none for a draw, and why it ended
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GameResult {
+    pub winner: Option<ColorChess>,
+    pub reason: Termination,
+}
+
+impl GameResult {
+    pub fn win_for(color: ColorChess, reason: Termination) -> GameResult {
+        GameResult {
+            winner: Some(color),
+            reason,
+        }
+    }
+
+    pub fn draw(reason: Termination) -> GameResult {
+        GameResult {
+            winner: None,
+            reason,
+        }
+    }
+
+    // Reads the value of a PGN "Result" tag, None for an unfinished game
+    pub fn from_pgn_tag(tag: &str, reason: Termination) -> Option<GameResult> {
+        let winner = match tag {
+            "1-0" => Some(ColorChess::White),
+            "0-1" => Some(ColorChess::Black),
+            "1/2-1/2" => None,
+            _ => return None,
+        };
+        Some(GameResult { winner, reason })
+    }
+
+    // Value of the PGN "Result" tag
+    pub fn pgn_tag(&self) -> &'static str {
+        match self.winner {
+            Some(ColorChess::White) => "1-0",
+            Some(ColorChess::Black) => "0-1",
+            None => "1/2-1/2",
+        }
+    }
+}
+
+// How the game ended in a sentence, e.g. "White resigns. Black wins."
+impl fmt::Display for GameResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let Some(winner) = self.winner else {
+            return f.write_str(match self.reason {
+                Termination::Stalemate => "Stalemate!",
+                Termination::Timeout => "Time ran out, but the other side cannot checkmate. Draw.",
+                Termination::Agreement => "Draw agreed.",
+                Termination::Repetition => "Draw by threefold repetition.",
+                Termination::FiftyMoves => "Draw by the fifty-move rule.",
+                Termination::InsufficientMaterial => "Draw: neither side can checkmate.",
+                Termination::Abandoned => "Game abandoned. Draw.",
+                Termination::Adjudication => "Draw by adjudication.",
+                _ => "Draw.",
+            });
+        };
+        let loser = winner.opposite();
+        match self.reason {
+            Termination::Checkmate => write!(f, "Checkmate! {:?} wins.", winner),
+            Termination::KingCaptured => {
+                write!(f, "{:?} captures the king. {:?} wins.", winner, winner)
+            }
+            Termination::Resignation => write!(f, "{:?} resigns. {:?} wins.", loser, winner),
+            Termination::Timeout => write!(f, "{:?} ran out of time. {:?} wins.", loser, winner),
+            Termination::Abandoned => write!(f, "{:?} left the game. {:?} wins.", loser, winner),
+            Termination::Adjudication => write!(f, "{:?} wins by adjudication.", winner),
+            Termination::Forfeit => write!(f, "{:?} forfeits. {:?} wins.", loser, winner),
+            _ => write!(f, "{:?} wins.", winner),
+        }
+    }
+}
+
+// How many pieces of each side attack every square, whatever is on it. Only
+// the front piece of a battery counts, as the ones behind it are blocked
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct AttackTable {
+    // By color index, then [row][col]
+    counts: [[[u32; 8]; 8]; 2],
+}
+
+impl AttackTable {
+    // None for a square off the board
+    pub fn count(&self, color: ColorChess, (row, col): (usize, usize)) -> u32 {
+        self.counts[color.index()]
+            .get(row)
+            .and_then(|squares| squares.get(col))
+            .copied()
+            .unwrap_or(0)
+    }
+
+    // Whether the piece on the square has more attackers than defenders;
+    // false for an empty square
+    pub fn is_underdefended(&self, board: &Position, square: (usize, usize)) -> bool {
+        board.get_piece(square).is_some_and(|piece| {
+            self.count(piece.color().opposite(), square) > self.count(piece.color(), square)
+        })
+    }
+}
+
+impl Position {
+    // Every square's attackers on both sides, counted
+    pub fn attack_table(&self) -> AttackTable {
+        let occupied = self.occupied();
+        let mut counts = [[[0; 8]; 8]; 2];
+        for color in [ColorChess::White, ColorChess::Black] {
+            for (row, squares) in counts[color.index()].iter_mut().enumerate() {
+                for (col, count) in squares.iter_mut().enumerate() {
+                    *count = self.attackers_to((row, col), color, occupied).count_ones();
+                }
+            }
+        }
+        AttackTable { counts }
+    }
+
+    // Whether any piece of attacker_color attacks the square, whatever is on
+    // it; false for a square off the board
+    pub fn is_square_attacked(
+        &self,
+        target_square: (usize, usize),
+        attacker_color: ColorChess,
+    ) -> bool {
+        on_board(target_square)
+            && self.attackers_to(target_square, attacker_color, self.occupied()) != 0
+    }
+
+    // Whether the piece on `from` attacks `target`, whatever is on it
+    pub fn attacks(&self, from: (usize, usize), target: (usize, usize)) -> bool {
+        let Some(piece) = self.get_piece(from).filter(|_| on_board(target)) else {
+            return false;
+        };
+        self.attackers_to(target, piece.color(), self.occupied()) & square_bit(from) != 0
+    }
+
+    // The pieces of attacker_color attacking a square, with the sliders' rays
+    // blocked by `occupied`. Each kind of piece is looked up from the target
+    // square: a knight there attacks exactly the squares knights could attack it
+    // from, and so on.
+    pub(crate) fn attackers_to(
+        &self,
+        square: (usize, usize),
+        attacker_color: ColorChess,
+        occupied: Bitboard,
+    ) -> Bitboard {
+        let attackers = |piece_type| self.pieces_of(attacker_color, piece_type);
+        let queens = attackers(PieceType::Queen);
+        let diagonal = attackers(PieceType::Bishop) | queens;
+        let straight = attackers(PieceType::Rook) | queens;
+
+        pawn_attacks(square, attacker_color.opposite()) & attackers(PieceType::Pawn)
+            | knight_attacks(square) & attackers(PieceType::Knight)
+            | king_attacks(square) & attackers(PieceType::King)
+            | bishop_attacks(square, occupied) & diagonal
+            | rook_attacks(square, occupied) & straight
+    }
+
+    pub fn is_in_check(&self, color: ColorChess) -> bool {
+        match self.find_king(color) {
+            Some(king_position) => self.is_square_attacked(king_position, color.opposite()),
+            None => false,
+        }
+    }
+
+    pub fn is_checkmate(&self, color: ColorChess) -> bool {
+        if self.find_king(color).is_none() {
+            return true;
+        }
+
+        if !self.is_in_check(color) {
+            return false;
+        }
+
+        self.get_all_legal_moves(color).is_empty()
+    }
+
+    pub fn is_stalemate(&self, color: ColorChess) -> bool {
+        if self.is_in_check(color) {
+            return false;
+        }
+        self.get_all_legal_moves(color).is_empty()
+    }
+
+    // Squares `color` can see in fog-of-war chess, by [row][col]: those its
+    // pieces stand on, attack or can move to
+    pub fn visible_squares(&self, color: ColorChess) -> [[bool; 8]; 8] {
+        let mut visible = [[false; 8]; 8];
+        for (row, squares) in visible.iter_mut().enumerate() {
+            for (col, square) in squares.iter_mut().enumerate() {
+                *square = self
+                    .get_piece((row, col))
+                    .is_some_and(|piece| piece.is_color(color))
+                    || self.is_square_attacked((row, col), color);
+            }
+        }
+        // Pawns see the squares ahead they can move to, pinned or not, so
+        // that a pin by a piece out of sight gives nothing away
+        for mv in self.get_all_moves(color) {
+            if !mv.is_castling() {
+                visible[mv.to.0][mv.to.1] = true;
+            }
+        }
+        visible
+    }
+
+    pub fn has_king(&self, color: ColorChess) -> bool {
+        self.find_king(color).is_some()
+    }
+
+    // Whether the side is checkmated or stalemated; Game::get_result also
+    // knows the endings that depend on the moves played or the material
+    pub fn is_game_over(&self, color: ColorChess) -> bool {
+        self.is_checkmate(color) || self.is_stalemate(color)
+    }
+
+    // Whether a side has the material to checkmate by some series of legal
+    // moves, however unlikely; the FIDE test for a win on time. A lone knight or
+    // bishops all on one square color can only mate with the help of the
+    // opponent's own pieces blocking its king in
+    pub fn has_mating_material(&self, color: ColorChess) -> bool {
+        let heavy = |side| {
+            [PieceType::Pawn, PieceType::Rook, PieceType::Queen]
+                .into_iter()
+                .any(|piece_type| self.pieces_of(side, piece_type) != 0)
+        };
+        if heavy(color) {
+            return true;
+        }
+        let knights = self.pieces_of(color, PieceType::Knight).count_ones();
+        // Whether a side has bishops on dark squares and on light ones
+        let bishop_colors = |side| {
+            squares(self.pieces_of(side, PieceType::Bishop)).fold(
+                [false; 2],
+                |mut colors, (row, col)| {
+                    colors[(row + col) % 2] = true;
+                    colors
+                },
+            )
+        };
+        let bishops = bishop_colors(color);
+        let opponent = color.opposite();
+        let blockers = heavy(opponent) || self.pieces_of(opponent, PieceType::Knight) != 0;
+        match (knights, bishops) {
+            (0, [false, false]) => false,
+            (0, [true, true]) => true,
+            // Bishops on one color need a blocker their bishops do not control,
+            // or an opposing bishop on the other color
+            (0, [dark, _]) => blockers || bishop_colors(opponent)[dark as usize],
+            (1, [false, false]) => blockers || self.pieces_of(opponent, PieceType::Bishop) != 0,
+            _ => true,
+        }
+    }
+}
+
+impl Game {
+    // Result decided on the board for the side to move, if the game is over:
+    // by checkmate, stalemate, threefold repetition, fifty moves each without
+    // a capture or pawn move, or when neither side has the pieces left to
+    // checkmate with. The fifty-move draw comes at once, as on the chess
+    // servers, rather than on a claim or at seventy-five moves; a mate on the
+    // last of those moves still counts
+    pub fn get_result(&self) -> Option<GameResult> {
+        let to_move = self.get_current_turn();
+        if self.is_checkmate(to_move) {
+            Some(GameResult::win_for(
+                to_move.opposite(),
+                Termination::Checkmate,
+            ))
+        } else if self.is_stalemate(to_move) {
+            Some(GameResult::draw(Termination::Stalemate))
+        } else if self.is_threefold_repetition() {
+            Some(GameResult::draw(Termination::Repetition))
+        } else if self.halfmove_clock >= 100 {
+            Some(GameResult::draw(Termination::FiftyMoves))
+        } else if !self.has_mating_material(ColorChess::White)
+            && !self.has_mating_material(ColorChess::Black)
+        {
+            Some(GameResult::draw(Termination::InsufficientMaterial))
+        } else {
+            None
+        }
+    }
+
+    // How many times the current position has occurred, counting this one.
+    // Only positions since the last capture or pawn move can repeat.
+    pub fn repetition_count(&self) -> usize {
+        let reversible = (self.halfmove_clock as usize).min(self.history.len());
+        1 + self.history[self.history.len() - reversible..]
+            .iter()
+            .filter(|record| record.hash == self.hash)
+            .count()
+    }
+
+    pub fn is_threefold_repetition(&self) -> bool {
+        self.repetition_count() >= 3
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fifty_moves_without_a_capture_or_pawn_move_draw() {
+        let mut game = Game::from_fen("8/8/8/8/8/2k5/8/K6R w - - 99 80").unwrap();
+        assert_eq!(game.get_result(), None);
+        game.play_moves("Rh2").unwrap();
+        assert_eq!(
+            game.get_result(),
+            Some(GameResult::draw(Termination::FiftyMoves))
+        );
+        // A mate on the hundredth ply still wins
+        let mut game = Game::from_fen("k7/8/1K6/8/8/8/8/7R w - - 99 80").unwrap();
+        game.play_moves("Rh8#").unwrap();
+        assert_eq!(
+            game.get_result(),
+            Some(GameResult::win_for(
+                ColorChess::White,
+                Termination::Checkmate
+            ))
+        );
+    }
+}
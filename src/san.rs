@@ -0,0 +1,232 @@
+use crate::board::{ColorChess, PieceType, square_name};
+use crate::error::ChessError;
+use crate::game::Game;
+use crate::moves::Move;
+use crate::position::Position;
+
+impl Position {
+    // Standard algebraic notation for a legal move in this position, e.g. "Nbd7",
+    // "exd5", "e8=Q+" or "O-O#"
+    pub fn move_to_san(&self, mv: Move) -> String {
+        let Some(piece) = self.get_piece(mv.from) else {
+            return format!("{}{}", square_name(mv.from), square_name(mv.to));
+        };
+        let color = self.get_current_turn();
+
+        let mut san = if mv.is_castling() {
+            if mv.to.1 == 6 { "O-O" } else { "O-O-O" }.to_string()
+        } else if piece.is_type(PieceType::Pawn) {
+            let mut san = String::new();
+            if mv.is_capture() {
+                san.push((b'a' + mv.from.1 as u8) as char);
+                san.push('x');
+            }
+            san.push_str(&square_name(mv.to));
+            if mv.is_promotion() {
+                san.push('=');
+                san.push(mv.promotion.unwrap_or(PieceType::Queen).letter());
+            }
+            san
+        } else {
+            let mut san = piece.piece_type().letter().to_string();
+
+            // Name the origin file or rank (or both) if another piece of the same
+            // type could also move to the target square
+            let rivals: Vec<Move> = self
+                .get_all_legal_moves(color)
+                .into_iter()
+                .filter(|other| {
+                    other.to == mv.to
+                        && other.from != mv.from
+                        && self
+                            .get_piece(other.from)
+                            .is_some_and(|p| p.piece_type() == piece.piece_type())
+                })
+                .collect();
+            if !rivals.is_empty() {
+                let file = (b'a' + mv.from.1 as u8) as char;
+                let rank = (b'1' + mv.from.0 as u8) as char;
+                if rivals.iter().all(|other| other.from.1 != mv.from.1) {
+                    san.push(file);
+                } else if rivals.iter().all(|other| other.from.0 != mv.from.0) {
+                    san.push(rank);
+                } else {
+                    san.push(file);
+                    san.push(rank);
+                }
+            }
+            if mv.is_capture() {
+                san.push('x');
+            }
+            san.push_str(&square_name(mv.to));
+            san
+        };
+
+        let mut after = *self;
+        let opponent = color.opposite();
+        if after.make(mv).is_ok() && after.is_in_check(opponent) {
+            if after.get_all_legal_moves(opponent).is_empty() {
+                san.push('#');
+            } else {
+                san.push('+');
+            }
+        }
+        san
+    }
+
+    // SAN of a sequence of moves played from this position, numbered like
+    // "12. Nf3 Nc6 13. Bb5" (or "12... Nc6 13. Bb5" when Black moves first)
+    pub fn line_to_san(&self, moves: &[Move]) -> String {
+        let mut position = *self;
+        let mut text = String::new();
+        for (i, &mv) in moves.iter().enumerate() {
+            let white_to_move = position.get_current_turn() == ColorChess::White;
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            if white_to_move {
+                text.push_str(&format!("{}. ", position.get_fullmove_number()));
+            } else if i == 0 {
+                text.push_str(&format!("{}... ", position.get_fullmove_number()));
+            }
+            text.push_str(&position.move_to_san(mv));
+            if position.make(mv).is_err() {
+                break;
+            }
+        }
+        text
+    }
+
+    // Finds the legal move written in SAN, e.g. "Nf3", "exd5", "e8=Q+" or
+    // "O-O"; check marks and annotations such as "!?" may be left off or added
+    pub fn parse_san(&self, text: &str) -> Result<Move, ChessError> {
+        let bare = |san: &str| san.trim_end_matches(['+', '#', '!', '?']).replace('0', "O");
+        let wanted = bare(text.trim());
+        self.get_all_legal_moves(self.get_current_turn())
+            .into_iter()
+            .find(|&mv| bare(&self.move_to_san(mv)) == wanted)
+            .ok_or_else(|| ChessError::BadSan(text.trim().to_string()))
+    }
+}
+
+impl Game {
+    // SAN of every move played so far, oldest first
+    pub fn history_san(&self) -> Vec<String> {
+        let mut board = self.get_start_position();
+        self.history
+            .iter()
+            .map_while(|record| {
+                let san = board.move_to_san(record.mv);
+                board.make(record.mv).ok()?;
+                Some(san)
+            })
+            .collect()
+    }
+
+    // Plays a list of moves in UCI or SAN separated by spaces, such as
+    // "e2e4 e7e5 g1f3" or PGN movetext, whose move numbers and result are
+    // skipped; returns how many were played
+    pub fn play_moves(&mut self, text: &str) -> Result<usize, ChessError> {
+        let mut played = 0;
+        for token in text.split_whitespace() {
+            if ["1-0", "0-1", "1/2-1/2", "*"].contains(&token) {
+                continue;
+            }
+            let token = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+            if token.is_empty() {
+                continue;
+            }
+            let mv = self
+                .parse_uci_move(token)
+                .or_else(|_| self.parse_san(token))
+                .map_err(|_| ChessError::IllegalMove {
+                    mv: token.to_string(),
+                    fen: self.to_fen(),
+                })?;
+            self.play_move(mv)?;
+            played += 1;
+        }
+        Ok(played)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Game;
+    use crate::position::Position;
+
+    const KIWIPETE: &str = "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1";
+
+    // SAN of the move written in UCI
+    fn san(fen: &str, uci: &str) -> String {
+        let position = Position::from_fen(fen).unwrap();
+        position.move_to_san(position.parse_uci_move(uci).unwrap())
+    }
+
+    #[test]
+    fn writes_captures_castling_and_promotions() {
+        assert_eq!(san(KIWIPETE, "e5f7"), "Nxf7");
+        assert_eq!(san(KIWIPETE, "g2h3"), "gxh3");
+        assert_eq!(san(KIWIPETE, "e1g1"), "O-O");
+        assert_eq!(san(KIWIPETE, "e1c1"), "O-O-O");
+        let en_passant = "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3";
+        assert_eq!(san(en_passant, "e5f6"), "exf6");
+        let promotion = "8/4P3/8/7k/8/8/8/4K3 w - - 0 1";
+        assert_eq!(san(promotion, "e7e8q"), "e8=Q+");
+        assert_eq!(san(promotion, "e7e8n"), "e8=N");
+    }
+
+    #[test]
+    fn names_the_file_rank_or_square_a_move_came_from() {
+        let queens = "8/7k/8/8/8/Q7/8/Q1Q1K3 w - - 0 1";
+        assert_eq!(san(queens, "a1b2"), "Qa1b2");
+        assert_eq!(san(queens, "a3b2"), "Q3b2");
+        assert_eq!(san(queens, "c1b2"), "Qcb2");
+        let rooks = "8/1k6/8/8/8/8/4K3/R6R w - - 0 1";
+        assert_eq!(san(rooks, "a1d1"), "Rad1");
+        assert_eq!(san(rooks, "h1f1"), "Rhf1");
+        assert_eq!(san(rooks, "a1a7"), "Ra7+");
+    }
+
+    #[test]
+    fn marks_checkmate_and_numbers_lines() {
+        let mut game = Game::new();
+        game.play_moves("f3 e5 g4 Qh4").unwrap();
+        assert_eq!(game.history_san(), ["f3", "e5", "g4", "Qh4#"]);
+
+        let mut game = Game::new();
+        game.play_moves("e4").unwrap();
+        let mut after = *game.position();
+        let reply = after.parse_san("e5").unwrap();
+        after.make(reply).unwrap();
+        let moves = [reply, after.parse_san("Nf3").unwrap()];
+        assert_eq!(game.line_to_san(&moves), "1... e5 2. Nf3");
+    }
+
+    #[test]
+    fn reads_its_own_san_back() {
+        for fen in [
+            KIWIPETE,
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+            "r3k2r/Pppp1ppp/1b3nbN/nP6/BBP1P3/q4N2/Pp1P2PP/R2Q1RK1 w kq - 0 1",
+            "rnbq1k1r/pp1Pbppp/2p5/8/2B5/8/PPP1NnPP/RNBQK2R w KQ - 1 8",
+        ] {
+            let position = Position::from_fen(fen).unwrap();
+            for mv in position.legal_moves() {
+                let san = position.move_to_san(mv);
+                assert_eq!(position.parse_san(&san).unwrap(), mv, "{} in {}", san, fen);
+            }
+        }
+    }
+
+    #[test]
+    fn reads_loose_san_and_refuses_ambiguous_or_illegal_moves() {
+        let position = Position::from_fen(KIWIPETE).unwrap();
+        let castle = position.parse_uci_move("e1g1").unwrap();
+        assert_eq!(position.parse_san("0-0").unwrap(), castle);
+        assert_eq!(position.parse_san("O-O+!?").unwrap(), castle);
+        let rooks = Position::from_fen("8/1k6/8/8/8/8/4K3/R6R w - - 0 1").unwrap();
+        assert!(rooks.parse_san("Rd1").is_err());
+        assert!(rooks.parse_san("Ke4").is_err());
+    }
+}
@@ -0,0 +1,883 @@
+use std::ops::Deref;
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::ChessError;
+use crate::eval::evaluate;
+use crate::game::Game;
+use crate::moves::{Move, MoveList};
+use crate::ordering::MoveOrdering;
+use crate::position::{Position, Undo};
+use crate::tablebase::{Tablebase, Wdl};
+use crate::tt::{Bound, TranspositionTable, TtEntry, score_from_tt, score_to_tt};
+
+// Score of being mated right now; mates further away score closer to zero
+pub const MATE_SCORE: i32 = 100_000;
+
+// Scores beyond this are forced mates rather than material
+pub const MATE_THRESHOLD: i32 = MATE_SCORE - 1_000;
+
+// Score of a tablebase win, less the plies it takes to reach the position;
+// well above any material balance but below the mate scores
+pub const TB_WIN_SCORE: i32 = 20_000;
+
+// Scores beyond this (and short of the mate scores) are tablebase wins
+pub const TB_WIN_THRESHOLD: i32 = TB_WIN_SCORE / 2;
+
+// Deepest iteration a search ever starts
+pub const MAX_DEPTH: u32 = 64;
+
+// Moves the remaining clock time is spread over when the number left is unknown
+pub const DEFAULT_MOVES_TO_GO: u32 = 30;
+
+// Kept back from the clock so that GUI and pipe latency never lose on time
+const MOVE_OVERHEAD: Duration = Duration::from_millis(50);
+
+// How long and how deep the search may go, and how deliberately weak it plays
+#[derive(Clone, Copy, Debug)]
+pub struct SearchLimits {
+    pub max_depth: u32,
+    pub time_limit: Option<Duration>,
+    // Any root move scoring within this many centipawns of the best may be played
+    pub random_margin: i32,
+    // Up to this many centipawns are added to or taken from every evaluation
+    pub eval_noise: i32,
+    // How many of the best moves get an exact score and a line of their own
+    pub multi_pv: usize,
+}
+
+impl SearchLimits {
+    pub fn depth(max_depth: u32) -> Self {
+        SearchLimits {
+            max_depth,
+            time_limit: None,
+            random_margin: 0,
+            eval_noise: 0,
+            multi_pv: 1,
+        }
+    }
+
+    pub fn time(time_limit: Duration) -> Self {
+        SearchLimits {
+            max_depth: MAX_DEPTH,
+            time_limit: Some(time_limit),
+            random_margin: 0,
+            eval_noise: 0,
+            multi_pv: 1,
+        }
+    }
+
+    // Time for one move when `remaining` is left on the clock for `moves_to_go` moves
+    pub fn clock(remaining: Duration, increment: Duration, moves_to_go: u32) -> Self {
+        let budget = remaining / moves_to_go.max(1) + increment * 3 / 4;
+        let available = remaining.saturating_sub(MOVE_OVERHEAD);
+        SearchLimits::time(budget.min(available).max(Duration::from_millis(1)))
+    }
+}
+
+// Longest principal variation read back from the transposition table
+const MAX_PV_LENGTH: usize = 16;
+
+// One of the lines of a multi-PV search
+#[derive(Clone, Debug)]
+pub struct SearchLine {
+    // Centipawns from the side to move's point of view
+    pub score: i32,
+    pub pv: Vec<Move>,
+}
+
+// Outcome of the deepest fully searched iteration
+#[derive(Clone, Debug)]
+pub struct SearchResult {
+    pub best_move: Option<Move>,
+    // Centipawns from the side to move's point of view
+    pub score: i32,
+    pub depth: u32,
+    pub nodes: u64,
+    pub elapsed: Duration,
+    // Expected line of play, starting with best_move
+    pub pv: Vec<Move>,
+    // The best `multi_pv` moves with their lines, best first
+    pub lines: Vec<SearchLine>,
+}
+
+impl SearchResult {
+    pub fn nodes_per_second(&self) -> u64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            (self.nodes as f64 / seconds) as u64
+        } else {
+            0
+        }
+    }
+}
+
+// What the search plays its moves on: the position, and the keys of the
+// positions before it, in the game and then along the line searched, that
+// could come round again
+#[derive(Clone)]
+struct SearchPosition {
+    position: Position,
+    keys: Vec<u64>,
+}
+
+impl SearchPosition {
+    // Only the positions since the last capture or pawn move can repeat
+    fn new(game: &Game) -> SearchPosition {
+        let history = game.get_history();
+        let reversible = (game.get_halfmove_clock() as usize).min(history.len());
+        SearchPosition {
+            position: *game.position(),
+            keys: history[history.len() - reversible..]
+                .iter()
+                .map(|record| record.hash)
+                .collect(),
+        }
+    }
+
+    fn make(&mut self, mv: Move) -> Result<Undo, ChessError> {
+        let undo = self.position.make(mv)?;
+        self.keys.push(undo.record().hash);
+        Ok(undo)
+    }
+
+    fn unmake(&mut self, undo: Undo) {
+        self.keys.pop();
+        self.position.unmake(undo);
+    }
+
+    // How many times the position has occurred, counting this one
+    fn repetition_count(&self) -> usize {
+        let reversible = (self.position.get_halfmove_clock() as usize).min(self.keys.len());
+        let key = self.position.hash();
+        1 + self.keys[self.keys.len() - reversible..]
+            .iter()
+            .filter(|&&earlier| earlier == key)
+            .count()
+    }
+}
+
+impl Deref for SearchPosition {
+    type Target = Position;
+
+    fn deref(&self) -> &Position {
+        &self.position
+    }
+}
+
+// Alpha-beta search driven by iterative deepening
+#[derive(Default)]
+pub struct Searcher {
+    nodes: u64,
+    deadline: Option<Instant>,
+    time_limit: Option<Duration>,
+    stopped: bool,
+    // Kept between searches, so later moves of a game reuse earlier work
+    tt: TranspositionTable,
+    ordering: MoveOrdering,
+    eval_noise: i32,
+    // Fixed per searcher so the eval noise does not change between searches
+    seed: u64,
+    // State of the generator behind the random move choice
+    rng: u64,
+    // Set from another thread to abandon the search early
+    stop_signal: Option<Arc<AtomicBool>>,
+    // While set, the search ignores its time limit; the clock starts once it is cleared
+    ponder_signal: Option<Arc<AtomicBool>>,
+    pondering: bool,
+    tablebase: Option<Arc<Tablebase>>,
+    // One per extra thread; each searches its share of the root moves with its
+    // own table
+    helpers: Vec<Searcher>,
+    // Lines of the root moves the helpers searched in the last iteration
+    helper_lines: Vec<(Move, Vec<Move>)>,
+}
+
+impl Searcher {
+    pub fn new() -> Self {
+        Searcher::default()
+    }
+
+    pub fn with_tt_size(size_mb: usize) -> Self {
+        Searcher {
+            tt: TranspositionTable::new(size_mb),
+            ..Searcher::default()
+        }
+    }
+
+    // Forgets everything learned in earlier searches, e.g. for a new game
+    pub fn clear(&mut self) {
+        self.tt.clear();
+        self.ordering.clear();
+        for helper in &mut self.helpers {
+            helper.clear();
+        }
+    }
+
+    // Spreads the root moves over this many threads
+    pub fn set_threads(&mut self, threads: usize) {
+        let size_mb = self.tt.size_mb();
+        self.helpers
+            .resize_with(threads.max(1) - 1, || Searcher::with_tt_size(size_mb));
+        let tablebase = self.tablebase.clone();
+        for helper in &mut self.helpers {
+            helper.set_tablebase(tablebase.clone());
+        }
+    }
+
+    pub fn threads(&self) -> usize {
+        self.helpers.len() + 1
+    }
+
+    // Lets another thread stop the search by setting the flag
+    pub fn set_stop_signal(&mut self, stop_signal: Option<Arc<AtomicBool>>) {
+        self.stop_signal = stop_signal;
+    }
+
+    // Searches started while the flag is set are pondering on the opponent's
+    // time; clearing it is a ponder hit, after which the time limit applies
+    pub fn set_ponder_signal(&mut self, ponder_signal: Option<Arc<AtomicBool>>) {
+        self.ponder_signal = ponder_signal;
+    }
+
+    // Endgame tablebases to play small endings perfectly with
+    pub fn set_tablebase(&mut self, tablebase: Option<Arc<Tablebase>>) {
+        for helper in &mut self.helpers {
+            helper.set_tablebase(tablebase.clone());
+        }
+        self.tablebase = tablebase;
+    }
+
+    // Searches one depth deeper at a time until the depth or time limit is
+    // reached, returning the best move of the last completed iteration
+    pub fn search(&mut self, board: &Game, limits: SearchLimits) -> SearchResult {
+        self.search_with_progress(board, limits, |_| {})
+    }
+
+    // Like search, but reports the result of every completed iteration as it
+    // happens, e.g. for a live analysis display
+    pub fn search_with_progress(
+        &mut self,
+        board: &Game,
+        limits: SearchLimits,
+        mut on_iteration: impl FnMut(&SearchResult),
+    ) -> SearchResult {
+        let start = Instant::now();
+        self.nodes = 0;
+        self.stopped = false;
+        self.time_limit = limits.time_limit;
+        self.pondering = self.is_ponder_set();
+        self.deadline = if self.pondering {
+            None
+        } else {
+            limits.time_limit.map(|limit| start + limit)
+        };
+        self.ordering.new_search();
+        self.eval_noise = limits.eval_noise.max(0);
+        if self.seed == 0 {
+            // Noise must not change between searches, or table entries would disagree
+            self.seed = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map_or(1, |d| d.as_nanos() as u64)
+                | 1;
+            self.rng = self.seed;
+        }
+        // Helpers share the limits, signals and eval noise of this search
+        for helper in &mut self.helpers {
+            helper.nodes = 0;
+            helper.stopped = false;
+            helper.time_limit = self.time_limit;
+            helper.pondering = self.pondering;
+            helper.deadline = self.deadline;
+            helper.stop_signal = self.stop_signal.clone();
+            helper.ponder_signal = self.ponder_signal.clone();
+            helper.eval_noise = self.eval_noise;
+            helper.seed = self.seed;
+            helper.ordering.new_search();
+        }
+
+        // The search makes and unmakes moves on its own copy of the position
+        let mut board = SearchPosition::new(board);
+        let mut root_moves = board.get_all_legal_moves(board.get_current_turn());
+        let hash_move = self
+            .tt
+            .probe(board.hash())
+            .and_then(|entry| entry.best_move);
+        self.ordering.order(&board, &mut root_moves, hash_move, 0);
+        let mut result = SearchResult {
+            best_move: root_moves.first().copied(),
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            elapsed: Duration::ZERO,
+            pv: Vec::new(),
+            lines: Vec::new(),
+        };
+
+        // In a tablebase ending the table move is simply the best there is
+        if let Some((mv, dtz)) = self
+            .tablebase
+            .as_ref()
+            .and_then(|tablebase| tablebase.best_move(&board))
+        {
+            result.best_move = Some(mv);
+            result.score = match dtz {
+                1.. => TB_WIN_SCORE - dtz,
+                0 => 0,
+                _ => -TB_WIN_SCORE - dtz,
+            };
+            result.depth = 1;
+            result.pv = vec![mv];
+            result.lines = vec![SearchLine {
+                score: result.score,
+                pv: result.pv.clone(),
+            }];
+            result.elapsed = start.elapsed();
+            on_iteration(&result);
+            return result;
+        }
+
+        let margin = limits.random_margin.max(0);
+        let multi_pv = limits.multi_pv.max(1);
+        let mut candidates = Vec::new();
+        for depth in 1..=limits.max_depth.max(1) {
+            let Some(scores) =
+                self.search_iteration(&mut board, &root_moves, depth, margin, multi_pv)
+            else {
+                break;
+            };
+            let Some(&(best_move, score)) = scores
+                .iter()
+                .reduce(|best, entry| if entry.1 > best.1 { entry } else { best })
+            else {
+                break;
+            };
+            result.best_move = Some(best_move);
+            result.score = score;
+            result.depth = depth;
+            result.nodes = self.total_nodes();
+            result.elapsed = start.elapsed();
+            result.pv = self.line(&mut board, best_move);
+            let mut ranked = scores.clone();
+            ranked.sort_by_key(|&(_, s)| std::cmp::Reverse(s));
+            result.lines = ranked
+                .iter()
+                .take(multi_pv)
+                .map(|&(mv, score)| SearchLine {
+                    score,
+                    pv: self.line(&mut board, mv),
+                })
+                .collect();
+            on_iteration(&result);
+            candidates = scores
+                .into_iter()
+                .filter(|&(_, s)| margin > 0 && s >= score - margin)
+                .collect();
+
+            if multi_pv > 1 {
+                // Keep the lines in the same order for the next iteration
+                root_moves = ranked.into_iter().map(|(mv, _)| mv).collect();
+            } else if let Some(index) = root_moves.iter().position(|&mv| mv == best_move) {
+                // Try the previous best move first in the next iteration
+                root_moves[..=index].rotate_right(1);
+            }
+            // A forced mate will not get any better by searching deeper, though
+            // the other lines still might
+            if multi_pv == 1 && score.abs() >= MATE_THRESHOLD {
+                break;
+            }
+        }
+
+        // Weaker settings pick any move that scored close enough to the best one
+        if candidates.len() > 1 {
+            let (mv, score) = candidates[(self.next_random() % candidates.len() as u64) as usize];
+            result.best_move = Some(mv);
+            result.score = score;
+            result.pv = self.line(&mut board, mv);
+        }
+
+        result.nodes = self.total_nodes();
+        result.elapsed = start.elapsed();
+        result
+    }
+
+    // Searches one iteration, on this thread alone or split between the helpers
+    fn search_iteration(
+        &mut self,
+        board: &mut SearchPosition,
+        root_moves: &[Move],
+        depth: u32,
+        margin: i32,
+        multi_pv: usize,
+    ) -> Option<Vec<(Move, i32)>> {
+        let scores = if self.helpers.is_empty() || root_moves.len() < multi_pv + 2 {
+            self.helper_lines.clear();
+            self.search_root(board, root_moves, &[], depth, margin, multi_pv)?
+        } else {
+            self.search_root_parallel(board, root_moves, depth, margin, multi_pv)?
+        };
+
+        let &(best_move, best_score) = scores
+            .iter()
+            .reduce(|best, entry| if entry.1 > best.1 { entry } else { best })?;
+        self.tt.store(TtEntry {
+            key: board.hash(),
+            depth,
+            score: best_score,
+            bound: Bound::Exact,
+            best_move: Some(best_move),
+        });
+        Some(scores)
+    }
+
+    // Searches the first (expected best) `multi_pv` moves alone, then lets every
+    // thread take every n-th of the other moves and search its share against
+    // those scores without hearing from the others, so that the merged scores
+    // do not depend on how the threads happen to be scheduled
+    fn search_root_parallel(
+        &mut self,
+        board: &mut SearchPosition,
+        root_moves: &[Move],
+        depth: u32,
+        margin: i32,
+        multi_pv: usize,
+    ) -> Option<Vec<(Move, i32)>> {
+        let (lead, rest) = root_moves.split_at(multi_pv);
+        let first = self.search_root(board, lead, &[], depth, margin, multi_pv)?;
+        let threads = self.threads().min(rest.len());
+        let share = |index: usize| -> Vec<Move> {
+            rest.iter().skip(index).step_by(threads).copied().collect()
+        };
+
+        let mut helpers = std::mem::take(&mut self.helpers);
+        let (own, shared) = thread::scope(|scope| {
+            let handles: Vec<_> = helpers
+                .iter_mut()
+                .take(threads - 1)
+                .enumerate()
+                .map(|(index, helper)| {
+                    let moves = share(index + 1);
+                    let mut board = board.clone();
+                    let first = &first;
+                    scope.spawn(move || {
+                        let scores = helper
+                            .search_root(&mut board, &moves, first, depth, margin, multi_pv)?;
+                        let lines = helper.wanted_lines(&mut board, &scores, margin, multi_pv);
+                        Some((scores, lines))
+                    })
+                })
+                .collect();
+            let own = self.search_root(board, &share(0), &first, depth, margin, multi_pv);
+            let shared: Vec<_> = handles
+                .into_iter()
+                .map(|handle| handle.join().ok().flatten())
+                .collect();
+            (own, shared)
+        });
+        self.helpers = helpers;
+
+        let mut scores = first;
+        scores.extend(own?);
+        let mut helper_lines = Vec::new();
+        for part in shared {
+            let (part_scores, lines) = part?;
+            scores.extend(part_scores);
+            helper_lines.extend(lines);
+        }
+        self.helper_lines = helper_lines;
+        // Back into root move order, so ties go the same way as on one thread
+        scores.sort_by_key(|&(mv, _)| root_moves.iter().position(|&root| root == mv));
+        Some(scores)
+    }
+
+    // Lines a helper reads from its own table for the moves that may be shown or
+    // played: its best `multi_pv` and those within `margin` of its best
+    fn wanted_lines(
+        &self,
+        board: &mut SearchPosition,
+        scores: &[(Move, i32)],
+        margin: i32,
+        multi_pv: usize,
+    ) -> Vec<(Move, Vec<Move>)> {
+        let best = scores.iter().map(|&(_, score)| score).max().unwrap_or(0);
+        let nth = nth_best_score(scores, multi_pv.min(scores.len()).max(1));
+        scores
+            .iter()
+            .filter(|&&(_, score)| score >= nth || (margin > 0 && score >= best - margin))
+            .map(|&(mv, _)| (mv, self.principal_variation(board, mv)))
+            .collect()
+    }
+
+    // The principal variation starting with a root move, from the table of
+    // whichever thread searched it
+    fn line(&self, board: &mut SearchPosition, mv: Move) -> Vec<Move> {
+        match self.helper_lines.iter().find(|(root, _)| *root == mv) {
+            Some((_, line)) => line.clone(),
+            None => self.principal_variation(board, mv),
+        }
+    }
+
+    fn total_nodes(&self) -> u64 {
+        self.nodes + self.helpers.iter().map(|helper| helper.nodes).sum::<u64>()
+    }
+
+    // Scores the given root moves; moves more than `margin` below the best, and
+    // outside the best `multi_pv`, only get an upper bound. `known` holds scores
+    // of other root moves that were already searched. Returns None if time ran
+    // out before all moves were searched.
+    fn search_root(
+        &mut self,
+        board: &mut SearchPosition,
+        root_moves: &[Move],
+        known: &[(Move, i32)],
+        depth: u32,
+        margin: i32,
+        multi_pv: usize,
+    ) -> Option<Vec<(Move, i32)>> {
+        let beta = MATE_SCORE + 1;
+        let mut all_scores = known.to_vec();
+
+        for &mv in root_moves {
+            // Lowering alpha by the margin gives near-best moves exact scores,
+            // and lowering it to the worst of the lines so far does for those
+            let best = all_scores.iter().map(|&(_, score)| score).max();
+            let mut alpha = best.map_or(-MATE_SCORE - 1, |score| score - margin - 1);
+            if multi_pv > 1 {
+                alpha = alpha.min(nth_best_score(&all_scores, multi_pv) - 1);
+            }
+            let Ok(undo) = board.make(mv) else {
+                continue;
+            };
+            let score = -self.alpha_beta(board, depth - 1, 1, -beta, -alpha);
+            board.unmake(undo);
+
+            if self.stopped {
+                return None;
+            }
+            all_scores.push((mv, score));
+        }
+        Some(all_scores.split_off(known.len()))
+    }
+
+    fn alpha_beta(
+        &mut self,
+        board: &mut SearchPosition,
+        depth: u32,
+        ply: i32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.out_of_time() {
+            return 0;
+        }
+        // Repeating a position (in the game or along this line) is scored as a draw
+        if board.repetition_count() >= 2 {
+            return 0;
+        }
+
+        let key = board.hash();
+        let entry = self.tt.probe(key);
+        if let Some(entry) = entry
+            && entry.depth >= depth
+        {
+            let score = score_from_tt(entry.score, ply);
+            match entry.bound {
+                Bound::Exact => return score,
+                Bound::Lower if score >= beta => return score,
+                Bound::Upper if score <= alpha => return score,
+                _ => {}
+            }
+        }
+
+        // Right after a capture or pawn move the material may have come within
+        // the tablebases' reach. Games end by the fifty-move rule, so cursed
+        // wins and blessed losses are draws
+        if board.get_halfmove_clock() == 0
+            && let Some(wdl) = self
+                .tablebase
+                .as_ref()
+                .and_then(|tablebase| tablebase.probe_wdl(board))
+        {
+            let score = match wdl {
+                Wdl::Win => TB_WIN_SCORE - ply,
+                Wdl::CursedWin | Wdl::Draw | Wdl::BlessedLoss => 0,
+                Wdl::Loss => -TB_WIN_SCORE + ply,
+            };
+            self.tt.store(TtEntry {
+                key,
+                depth: MAX_DEPTH,
+                score,
+                bound: Bound::Exact,
+                best_move: None,
+            });
+            return score;
+        }
+
+        if depth == 0 {
+            return self.quiescence(board, ply, 0, alpha, beta);
+        }
+
+        let color = board.get_current_turn();
+        let mut moves = MoveList::new();
+        board.moves_into(&mut moves);
+        if moves.is_empty() {
+            return if board.is_in_check(color) {
+                -MATE_SCORE + ply
+            } else {
+                0
+            };
+        }
+        let hash_move = entry.and_then(|entry| entry.best_move);
+        self.ordering
+            .order(board, &mut moves, hash_move, ply as usize);
+
+        let original_alpha = alpha;
+        let mut best_score = -MATE_SCORE - 1;
+        let mut best_move = None;
+        for mv in moves {
+            let Ok(undo) = board.make(mv) else {
+                continue;
+            };
+            let score = -self.alpha_beta(board, depth - 1, ply + 1, -beta, -alpha);
+            board.unmake(undo);
+
+            if self.stopped {
+                return 0;
+            }
+            if score > best_score {
+                best_score = score;
+                best_move = Some(mv);
+            }
+            if score >= beta {
+                self.ordering.record_cutoff(mv, ply as usize, depth);
+                break;
+            }
+            alpha = alpha.max(score);
+        }
+
+        let bound = if best_score >= beta {
+            Bound::Lower
+        } else if best_score > original_alpha {
+            Bound::Exact
+        } else {
+            Bound::Upper
+        };
+        self.tt.store(TtEntry {
+            key,
+            depth,
+            score: score_to_tt(best_score, ply),
+            bound,
+            best_move,
+        });
+        best_score
+    }
+
+    // Keeps searching captures, promotions and (on its first ply) checking moves
+    // until the position is quiet, so the static eval is never taken in the
+    // middle of an exchange
+    fn quiescence(
+        &mut self,
+        board: &mut SearchPosition,
+        ply: i32,
+        qdepth: u32,
+        mut alpha: i32,
+        beta: i32,
+    ) -> i32 {
+        self.nodes += 1;
+        if self.out_of_time() {
+            return 0;
+        }
+
+        let color = board.get_current_turn();
+        let in_check = board.is_in_check(color);
+
+        // Standing pat: the side to move can usually do at least as well as
+        // the static eval by not capturing; in check every evasion is searched
+        if !in_check {
+            let stand_pat = self.evaluate(board);
+            if stand_pat >= beta {
+                return beta;
+            }
+            alpha = alpha.max(stand_pat);
+        }
+
+        let mut moves = MoveList::new();
+        board.moves_into(&mut moves);
+        if moves.is_empty() {
+            return if in_check { -MATE_SCORE + ply } else { 0 };
+        }
+        self.ordering.order(board, &mut moves, None, ply as usize);
+
+        for mv in moves {
+            let tactical = in_check || mv.is_capture() || mv.is_promotion();
+            if !tactical && qdepth > 0 {
+                continue;
+            }
+
+            let Ok(undo) = board.make(mv) else {
+                continue;
+            };
+            // Quiet moves are only worth a look here if they give check
+            if !tactical && !board.is_in_check(board.get_current_turn()) {
+                board.unmake(undo);
+                continue;
+            }
+            let score = -self.quiescence(board, ply + 1, qdepth + 1, -beta, -alpha);
+            board.unmake(undo);
+
+            if self.stopped {
+                return 0;
+            }
+            if score >= beta {
+                return beta;
+            }
+            alpha = alpha.max(score);
+        }
+        alpha
+    }
+
+    // Static eval plus this position's share of the configured noise; the noise
+    // depends only on the position, so transpositions still agree
+    fn evaluate(&self, board: &Position) -> i32 {
+        let score = evaluate(board);
+        if self.eval_noise == 0 {
+            return score;
+        }
+        let spread = 2 * self.eval_noise as u64 + 1;
+        score + (splitmix(board.hash() ^ self.seed) % spread) as i32 - self.eval_noise
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng = splitmix(self.rng);
+        self.rng
+    }
+
+    // Follows the table's best moves from the position after first_move
+    fn principal_variation(&self, board: &mut SearchPosition, first_move: Move) -> Vec<Move> {
+        let mut pv = vec![first_move];
+        let Ok(undo) = board.make(first_move) else {
+            return pv;
+        };
+        let mut undos = vec![undo];
+        while pv.len() < MAX_PV_LENGTH && board.repetition_count() < 2 {
+            let Some(mv) = self
+                .tt
+                .probe(board.hash())
+                .and_then(|entry| entry.best_move)
+            else {
+                break;
+            };
+            // A hash collision could suggest a move that is illegal here
+            if !board.legal_moves().contains(&mv) {
+                break;
+            }
+            let Ok(undo) = board.make(mv) else {
+                break;
+            };
+            pv.push(mv);
+            undos.push(undo);
+        }
+        for undo in undos.into_iter().rev() {
+            board.unmake(undo);
+        }
+        pv
+    }
+
+    fn is_ponder_set(&self) -> bool {
+        self.ponder_signal
+            .as_ref()
+            .is_some_and(|ponder| ponder.load(Ordering::Relaxed))
+    }
+
+    fn out_of_time(&mut self) -> bool {
+        if self.pondering && !self.is_ponder_set() {
+            self.pondering = false;
+            self.deadline = self.time_limit.map(|limit| Instant::now() + limit);
+        }
+        if let Some(deadline) = self.deadline
+            && Instant::now() >= deadline
+        {
+            self.stopped = true;
+        }
+        if let Some(stop_signal) = &self.stop_signal
+            && stop_signal.load(Ordering::Relaxed)
+        {
+            self.stopped = true;
+        }
+        self.stopped
+    }
+}
+
+// The n-th highest score so far, or the lowest possible while there are fewer
+fn nth_best_score(scores: &[(Move, i32)], n: usize) -> i32 {
+    if scores.len() < n {
+        return -MATE_SCORE;
+    }
+    let mut sorted: Vec<i32> = scores.iter().map(|&(_, score)| score).collect();
+    sorted.sort_unstable_by(|a, b| b.cmp(a));
+    sorted[n - 1]
+}
+
+// One step of SplitMix64, a small and fast pseudo-random generator
+pub fn splitmix(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn threads_agree_at_a_fixed_depth() {
+        let fens = [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            "r1bqkb1r/pppp1ppp/2n2n2/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R w KQkq - 4 4",
+            "r3k2r/p1ppqpb1/bn2pnp1/3PN3/1p2P3/2N2Q1p/PPPBBPPP/R3K2R w KQkq - 0 1",
+            "8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1",
+        ];
+        for fen in fens {
+            let board = Game::from_fen(fen).unwrap();
+            let alone = Searcher::new().search(&board, SearchLimits::depth(4));
+            let mut searcher = Searcher::new();
+            searcher.set_threads(4);
+            let shared = searcher.search(&board, SearchLimits::depth(4));
+            assert_eq!(
+                (shared.best_move, shared.score),
+                (alone.best_move, alone.score),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    #[test]
+    fn finds_mates() {
+        let mate = Game::from_fen("6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1").unwrap();
+        let result = Searcher::new().search(&mate, SearchLimits::depth(3));
+        assert_eq!(result.score, MATE_SCORE - 1);
+        assert_eq!(
+            result.best_move.map(|mv| mv.to_uci()).as_deref(),
+            Some("a1a8")
+        );
+
+        // Kb6 or Kc7 leaves Black one move, after which the rook mates
+        let mut board = Game::from_fen("k7/8/2K5/8/8/8/8/7R w - - 0 1").unwrap();
+        let result = Searcher::new().search(&board, SearchLimits::depth(5));
+        assert_eq!(result.score, MATE_SCORE - 3);
+        board.play_move(result.best_move.unwrap()).unwrap();
+        for reply in board.get_all_legal_moves(board.get_current_turn()) {
+            let mut after = board.clone();
+            after.play_move(reply).unwrap();
+            let result = Searcher::new().search(&after, SearchLimits::depth(3));
+            assert_eq!(result.score, MATE_SCORE - 1, "{}", after.to_fen());
+        }
+    }
+}
@@ -0,0 +1,1773 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use crate::board::{ColorChess, PieceType};
+use crate::error::ChessError;
+use crate::moves::Move;
+use crate::position::Position;
+
+// Syzygy endgame tablebases: WDL files (.rtbw) give the result of a position,
+// DTZ files (.rtbz) the number of plies until the next capture or pawn move
+// when playing for that result. The format follows Ronald de Man's probing code.
+
+const WDL_MAGIC: [u8; 4] = [0x71, 0xE8, 0x23, 0x5D];
+const DTZ_MAGIC: [u8; 4] = [0xD7, 0x66, 0x0C, 0xA5];
+
+// Largest tables that exist, kings included
+const MAX_PIECES: usize = 7;
+
+// Pieces inside the tables are 1..=6 for pawn up to king, plus 8 for black
+const BLACK_BIT: u8 = 8;
+
+// Piece letters by code, in the order they appear in table names
+const NAME_ORDER: [(u8, char); 6] = [(6, 'K'), (5, 'Q'), (4, 'R'), (3, 'B'), (2, 'N'), (1, 'P')];
+
+// DTZ of a zeroing move by the WDL it leads to (loss to win)
+const WDL_TO_DTZ: [i32; 5] = [-1, -101, 0, 101, 1];
+
+// Which of the four DTZ value maps applies for each WDL, and the flags telling
+// that a table stores wins or losses in plies rather than moves
+const WDL_TO_MAP: [usize; 5] = [1, 3, 0, 2, 0];
+const PLIES_FLAGS: [u8; 5] = [8, 0, 0, 0, 4];
+
+// Squares are 0..64 from a1 to h8 throughout, as in the files themselves
+
+// The a1-d1-d4 triangle the first pieces are moved into, numbered off the diagonal first
+#[rustfmt::skip]
+const TRIANGLE: [usize; 64] = [
+    6, 0, 1, 2, 2, 1, 0, 6,
+    0, 7, 3, 4, 4, 3, 7, 0,
+    1, 3, 8, 5, 5, 8, 3, 1,
+    2, 4, 5, 9, 9, 5, 4, 2,
+    2, 4, 5, 9, 9, 5, 4, 2,
+    1, 3, 8, 5, 5, 8, 3, 1,
+    0, 7, 3, 4, 4, 3, 7, 0,
+    6, 0, 1, 2, 2, 1, 0, 6,
+];
+
+const INV_TRIANGLE: [usize; 10] = [1, 2, 3, 10, 11, 19, 0, 9, 18, 27];
+
+// Squares below the a1-h8 diagonal numbered 0..28, the diagonal itself 28..36
+#[rustfmt::skip]
+const LOWER: [usize; 64] = [
+    28,  0,  1,  2,  3,  4,  5,  6,
+     0, 29,  7,  8,  9, 10, 11, 12,
+     1,  7, 30, 13, 14, 15, 16, 17,
+     2,  8, 13, 31, 18, 19, 20, 21,
+     3,  9, 14, 18, 32, 22, 23, 24,
+     4, 10, 15, 19, 22, 33, 25, 26,
+     5, 11, 16, 20, 23, 25, 34, 27,
+     6, 12, 17, 21, 24, 26, 27, 35,
+];
+
+#[rustfmt::skip]
+const DIAG: [usize; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  8,
+     0,  1,  0,  0,  0,  0,  9,  0,
+     0,  0,  2,  0,  0, 10,  0,  0,
+     0,  0,  0,  3, 11,  0,  0,  0,
+     0,  0,  0, 12,  4,  0,  0,  0,
+     0,  0, 13,  0,  0,  5,  0,  0,
+     0, 14,  0,  0,  0,  0,  6,  0,
+    15,  0,  0,  0,  0,  0,  0,  7,
+];
+
+// Leading pawn squares by file group, and the order further leading pawns are counted in
+#[rustfmt::skip]
+const FLAP: [usize; 64] = [
+    0,  0,  0,  0,  0,  0,  0, 0,
+    0,  6, 12, 18, 18, 12,  6, 0,
+    1,  7, 13, 19, 19, 13,  7, 1,
+    2,  8, 14, 20, 20, 14,  8, 2,
+    3,  9, 15, 21, 21, 15,  9, 3,
+    4, 10, 16, 22, 22, 16, 10, 4,
+    5, 11, 17, 23, 23, 17, 11, 5,
+    0,  0,  0,  0,  0,  0,  0, 0,
+];
+
+#[rustfmt::skip]
+const PTWIST: [usize; 64] = [
+     0,  0,  0,  0,  0,  0,  0,  0,
+    47, 35, 23, 11, 10, 22, 34, 46,
+    45, 33, 21,  9,  8, 20, 32, 44,
+    43, 31, 19,  7,  6, 18, 30, 42,
+    41, 29, 17,  5,  4, 16, 28, 40,
+    39, 27, 15,  3,  2, 14, 26, 38,
+    37, 25, 13,  1,  0, 12, 24, 36,
+     0,  0,  0,  0,  0,  0,  0,  0,
+];
+
+#[rustfmt::skip]
+const INV_FLAP: [usize; 24] = [
+     8, 16, 24, 32, 40, 48,
+     9, 17, 25, 33, 41, 49,
+    10, 18, 26, 34, 42, 50,
+    11, 19, 27, 35, 43, 51,
+];
+
+const FILE_TO_FILE: [usize; 8] = [0, 1, 2, 3, 3, 2, 1, 0];
+
+// Positions of the leading group for each encoding type
+const PIVOT_FACTOR: [u64; 3] = [31332, 28056, 462];
+
+// Result for the side to move. Cursed wins and blessed losses are the ones the
+// fifty-move rule turns into draws.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Wdl {
+    Loss,
+    BlessedLoss,
+    Draw,
+    CursedWin,
+    Win,
+}
+
+impl Wdl {
+    fn from_value(value: i32) -> Wdl {
+        match value {
+            ..=-2 => Wdl::Loss,
+            -1 => Wdl::BlessedLoss,
+            0 => Wdl::Draw,
+            1 => Wdl::CursedWin,
+            _ => Wdl::Win,
+        }
+    }
+}
+
+// Tables found on disk; each one is read into memory the first time it is probed
+pub struct Tablebase {
+    dirs: Vec<PathBuf>,
+    // Names such as "KQvK" of the WDL tables present
+    available: HashSet<String>,
+    max_pieces: usize,
+    wdl: Mutex<HashMap<String, Option<Arc<Table>>>>,
+    dtz: Mutex<HashMap<String, Option<Arc<Table>>>>,
+}
+
+impl Tablebase {
+    // Scans the directories in `paths` (separated like PATH) for tables and
+    // checks a few known positions against them
+    pub fn open(paths: &str) -> Result<Tablebase, ChessError> {
+        let dirs: Vec<PathBuf> = std::env::split_paths(paths).collect();
+        let mut available = HashSet::new();
+        for dir in &dirs {
+            let entries = fs::read_dir(dir).map_err(|e| ChessError::io(dir.display(), e))?;
+            for entry in entries.flatten() {
+                let file_name = entry.file_name();
+                let Some(name) = file_name.to_str().and_then(|n| n.strip_suffix(".rtbw")) else {
+                    continue;
+                };
+                if Material::from_name(name).is_some() {
+                    available.insert(name.to_string());
+                }
+            }
+        }
+        let max_pieces = available
+            .iter()
+            .map(|name| name.len() - 1)
+            .max()
+            .unwrap_or(0);
+        if max_pieces == 0 {
+            return Err(ChessError::BadTablebase(format!(
+                "{}: no Syzygy tables found",
+                paths
+            )));
+        }
+        let tablebase = Tablebase {
+            dirs,
+            available,
+            max_pieces,
+            wdl: Mutex::new(HashMap::new()),
+            dtz: Mutex::new(HashMap::new()),
+        };
+        tablebase.self_check()?;
+        Ok(tablebase)
+    }
+
+    // Largest number of pieces, kings included, that the tables cover
+    pub fn max_pieces(&self) -> usize {
+        self.max_pieces
+    }
+
+    // Whether the position is small enough and has no castling rights left
+    pub fn can_probe(&self, board: &Position) -> bool {
+        Material::of(board).count() <= self.max_pieces && board.fen_castling() == "-"
+    }
+
+    pub fn probe_wdl(&self, board: &Position) -> Option<Wdl> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        self.wdl(&mut board.clone()).map(Wdl::from_value)
+    }
+
+    // Plies to the next capture or pawn move with best play, negative when losing;
+    // values beyond 100 mark cursed wins and blessed losses
+    pub fn probe_dtz(&self, board: &Position) -> Option<i32> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        self.dtz(&mut board.clone())
+    }
+
+    // The move that keeps the best result and, when winning, heads for the next
+    // capture or pawn move the fastest. Also returns its DTZ from the mover's side
+    pub fn best_move(&self, board: &Position) -> Option<(Move, i32)> {
+        if !self.can_probe(board) {
+            return None;
+        }
+        let mut board = *board;
+        let color = board.get_current_turn();
+        let mut best: Option<(Move, i32, i32)> = None;
+        for mv in board.get_all_legal_moves(color) {
+            let Ok(undo) = board.make(mv) else {
+                continue;
+            };
+            let dtz = if board.is_checkmate(color.opposite()) {
+                Some(1)
+            } else if board.get_halfmove_clock() == 0 {
+                self.wdl(&mut board)
+                    .map(|wdl| WDL_TO_DTZ[(2 - wdl) as usize])
+            } else {
+                self.dtz(&mut board).map(|dtz| -dtz - dtz.signum())
+            };
+            board.unmake(undo);
+            let dtz = dtz?;
+            let rank = match dtz {
+                1.. => 1000 - dtz,
+                0 => 0,
+                _ => -1000 - dtz,
+            };
+            if best.is_none_or(|(_, _, best_rank)| rank > best_rank) {
+                best = Some((mv, dtz, rank));
+            }
+        }
+        best.map(|(mv, dtz, _)| (mv, dtz))
+    }
+
+    // Catches tables that load but decode wrongly, which would otherwise
+    // quietly spoil the engine's endgames
+    fn self_check(&self) -> Result<(), ChessError> {
+        let checks = [
+            ("KQvK", "4k3/8/8/8/8/8/8/Q3K3 w - - 0 1", Wdl::Win),
+            ("KQvK", "4k3/8/8/8/8/8/8/Q3K3 b - - 0 1", Wdl::Loss),
+            ("KQvK", "8/8/8/8/8/8/6k1/4K2Q b - - 0 1", Wdl::Draw),
+            ("KPvK", "8/8/8/8/8/8/4P3/4K2k w - - 0 1", Wdl::Win),
+        ];
+        for (name, fen, expected) in checks {
+            if !self.available.contains(name) {
+                continue;
+            }
+            let board = Position::from_fen(fen)?;
+            match self.probe_wdl(&board) {
+                Some(wdl) if wdl == expected => {}
+                found => {
+                    return Err(ChessError::BadTablebase(format!(
+                        "{} gives {:?} instead of {:?} for {}; tablebases disabled",
+                        name, found, expected, fen
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // The table for the position's material, and whether it is stored with the
+    // colours swapped
+    fn table(&self, board: &Position, dtz: bool) -> Option<(Arc<Table>, bool)> {
+        let material = Material::of(board);
+        let (name, mirrored) = [false, true].into_iter().find_map(|mirrored| {
+            let name = material.name(mirrored);
+            self.available.contains(&name).then_some((name, mirrored))
+        })?;
+        let cache = if dtz { &self.dtz } else { &self.wdl };
+        let mut cache = cache.lock().ok()?;
+        let table = cache
+            .entry(name)
+            .or_insert_with_key(|name| {
+                let extension = if dtz { "rtbz" } else { "rtbw" };
+                let bytes = self
+                    .dirs
+                    .iter()
+                    .find_map(|dir| fs::read(dir.join(format!("{}.{}", name, extension))).ok())?;
+                Table::parse(bytes, name, dtz).map(Arc::new)
+            })
+            .clone()?;
+        Some((table, mirrored))
+    }
+
+    // Value from the WDL table alone: -2 (loss) to 2 (win)
+    fn probe_wdl_table(&self, board: &Position) -> Option<i32> {
+        if Material::of(board).count() == 2 {
+            return Some(0);
+        }
+        let (table, mirrored) = self.table(board, false)?;
+        match table.lookup(board, mirrored)? {
+            Lookup::Found(part, index) => {
+                Some(i32::from(part.pairs.decompress(&table.bytes, index)?) - 2)
+            }
+            Lookup::OtherSide => None,
+        }
+    }
+
+    // Value from the DTZ table alone, or Some(None) if the table only holds the
+    // other side to move
+    fn probe_dtz_table(&self, board: &Position, wdl: i32) -> Option<Option<i32>> {
+        let (table, mirrored) = self.table(board, true)?;
+        let (part, index) = match table.lookup(board, mirrored)? {
+            Lookup::Found(part, index) => (part, index),
+            Lookup::OtherSide => return Some(None),
+        };
+        let mut value = usize::from(part.pairs.decompress(&table.bytes, index)?);
+        let slot = (wdl + 2) as usize;
+        if part.flags & 2 != 0 {
+            let at = part.map_index[WDL_TO_MAP[slot]] + value;
+            value = if part.flags & 16 != 0 {
+                usize::from(u16_le(&table.bytes, table.map + 2 * at)?)
+            } else {
+                usize::from(*table.bytes.get(table.map + at)?)
+            };
+        }
+        if part.flags & PLIES_FLAGS[slot] == 0 || wdl & 1 != 0 {
+            value *= 2;
+        }
+        Some(Some(value as i32))
+    }
+
+    // A table may hold any value for positions where a capture is best, so
+    // captures (other than en passant) are searched with the table value as the
+    // floor. Also tells whether the value comes from a winning capture
+    fn probe_ab(&self, board: &mut Position, mut alpha: i32, beta: i32) -> Option<(i32, bool)> {
+        for mv in board.legal_moves() {
+            if !mv.is_capture() || mv.is_en_passant() {
+                continue;
+            }
+            let Ok(undo) = board.make(mv) else {
+                continue;
+            };
+            let value = self.probe_ab(board, -beta, -alpha);
+            board.unmake(undo);
+            let value = -value?.0;
+            if value > alpha {
+                if value >= beta {
+                    return Some((value, true));
+                }
+                alpha = value;
+            }
+        }
+        let value = self.probe_wdl_table(board)?;
+        if alpha >= value {
+            Some((alpha, alpha > 0))
+        } else {
+            Some((value, false))
+        }
+    }
+
+    // Best value of the en passant captures available, if there are any
+    fn best_en_passant(&self, board: &mut Position, moves: &[Move]) -> Option<Option<i32>> {
+        let mut best = None;
+        for &mv in moves.iter().filter(|mv| mv.is_en_passant()) {
+            let Ok(undo) = board.make(mv) else {
+                continue;
+            };
+            let value = self.probe_ab(board, -2, 2);
+            board.unmake(undo);
+            let value = -value?.0;
+            best = Some(best.map_or(value, |best: i32| best.max(value)));
+        }
+        Some(best)
+    }
+
+    fn wdl(&self, board: &mut Position) -> Option<i32> {
+        let (mut value, _) = self.probe_ab(board, -2, 2)?;
+        let moves = board.legal_moves();
+        if let Some(en_passant) = self.best_en_passant(board, &moves)? {
+            // The tables know nothing of en passant, and without it the position
+            // might even be stalemate
+            if en_passant >= value || (value == 0 && moves.iter().all(|mv| mv.is_en_passant())) {
+                value = en_passant;
+            }
+        }
+        Some(value)
+    }
+
+    fn dtz_no_en_passant(&self, board: &mut Position, moves: &[Move]) -> Option<i32> {
+        let (wdl, by_capture) = self.probe_ab(board, -2, 2)?;
+        if wdl == 0 {
+            return Some(0);
+        }
+        if by_capture {
+            return Some(WDL_TO_DTZ[(wdl + 2) as usize]);
+        }
+        let is_pawn_move = |board: &Position, mv: &Move| {
+            board
+                .get_piece(mv.from)
+                .is_some_and(|piece| piece.is_type(PieceType::Pawn))
+        };
+
+        if wdl > 0 {
+            // A pawn move that keeps the win is as quick as progress gets
+            for &mv in moves {
+                if mv.is_capture() || !is_pawn_move(board, &mv) {
+                    continue;
+                }
+                let Ok(undo) = board.make(mv) else {
+                    continue;
+                };
+                let value = self.wdl(board);
+                board.unmake(undo);
+                if -value? == wdl {
+                    return Some(WDL_TO_DTZ[(wdl + 2) as usize]);
+                }
+            }
+        }
+
+        if let Some(dtz) = self.probe_dtz_table(board, wdl)? {
+            let dtz = 1 + dtz + if wdl & 1 != 0 { 100 } else { 0 };
+            return Some(if wdl > 0 { dtz } else { -dtz });
+        }
+
+        // The table holds the other side to move, so look one ply ahead
+        if wdl > 0 {
+            let mut best = i32::MAX;
+            for &mv in moves {
+                if mv.is_capture() || is_pawn_move(board, &mv) {
+                    continue;
+                }
+                let Ok(undo) = board.make(mv) else {
+                    continue;
+                };
+                let value = self.dtz(board);
+                board.unmake(undo);
+                let value = -value?;
+                if value > 0 && value + 1 < best {
+                    best = value + 1;
+                }
+            }
+            Some(best)
+        } else {
+            let mut best = -1;
+            for &mv in moves {
+                let Ok(undo) = board.make(mv) else {
+                    continue;
+                };
+                let value = if board.get_halfmove_clock() == 0 {
+                    if wdl == -2 {
+                        Some(-1)
+                    } else {
+                        self.probe_ab(board, 1, 2)
+                            .map(|(value, _)| if value == 2 { 0 } else { -101 })
+                    }
+                } else {
+                    self.dtz(board).map(|dtz| -dtz - 1)
+                };
+                board.unmake(undo);
+                best = best.min(value?);
+            }
+            Some(best)
+        }
+    }
+
+    fn dtz(&self, board: &mut Position) -> Option<i32> {
+        let moves = board.legal_moves();
+        let mut value = self.dtz_no_en_passant(board, &moves)?;
+        let Some(en_passant) = self.best_en_passant(board, &moves)? else {
+            return Some(value);
+        };
+        let en_passant = WDL_TO_DTZ[(en_passant + 2) as usize];
+        let better = match value {
+            ..-100 => en_passant >= 0,
+            -100..=-1 => !(-100..0).contains(&en_passant),
+            101.. => en_passant > 0,
+            1..=100 => en_passant == 1,
+            _ => en_passant >= 0 || moves.iter().all(|mv| mv.is_en_passant()),
+        };
+        if better {
+            value = en_passant;
+        }
+        Some(value)
+    }
+}
+
+// Piece counts by table code
+struct Material {
+    counts: [u8; 16],
+}
+
+impl Material {
+    fn of(board: &Position) -> Material {
+        let mut counts = [0; 16];
+        for row in 0..8 {
+            for col in 0..8 {
+                if let Some(piece) = board.get_piece((row, col)) {
+                    counts[usize::from(piece_code(piece.piece_type(), piece.color()))] += 1;
+                }
+            }
+        }
+        Material { counts }
+    }
+
+    // "KRPvKR": White's pieces before the v, Black's after
+    fn from_name(name: &str) -> Option<Material> {
+        let (white, black) = name.split_once('v')?;
+        let mut counts = [0; 16];
+        for (side, color_bit) in [(white, 0), (black, BLACK_BIT)] {
+            if !side.starts_with('K') {
+                return None;
+            }
+            for letter in side.chars() {
+                let &(code, _) = NAME_ORDER.iter().find(|&&(_, l)| l == letter)?;
+                counts[usize::from(code | color_bit)] += 1;
+            }
+        }
+        let material = Material { counts };
+        (material.count() <= MAX_PIECES).then_some(material)
+    }
+
+    fn name(&self, black_first: bool) -> String {
+        let side = |color_bit: u8| -> String {
+            NAME_ORDER
+                .iter()
+                .flat_map(|&(code, letter)| {
+                    std::iter::repeat_n(
+                        letter,
+                        usize::from(self.counts[usize::from(code | color_bit)]),
+                    )
+                })
+                .collect()
+        };
+        let (first, second) = if black_first {
+            (BLACK_BIT, 0)
+        } else {
+            (0, BLACK_BIT)
+        };
+        format!("{}v{}", side(first), side(second))
+    }
+
+    fn count(&self) -> usize {
+        self.counts.iter().map(|&count| usize::from(count)).sum()
+    }
+}
+
+fn piece_code(piece_type: PieceType, color: ColorChess) -> u8 {
+    let code = match piece_type {
+        PieceType::Pawn => 1,
+        PieceType::Knight => 2,
+        PieceType::Bishop => 3,
+        PieceType::Rook => 4,
+        PieceType::Queen => 5,
+        PieceType::King => 6,
+    };
+    match color {
+        ColorChess::White => code,
+        ColorChess::Black => code | BLACK_BIT,
+    }
+}
+
+// Index arithmetic shared by all tables
+struct Indices {
+    // binomial[k][n] is n choose k + 1
+    binomial: [[u64; 64]; MAX_PIECES - 1],
+    pawn_index: [[u64; 24]; MAX_PIECES - 1],
+    pawn_factor: [[u64; 4]; MAX_PIECES - 1],
+    // The 462 ways to place two kings with the first in the a1-d1-d4 triangle
+    king_pairs: [[u64; 64]; 10],
+}
+
+fn indices() -> &'static Indices {
+    static INDICES: OnceLock<Indices> = OnceLock::new();
+    INDICES.get_or_init(Indices::new)
+}
+
+impl Indices {
+    fn new() -> Indices {
+        let mut binomial = [[0; 64]; MAX_PIECES - 1];
+        for (k, row) in binomial.iter_mut().enumerate() {
+            for (n, entry) in row.iter_mut().enumerate() {
+                let (mut f, mut l) = (n as i64, 1i64);
+                for i in 1..=k as i64 {
+                    f *= n as i64 - i;
+                    l *= i + 1;
+                }
+                *entry = (f / l) as u64;
+            }
+        }
+
+        let mut pawn_index = [[0; 24]; MAX_PIECES - 1];
+        let mut pawn_factor = [[0; 4]; MAX_PIECES - 1];
+        for pawns in 0..MAX_PIECES - 1 {
+            for (file, factor) in pawn_factor[pawns].iter_mut().enumerate() {
+                let mut s = 0;
+                for j in 6 * file..6 * file + 6 {
+                    pawn_index[pawns][j] = s;
+                    s += if pawns == 0 {
+                        1
+                    } else {
+                        binomial[pawns - 1][PTWIST[INV_FLAP[j]]]
+                    };
+                }
+                *factor = s;
+            }
+        }
+
+        // With the first king on the diagonal the second stays on or below it,
+        // and the cases with both on the diagonal are numbered last
+        let mut king_pairs = [[0; 64]; 10];
+        let mut code = 0;
+        let mut both_on_diagonal = Vec::new();
+        for (index, &first) in INV_TRIANGLE.iter().enumerate() {
+            for (second, pair) in king_pairs[index].iter_mut().enumerate() {
+                let touching =
+                    (first / 8).abs_diff(second / 8) <= 1 && (first % 8).abs_diff(second % 8) <= 1;
+                if touching || (off_diagonal(first) == 0 && off_diagonal(second) > 0) {
+                    continue;
+                }
+                if off_diagonal(first) == 0 && off_diagonal(second) == 0 {
+                    both_on_diagonal.push((index, second));
+                } else {
+                    *pair = code;
+                    code += 1;
+                }
+            }
+        }
+        for (index, second) in both_on_diagonal {
+            king_pairs[index][second] = code;
+            code += 1;
+        }
+
+        Indices {
+            binomial,
+            pawn_index,
+            pawn_factor,
+            king_pairs,
+        }
+    }
+}
+
+// Which side of the a1-h8 diagonal a square is on: 1 above, -1 below
+fn off_diagonal(square: usize) -> i32 {
+    ((square / 8) as i32 - (square % 8) as i32).signum()
+}
+
+fn flip_diagonal(square: usize) -> usize {
+    ((square >> 3) | (square << 3)) & 63
+}
+
+// n choose k
+fn subfactor(k: u64, n: u64) -> u64 {
+    let (mut f, mut l) = (n, 1);
+    for i in 1..k {
+        f *= n.saturating_sub(i);
+        l *= i + 1;
+    }
+    f / l
+}
+
+fn u16_le(bytes: &[u8], at: usize) -> Option<u16> {
+    Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?))
+}
+
+fn u32_le(bytes: &[u8], at: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?))
+}
+
+// Big-endian read that treats bytes past the end of the file as zero
+fn padded_be(bytes: &[u8], at: usize, len: usize) -> u64 {
+    (0..len).fold(0, |value, i| {
+        (value << 8) | u64::from(bytes.get(at + i).copied().unwrap_or(0))
+    })
+}
+
+// The two halves of a symbol made of a pair of smaller ones
+fn symbol_pair(bytes: &[u8], at: usize) -> Option<(usize, usize)> {
+    let w = bytes.get(at..at + 3)?;
+    let left = (usize::from(w[1] & 0xF) << 8) | usize::from(w[0]);
+    let right = (usize::from(w[2]) << 4) | usize::from(w[1] >> 4);
+    Some((left, right))
+}
+
+// One compressed stream of values, Huffman coded symbols that stand for runs of values
+struct PairsData {
+    // Tables where every position has the same value store nothing else
+    constant: Option<u16>,
+    idx_bits: u32,
+    block_size: u32,
+    min_len: usize,
+    // Offsets (in the file) of the symbol offsets per code length, the symbol
+    // patterns, the block index, the block sizes and the blocks themselves
+    offsets: usize,
+    sympat: usize,
+    index_table: usize,
+    size_table: usize,
+    data: usize,
+    // Number of values each symbol stands for, minus one
+    symlen: Vec<u16>,
+    // Lowest left-aligned code of each length
+    base: Vec<u64>,
+}
+
+impl PairsData {
+    // Reads the header at `at`; returns the stream, the sizes of its index,
+    // size table and data, and where the next header starts
+    fn parse(
+        bytes: &[u8],
+        at: usize,
+        positions: u64,
+        wdl: bool,
+    ) -> Option<(PairsData, [u64; 3], usize)> {
+        let flags = *bytes.get(at)?;
+        if flags & 0x80 != 0 {
+            let value = if wdl {
+                u16::from(*bytes.get(at + 1)?)
+            } else {
+                0
+            };
+            let data = PairsData {
+                constant: Some(value),
+                idx_bits: 0,
+                block_size: 0,
+                min_len: 0,
+                offsets: 0,
+                sympat: 0,
+                index_table: 0,
+                size_table: 0,
+                data: 0,
+                symlen: Vec::new(),
+                base: Vec::new(),
+            };
+            return Some((data, [0; 3], at + 2));
+        }
+
+        let header = bytes.get(at..at + 10)?;
+        let block_size = u32::from(header[1]);
+        let idx_bits = u32::from(header[2]);
+        let real_blocks = u64::from(u32_le(bytes, at + 4)?);
+        let blocks = real_blocks + u64::from(header[3]);
+        let max_len = usize::from(header[8]);
+        let min_len = usize::from(header[9]);
+        if block_size >= 32
+            || !(1..64).contains(&idx_bits)
+            || min_len == 0
+            || max_len < min_len
+            || max_len > 64
+        {
+            return None;
+        }
+        let lengths = max_len - min_len + 1;
+        let offsets = at + 10;
+        let symbols = usize::from(u16_le(bytes, offsets + 2 * lengths)?);
+        let sympat = offsets + 2 * lengths + 2;
+        let next = sympat + 3 * symbols + (symbols & 1);
+        if next > bytes.len() {
+            return None;
+        }
+
+        let mut symlen = vec![0; symbols];
+        let mut state = vec![0u8; symbols];
+        for symbol in 0..symbols {
+            symbol_length(bytes, sympat, symbol, &mut symlen, &mut state)?;
+        }
+
+        let mut base = vec![0u64; lengths];
+        for i in (0..lengths - 1).rev() {
+            let this = u64::from(u16_le(bytes, offsets + 2 * i)?);
+            let next = u64::from(u16_le(bytes, offsets + 2 * i + 2)?);
+            base[i] = base[i + 1].wrapping_add(this).wrapping_sub(next) / 2;
+        }
+        for (i, value) in base.iter_mut().enumerate() {
+            *value = value.checked_shl((64 - (min_len + i)) as u32).unwrap_or(0);
+        }
+
+        let indices = (positions + (1 << idx_bits) - 1) >> idx_bits;
+        let sizes = [6 * indices, 2 * blocks, (1 << block_size) * real_blocks];
+        let data = PairsData {
+            constant: None,
+            idx_bits,
+            block_size,
+            min_len,
+            offsets,
+            sympat,
+            index_table: 0,
+            size_table: 0,
+            data: 0,
+            symlen,
+            base,
+        };
+        Some((data, sizes, next))
+    }
+
+    fn decompress(&self, bytes: &[u8], index: u64) -> Option<u16> {
+        if let Some(value) = self.constant {
+            return Some(value);
+        }
+
+        // The block index points near the right block; the sizes find it exactly
+        let main = (index >> self.idx_bits) as usize;
+        let mut lit = (index & ((1 << self.idx_bits) - 1)) as i64 - (1i64 << (self.idx_bits - 1));
+        let entry = self.index_table + 6 * main;
+        let mut block = u32_le(bytes, entry)? as usize;
+        lit += i64::from(u16_le(bytes, entry + 4)?);
+        let block_len = |block: usize| u16_le(bytes, self.size_table + 2 * block).map(i64::from);
+        while lit < 0 {
+            block = block.checked_sub(1)?;
+            lit += block_len(block)? + 1;
+        }
+        while lit > block_len(block)? {
+            lit -= block_len(block)? + 1;
+            block += 1;
+        }
+
+        let mut at = self.data + (block << self.block_size);
+        let mut code = padded_be(bytes, at, 8);
+        at += 8;
+        let mut bits = 0;
+        let mut symbol;
+        loop {
+            let mut len = self.min_len;
+            while code < self.base[len - self.min_len] {
+                len += 1;
+            }
+            let offset = usize::from(u16_le(bytes, self.offsets + 2 * (len - self.min_len))?);
+            symbol = offset + ((code - self.base[len - self.min_len]) >> (64 - len)) as usize;
+            let run = i64::from(*self.symlen.get(symbol)?) + 1;
+            if lit < run {
+                break;
+            }
+            lit -= run;
+            code = code.checked_shl(len as u32).unwrap_or(0);
+            bits += len;
+            if bits >= 32 {
+                bits -= 32;
+                code |= padded_be(bytes, at, 4) << bits;
+                at += 4;
+            }
+        }
+
+        // Walk down the pair tree to the value itself
+        while self.symlen[symbol] != 0 {
+            let (left, right) = symbol_pair(bytes, self.sympat + 3 * symbol)?;
+            let run = i64::from(*self.symlen.get(left)?) + 1;
+            if lit < run {
+                symbol = left;
+            } else {
+                lit -= run;
+                symbol = right;
+            }
+            self.symlen.get(symbol)?;
+        }
+        symbol_pair(bytes, self.sympat + 3 * symbol).map(|(value, _)| value as u16)
+    }
+}
+
+// Fills in symlen[symbol], after the symbols it is made of; state marks symbols
+// in progress and done, so that a corrupt file cannot recurse forever
+fn symbol_length(
+    bytes: &[u8],
+    sympat: usize,
+    symbol: usize,
+    symlen: &mut [u16],
+    state: &mut [u8],
+) -> Option<()> {
+    match state[symbol] {
+        2 => return Some(()),
+        1 => return None,
+        _ => state[symbol] = 1,
+    }
+    let (left, right) = symbol_pair(bytes, sympat + 3 * symbol)?;
+    if right == 0xFFF {
+        symlen[symbol] = 0;
+    } else {
+        if left >= symlen.len() || right >= symlen.len() {
+            return None;
+        }
+        symbol_length(bytes, sympat, left, symlen, state)?;
+        symbol_length(bytes, sympat, right, symlen, state)?;
+        symlen[symbol] = symlen[left].wrapping_add(symlen[right]).wrapping_add(1);
+    }
+    state[symbol] = 2;
+    Some(())
+}
+
+// One stream of a table: pawnless tables have one per side to move, tables
+// with pawns one per side and file of the leading pawn. DTZ tables only store
+// one side to move.
+struct Part {
+    // Table codes of the pieces in the order they are encoded
+    pieces: Vec<u8>,
+    // Size of the group starting at each piece (0 inside a group)
+    norm: Vec<u8>,
+    factor: Vec<u64>,
+    pairs: PairsData,
+    // DTZ only: the header flags, and where the four value maps start
+    flags: u8,
+    map_index: [usize; 4],
+}
+
+enum Lookup<'a> {
+    Found(&'a Part, u64),
+    // A DTZ table that only holds the other side to move
+    OtherSide,
+}
+
+struct Table {
+    bytes: Vec<u8>,
+    dtz: bool,
+    symmetric: bool,
+    has_pawns: bool,
+    // Leading pawns first; they belong to the side with fewer pawns
+    pawns: [usize; 2],
+    // 0 when at least three pieces are unique, 2 when only the kings are
+    enc_type: usize,
+    // parts[file][side]
+    parts: Vec<Vec<Option<Part>>>,
+    // DTZ only: start of the value maps
+    map: usize,
+}
+
+impl Table {
+    fn parse(bytes: Vec<u8>, name: &str, dtz: bool) -> Option<Table> {
+        let magic = if dtz { DTZ_MAGIC } else { WDL_MAGIC };
+        if bytes.get(0..4)? != magic {
+            return None;
+        }
+        let material = Material::from_name(name)?;
+        let num = material.count();
+        let (white_pawns, black_pawns) = (
+            usize::from(material.counts[1]),
+            usize::from(material.counts[9]),
+        );
+        let has_pawns = white_pawns + black_pawns > 0;
+        let pawns = if black_pawns > 0 && (white_pawns == 0 || black_pawns < white_pawns) {
+            [black_pawns, white_pawns]
+        } else {
+            [white_pawns, black_pawns]
+        };
+        let unique = material.counts.iter().filter(|&&count| count == 1).count();
+        let enc_type = if unique >= 3 { 0 } else { 2 };
+        let symmetric = material.name(false) == material.name(true);
+
+        let flags = *bytes.get(4)?;
+        let sides = if !dtz && flags & 1 != 0 { 2 } else { 1 };
+        let files = if flags & 2 != 0 { 4 } else { 1 };
+        let mut at = 5;
+
+        // Piece order and encoding of every part; all four files are listed
+        // even when fewer are stored
+        let header_files = if has_pawns { 4 } else { 1 };
+        let mut layouts = Vec::new();
+        for file in 0..header_files {
+            let mut file_layouts = Vec::new();
+            for side in 0..sides {
+                let shift = 4 * side;
+                let nibble = |at: usize| bytes.get(at).map(|&byte| (byte >> shift) & 0xF);
+                let order = usize::from(nibble(at)?);
+                let layout = if has_pawns {
+                    let order2 = if pawns[1] > 0 {
+                        usize::from(nibble(at + 1)?)
+                    } else {
+                        0xF
+                    };
+                    let first = 1 + usize::from(pawns[1] > 0);
+                    let pieces = (0..num)
+                        .map(|i| nibble(at + first + i))
+                        .collect::<Option<Vec<u8>>>()?;
+                    let norm = pawn_norm(pawns, &pieces);
+                    let (factor, size) = pawn_factors(&norm, order, order2, file)?;
+                    (pieces, norm, factor, size)
+                } else {
+                    let pieces = (0..num)
+                        .map(|i| nibble(at + 1 + i))
+                        .collect::<Option<Vec<u8>>>()?;
+                    let norm = piece_norm(enc_type, &pieces);
+                    let (factor, size) = piece_factors(enc_type, &norm, order)?;
+                    (pieces, norm, factor, size)
+                };
+                file_layouts.push(layout);
+            }
+            layouts.push(file_layouts);
+            at += num
+                + if has_pawns {
+                    1 + usize::from(pawns[1] > 0)
+                } else {
+                    1
+                };
+        }
+        at += at & 1;
+
+        let mut parts: Vec<Vec<Option<Part>>> = Vec::new();
+        let mut sizes = Vec::new();
+        for file_layouts in layouts.into_iter().take(files) {
+            let mut file_parts = Vec::new();
+            for (pieces, norm, factor, size) in file_layouts {
+                let (pairs, part_sizes, next) = PairsData::parse(&bytes, at, size, !dtz)?;
+                file_parts.push(Some(Part {
+                    pieces,
+                    norm,
+                    factor,
+                    flags: bytes[at],
+                    pairs,
+                    map_index: [0; 4],
+                }));
+                sizes.push(part_sizes);
+                at = next;
+            }
+            parts.push(file_parts);
+        }
+
+        let map = at;
+        if dtz {
+            for part in parts.iter_mut().flatten().flatten() {
+                if part.flags & 2 == 0 {
+                    continue;
+                }
+                for i in 0..4 {
+                    if part.flags & 16 != 0 {
+                        part.map_index[i] = (at - map) / 2 + 1;
+                        at += 2 + 2 * usize::from(u16_le(&bytes, at)?);
+                    } else {
+                        part.map_index[i] = at + 1 - map;
+                        at += 1 + usize::from(*bytes.get(at)?);
+                    }
+                }
+            }
+            at += at & 1;
+        }
+
+        let mut streams: Vec<(&mut PairsData, [u64; 3])> = parts
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .map(|part| &mut part.pairs)
+            .zip(sizes)
+            .collect();
+        for (pairs, sizes) in &mut streams {
+            pairs.index_table = at;
+            at += sizes[0] as usize;
+        }
+        for (pairs, sizes) in &mut streams {
+            pairs.size_table = at;
+            at += sizes[1] as usize;
+        }
+        for (pairs, sizes) in &mut streams {
+            at = (at + 0x3F) & !0x3F;
+            pairs.data = at;
+            at += sizes[2] as usize;
+        }
+        if at > bytes.len() + 64 {
+            return None;
+        }
+
+        Some(Table {
+            bytes,
+            dtz,
+            symmetric,
+            has_pawns,
+            pawns,
+            enc_type,
+            parts,
+            map,
+        })
+    }
+
+    fn part(&self, file: usize, side: usize) -> Option<&Part> {
+        self.parts.get(file)?.get(side)?.as_ref()
+    }
+
+    // Finds the part for the position and its index within it
+    fn lookup(&self, board: &Position, mirrored: bool) -> Option<Lookup<'_>> {
+        let white_to_move = board.get_current_turn() == ColorChess::White;
+        // Colour flip for the piece codes, vertical flip for the squares, and the
+        // side to move as the table sees it
+        let (color_flip, square_flip, side) = if self.symmetric {
+            if white_to_move {
+                (0, 0, 0)
+            } else {
+                (BLACK_BIT, 0x38, 0)
+            }
+        } else if mirrored {
+            (BLACK_BIT, 0x38, usize::from(white_to_move))
+        } else {
+            (0, 0, usize::from(!white_to_move))
+        };
+
+        let mut bitboards = [0u64; 16];
+        for row in 0..8 {
+            for col in 0..8 {
+                if let Some(piece) = board.get_piece((row, col)) {
+                    bitboards[usize::from(piece_code(piece.piece_type(), piece.color()))] |=
+                        1 << (row * 8 + col);
+                }
+            }
+        }
+        let mut squares = [0usize; MAX_PIECES];
+        let fill = |squares: &mut [usize], pieces: &[u8], from: usize, flip: usize| {
+            let mut i = from;
+            while i < pieces.len() {
+                let mut bitboard = bitboards[usize::from(pieces[i] ^ color_flip)];
+                if bitboard == 0 {
+                    return None;
+                }
+                while bitboard != 0 && i < pieces.len() {
+                    squares[i] = bitboard.trailing_zeros() as usize ^ flip;
+                    bitboard &= bitboard - 1;
+                    i += 1;
+                }
+            }
+            Some(())
+        };
+
+        // DTZ tables hold a single side to move, named by the part's flags
+        let slot = if self.dtz { 0 } else { side };
+        if !self.has_pawns {
+            let part = self.part(0, slot)?;
+            if self.dtz && usize::from(part.flags & 1) != side && !self.symmetric {
+                return Some(Lookup::OtherSide);
+            }
+            fill(&mut squares, &part.pieces, 0, 0)?;
+            let squares = &mut squares[..part.pieces.len()];
+            return Some(Lookup::Found(
+                part,
+                encode_piece(self.enc_type, part, squares),
+            ));
+        }
+
+        let lead = &self.part(0, 0)?.pieces[..self.pawns[0]];
+        fill(&mut squares, lead, 0, square_flip)?;
+        let file = pawn_file(self.pawns[0], &mut squares);
+        let part = self.part(file, slot)?;
+        if self.dtz && usize::from(part.flags & 1) != side {
+            return Some(Lookup::OtherSide);
+        }
+        fill(&mut squares, &part.pieces, self.pawns[0], square_flip)?;
+        let squares = &mut squares[..part.pieces.len()];
+        Some(Lookup::Found(part, encode_pawn(self.pawns, part, squares)))
+    }
+}
+
+fn piece_norm(enc_type: usize, pieces: &[u8]) -> Vec<u8> {
+    let mut norm = vec![0u8; pieces.len()];
+    norm[0] = if enc_type == 0 { 3 } else { 2 };
+    let mut i = usize::from(norm[0]);
+    while i < pieces.len() {
+        norm[i] = pieces[i..]
+            .iter()
+            .take_while(|&&piece| piece == pieces[i])
+            .count() as u8;
+        i += usize::from(norm[i]);
+    }
+    norm
+}
+
+fn pawn_norm(pawns: [usize; 2], pieces: &[u8]) -> Vec<u8> {
+    let mut norm = vec![0u8; pieces.len()];
+    norm[0] = pawns[0] as u8;
+    if pawns[1] > 0 {
+        norm[pawns[0]] = pawns[1] as u8;
+    }
+    let mut i = pawns[0] + pawns[1];
+    while i < pieces.len() {
+        norm[i] = pieces[i..]
+            .iter()
+            .take_while(|&&piece| piece == pieces[i])
+            .count() as u8;
+        i += usize::from(norm[i]);
+    }
+    norm
+}
+
+// Multipliers of each group's index; `order` says where the leading group
+// comes among them. Also returns the number of positions.
+fn piece_factors(enc_type: usize, norm: &[u8], order: usize) -> Option<(Vec<u64>, u64)> {
+    let num = norm.len();
+    let mut factor = vec![0; num];
+    let mut free = 64 - u64::from(norm[0]);
+    let mut f = 1u64;
+    let mut i = usize::from(norm[0]);
+    let mut k = 0;
+    while i < num || k == order {
+        if k == order {
+            factor[0] = f;
+            f = f.checked_mul(PIVOT_FACTOR[enc_type])?;
+        } else {
+            factor[i] = f;
+            f = f.checked_mul(subfactor(u64::from(norm[i]), free))?;
+            free = free.checked_sub(u64::from(norm[i]))?;
+            i += usize::from(norm[i]);
+        }
+        k += 1;
+    }
+    Some((factor, f))
+}
+
+fn pawn_factors(norm: &[u8], order: usize, order2: usize, file: usize) -> Option<(Vec<u64>, u64)> {
+    let num = norm.len();
+    let lead = usize::from(norm[0]);
+    let mut factor = vec![0; num];
+    let mut i = lead;
+    if order2 < 0xF {
+        i += usize::from(norm[i]);
+    }
+    let mut free = 64u64.checked_sub(i as u64)?;
+    let mut f = 1u64;
+    let mut k = 0;
+    while i < num || k == order || k == order2 {
+        if k == order {
+            factor[0] = f;
+            f = f.checked_mul(indices().pawn_factor.get(lead.checked_sub(1)?)?[file])?;
+        } else if k == order2 {
+            factor[lead] = f;
+            f = f.checked_mul(subfactor(u64::from(norm[lead]), 48 - lead as u64))?;
+        } else {
+            factor[i] = f;
+            f = f.checked_mul(subfactor(u64::from(norm[i]), free))?;
+            free = free.checked_sub(u64::from(norm[i]))?;
+            i += usize::from(norm[i]);
+        }
+        k += 1;
+    }
+    Some((factor, f))
+}
+
+fn encode_piece(enc_type: usize, part: &Part, squares: &mut [usize]) -> u64 {
+    let ix = indices();
+    // Mirror the first piece into the a1-d1-d4 triangle
+    if squares[0] & 0x04 != 0 {
+        squares.iter_mut().for_each(|square| *square ^= 0x07);
+    }
+    if squares[0] & 0x20 != 0 {
+        squares.iter_mut().for_each(|square| *square ^= 0x38);
+    }
+    let leading = if enc_type == 0 { 3 } else { 2 };
+    if let Some(i) = squares.iter().position(|&square| off_diagonal(square) != 0)
+        && i < leading
+        && off_diagonal(squares[i]) > 0
+    {
+        squares
+            .iter_mut()
+            .for_each(|square| *square = flip_diagonal(*square));
+    }
+
+    let (index, grouped) = if enc_type == 0 {
+        let s = &squares[..3];
+        let a = usize::from(s[1] > s[0]);
+        let b = usize::from(s[2] > s[0]) + usize::from(s[2] > s[1]);
+        let index = if off_diagonal(s[0]) != 0 {
+            TRIANGLE[s[0]] * 63 * 62 + (s[1] - a) * 62 + (s[2] - b)
+        } else if off_diagonal(s[1]) != 0 {
+            6 * 63 * 62 + DIAG[s[0]] * 28 * 62 + LOWER[s[1]] * 62 + s[2] - b
+        } else if off_diagonal(s[2]) != 0 {
+            6 * 63 * 62 + 4 * 28 * 62 + DIAG[s[0]] * 7 * 28 + (DIAG[s[1]] - a) * 28 + LOWER[s[2]]
+        } else {
+            6 * 63 * 62
+                + 4 * 28 * 62
+                + 4 * 7 * 28
+                + DIAG[s[0]] * 7 * 6
+                + (DIAG[s[1]] - a) * 6
+                + (DIAG[s[2]] - b)
+        };
+        (index as u64, 3)
+    } else {
+        (ix.king_pairs[TRIANGLE[squares[0]]][squares[1]], 2)
+    };
+    encode_groups(part, squares, grouped, index * part.factor[0])
+}
+
+fn encode_pawn(pawns: [usize; 2], part: &Part, squares: &mut [usize]) -> u64 {
+    let ix = indices();
+    if squares[0] & 0x04 != 0 {
+        squares.iter_mut().for_each(|square| *square ^= 0x07);
+    }
+    squares[1..pawns[0]].sort_unstable_by_key(|&square| std::cmp::Reverse(PTWIST[square]));
+    let t = pawns[0] - 1;
+    let mut index = ix.pawn_index[t][FLAP[squares[0]]];
+    for i in (1..=t).rev() {
+        index += ix.binomial[t - i][PTWIST[squares[i]]];
+    }
+    index *= part.factor[0];
+
+    // The other side's pawns, which can only stand on the middle six ranks
+    let (start, end) = (pawns[0], pawns[0] + pawns[1]);
+    if end > start {
+        squares[start..end].sort_unstable();
+        let mut s = 0;
+        for m in start..end {
+            let below = squares[..start]
+                .iter()
+                .filter(|&&other| squares[m] > other)
+                .count();
+            s += ix.binomial[m - start][squares[m] - below - 8];
+        }
+        index += s * part.factor[start];
+    }
+    encode_groups(part, squares, end, index)
+}
+
+// Adds the remaining groups of like pieces, each as a combination of the
+// squares not taken by earlier pieces
+fn encode_groups(part: &Part, squares: &mut [usize], mut i: usize, mut index: u64) -> u64 {
+    let ix = indices();
+    while i < squares.len() {
+        let t = usize::from(part.norm[i]);
+        squares[i..i + t].sort_unstable();
+        let mut s = 0;
+        for m in i..i + t {
+            let below = squares[..i]
+                .iter()
+                .filter(|&&other| squares[m] > other)
+                .count();
+            s += ix.binomial[m - i][squares[m] - below];
+        }
+        index += s * part.factor[i];
+        i += t;
+    }
+    index
+}
+
+// Moves the leading pawn nearest the first rank of its file group to the front;
+// returns the file (a to d) that picks the part
+fn pawn_file(lead: usize, squares: &mut [usize]) -> usize {
+    for i in 1..lead {
+        if FLAP[squares[0]] > FLAP[squares[i]] {
+            squares.swap(0, i);
+        }
+    }
+    FILE_TO_FILE[squares[0] & 7]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WHITE_KING: u8 = 6;
+    const WHITE_QUEEN: u8 = 5;
+    const BLACK_KING: u8 = 6 | BLACK_BIT;
+
+    fn adjacent(a: usize, b: usize) -> bool {
+        a != b && (a / 8).abs_diff(b / 8) <= 1 && (a % 8).abs_diff(b % 8) <= 1
+    }
+
+    fn king_steps(square: usize) -> impl Iterator<Item = usize> {
+        (0..64).filter(move |&to| adjacent(square, to))
+    }
+
+    // Squares a queen on `from` reaches, stopping short of the occupied ones
+    fn queen_steps(from: usize, occupied: &[usize]) -> Vec<usize> {
+        let mut steps = Vec::new();
+        for (dr, dc) in [
+            (1, 0),
+            (-1, 0),
+            (0, 1),
+            (0, -1),
+            (1, 1),
+            (1, -1),
+            (-1, 1),
+            (-1, -1),
+        ] {
+            let (mut row, mut col) = ((from / 8) as i32 + dr, (from % 8) as i32 + dc);
+            while (0..8).contains(&row) && (0..8).contains(&col) {
+                let to = (row * 8 + col) as usize;
+                if occupied.contains(&to) {
+                    break;
+                }
+                steps.push(to);
+                row += dr;
+                col += dc;
+            }
+        }
+        steps
+    }
+
+    fn queen_attacks(queen: usize, target: usize, blockers: &[usize]) -> bool {
+        let (rows, cols) = (
+            (target / 8) as i32 - (queen / 8) as i32,
+            (target % 8) as i32 - (queen % 8) as i32,
+        );
+        if queen == target || (rows != 0 && cols != 0 && rows.abs() != cols.abs()) {
+            return false;
+        }
+        let step = rows.signum() * 8 + cols.signum();
+        let mut square = queen as i32 + step;
+        while square != target as i32 {
+            if blockers.contains(&(square as usize)) {
+                return false;
+            }
+            square += step;
+        }
+        true
+    }
+
+    fn placement(index: usize) -> (usize, usize, usize) {
+        (index / 4096, index / 64 % 64, index % 64)
+    }
+
+    fn distinct(wk: usize, wq: usize, bk: usize) -> bool {
+        wk != wq && wk != bk && wq != bk
+    }
+
+    fn legal_white_to_move(wk: usize, wq: usize, bk: usize) -> bool {
+        distinct(wk, wq, bk) && !adjacent(wk, bk) && !queen_attacks(wq, bk, &[wk])
+    }
+
+    fn legal_black_to_move(wk: usize, wq: usize, bk: usize) -> bool {
+        distinct(wk, wq, bk) && !adjacent(wk, bk)
+    }
+
+    // KQvK solved backwards from the mates, indexed by wk * 4096 + wq * 64 + bk:
+    // plies to mate for White to move, and for Black to move when lost
+    struct Solution {
+        white: Vec<Option<i32>>,
+        black: Vec<Option<i32>>,
+    }
+
+    fn solve() -> Solution {
+        let mut white = vec![None; 1 << 18];
+        let mut black = vec![None; 1 << 18];
+        // Black's moves left to refute, or None when Black can draw
+        let mut moves_left = vec![None; 1 << 18];
+        let mut queue = std::collections::VecDeque::new();
+        for index in 0..1 << 18 {
+            let (wk, wq, bk) = placement(index);
+            if !legal_black_to_move(wk, wq, bk) {
+                continue;
+            }
+            let targets: Vec<usize> = king_steps(bk)
+                .filter(|&to| !adjacent(to, wk) && (to == wq || !queen_attacks(wq, to, &[wk])))
+                .collect();
+            if targets.contains(&wq) {
+                continue;
+            }
+            if targets.is_empty() {
+                if queen_attacks(wq, bk, &[wk]) {
+                    black[index] = Some(0);
+                    queue.push_back((index, false));
+                }
+                continue;
+            }
+            moves_left[index] = Some(targets.len());
+        }
+        while let Some((index, white_to_move)) = queue.pop_front() {
+            let (wk, wq, bk) = placement(index);
+            if white_to_move {
+                let plies = white[index].unwrap();
+                for from in king_steps(bk) {
+                    let before = wk * 4096 + wq * 64 + from;
+                    if !legal_black_to_move(wk, wq, from) || black[before].is_some() {
+                        continue;
+                    }
+                    if let Some(left) = moves_left[before].as_mut() {
+                        *left -= 1;
+                        if *left == 0 {
+                            black[before] = Some(plies + 1);
+                            queue.push_back((before, false));
+                        }
+                    }
+                }
+            } else {
+                let plies = black[index].unwrap();
+                let befores = king_steps(wk)
+                    .filter(|&from| from != wq && from != bk)
+                    .map(|from| (from, wq))
+                    .chain(
+                        queen_steps(wq, &[wk, bk])
+                            .into_iter()
+                            .map(|from| (wk, from)),
+                    );
+                for (king, queen) in befores {
+                    let before = king * 4096 + queen * 64 + bk;
+                    if legal_white_to_move(king, queen, bk) && white[before].is_none() {
+                        white[before] = Some(plies + 1);
+                        queue.push_back((before, true));
+                    }
+                }
+            }
+        }
+        Solution { white, black }
+    }
+
+    // One part's stream with every value its own symbol and all codes the same
+    // length: the header, then the block index, block sizes and blocks
+    fn stream(flags: u8, values: &[u16]) -> [Vec<u8>; 4] {
+        const BLOCK_SIZE: u32 = 10;
+        const IDX_BITS: u32 = 10;
+        let mut symbols = values.to_vec();
+        symbols.sort_unstable();
+        symbols.dedup();
+        let len = (usize::BITS - (symbols.len() - 1).leading_zeros()).max(1) as usize;
+        let per_block = (8 << BLOCK_SIZE) / len;
+        let blocks = values.len().div_ceil(per_block);
+
+        let mut header = vec![flags, BLOCK_SIZE as u8, IDX_BITS as u8, 0];
+        header.extend((blocks as u32).to_le_bytes());
+        header.extend([len as u8, len as u8, 0, 0]);
+        header.extend((symbols.len() as u16).to_le_bytes());
+        for &value in &symbols {
+            header.extend([value as u8, (value >> 8) as u8 | 0xF0, 0xFF]);
+        }
+        if symbols.len() % 2 == 1 {
+            header.push(0);
+        }
+
+        let mut index = Vec::new();
+        for main in 0..values.len().div_ceil(1 << IDX_BITS) {
+            let middle = (main << IDX_BITS) + (1 << (IDX_BITS - 1));
+            let block = (middle / per_block).min(blocks - 1);
+            index.extend((block as u32).to_le_bytes());
+            index.extend(((middle - block * per_block) as u16).to_le_bytes());
+        }
+        let mut sizes = Vec::new();
+        let mut data = vec![0u8; blocks << BLOCK_SIZE];
+        for (block, chunk) in values.chunks(per_block).enumerate() {
+            sizes.extend((chunk.len() as u16 - 1).to_le_bytes());
+            for (i, value) in chunk.iter().enumerate() {
+                let code = symbols.binary_search(value).unwrap();
+                for bit in 0..len {
+                    if code >> (len - 1 - bit) & 1 != 0 {
+                        let at = (block << (BLOCK_SIZE + 3)) + i * len + bit;
+                        data[at / 8] |= 0x80 >> (at % 8);
+                    }
+                }
+            }
+        }
+        [header, index, sizes, data]
+    }
+
+    // A KQvK file with the pieces stored in the order white king, queen, black king
+    fn table_file(dtz: bool, parts: &[[Vec<u8>; 4]]) -> Vec<u8> {
+        let mut bytes = if dtz { DTZ_MAGIC } else { WDL_MAGIC }.to_vec();
+        bytes.extend([u8::from(!dtz), 0]);
+        let sides = if dtz { 0x01 } else { 0x11 };
+        bytes.extend([WHITE_KING, WHITE_QUEEN, BLACK_KING].map(|code| code * sides));
+        bytes.push(0);
+        for part in parts {
+            bytes.extend(&part[0]);
+        }
+        for section in 1..3 {
+            for part in parts {
+                bytes.extend(&part[section]);
+            }
+        }
+        for part in parts {
+            bytes.resize(bytes.len().next_multiple_of(64), 0);
+            bytes.extend(&part[3]);
+        }
+        bytes
+    }
+
+    // The part the lookup finds for KQvK, to index placements with
+    fn kqk_part() -> Part {
+        let pieces = vec![WHITE_KING, WHITE_QUEEN, BLACK_KING];
+        let norm = piece_norm(0, &pieces);
+        let (factor, size) = piece_factors(0, &norm, 0).unwrap();
+        let (pairs, _, _) = PairsData::parse(&[0x80, 0], 0, size, true).unwrap();
+        Part {
+            pieces,
+            norm,
+            factor,
+            pairs,
+            flags: 0,
+            map_index: [0; 4],
+        }
+    }
+
+    fn fen(wk: usize, wq: usize, bk: usize, white_to_move: bool) -> String {
+        let mut rows = Vec::new();
+        for row in (0..8).rev() {
+            let mut text = String::new();
+            let mut empty = 0;
+            for col in 0..8 {
+                let letter = [(wk, 'K'), (wq, 'Q'), (bk, 'k')]
+                    .into_iter()
+                    .find(|&(square, _)| square == row * 8 + col);
+                match letter {
+                    Some((_, letter)) => {
+                        if empty > 0 {
+                            text.push_str(&empty.to_string());
+                        }
+                        empty = 0;
+                        text.push(letter);
+                    }
+                    None => empty += 1,
+                }
+            }
+            if empty > 0 {
+                text.push_str(&empty.to_string());
+            }
+            rows.push(text);
+        }
+        let side = if white_to_move { "w" } else { "b" };
+        format!("{} {} - - 0 1", rows.join("/"), side)
+    }
+
+    #[test]
+    fn material_names() {
+        let material = Material::from_name("KRPvKR").unwrap();
+        assert_eq!(material.count(), 5);
+        assert_eq!(material.name(false), "KRPvKR");
+        assert_eq!(material.name(true), "KRvKRP");
+        assert!(Material::from_name("QKvK").is_none());
+        assert!(Material::from_name("KQQQvKRRR").is_none());
+    }
+
+    #[test]
+    fn index_arithmetic() {
+        let ix = indices();
+        assert_eq!(ix.binomial[0][10], 10);
+        assert_eq!(ix.binomial[1][10], 45);
+        assert_eq!(ix.binomial[2][62], 37820);
+        assert_eq!(subfactor(2, 62), 1891);
+        assert_eq!(ix.pawn_factor[0], [6; 4]);
+
+        // Two kings fill 0..462 once each
+        let mut codes: Vec<u64> = INV_TRIANGLE
+            .iter()
+            .enumerate()
+            .flat_map(|(index, &first)| {
+                (0..64)
+                    .filter(move |&second| {
+                        first != second
+                            && !adjacent(first, second)
+                            && (off_diagonal(first) != 0 || off_diagonal(second) <= 0)
+                    })
+                    .map(move |second| ix.king_pairs[index][second])
+            })
+            .collect();
+        codes.sort_unstable();
+        assert_eq!(codes, (0..PIVOT_FACTOR[2]).collect::<Vec<_>>());
+    }
+
+    // The eight symmetries of the board give one index, and different
+    // positions different ones
+    #[test]
+    fn encoding_folds_the_symmetries_only() {
+        let transforms: [fn(usize) -> usize; 8] = [
+            |s| s,
+            |s| s ^ 7,
+            |s| s ^ 56,
+            |s| s ^ 63,
+            flip_diagonal,
+            |s| flip_diagonal(s) ^ 7,
+            |s| flip_diagonal(s) ^ 56,
+            |s| flip_diagonal(s) ^ 63,
+        ];
+        let part = kqk_part();
+        let mut seen = HashMap::new();
+        for index in 0..1 << 18 {
+            let (wk, wq, bk) = placement(index);
+            if !distinct(wk, wq, bk) {
+                continue;
+            }
+            let encoded = encode_piece(0, &part, &mut [wk, wq, bk]);
+            assert!(encoded < PIVOT_FACTOR[0]);
+            let class = transforms
+                .iter()
+                .map(|t| (t(wk), t(wq), t(bk)))
+                .min()
+                .unwrap();
+            for t in transforms {
+                assert_eq!(encode_piece(0, &part, &mut [t(wk), t(wq), t(bk)]), encoded);
+            }
+            assert_eq!(*seen.entry(encoded).or_insert(class), class);
+        }
+    }
+
+    // No Syzygy files come with the repository, so KQvK is solved here and
+    // written out in their format, then probed like any other table
+    #[test]
+    fn kqvk_fixture() {
+        let solution = solve();
+        // Every legal position with White to move is won, the longest in ten
+        // moves; with Black to move a stalemate or a loose queen draws
+        let longest = (0..1 << 18)
+            .filter(|&index| {
+                let (wk, wq, bk) = placement(index);
+                legal_white_to_move(wk, wq, bk)
+            })
+            .map(|index| solution.white[index].expect("every KQvK is won"))
+            .max();
+        assert_eq!(longest, Some(19));
+
+        let part = kqk_part();
+        let size = PIVOT_FACTOR[0] as usize;
+        let (mut white_wdl, mut black_wdl, mut dtz) = (vec![2; size], vec![2; size], vec![0; size]);
+        for index in 0..1 << 18 {
+            let (wk, wq, bk) = placement(index);
+            if !distinct(wk, wq, bk) {
+                continue;
+            }
+            let at = encode_piece(0, &part, &mut [wk, wq, bk]) as usize;
+            if let Some(plies) = solution.white[index] {
+                white_wdl[at] = 4;
+                dtz[at] = plies as u16 - 1;
+            }
+            if legal_black_to_move(wk, wq, bk) {
+                black_wdl[at] = if solution.black[index].is_some() {
+                    0
+                } else {
+                    2
+                };
+            }
+        }
+        let dir = std::env::temp_dir().join(format!("chess-rs-tablebase-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let wdl = table_file(false, &[stream(0, &white_wdl), stream(0, &black_wdl)]);
+        fs::write(dir.join("KQvK.rtbw"), wdl).unwrap();
+        // The DTZ table holds White to move, counted in plies
+        fs::write(dir.join("KQvK.rtbz"), table_file(true, &[stream(4, &dtz)])).unwrap();
+        let tablebase = Tablebase::open(dir.to_str().unwrap()).unwrap();
+
+        let probe = |fen: &str| {
+            let board = Position::from_fen(fen).unwrap();
+            (tablebase.probe_wdl(&board), tablebase.probe_dtz(&board))
+        };
+        assert_eq!(
+            probe("k7/8/1K6/8/8/8/8/6Q1 w - - 0 1"),
+            (Some(Wdl::Win), Some(1))
+        );
+        assert_eq!(
+            probe("k7/8/1K6/8/8/8/8/6Q1 b - - 0 1"),
+            (Some(Wdl::Loss), Some(-2))
+        );
+        assert_eq!(
+            probe("k7/2Q5/1K6/8/8/8/8/8 b - - 0 1"),
+            (Some(Wdl::Draw), Some(0))
+        );
+        assert_eq!(
+            probe("8/8/8/8/8/8/6k1/4K2Q b - - 0 1"),
+            (Some(Wdl::Draw), Some(0))
+        );
+        assert_eq!(probe("8/8/8/8/8/8/8/KQ5k b - - 0 1").0, Some(Wdl::Loss));
+        // Black's only move, Kb8, is mated next
+        let board = Position::from_fen("k7/8/1K6/8/8/8/8/6Q1 b - - 0 1").unwrap();
+        assert_eq!(tablebase.best_move(&board).map(|(_, dtz)| dtz), Some(-2));
+
+        // And the tables read back the solution, checked on a sample of positions
+        for index in 0..1 << 18 {
+            let (wk, wq, bk) = placement(index);
+            if wk % 8 > 3 || wk / 8 > wk % 8 || index % 41 != 0 {
+                continue;
+            }
+            if legal_white_to_move(wk, wq, bk) {
+                let expected = solution.white[index].unwrap();
+                assert_eq!(
+                    probe(&fen(wk, wq, bk, true)),
+                    (Some(Wdl::Win), Some(expected))
+                );
+            }
+            if legal_black_to_move(wk, wq, bk) {
+                let expected = match solution.black[index] {
+                    // A side already mated reads -1
+                    Some(plies) => (Some(Wdl::Loss), Some(-plies.max(1))),
+                    None => (Some(Wdl::Draw), Some(0)),
+                };
+                assert_eq!(
+                    probe(&fen(wk, wq, bk, false)),
+                    expected,
+                    "{}",
+                    fen(wk, wq, bk, false)
+                );
+            }
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
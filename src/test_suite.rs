@@ -0,0 +1,59 @@
+use std::{fs, path::Path, time::Duration};
+
+use chess_rs::epd::{self, Epd};
+use chess_rs::{SearchLimits, Searcher};
+
+// Searches every position of an EPD suite that has a `bm` or `am`
+// operation, printing whether the move found passes, and returns how many
+// passed out of how many were tried
+pub fn run(path: &Path, movetime: Duration) -> Result<(usize, usize), String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    let records = epd::parse_epd_file(&text).map_err(|e| format!("{} {}", path.display(), e))?;
+    let mut searcher = Searcher::new();
+    let (mut solved, mut tried) = (0, 0);
+    for (i, record) in records.iter().enumerate() {
+        let name = record
+            .id()
+            .map_or_else(|| format!("#{}", i + 1), str::to_string);
+        let (best, avoid) = match (record.best_moves(), record.avoid_moves()) {
+            (Ok(best), Ok(avoid)) => (best, avoid),
+            (Err(e), _) | (_, Err(e)) => {
+                println!("{:<16} skipped: {}", name, e);
+                continue;
+            }
+        };
+        if best.is_empty() && avoid.is_empty() {
+            continue;
+        }
+        // Each position is searched afresh, so results do not depend on order
+        searcher.clear();
+        let result = searcher.search(&record.board, SearchLimits::time(movetime));
+        let found = result.best_move;
+        let passed =
+            found.is_some_and(|mv| (best.is_empty() || best.contains(&mv)) && !avoid.contains(&mv));
+        tried += 1;
+        if passed {
+            solved += 1;
+        }
+        println!(
+            "{:<16} {:<6} {:<8} {}",
+            name,
+            if passed { "solved" } else { "missed" },
+            found.map_or("-".to_string(), |mv| record.board.move_to_san(mv)),
+            expected(record)
+        );
+    }
+    Ok((solved, tried))
+}
+
+// What the record asks for, e.g. "bm Qg6" or "am Bxh7+"
+fn expected(record: &Epd) -> String {
+    ["bm", "am"]
+        .iter()
+        .filter_map(|&opcode| {
+            let operands = record.operands(opcode)?;
+            Some(format!("{} {}", opcode, operands.join(" ")))
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
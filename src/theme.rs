@@ -0,0 +1,238 @@
+use chess_rs::{ColorChess, Piece, PieceType};
+use serde::{Deserialize, Serialize};
+use tui::style::Color;
+
+// Named color schemes for the board and the text around it
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    #[default]
+    Classic,
+    Solarized,
+    HighContrast,
+    LichessBrown,
+    LichessGreen,
+}
+
+pub const THEMES: [Theme; 5] = [
+    Theme::Classic,
+    Theme::Solarized,
+    Theme::HighContrast,
+    Theme::LichessBrown,
+    Theme::LichessGreen,
+];
+
+// The colors a theme gives each part of the screen
+#[derive(Clone, Copy)]
+pub struct Palette {
+    pub light_square: Color,
+    pub dark_square: Color,
+    pub white_piece: Color,
+    pub black_piece: Color,
+    // Square of the picked-up piece, its legal destinations and the keyboard cursor
+    pub selected: Color,
+    pub legal_move: Color,
+    pub cursor: Color,
+    // King of the side to move while it is in check
+    pub check: Color,
+    // A clock running low on time
+    pub low_time: Color,
+    // Text drawn on top of a highlighted square
+    pub highlight_text: Color,
+    // Titles and results, ordinary text, and hints or stale values
+    pub accent: Color,
+    pub text: Color,
+    pub dim: Color,
+}
+
+impl Theme {
+    pub fn label(self) -> &'static str {
+        match self {
+            Theme::Classic => "Classic",
+            Theme::Solarized => "Solarized",
+            Theme::HighContrast => "High contrast",
+            Theme::LichessBrown => "Lichess brown",
+            Theme::LichessGreen => "Lichess green",
+        }
+    }
+
+    // The next theme, wrapping around after the last one
+    pub fn next(self) -> Theme {
+        let index = THEMES.iter().position(|&t| t == self).unwrap_or(0);
+        THEMES[(index + 1) % THEMES.len()]
+    }
+
+    pub fn palette(self) -> Palette {
+        match self {
+            Theme::Classic => Palette {
+                light_square: Color::Rgb(240, 217, 181),
+                dark_square: Color::Rgb(181, 136, 99),
+                white_piece: Color::White,
+                black_piece: Color::Blue,
+                selected: Color::Yellow,
+                legal_move: Color::Green,
+                cursor: Color::Cyan,
+                check: Color::Red,
+                low_time: Color::LightRed,
+                highlight_text: Color::Black,
+                accent: Color::Yellow,
+                text: Color::Reset,
+                dim: Color::Gray,
+            },
+            Theme::Solarized => Palette {
+                light_square: Color::Rgb(238, 232, 213),
+                dark_square: Color::Rgb(147, 161, 161),
+                white_piece: Color::Rgb(203, 75, 22),
+                black_piece: Color::Rgb(7, 54, 66),
+                selected: Color::Rgb(181, 137, 0),
+                legal_move: Color::Rgb(133, 153, 0),
+                cursor: Color::Rgb(42, 161, 152),
+                check: Color::Rgb(220, 50, 47),
+                low_time: Color::Rgb(211, 54, 130),
+                highlight_text: Color::Rgb(0, 43, 54),
+                accent: Color::Rgb(181, 137, 0),
+                text: Color::Rgb(131, 148, 150),
+                dim: Color::Rgb(88, 110, 117),
+            },
+            // Only the 16 basic colors, which every terminal palette keeps distinct
+            Theme::HighContrast => Palette {
+                light_square: Color::Gray,
+                dark_square: Color::DarkGray,
+                white_piece: Color::White,
+                black_piece: Color::Black,
+                selected: Color::LightYellow,
+                legal_move: Color::LightGreen,
+                cursor: Color::LightCyan,
+                check: Color::LightRed,
+                low_time: Color::LightMagenta,
+                highlight_text: Color::Black,
+                accent: Color::LightYellow,
+                text: Color::White,
+                dim: Color::Gray,
+            },
+            Theme::LichessBrown => Palette {
+                light_square: Color::Rgb(240, 217, 181),
+                dark_square: Color::Rgb(181, 136, 99),
+                white_piece: Color::Rgb(255, 255, 255),
+                black_piece: Color::Rgb(0, 0, 0),
+                selected: Color::Rgb(130, 151, 105),
+                legal_move: Color::Rgb(100, 111, 64),
+                cursor: Color::Rgb(155, 199, 0),
+                check: Color::Rgb(230, 60, 50),
+                low_time: Color::Rgb(204, 51, 51),
+                highlight_text: Color::Rgb(0, 0, 0),
+                accent: Color::Rgb(213, 160, 56),
+                text: Color::Reset,
+                dim: Color::Gray,
+            },
+            Theme::LichessGreen => Palette {
+                light_square: Color::Rgb(255, 255, 221),
+                dark_square: Color::Rgb(134, 166, 102),
+                white_piece: Color::Rgb(255, 255, 255),
+                black_piece: Color::Rgb(0, 0, 0),
+                selected: Color::Rgb(205, 210, 106),
+                legal_move: Color::Rgb(100, 109, 64),
+                cursor: Color::Rgb(95, 160, 220),
+                check: Color::Rgb(230, 60, 50),
+                low_time: Color::Rgb(204, 51, 51),
+                highlight_text: Color::Rgb(0, 0, 0),
+                accent: Color::Rgb(98, 153, 36),
+                text: Color::Reset,
+                dim: Color::Gray,
+            },
+        }
+    }
+}
+
+// How pieces are drawn: glyphs that need a font with the chess symbols, or
+// plain letters that line up in any terminal
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PieceStyle {
+    // The solid glyphs for both sides, told apart by color
+    #[default]
+    Filled,
+    // Outlined glyphs for White and solid ones for Black
+    Outlined,
+    // FEN letters, upper case for White and lower case for Black
+    Ascii,
+}
+
+// Blindfold play: whose pieces are left off the board
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Blindfold {
+    #[default]
+    Off,
+    All,
+    // Only the player's own pieces, or only the opponent's
+    Mine,
+    Theirs,
+}
+
+const BLINDFOLDS: [Blindfold; 4] = [
+    Blindfold::Off,
+    Blindfold::All,
+    Blindfold::Mine,
+    Blindfold::Theirs,
+];
+
+impl Blindfold {
+    pub fn label(self) -> &'static str {
+        match self {
+            Blindfold::Off => "Off",
+            Blindfold::All => "All pieces",
+            Blindfold::Mine => "My pieces",
+            Blindfold::Theirs => "Opponent's pieces",
+        }
+    }
+
+    pub fn next(self) -> Blindfold {
+        let index = BLINDFOLDS.iter().position(|&b| b == self).unwrap_or(0);
+        BLINDFOLDS[(index + 1) % BLINDFOLDS.len()]
+    }
+
+    // Whether pieces of a side are hidden, for the player on the given side
+    pub fn hides(self, color: ColorChess, player: ColorChess) -> bool {
+        match self {
+            Blindfold::Off => false,
+            Blindfold::All => true,
+            Blindfold::Mine => color == player,
+            Blindfold::Theirs => color != player,
+        }
+    }
+}
+
+pub const PIECE_STYLES: [PieceStyle; 3] =
+    [PieceStyle::Filled, PieceStyle::Outlined, PieceStyle::Ascii];
+
+impl PieceStyle {
+    pub fn label(self) -> &'static str {
+        match self {
+            PieceStyle::Filled => "Filled",
+            PieceStyle::Outlined => "Outlined",
+            PieceStyle::Ascii => "ASCII",
+        }
+    }
+
+    // The next style, wrapping around after the last one
+    pub fn next(self) -> PieceStyle {
+        let index = PIECE_STYLES.iter().position(|&s| s == self).unwrap_or(0);
+        PIECE_STYLES[(index + 1) % PIECE_STYLES.len()]
+    }
+
+    pub fn glyph(self, piece: Piece) -> char {
+        match (self, piece.color()) {
+            (PieceStyle::Ascii, _) => piece.to_fen_char(),
+            (PieceStyle::Outlined, ColorChess::White) => match piece.piece_type() {
+                PieceType::King => '♔',
+                PieceType::Queen => '♕',
+                PieceType::Rook => '♖',
+                PieceType::Bishop => '♗',
+                PieceType::Knight => '♘',
+                PieceType::Pawn => '♙',
+            },
+            _ => piece.to_char(),
+        }
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+// How long a notification stays on screen after a newer one arrives
+const TOAST_DURATION: Duration = Duration::from_secs(4);
+
+// Most notifications kept at once; the oldest go first
+const MAX_TOASTS: usize = 5;
+
+pub struct Toast {
+    pub text: String,
+    pub posted: Instant,
+}
+
+// Notifications in the order they were posted. The newest one stays in the
+// message area until another replaces it, so prompts are not missed; the ones
+// before it are stacked above it for a few seconds instead of being lost
+#[derive(Default)]
+pub struct Toasts {
+    queue: VecDeque<Toast>,
+}
+
+impl Toasts {
+    pub fn push(&mut self, text: impl Into<String>) {
+        let text = text.into();
+        if text.is_empty() {
+            return;
+        }
+        if self.queue.len() == MAX_TOASTS {
+            self.queue.pop_front();
+        }
+        self.queue.push_back(Toast {
+            text,
+            posted: Instant::now(),
+        });
+    }
+
+    // The newest notification, or "" before there is any
+    pub fn latest(&self) -> &str {
+        self.queue.back().map_or("", |toast| toast.text.as_str())
+    }
+
+    // Notifications before the newest that have not expired yet, oldest first
+    pub fn recent(&self) -> impl Iterator<Item = &Toast> {
+        let older = self.queue.len().saturating_sub(1);
+        self.queue
+            .iter()
+            .take(older)
+            .filter(|toast| toast.posted.elapsed() < TOAST_DURATION)
+    }
+}
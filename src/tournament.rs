@@ -0,0 +1,516 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use chess_rs::clock::{Clock, TimeControl};
+use chess_rs::pgn::{self, MoveTiming};
+use chess_rs::search::DEFAULT_MOVES_TO_GO;
+use chess_rs::{
+    ColorChess, Difficulty, Game, GameResult, Move, OpeningBook, SearchLimits, Searcher,
+    Termination, UciEngine, epd,
+};
+use serde::Deserialize;
+
+// Games still going after this many plies are drawn
+const MAX_GAME_PLIES: usize = 600;
+
+// A tournament file: who plays, how long they think and where the games go
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct TournamentConfig {
+    // Event tag of the games
+    pub event: String,
+    // Games each pair of engines plays, half of them with either color
+    pub games: u32,
+    // Whether the first engine plays all the others instead of everyone
+    // playing everyone
+    pub gauntlet: bool,
+    // Clock for each game; without one every move gets `movetime` ms
+    pub time_control: Option<TimeControl>,
+    pub movetime: u64,
+    // EPD positions, PGN games or a Polyglot book to start the games from
+    pub openings: Option<PathBuf>,
+    // Moves taken from each PGN game or out of the book
+    pub opening_plies: usize,
+    // File the games are added to
+    pub pgn: PathBuf,
+    #[serde(rename = "engine")]
+    pub engines: Vec<EntrantConfig>,
+}
+
+impl Default for TournamentConfig {
+    fn default() -> Self {
+        TournamentConfig {
+            event: "chess-rs tournament".to_string(),
+            games: 2,
+            gauntlet: false,
+            time_control: None,
+            movetime: 100,
+            openings: None,
+            opening_plies: 8,
+            pgn: PathBuf::from("tournament.pgn"),
+            engines: Vec::new(),
+        }
+    }
+}
+
+impl TournamentConfig {
+    pub fn load(path: &Path) -> Result<TournamentConfig, String> {
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        toml::from_str(&text).map_err(|e| format!("{}: {}", path.display(), e.message()))
+    }
+}
+
+// An [[engine]] of the tournament: an external UCI engine when `path` is
+// set, the built-in one at `difficulty` otherwise
+#[derive(Deserialize)]
+#[serde(default)]
+pub struct EntrantConfig {
+    pub name: Option<String>,
+    pub path: Option<String>,
+    pub args: Vec<String>,
+    pub options: BTreeMap<String, String>,
+    pub difficulty: Difficulty,
+    pub threads: usize,
+}
+
+impl Default for EntrantConfig {
+    fn default() -> Self {
+        EntrantConfig {
+            name: None,
+            path: None,
+            args: Vec::new(),
+            options: BTreeMap::new(),
+            difficulty: Difficulty::Strong,
+            threads: 1,
+        }
+    }
+}
+
+enum Player {
+    Builtin {
+        searcher: Box<Searcher>,
+        difficulty: Difficulty,
+    },
+    Uci(UciEngine),
+}
+
+struct Entrant {
+    name: String,
+    player: Player,
+}
+
+impl Entrant {
+    fn start(config: &EntrantConfig) -> Result<Entrant, String> {
+        let player = match &config.path {
+            Some(path) => {
+                let mut engine = UciEngine::spawn(path, &config.args)?;
+                for (name, value) in &config.options {
+                    engine.set_option(name, value)?;
+                }
+                Player::Uci(engine)
+            }
+            None => {
+                let mut searcher = Searcher::new();
+                searcher.set_threads(config.threads.max(1));
+                Player::Builtin {
+                    searcher: Box::new(searcher),
+                    difficulty: config.difficulty,
+                }
+            }
+        };
+        let name = config.name.clone().unwrap_or_else(|| match &player {
+            Player::Builtin { difficulty, .. } => format!("chess-rs {}", difficulty.label()),
+            Player::Uci(engine) => engine.get_name().to_string(),
+        });
+        Ok(Entrant { name, player })
+    }
+
+    fn new_game(&mut self) -> Result<(), String> {
+        match &mut self.player {
+            Player::Builtin { searcher, .. } => {
+                searcher.clear();
+                Ok(())
+            }
+            Player::Uci(engine) => Ok(engine.new_game()?),
+        }
+    }
+
+    // The move found in `time`; the built-in engine keeps the depth, move
+    // randomness and eval noise of its difficulty
+    fn think(&mut self, board: &Game, time: Duration) -> Result<Option<Move>, String> {
+        match &mut self.player {
+            Player::Builtin {
+                searcher,
+                difficulty,
+            } => {
+                let limits = SearchLimits {
+                    time_limit: Some(time),
+                    ..difficulty.limits()
+                };
+                Ok(searcher.search(board, limits).best_move)
+            }
+            Player::Uci(engine) => Ok(engine.search(board, SearchLimits::time(time))?.best_move),
+        }
+    }
+}
+
+// Positions the games start from, each played once with either color
+enum Openings {
+    Start,
+    Positions(Vec<Game>),
+    // Lines of random book moves, a new one for each pair of games
+    Book(OpeningBook, usize),
+}
+
+impl Openings {
+    fn load(path: Option<&Path>, plies: usize) -> Result<Openings, String> {
+        let Some(path) = path else {
+            return Ok(Openings::Start);
+        };
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        if extension == "bin" {
+            return Ok(Openings::Book(OpeningBook::open(path)?, plies));
+        }
+        let text = fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let positions: Vec<Game> = if extension == "pgn" {
+            pgn::parse_pgn_games(&text)
+                .map_err(|e| format!("{}: {}", path.display(), e))?
+                .into_iter()
+                .map(|game| {
+                    let mut board = game.board.get_start_position();
+                    for record in game.board.get_history().iter().take(plies) {
+                        if board.play_move(record.mv).is_err() {
+                            break;
+                        }
+                    }
+                    board
+                })
+                .collect()
+        } else {
+            epd::parse_epd_file(&text)
+                .map_err(|e| format!("{} {}", path.display(), e))?
+                .into_iter()
+                .map(|record| record.board)
+                .collect()
+        };
+        if positions.is_empty() {
+            return Err(format!("{}: no openings in it", path.display()));
+        }
+        Ok(Openings::Positions(positions))
+    }
+
+    // The position for the `index`th pair of games
+    fn get(&self, index: usize) -> Game {
+        match self {
+            Openings::Start => Game::new(),
+            Openings::Positions(positions) => positions[index % positions.len()].clone(),
+            Openings::Book(book, plies) => {
+                let mut board = Game::new();
+                for _ in 0..*plies {
+                    let Some(mv) = book.choose_move(&board, 1.0) else {
+                        break;
+                    };
+                    if board.play_move(mv).is_err() {
+                        break;
+                    }
+                }
+                board
+            }
+        }
+    }
+}
+
+// How a game ended
+struct Outcome {
+    result: GameResult,
+    // The reason in the tournament's words, e.g. "Stockfish lost on time"
+    reason: String,
+}
+
+// Plays every pairing of the tournament, adding each game to the PGN file
+// as it ends, and prints the crosstable at the end
+pub fn run(config: &TournamentConfig) -> Result<(), String> {
+    if config.engines.len() < 2 {
+        return Err("a tournament needs at least two [[engine]] sections".to_string());
+    }
+    let openings = Openings::load(config.openings.as_deref(), config.opening_plies)?;
+    let mut entrants = config
+        .engines
+        .iter()
+        .map(Entrant::start)
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let count = entrants.len();
+    let pairings: Vec<(usize, usize)> = if config.gauntlet {
+        (1..count).map(|other| (0, other)).collect()
+    } else {
+        (0..count)
+            .flat_map(|first| (first + 1..count).map(move |second| (first, second)))
+            .collect()
+    };
+    let total = pairings.len() * config.games as usize;
+    // Half points scored by each engine against each other one, and games
+    // played between them
+    let mut half_points = vec![vec![0u32; count]; count];
+    let mut played = vec![vec![0u32; count]; count];
+    let mut round = 0;
+    let mut opening_index = 0;
+    for &(first, second) in &pairings {
+        let mut opening = openings.get(opening_index);
+        for game in 0..config.games as usize {
+            if game > 0 && game.is_multiple_of(2) {
+                opening_index += 1;
+                opening = openings.get(opening_index);
+            }
+            let (white, black) = if game.is_multiple_of(2) {
+                (first, second)
+            } else {
+                (second, first)
+            };
+            round += 1;
+            let (board, timings, outcome) =
+                play_game(&mut entrants, white, black, &opening, config);
+            let score = |side| match outcome.result.winner {
+                Some(winner) if winner == side => 2,
+                Some(_) => 0,
+                None => 1,
+            };
+            half_points[white][black] += score(ColorChess::White);
+            half_points[black][white] += score(ColorChess::Black);
+            played[white][black] += 1;
+            played[black][white] += 1;
+            println!(
+                "Game {} of {}: {} - {} {} ({})",
+                round,
+                total,
+                entrants[white].name,
+                entrants[black].name,
+                outcome.result.pgn_tag(),
+                outcome.reason
+            );
+
+            let mut text = board.to_pgn(
+                &entrants[white].name,
+                &entrants[black].name,
+                Some(outcome.result),
+                &timings,
+            );
+            text = set_tag(&text, "Event", &config.event);
+            text = set_tag(&text, "Round", &round.to_string());
+            append(&config.pgn, &text)?;
+        }
+        opening_index += 1;
+    }
+
+    let names: Vec<&str> = entrants
+        .iter()
+        .map(|entrant| entrant.name.as_str())
+        .collect();
+    print_crosstable(&names, &half_points, &played);
+    println!("Games saved to {}.", config.pgn.display());
+    Ok(())
+}
+
+fn play_game(
+    entrants: &mut [Entrant],
+    white: usize,
+    black: usize,
+    opening: &Game,
+    config: &TournamentConfig,
+) -> (Game, Vec<Option<MoveTiming>>, Outcome) {
+    let mut board = opening.clone();
+    // Opening moves were not thought about
+    let mut timings = vec![None; board.get_history().len()];
+    board.set_clock(config.time_control.map(Clock::new));
+    for index in [white, black] {
+        if let Err(e) = entrants[index].new_game() {
+            let side = if index == white {
+                ColorChess::White
+            } else {
+                ColorChess::Black
+            };
+            return (board, timings, forfeit(&entrants[index].name, side, &e));
+        }
+    }
+    let start_plies = board.get_history().len();
+    loop {
+        if let Some(outcome) = adjudicate(&board, start_plies) {
+            return (board, timings, outcome);
+        }
+        let side = board.get_current_turn();
+        let index = if side == ColorChess::White {
+            white
+        } else {
+            black
+        };
+        let time = match (board.clock_mut(), config.time_control) {
+            (Some(clock), Some(control)) => {
+                clock.start(side);
+                let remaining = clock.get_remaining(side);
+                SearchLimits::clock(remaining, control.bonus, DEFAULT_MOVES_TO_GO)
+                    .time_limit
+                    .unwrap_or(remaining)
+            }
+            _ => Duration::from_millis(config.movetime.max(1)),
+        };
+        let started = Instant::now();
+        let mv = match entrants[index].think(&board, time) {
+            Ok(Some(mv)) => mv,
+            Ok(None) => {
+                let error = "returned no move".to_string();
+                return (board, timings, forfeit(&entrants[index].name, side, &error));
+            }
+            Err(e) => return (board, timings, forfeit(&entrants[index].name, side, &e)),
+        };
+        if let Err(e) = board.play_move(mv) {
+            return (
+                board,
+                timings,
+                forfeit(&entrants[index].name, side, &e.to_string()),
+            );
+        }
+        // Without a clock every move takes the time it is given
+        let Some(clock) = board.clock_mut() else {
+            timings.push(None);
+            continue;
+        };
+        clock.complete_move(side);
+        let left = clock.get_remaining(side);
+        timings.push(Some(MoveTiming {
+            spent: started.elapsed(),
+            clock: Some(left),
+        }));
+        if left.is_zero() {
+            let winner = side.opposite();
+            let outcome = if board.has_mating_material(winner) {
+                Outcome {
+                    result: GameResult::win_for(winner, Termination::Timeout),
+                    reason: format!("{} lost on time", entrants[index].name),
+                }
+            } else {
+                Outcome {
+                    result: GameResult::draw(Termination::Timeout),
+                    reason: format!(
+                        "{} lost on time, but {:?} cannot checkmate",
+                        entrants[index].name, winner
+                    ),
+                }
+            };
+            return (board, timings, outcome);
+        }
+    }
+}
+
+// The game lost by an engine that failed
+fn forfeit(name: &str, side: ColorChess, error: &str) -> Outcome {
+    Outcome {
+        result: GameResult::win_for(side.opposite(), Termination::Forfeit),
+        reason: format!("{} failed: {}", name, error),
+    }
+}
+
+// Ends the game when the rules do, or when it has gone on too long
+fn adjudicate(board: &Game, start_plies: usize) -> Option<Outcome> {
+    let result = if let Some(result) = board.get_result() {
+        result
+    } else if board.get_history().len() - start_plies >= MAX_GAME_PLIES {
+        GameResult::draw(Termination::Adjudication)
+    } else {
+        return None;
+    };
+    let reason = match result.reason {
+        Termination::Checkmate => "checkmate",
+        Termination::Stalemate => "stalemate",
+        Termination::Repetition => "threefold repetition",
+        Termination::FiftyMoves => "fifty-move rule",
+        Termination::InsufficientMaterial => "insufficient material",
+        _ => "move limit",
+    };
+    Some(Outcome {
+        result,
+        reason: reason.to_string(),
+    })
+}
+
+// Sets a tag of a PGN game, adding it after the others if it is missing
+fn set_tag(pgn: &str, name: &str, value: &str) -> String {
+    let value = value.replace('\\', "\\\\").replace('"', "\\\"");
+    let line = format!("[{} \"{}\"]", name, value);
+    let prefix = format!("[{} ", name);
+    let mut lines: Vec<String> = pgn.lines().map(str::to_string).collect();
+    match lines.iter().position(|text| text.starts_with(&prefix)) {
+        Some(index) => lines[index] = line,
+        None => {
+            let index = lines
+                .iter()
+                .position(|text| !text.starts_with('['))
+                .unwrap_or(lines.len());
+            lines.insert(index, line);
+        }
+    }
+    lines.join("\n") + "\n"
+}
+
+fn append(path: &Path, pgn: &str) -> Result<(), String> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|e| format!("{}: {}", path.display(), e))?;
+    writeln!(file, "{}", pgn).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+// Standings by score, with each engine's points against every other one
+fn print_crosstable(names: &[&str], half_points: &[Vec<u32>], played: &[Vec<u32>]) {
+    let count = names.len();
+    let total = |index: usize| -> u32 { half_points[index].iter().sum() };
+    let games = |index: usize| -> u32 { played[index].iter().sum() };
+    let mut order: Vec<usize> = (0..count).collect();
+    order.sort_by_key(|&index| std::cmp::Reverse(total(index)));
+    let width = names
+        .iter()
+        .map(|name| name.len())
+        .max()
+        .unwrap_or(0)
+        .max(6);
+    let points = |half: u32| {
+        if half.is_multiple_of(2) {
+            (half / 2).to_string()
+        } else {
+            format!("{}.5", half / 2)
+        }
+    };
+
+    let mut header = format!("{:>4}  {:<width$}  {:>9}", "", "Engine", "Score");
+    for rank in 1..=count {
+        header.push_str(&format!("  {:>5}", rank));
+    }
+    println!();
+    println!("{}", header);
+    for (rank, &index) in order.iter().enumerate() {
+        let mut row = format!(
+            "{:>4}  {:<width$}  {:>9}",
+            rank + 1,
+            names[index],
+            format!("{}/{}", points(total(index)), games(index))
+        );
+        for &other in &order {
+            let cell = if other == index || played[index][other] == 0 {
+                "-".to_string()
+            } else {
+                points(half_points[index][other])
+            };
+            row.push_str(&format!("  {:>5}", cell));
+        }
+        println!("{}", row);
+    }
+}
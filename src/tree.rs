@@ -0,0 +1,475 @@
+use crate::board::ColorChess;
+use crate::game::Game;
+use crate::moves::Move;
+
+// The node every game tree starts from, the position before the first move
+pub const ROOT: usize = 0;
+
+// A game with its variations, as PGN has them: each node is a move, its
+// first child the move that follows in the same line and any others
+// alternatives to that one. Nodes keep their index for good, so that they
+// can be pointed at while the tree changes
+#[derive(Clone)]
+pub struct GameTree {
+    start: Game,
+    nodes: Vec<Node>,
+    // Goes up with every change, so that what is drawn from the tree knows
+    // when to redo it
+    revision: u64,
+}
+
+#[derive(Clone)]
+struct Node {
+    // None for the root
+    mv: Option<Move>,
+    parent: usize,
+    children: Vec<usize>,
+    // Numeric Annotation Glyphs, as in PGN: $1 is "!", $14 "+="
+    nags: Vec<u8>,
+    comment: Option<String>,
+    eval: Option<Eval>,
+}
+
+// What the engine made of the position after a move
+#[derive(Clone, Copy)]
+pub struct Eval {
+    // Centipawns from White's point of view
+    pub white_score: i32,
+    pub depth: u32,
+}
+
+// The move assessments written after the SAN, as NAGs $1 to $6
+const NAG_SYMBOLS: [&str; 6] = ["!", "?", "!!", "??", "!?", "?!"];
+
+// A piece of movetext: a move, with the node it leads to, or a move number,
+// each with the parentheses that open or close a variation on it
+pub struct Token {
+    pub text: String,
+    pub node: Option<usize>,
+}
+
+impl GameTree {
+    pub fn new(start: Game) -> GameTree {
+        GameTree {
+            start,
+            nodes: vec![Node {
+                mv: None,
+                parent: ROOT,
+                children: Vec::new(),
+                nags: Vec::new(),
+                comment: None,
+                eval: None,
+            }],
+            revision: 0,
+        }
+    }
+
+    // The moves played on a board as a tree with no variations
+    pub fn from_board(board: &Game) -> GameTree {
+        let mut tree = GameTree::new(board.get_start_position());
+        let mut node = ROOT;
+        for record in board.get_history() {
+            node = tree.add(node, record.mv);
+        }
+        tree
+    }
+
+    pub fn start(&self) -> &Game {
+        &self.start
+    }
+
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    pub fn mv(&self, node: usize) -> Option<Move> {
+        self.nodes[node].mv
+    }
+
+    pub fn parent(&self, node: usize) -> Option<usize> {
+        (node != ROOT).then(|| self.nodes[node].parent)
+    }
+
+    pub fn children(&self, node: usize) -> &[usize] {
+        &self.nodes[node].children
+    }
+
+    pub fn nags(&self, node: usize) -> &[u8] {
+        &self.nodes[node].nags
+    }
+
+    pub fn set_nags(&mut self, node: usize, nags: Vec<u8>) {
+        if node != ROOT {
+            self.nodes[node].nags = nags;
+            self.revision += 1;
+        }
+    }
+
+    // Adds a NAG to the node's move unless it has it already
+    pub fn annotate(&mut self, node: usize, nag: u8) {
+        if node != ROOT && !self.nodes[node].nags.contains(&nag) {
+            self.nodes[node].nags.push(nag);
+            self.revision += 1;
+        }
+    }
+
+    // The comment after the node's move; the root's comes before the first
+    pub fn comment(&self, node: usize) -> Option<&str> {
+        self.nodes[node].comment.as_deref()
+    }
+
+    pub fn set_comment(&mut self, node: usize, comment: Option<String>) {
+        self.nodes[node].comment = comment;
+        self.revision += 1;
+    }
+
+    // Adds to the comment after the node's move, as when PGN has several
+    pub fn add_comment(&mut self, node: usize, text: &str) {
+        let comment = match self.nodes[node].comment.take() {
+            Some(comment) => format!("{} {}", comment, text),
+            None => text.to_string(),
+        };
+        self.set_comment(node, Some(comment));
+    }
+
+    pub fn eval(&self, node: usize) -> Option<Eval> {
+        self.nodes[node].eval
+    }
+
+    // Keeps the deepest eval of the position after the node's move. The
+    // move list does not show evals, so the revision stays as it is
+    pub fn record_eval(&mut self, node: usize, eval: Eval) {
+        let deeper = self.nodes[node]
+            .eval
+            .is_none_or(|known| eval.depth >= known.depth);
+        if node != ROOT && deeper {
+            self.nodes[node].eval = Some(eval);
+        }
+    }
+
+    // The node's move in SAN from the position before it, followed by its
+    // annotations, as in "Nf3!?" or "Nf3 $14"
+    pub fn move_text(&self, board: &Game, node: usize) -> String {
+        let Some(mv) = self.nodes[node].mv else {
+            return String::new();
+        };
+        let mut text = board.move_to_san(mv);
+        for &nag in &self.nodes[node].nags {
+            if !NAG_SYMBOLS.contains(&nag_text(nag).as_str()) {
+                text.push(' ');
+            }
+            text.push_str(&nag_text(nag));
+        }
+        text
+    }
+
+    // The node a move leads to from another, added as the last alternative
+    // unless it is there already
+    pub fn add(&mut self, node: usize, mv: Move) -> usize {
+        if let Some(&child) = self.nodes[node]
+            .children
+            .iter()
+            .find(|&&child| self.nodes[child].mv == Some(mv))
+        {
+            return child;
+        }
+        let child = self.nodes.len();
+        self.nodes.push(Node {
+            mv: Some(mv),
+            parent: node,
+            children: Vec::new(),
+            nags: Vec::new(),
+            comment: None,
+            eval: None,
+        });
+        self.nodes[node].children.push(child);
+        self.revision += 1;
+        child
+    }
+
+    // Moves from the start to the node
+    pub fn moves_to(&self, node: usize) -> Vec<Move> {
+        let mut moves = Vec::new();
+        let mut node = node;
+        while let Some(parent) = self.parent(node) {
+            moves.extend(self.nodes[node].mv);
+            node = parent;
+        }
+        moves.reverse();
+        moves
+    }
+
+    // The position after the node's move
+    pub fn board_at(&self, node: usize) -> Game {
+        let mut board = self.start.clone();
+        for mv in self.moves_to(node) {
+            if board.make(mv).is_err() {
+                break;
+            }
+        }
+        board
+    }
+
+    // How many moves from the start the node is
+    pub fn depth(&self, node: usize) -> usize {
+        let mut depth = 0;
+        let mut node = node;
+        while let Some(parent) = self.parent(node) {
+            depth += 1;
+            node = parent;
+        }
+        depth
+    }
+
+    // The last node of the line through the node, following first children
+    pub fn line_end(&self, node: usize) -> usize {
+        let mut node = node;
+        while let Some(&next) = self.nodes[node].children.first() {
+            node = next;
+        }
+        node
+    }
+
+    // The nodes of the main line, from its first move
+    pub fn main_line(&self) -> Vec<usize> {
+        let mut line = Vec::new();
+        let mut node = ROOT;
+        while let Some(&next) = self.nodes[node].children.first() {
+            line.push(next);
+            node = next;
+        }
+        line
+    }
+
+    // Whether every move up to the node is the first choice, which makes it
+    // part of the main line
+    pub fn is_main_line(&self, node: usize) -> bool {
+        let mut node = node;
+        while let Some(parent) = self.parent(node) {
+            if self.nodes[parent].children[0] != node {
+                return false;
+            }
+            node = parent;
+        }
+        true
+    }
+
+    // The node's move with its number, as in "12. Nf3" or "12... Nc6"
+    pub fn move_label(&self, node: usize) -> String {
+        let Some(parent) = self.parent(node) else {
+            return String::new();
+        };
+        let board = self.board_at(parent);
+        format!(
+            "{} {}",
+            move_number(&board, true).unwrap_or_default(),
+            self.move_text(&board, node)
+        )
+    }
+
+    // Makes the game on a board the main line, keeping whatever else the tree
+    // holds as variations; a game from another position starts a new tree.
+    // Returns the node of the last move played
+    pub fn sync(&mut self, board: &Game) -> usize {
+        let start = board.get_start_position();
+        if start.hash() != self.start.hash() {
+            *self = GameTree::new(start);
+        }
+        let mut node = ROOT;
+        for record in board.get_history() {
+            let child = self.add(node, record.mv);
+            let children = &mut self.nodes[node].children;
+            if children[0] != child {
+                children.retain(|&other| other != child);
+                children.insert(0, child);
+                self.revision += 1;
+            }
+            node = child;
+        }
+        node
+    }
+
+    // Moves the variation the node is in one place up among the alternatives
+    // where it branches off, returning the node it swapped places with; None
+    // on the main line
+    pub fn promote(&mut self, node: usize) -> Option<usize> {
+        let branch = self.branch(node, |index, _| index > 0)?;
+        self.swap(branch, -1)
+    }
+
+    // Moves the line the node is in one place down among the alternatives
+    // where it last branched off, returning the node it swapped places with;
+    // None when it is the last one everywhere
+    pub fn demote(&mut self, node: usize) -> Option<usize> {
+        let branch = self.branch(node, |index, count| index + 1 < count)?;
+        self.swap(branch, 1)
+    }
+
+    // The nearest node from this one back to the start whose place among
+    // its parent's children matches; given the index and the count
+    fn branch(&self, node: usize, matches: impl Fn(usize, usize) -> bool) -> Option<usize> {
+        let mut node = node;
+        while let Some(parent) = self.parent(node) {
+            let children = &self.nodes[parent].children;
+            let index = children.iter().position(|&child| child == node)?;
+            if matches(index, children.len()) {
+                return Some(node);
+            }
+            node = parent;
+        }
+        None
+    }
+
+    fn swap(&mut self, node: usize, places: isize) -> Option<usize> {
+        let parent = self.nodes[node].parent;
+        let children = &mut self.nodes[parent].children;
+        let index = children.iter().position(|&child| child == node)?;
+        let other = index.checked_add_signed(places)?;
+        children.swap(index, other);
+        self.revision += 1;
+        Some(children[index])
+    }
+
+    // The whole tree as movetext, with the main line cut short after `plies`
+    // moves; `comment` gives what goes in braces after a move, if anything
+    pub fn movetext(&self, plies: usize, comment: impl Fn(usize) -> Option<String>) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        if let Some(note) = comment(ROOT) {
+            tokens.push(Token {
+                text: format!("{{{}}}", note),
+                node: None,
+            });
+        }
+        self.write_line(ROOT, &self.start, plies, true, &comment, &mut tokens);
+        tokens
+    }
+
+    // A variation in parentheses: the node's move and the line after it
+    pub fn variation(&self, node: usize) -> Vec<Token> {
+        let mut tokens = Vec::new();
+        let Some(parent) = self.parent(node) else {
+            return tokens;
+        };
+        self.write_variation(node, &self.board_at(parent), &|_| None, &mut tokens);
+        tokens
+    }
+
+    // The moves after a node, following the first children for at most
+    // `plies` moves, with the alternatives to each in parentheses after it
+    fn write_line(
+        &self,
+        node: usize,
+        board: &Game,
+        plies: usize,
+        interrupted: bool,
+        comment: &dyn Fn(usize) -> Option<String>,
+        tokens: &mut Vec<Token>,
+    ) {
+        let mut board = board.clone();
+        let mut node = node;
+        // A black move is numbered again when something comes between it
+        // and White's, as in "1. e4 (1. d4) 1... e5"
+        let mut interrupted = interrupted;
+        for _ in 0..plies {
+            let Some((&next, alternatives)) = self.nodes[node].children.split_first() else {
+                break;
+            };
+            let Some(mv) = self.nodes[next].mv else {
+                break;
+            };
+            if let Some(number) = move_number(&board, interrupted) {
+                tokens.push(Token {
+                    text: number,
+                    node: None,
+                });
+            }
+            tokens.push(Token {
+                text: self.move_text(&board, next),
+                node: Some(next),
+            });
+            let note = comment(next);
+            interrupted = note.is_some() || !alternatives.is_empty();
+            if let Some(note) = note {
+                tokens.push(Token {
+                    text: format!("{{{}}}", note),
+                    node: None,
+                });
+            }
+            for &alternative in alternatives {
+                self.write_variation(alternative, &board, comment, tokens);
+            }
+            if board.make(mv).is_err() {
+                break;
+            }
+            node = next;
+        }
+    }
+
+    fn write_variation(
+        &self,
+        node: usize,
+        board: &Game,
+        comment: &dyn Fn(usize) -> Option<String>,
+        tokens: &mut Vec<Token>,
+    ) {
+        let Some(mv) = self.nodes[node].mv else {
+            return;
+        };
+        let first = tokens.len();
+        tokens.push(Token {
+            text: move_number(board, true).unwrap_or_default(),
+            node: None,
+        });
+        tokens.push(Token {
+            text: self.move_text(board, node),
+            node: Some(node),
+        });
+        let note = comment(node);
+        let interrupted = note.is_some();
+        if let Some(note) = note {
+            tokens.push(Token {
+                text: format!("{{{}}}", note),
+                node: None,
+            });
+        }
+        let mut board = board.clone();
+        if board.make(mv).is_ok() {
+            self.write_line(node, &board, usize::MAX, interrupted, comment, tokens);
+        }
+        tokens[first].text.insert(0, '(');
+        if let Some(last) = tokens.last_mut() {
+            last.text.push(')');
+        }
+    }
+}
+
+// A NAG as it is written: the symbol of a move assessment, "$n" otherwise
+pub fn nag_text(nag: u8) -> String {
+    match NAG_SYMBOLS.get((nag as usize).wrapping_sub(1)) {
+        Some(symbol) => symbol.to_string(),
+        None => format!("${}", nag),
+    }
+}
+
+// A NAG from its symbol, such as "!?", or its number, as "$14" or "14"
+pub fn parse_nag(text: &str) -> Option<u8> {
+    if let Some(index) = NAG_SYMBOLS.iter().position(|&symbol| symbol == text) {
+        return Some(index as u8 + 1);
+    }
+    text.strip_prefix('$')
+        .unwrap_or(text)
+        .parse()
+        .ok()
+        .filter(|&nag| nag > 0)
+}
+
+// "12." before a white move; "12..." before a black one, or nothing when
+// it follows White's move on the same line
+fn move_number(board: &Game, interrupted: bool) -> Option<String> {
+    let number = board.get_fullmove_number();
+    match board.get_current_turn() {
+        ColorChess::White => Some(format!("{}.", number)),
+        ColorChess::Black if interrupted => Some(format!("{}...", number)),
+        ColorChess::Black => None,
+    }
+}
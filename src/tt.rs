@@ -0,0 +1,107 @@
+use crate::moves::Move;
+use crate::search::MATE_THRESHOLD;
+
+// How a stored score relates to the true score of the position
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Bound {
+    Exact,
+    // The search failed high: the true score is at least this
+    Lower,
+    // The search failed low: the true score is at most this
+    Upper,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TtEntry {
+    pub key: u64,
+    pub depth: u32,
+    pub score: i32,
+    pub bound: Bound,
+    pub best_move: Option<Move>,
+}
+
+// Fixed-size hash table of search results indexed by Zobrist key
+pub struct TranspositionTable {
+    entries: Vec<Option<TtEntry>>,
+}
+
+impl Default for TranspositionTable {
+    fn default() -> Self {
+        TranspositionTable::new(16)
+    }
+}
+
+impl TranspositionTable {
+    // Allocates the largest power-of-two number of entries that fits in size_mb
+    pub fn new(size_mb: usize) -> Self {
+        let entry_size = std::mem::size_of::<Option<TtEntry>>();
+        let wanted = (size_mb.max(1) * 1024 * 1024 / entry_size).max(1);
+        let len = if wanted.is_power_of_two() {
+            wanted
+        } else {
+            wanted.next_power_of_two() / 2
+        };
+        TranspositionTable {
+            entries: vec![None; len],
+        }
+    }
+
+    fn index(&self, key: u64) -> usize {
+        (key as usize) & (self.entries.len() - 1)
+    }
+
+    pub fn probe(&self, key: u64) -> Option<TtEntry> {
+        self.entries[self.index(key)].filter(|entry| entry.key == key)
+    }
+
+    // Keeps the existing entry only if it is for the same position and deeper
+    pub fn store(&mut self, entry: TtEntry) {
+        let index = self.index(entry.key);
+        if let Some(existing) = self.entries[index]
+            && existing.key == entry.key
+            && existing.depth > entry.depth
+        {
+            return;
+        }
+        self.entries[index] = Some(entry);
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.fill(None);
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    // The size in megabytes that `new` would make this table with
+    pub fn size_mb(&self) -> usize {
+        self.entries.len() * std::mem::size_of::<Option<TtEntry>>() / (1024 * 1024)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+// Mate scores count plies from the root; the table stores them counted from the
+// position itself so they stay valid wherever the position is reached
+pub(crate) fn score_to_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score + ply
+    } else if score <= -MATE_THRESHOLD {
+        score - ply
+    } else {
+        score
+    }
+}
+
+pub(crate) fn score_from_tt(score: i32, ply: i32) -> i32 {
+    if score >= MATE_THRESHOLD {
+        score - ply
+    } else if score <= -MATE_THRESHOLD {
+        score + ply
+    } else {
+        score
+    }
+}
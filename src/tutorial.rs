@@ -0,0 +1,150 @@
+use chess_rs::{ChessError, Game, Move, MoveFlags, PieceType, square_name};
+
+// What a lesson asks the player to do with their move
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Task {
+    // Move a piece to the named square, capturing whatever is there
+    Reach(&'static str),
+    Check,
+    Checkmate,
+    // Any legal move: only those get the king out of check
+    EscapeCheck,
+    CastleKingSide,
+    CastleQueenSide,
+    EnPassant,
+    Promote(PieceType),
+}
+
+// One step of the rules tutorial: a position, what it teaches, and an
+// exercise solved with a single move
+pub struct Lesson {
+    pub title: &'static str,
+    pub text: &'static str,
+    pub fen: &'static str,
+    pub task: Task,
+    // Shown after a move that does not do what was asked
+    pub hint: &'static str,
+}
+
+pub const LESSONS: [Lesson; 14] = [
+    Lesson {
+        title: "The rook",
+        text: "Rooks move any number of squares along a rank or file. Move the rook to a4.",
+        fen: "8/7k/8/8/3R4/8/8/K7 w - - 0 1",
+        task: Task::Reach("a4"),
+        hint: "Slide the rook along the fourth rank to a4.",
+    },
+    Lesson {
+        title: "The bishop",
+        text: "Bishops move any number of squares diagonally. Move the bishop to a7.",
+        fen: "8/7k/8/8/3B4/8/8/K7 w - - 0 1",
+        task: Task::Reach("a7"),
+        hint: "Go up and to the left: c5, b6, a7.",
+    },
+    Lesson {
+        title: "The queen",
+        text: "The queen moves like a rook and a bishop together. Move the queen to g1.",
+        fen: "8/7k/8/8/3Q4/8/8/K7 w - - 0 1",
+        task: Task::Reach("g1"),
+        hint: "g1 lies on the diagonal down and to the right: e3, f2, g1.",
+    },
+    Lesson {
+        title: "The knight",
+        text: "Knights jump in an L, two squares one way and one across, over anything in between. Move the knight to e6.",
+        fen: "8/7k/8/8/3N4/8/8/K7 w - - 0 1",
+        task: Task::Reach("e6"),
+        hint: "Two squares up and one to the right reaches e6.",
+    },
+    Lesson {
+        title: "The king",
+        text: "The king moves one square in any direction. Move the king to b2.",
+        fen: "8/7k/8/8/8/8/8/K7 w - - 0 1",
+        task: Task::Reach("b2"),
+        hint: "b2 is the square diagonally up and to the right of the king.",
+    },
+    Lesson {
+        title: "The pawn",
+        text: "Pawns move straight ahead one square, or two from their starting square. Move the pawn to e4.",
+        fen: "8/7k/8/8/8/8/4P3/K7 w - - 0 1",
+        task: Task::Reach("e4"),
+        hint: "From e2 the pawn may go two squares at once, to e4.",
+    },
+    Lesson {
+        title: "Capturing",
+        text: "A piece captures by moving onto an enemy piece; pawns capture one square diagonally forward. Take the pawn on d5.",
+        fen: "7k/8/8/3p4/4P3/8/8/K7 w - - 0 1",
+        task: Task::Reach("d5"),
+        hint: "Pawns cannot capture straight ahead: take diagonally, e4 to d5.",
+    },
+    Lesson {
+        title: "Check",
+        text: "A king attacked by an enemy piece is in check. Give check with the rook.",
+        fen: "8/7k/8/8/8/8/8/K2R4 w - - 0 1",
+        task: Task::Check,
+        hint: "Put the rook on the king's rank or file, such as d7 or h1.",
+    },
+    Lesson {
+        title: "Getting out of check",
+        text: "Your king is in check: you must move it, block the attack or capture the attacker. Get out of check.",
+        fen: "7k/8/8/8/8/8/8/K6r w - - 0 1",
+        task: Task::EscapeCheck,
+        hint: "Step off the first rank, where the rook attacks.",
+    },
+    Lesson {
+        title: "Checkmate",
+        text: "Check that cannot be escaped is checkmate, and wins the game. Checkmate the black king.",
+        fen: "6k1/5ppp/8/8/8/8/8/R5K1 w - - 0 1",
+        task: Task::Checkmate,
+        hint: "The king is shut in by its own pawns. Attack it along the back rank.",
+    },
+    Lesson {
+        title: "Castling kingside",
+        text: "Castling moves the king two squares towards a rook, which jumps over it. Castle kingside by moving the king to g1.",
+        fen: "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+        task: Task::CastleKingSide,
+        hint: "Move the king from e1 to g1; the rook comes to f1 by itself.",
+    },
+    Lesson {
+        title: "Castling queenside",
+        text: "Neither the king nor the rook may have moved, and the king may not castle out of, through or into check. Castle queenside.",
+        fen: "r3k2r/8/8/8/8/8/8/R3K2R w KQkq - 0 1",
+        task: Task::CastleQueenSide,
+        hint: "Move the king from e1 to c1; the rook comes to d1 by itself.",
+    },
+    Lesson {
+        title: "En passant",
+        text: "Black's pawn just moved d7-d5, past yours. Right after such a move, a pawn may capture it as if it had moved one square. Take en passant.",
+        fen: "7k/8/8/3pP3/8/8/8/K7 w - d6 0 2",
+        task: Task::EnPassant,
+        hint: "Move the e5 pawn to d6; the pawn on d5 is taken.",
+    },
+    Lesson {
+        title: "Promotion",
+        text: "A pawn reaching the last rank becomes a queen, rook, bishop or knight. Promote the pawn to a queen.",
+        fen: "7k/4P3/8/8/8/8/8/K7 w - - 0 1",
+        task: Task::Promote(PieceType::Queen),
+        hint: "Push the pawn to e8 and pick the queen.",
+    },
+];
+
+impl Lesson {
+    pub fn board(&self) -> Result<Game, ChessError> {
+        Game::from_fen(self.fen)
+    }
+
+    // Whether a legal move does what the lesson asks; `after` is the
+    // position it leads to
+    pub fn is_solved_by(&self, mv: Move, after: &Game) -> bool {
+        let to_move = after.get_current_turn();
+        match self.task {
+            Task::Reach(square) => square_name(mv.to) == square,
+            Task::Check => after.is_in_check(to_move),
+            Task::Checkmate => after.is_checkmate(to_move),
+            Task::EscapeCheck => true,
+            Task::CastleKingSide => mv.flags.contains(MoveFlags::CASTLE_KING_SIDE),
+            Task::CastleQueenSide => mv.flags.contains(MoveFlags::CASTLE_QUEEN_SIDE),
+            Task::EnPassant => mv.flags.contains(MoveFlags::EN_PASSANT),
+            Task::Promote(piece_type) => mv.promotion == Some(piece_type),
+        }
+    }
+}
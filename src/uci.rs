@@ -0,0 +1,376 @@
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::{
+    Arc,
+    atomic::{AtomicBool, Ordering},
+    mpsc::{self, Receiver, RecvTimeoutError},
+};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::ChessError;
+use crate::moves::{Move, PROMOTION_PIECES};
+use crate::position::Position;
+use crate::search::{MATE_SCORE, MAX_DEPTH, SearchLimits, SearchLine, SearchResult};
+
+// How long an engine may take to answer `uci` and `isready`
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Extra time an engine gets past its move time before it is told to stop, and
+// again after that before it is given up on
+const GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+// How often a running search checks the stop signal
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+// An external engine such as Stockfish, driven over the Universal Chess Interface
+pub struct UciEngine {
+    name: String,
+    child: Child,
+    stdin: ChildStdin,
+    // Lines of the engine's output, read by a background thread
+    lines: Receiver<String>,
+    stop_signal: Option<Arc<AtomicBool>>,
+    // MultiPV value last sent to the engine
+    multi_pv: usize,
+}
+
+impl UciEngine {
+    // Starts the engine and waits for it to finish the `uci` handshake
+    pub fn spawn(path: &str, args: &[String]) -> Result<UciEngine, ChessError> {
+        let mut child = Command::new(path)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| ChessError::Engine(format!("{}: {}", path, e)))?;
+        let (Some(stdin), Some(stdout)) = (child.stdin.take(), child.stdout.take()) else {
+            let _ = child.kill();
+            return Err(ChessError::Engine(format!(
+                "{}: could not open pipes",
+                path
+            )));
+        };
+
+        let (line_tx, lines) = mpsc::channel();
+        thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if line_tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut engine = UciEngine {
+            name: path.to_string(),
+            child,
+            stdin,
+            lines,
+            stop_signal: None,
+            multi_pv: 1,
+        };
+        engine.send("uci")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        loop {
+            let line = engine.read_line(deadline)?;
+            if let Some(name) = line.strip_prefix("id name ") {
+                engine.name = name.trim().to_string();
+            } else if line.trim() == "uciok" {
+                break;
+            }
+        }
+        engine.sync()?;
+        Ok(engine)
+    }
+
+    // Name the engine reported during the handshake
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn set_option(&mut self, name: &str, value: &str) -> Result<(), ChessError> {
+        self.send(&format!("setoption name {} value {}", name, value))?;
+        self.sync()
+    }
+
+    // Tells the engine that the next search belongs to a different game
+    pub fn new_game(&mut self) -> Result<(), ChessError> {
+        self.send("ucinewgame")?;
+        self.sync()
+    }
+
+    // Lets another thread cut a running search short by setting the flag
+    pub fn set_stop_signal(&mut self, signal: Option<Arc<AtomicBool>>) {
+        self.stop_signal = signal;
+    }
+
+    pub fn search(
+        &mut self,
+        board: &Position,
+        limits: SearchLimits,
+    ) -> Result<SearchResult, ChessError> {
+        self.search_with_progress(board, limits, |_| {})
+    }
+
+    // Like search, but calls on_info whenever the engine reports a new principal
+    // variation. Randomness and eval noise in the limits are ignored
+    pub fn search_with_progress(
+        &mut self,
+        board: &Position,
+        limits: SearchLimits,
+        mut on_info: impl FnMut(&SearchResult),
+    ) -> Result<SearchResult, ChessError> {
+        // Output left over from an abandoned search must not be read as this one's
+        while self.lines.try_recv().is_ok() {}
+
+        let multi_pv = limits.multi_pv.max(1);
+        if multi_pv != self.multi_pv {
+            self.set_option("MultiPV", &multi_pv.to_string())?;
+            self.multi_pv = multi_pv;
+        }
+
+        self.send(&format!("position fen {}", board.to_fen()))?;
+        self.send(&go_command(limits))?;
+
+        let start = Instant::now();
+        let stop_at = limits.time_limit.map(|time| start + time + GRACE_PERIOD);
+        let mut give_up_at = None;
+        let mut result = SearchResult {
+            best_move: None,
+            score: 0,
+            depth: 0,
+            nodes: 0,
+            elapsed: Duration::ZERO,
+            pv: Vec::new(),
+            lines: Vec::new(),
+        };
+
+        loop {
+            let stop_requested = self
+                .stop_signal
+                .as_ref()
+                .is_some_and(|signal| signal.load(Ordering::Relaxed));
+            if give_up_at.is_none()
+                && (stop_requested || stop_at.is_some_and(|time| Instant::now() >= time))
+            {
+                self.send("stop")?;
+                give_up_at = Some(Instant::now() + GRACE_PERIOD);
+            }
+            if give_up_at.is_some_and(|time| Instant::now() >= time) {
+                return Err(ChessError::Engine(format!(
+                    "{} did not stop searching",
+                    self.name
+                )));
+            }
+
+            let line = match self.lines.recv_timeout(POLL_INTERVAL) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => continue,
+                Err(RecvTimeoutError::Disconnected) => {
+                    return Err(ChessError::Engine(format!("{} exited", self.name)));
+                }
+            };
+            let mut words = line.split_whitespace();
+            match words.next() {
+                Some("info") if parse_info(board, &mut words, &mut result) => {
+                    result.elapsed = start.elapsed();
+                    on_info(&result);
+                }
+                Some("bestmove") => {
+                    result.elapsed = start.elapsed();
+                    result.best_move = words
+                        .next()
+                        .and_then(|text| board.parse_uci_move(text).ok());
+                    if result.pv.first() != result.best_move.as_ref() {
+                        result.pv = result.best_move.into_iter().collect();
+                        result.lines = vec![SearchLine {
+                            score: result.score,
+                            pv: result.pv.clone(),
+                        }];
+                    }
+                    return Ok(result);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn send(&mut self, command: &str) -> Result<(), ChessError> {
+        writeln!(self.stdin, "{}", command)
+            .and_then(|_| self.stdin.flush())
+            .map_err(|e| ChessError::Engine(format!("{}: {}", self.name, e)))
+    }
+
+    fn read_line(&self, deadline: Instant) -> Result<String, ChessError> {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        self.lines.recv_timeout(timeout).map_err(|e| {
+            ChessError::Engine(match e {
+                RecvTimeoutError::Timeout => format!("{} did not answer in time", self.name),
+                RecvTimeoutError::Disconnected => format!("{} exited", self.name),
+            })
+        })
+    }
+
+    // Waits until the engine has processed everything sent so far
+    fn sync(&mut self) -> Result<(), ChessError> {
+        self.send("isready")?;
+        let deadline = Instant::now() + HANDSHAKE_TIMEOUT;
+        while self.read_line(deadline)?.trim() != "readyok" {}
+        Ok(())
+    }
+}
+
+impl Drop for UciEngine {
+    fn drop(&mut self) {
+        let _ = self.send("quit");
+        // Give the engine a moment to exit on its own before killing it
+        let deadline = Instant::now() + Duration::from_millis(500);
+        while Instant::now() < deadline {
+            if let Ok(Some(_)) = self.child.try_wait() {
+                return;
+            }
+            thread::sleep(POLL_INTERVAL);
+        }
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+impl Position {
+    // Finds the legal move written in UCI notation, e.g. "e2e4" or "e7e8q"
+    pub fn parse_uci_move(&self, text: &str) -> Result<Move, ChessError> {
+        let wanted = self.build_uci_move(text)?;
+        self.get_all_legal_moves(self.current_turn)
+            .into_iter()
+            .find(|mv| {
+                mv.from == wanted.from && mv.to == wanted.to && mv.promotion == wanted.promotion
+            })
+            .ok_or_else(|| ChessError::IllegalMove {
+                mv: text.to_string(),
+                fen: self.to_fen(),
+            })
+    }
+
+    // Reads a move in UCI notation and classifies it like build_move, without
+    // checking that it is legal; for moves that were checked elsewhere
+    pub fn build_uci_move(&self, text: &str) -> Result<Move, ChessError> {
+        let not_uci = || ChessError::BadUci(text.to_string());
+        if !text.is_ascii() || !(4..=5).contains(&text.len()) {
+            return Err(not_uci());
+        }
+        let from = self.parse_move(&text[0..2]).map_err(|_| not_uci())?;
+        let to = self.parse_move(&text[2..4]).map_err(|_| not_uci())?;
+        let mv = self.build_move(from, to)?;
+        match text[4..].chars().next() {
+            Some(letter) => PROMOTION_PIECES
+                .into_iter()
+                .find(|piece_type| piece_type.letter() == letter.to_ascii_uppercase())
+                .map(|piece_type| mv.with_promotion(piece_type))
+                .ok_or_else(not_uci),
+            None => Ok(mv),
+        }
+    }
+}
+
+fn go_command(limits: SearchLimits) -> String {
+    let mut command = "go".to_string();
+    if limits.time_limit.is_none() || limits.max_depth < MAX_DEPTH {
+        command += &format!(" depth {}", limits.max_depth);
+    }
+    if let Some(time) = limits.time_limit {
+        command += &format!(" movetime {}", time.as_millis().max(1));
+    }
+    command
+}
+
+// Copies the depth, score, nodes and PV of an `info` line into result, or into
+// one of its lines for `multipv` reports past the first; returns false for
+// lines without a PV, such as current move reports
+fn parse_info<'a>(
+    board: &Position,
+    mut words: impl Iterator<Item = &'a str>,
+    result: &mut SearchResult,
+) -> bool {
+    let mut depth = None;
+    let mut score = None;
+    let mut nodes = None;
+    let mut pv = None;
+    let mut line: usize = 1;
+    while let Some(word) = words.next() {
+        match word {
+            "depth" => depth = words.next().and_then(|w| w.parse().ok()),
+            "nodes" => nodes = words.next().and_then(|w| w.parse().ok()),
+            "score" => {
+                score = match (words.next(), words.next().and_then(|w| w.parse().ok())) {
+                    (Some("cp"), Some(cp)) => Some(cp),
+                    (Some("mate"), Some(moves)) => Some(mate_score(moves)),
+                    _ => None,
+                }
+            }
+            "multipv" => line = words.next().and_then(|w| w.parse().ok()).unwrap_or(1),
+            "pv" => {
+                pv = Some(parse_line(board, &mut words));
+                break;
+            }
+            "string" => break,
+            _ => {}
+        }
+    }
+
+    let Some(pv) = pv.filter(|pv| !pv.is_empty()) else {
+        return false;
+    };
+    let Some(index) = line
+        .checked_sub(1)
+        .filter(|&index| index <= result.lines.len())
+    else {
+        return false;
+    };
+    let score = score.unwrap_or(result.score);
+    let entry = SearchLine {
+        score,
+        pv: pv.clone(),
+    };
+    match result.lines.get_mut(index) {
+        Some(old) => *old = entry,
+        None => result.lines.push(entry),
+    }
+    if index > 0 {
+        return true;
+    }
+    result.pv = pv;
+    result.depth = depth.unwrap_or(result.depth);
+    result.score = score;
+    result.nodes = nodes.unwrap_or(result.nodes);
+    true
+}
+
+// Plays the line out on a copy of the position, stopping at the first move
+// that is not legal
+fn parse_line<'a>(board: &Position, words: impl Iterator<Item = &'a str>) -> Vec<Move> {
+    let mut board = *board;
+    let mut line = Vec::new();
+    for word in words {
+        let Ok(mv) = board.parse_uci_move(word) else {
+            break;
+        };
+        if board.make(mv).is_err() {
+            break;
+        }
+        line.push(mv);
+    }
+    line
+}
+
+// Converts "mate N" into the search's scale, where mating in fewer plies scores higher
+fn mate_score(moves: i32) -> i32 {
+    if moves > 0 {
+        MATE_SCORE - (2 * moves - 1)
+    } else {
+        -MATE_SCORE - 2 * moves
+    }
+}
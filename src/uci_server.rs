@@ -0,0 +1,355 @@
+use std::{
+    io::{self, BufRead},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::Duration,
+};
+
+use chess_rs::search::{DEFAULT_MOVES_TO_GO, MATE_SCORE, MATE_THRESHOLD, MAX_DEPTH};
+use chess_rs::{ColorChess, Game, SearchLimits, SearchLine, SearchResult, Searcher, Tablebase};
+
+const DEFAULT_HASH_MB: usize = 16;
+const MAX_HASH_MB: usize = 1024;
+const MAX_MULTI_PV: usize = 64;
+const MAX_THREADS: usize = 64;
+
+// A `go` running on its own thread, so that `stop` can still be read meanwhile
+struct RunningSearch {
+    handle: JoinHandle<Searcher>,
+    stop: Arc<AtomicBool>,
+    // Set during `go ponder` until `ponderhit`
+    ponder: Arc<AtomicBool>,
+}
+
+// Plays the engine side of the Universal Chess Interface on stdin/stdout, for
+// GUIs such as Arena or cutechess-cli
+struct UciServer {
+    // None after a `position` that could not be read, until the next one
+    board: Option<Game>,
+    // None while a search has it
+    searcher: Option<Searcher>,
+    running: Option<RunningSearch>,
+    // From the SyzygyPath option; kept for searchers made later
+    tablebase: Option<Arc<Tablebase>>,
+    multi_pv: usize,
+    threads: usize,
+}
+
+// Reads commands until `quit` or the end of input
+pub fn run() -> io::Result<()> {
+    let mut server = UciServer::new();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.first().copied() {
+            Some("uci") => {
+                println!("id name Chess-rs {}", env!("CARGO_PKG_VERSION"));
+                println!("id author Harshit Dhanwalkar");
+                println!(
+                    "option name Hash type spin default {} min 1 max {}",
+                    DEFAULT_HASH_MB, MAX_HASH_MB
+                );
+                println!(
+                    "option name Threads type spin default 1 min 1 max {}",
+                    MAX_THREADS
+                );
+                println!(
+                    "option name MultiPV type spin default 1 min 1 max {}",
+                    MAX_MULTI_PV
+                );
+                println!("option name Ponder type check default false");
+                println!("option name SyzygyPath type string default <empty>");
+                println!("uciok");
+            }
+            Some("isready") => println!("readyok"),
+            Some("setoption") => server.set_option(&words[1..]),
+            Some("ucinewgame") => server.searcher().clear(),
+            Some("position") => {
+                server.finish_search();
+                if let Err(e) = server.set_position(&words[1..]) {
+                    println!("info string {}", e);
+                }
+            }
+            Some("go") => server.go(&words[1..]),
+            Some("stop") => server.finish_search(),
+            // The predicted move was played: the search goes on, now on the clock
+            Some("ponderhit") => {
+                if let Some(running) = &server.running {
+                    running.ponder.store(false, Ordering::Relaxed);
+                }
+            }
+            Some("quit") => break,
+            _ => {}
+        }
+    }
+    server.finish_search();
+    Ok(())
+}
+
+impl UciServer {
+    fn new() -> UciServer {
+        UciServer {
+            board: Some(Game::new()),
+            searcher: Some(Searcher::new()),
+            running: None,
+            tablebase: None,
+            multi_pv: 1,
+            threads: 1,
+        }
+    }
+
+    // Stops a running search, which then prints its bestmove, and takes the searcher back
+    fn finish_search(&mut self) {
+        if let Some(running) = self.running.take() {
+            running.stop.store(true, Ordering::Relaxed);
+            self.searcher = running.handle.join().ok();
+        }
+    }
+
+    fn searcher(&mut self) -> &mut Searcher {
+        self.finish_search();
+        self.searcher.get_or_insert_with(Searcher::new)
+    }
+
+    fn set_option(&mut self, words: &[&str]) {
+        // setoption name <id> value <x>
+        let name = words.iter().position(|&w| w == "name");
+        let value = words.iter().position(|&w| w == "value");
+        let (Some(name), Some(value)) = (name, value) else {
+            return;
+        };
+        let option = words[name + 1..value].join(" ");
+        if option.eq_ignore_ascii_case("hash") {
+            let size_mb = words
+                .get(value + 1)
+                .and_then(|w| w.parse::<usize>().ok())
+                .unwrap_or(DEFAULT_HASH_MB)
+                .clamp(1, MAX_HASH_MB);
+            self.finish_search();
+            let mut searcher = Searcher::with_tt_size(size_mb);
+            searcher.set_tablebase(self.tablebase.clone());
+            searcher.set_threads(self.threads);
+            self.searcher = Some(searcher);
+        } else if option.eq_ignore_ascii_case("threads") {
+            self.threads = words
+                .get(value + 1)
+                .and_then(|w| w.parse::<usize>().ok())
+                .unwrap_or(1)
+                .clamp(1, MAX_THREADS);
+            let threads = self.threads;
+            self.searcher().set_threads(threads);
+        } else if option.eq_ignore_ascii_case("multipv") {
+            self.multi_pv = words
+                .get(value + 1)
+                .and_then(|w| w.parse::<usize>().ok())
+                .unwrap_or(1)
+                .clamp(1, MAX_MULTI_PV);
+        } else if option.eq_ignore_ascii_case("syzygypath") {
+            let path = words[value + 1..].join(" ");
+            self.tablebase = match path.as_str() {
+                "" | "<empty>" => None,
+                path => match Tablebase::open(path) {
+                    Ok(tablebase) => {
+                        println!(
+                            "info string Syzygy tables up to {} pieces",
+                            tablebase.max_pieces()
+                        );
+                        Some(Arc::new(tablebase))
+                    }
+                    Err(e) => {
+                        println!("info string {}", e);
+                        None
+                    }
+                },
+            };
+            let tablebase = self.tablebase.clone();
+            self.searcher().set_tablebase(tablebase);
+        }
+    }
+
+    // position [startpos | fen <fen>] [moves <move>...]; the moves before an
+    // illegal one are kept, as other engines do, and a position that cannot be
+    // read leaves none, so `go` does not answer for the one before
+    fn set_position(&mut self, words: &[&str]) -> Result<(), String> {
+        self.board = None;
+        let moves_at = words
+            .iter()
+            .position(|&w| w == "moves")
+            .unwrap_or(words.len());
+        let mut board = match words.first() {
+            Some(&"startpos") => Game::new(),
+            Some(&"fen") => Game::from_fen(&words[1..moves_at].join(" "))?,
+            _ => return Err("position expects 'startpos' or 'fen'".to_string()),
+        };
+        let mut played = Ok(());
+        for text in words.iter().skip(moves_at + 1) {
+            match board
+                .parse_uci_move(text)
+                .and_then(|mv| board.play_move(mv))
+            {
+                Ok(()) => {}
+                Err(e) => {
+                    played = Err(e.to_string());
+                    break;
+                }
+            }
+        }
+        self.board = Some(board);
+        played
+    }
+
+    fn go(&mut self, words: &[&str]) {
+        self.finish_search();
+        let Some(board) = self.board.clone() else {
+            println!("info string no position to search");
+            println!("bestmove 0000");
+            return;
+        };
+        let mut searcher = self.searcher.take().unwrap_or_else(|| {
+            let mut searcher = Searcher::new();
+            searcher.set_tablebase(self.tablebase.clone());
+            searcher.set_threads(self.threads);
+            searcher
+        });
+        let (mut limits, infinite) = parse_go(words, board.get_current_turn());
+        limits.multi_pv = self.multi_pv;
+        let stop = Arc::new(AtomicBool::new(false));
+        let ponder = Arc::new(AtomicBool::new(words.contains(&"ponder")));
+        let (thread_stop, thread_ponder) = (stop.clone(), ponder.clone());
+        let handle = thread::spawn(move || {
+            searcher.set_stop_signal(Some(thread_stop.clone()));
+            searcher.set_ponder_signal(Some(thread_ponder.clone()));
+            let result = searcher.search_with_progress(&board, limits, |result| {
+                for (index, line) in result.lines.iter().enumerate() {
+                    println!("{}", info_line(result, index + 1, line));
+                }
+            });
+            // With `go infinite` the bestmove must wait for `stop`, even after a
+            // mate is found, and while pondering it waits for `ponderhit` as well
+            while (infinite || thread_ponder.load(Ordering::Relaxed))
+                && !thread_stop.load(Ordering::Relaxed)
+            {
+                thread::sleep(Duration::from_millis(10));
+            }
+            match (result.best_move, result.pv.get(1)) {
+                (Some(mv), Some(reply)) => {
+                    println!("bestmove {} ponder {}", mv.to_uci(), reply.to_uci())
+                }
+                (Some(mv), None) => println!("bestmove {}", mv.to_uci()),
+                (None, _) => println!("bestmove 0000"),
+            }
+            searcher.set_stop_signal(None);
+            searcher.set_ponder_signal(None);
+            searcher
+        });
+        self.running = Some(RunningSearch {
+            handle,
+            stop,
+            ponder,
+        });
+    }
+}
+
+// Turns the `go` parameters into search limits; also returns whether the
+// search is infinite
+fn parse_go(words: &[&str], side: ColorChess) -> (SearchLimits, bool) {
+    let mut limits = SearchLimits::depth(MAX_DEPTH);
+    let mut infinite = false;
+    let (mut time, mut increment, mut moves_to_go) = (None, 0, DEFAULT_MOVES_TO_GO as u64);
+    let mut words = words.iter();
+    while let Some(&word) = words.next() {
+        let mut number = || words.next().and_then(|w| w.parse::<u64>().ok());
+        match (word, side) {
+            ("depth", _) => {
+                if let Some(depth) = number() {
+                    limits.max_depth = (depth as u32).clamp(1, MAX_DEPTH);
+                }
+            }
+            ("movetime", _) => limits.time_limit = number().map(Duration::from_millis),
+            ("wtime", ColorChess::White) | ("btime", ColorChess::Black) => time = number(),
+            ("winc", ColorChess::White) | ("binc", ColorChess::Black) => {
+                increment = number().unwrap_or(0)
+            }
+            ("movestogo", _) => moves_to_go = number().unwrap_or(moves_to_go),
+            ("infinite", _) => infinite = true,
+            _ => {}
+        }
+    }
+    if limits.time_limit.is_none()
+        && !infinite
+        && let Some(time) = time
+    {
+        limits.time_limit = SearchLimits::clock(
+            Duration::from_millis(time),
+            Duration::from_millis(increment),
+            moves_to_go as u32,
+        )
+        .time_limit;
+    }
+    (limits, infinite)
+}
+
+fn info_line(result: &SearchResult, multi_pv: usize, line: &SearchLine) -> String {
+    let pv: Vec<String> = line.pv.iter().map(|mv| mv.to_uci()).collect();
+    format!(
+        "info depth {} multipv {} score {} nodes {} nps {} time {} pv {}",
+        result.depth,
+        multi_pv,
+        uci_score(line.score),
+        result.nodes,
+        result.nodes_per_second(),
+        result.elapsed.as_millis(),
+        pv.join(" ")
+    )
+}
+
+// "cp <centipawns>" or "mate <moves>", negative when the engine is getting mated
+fn uci_score(score: i32) -> String {
+    if score >= MATE_THRESHOLD {
+        format!("mate {}", (MATE_SCORE - score + 1) / 2)
+    } else if score <= -MATE_THRESHOLD {
+        format!("mate -{}", (MATE_SCORE + score) / 2)
+    } else {
+        format!("cp {}", score)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fen(server: &UciServer) -> Option<String> {
+        server.board.as_ref().map(|board| board.to_fen())
+    }
+
+    #[test]
+    fn moves_before_an_illegal_one_are_played() {
+        let mut server = UciServer::new();
+        server
+            .set_position(&["startpos", "moves", "e2e4", "e7e5"])
+            .unwrap();
+        assert!(
+            server
+                .set_position(&["startpos", "moves", "e2e4", "e2e5", "g1f3"])
+                .is_err()
+        );
+        assert_eq!(
+            fen(&server).as_deref(),
+            Some("rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1")
+        );
+    }
+
+    #[test]
+    fn an_unreadable_position_leaves_none() {
+        let mut server = UciServer::new();
+        assert!(server.set_position(&["fen", "8/8/8", "w"]).is_err());
+        assert_eq!(fen(&server), None);
+        assert!(server.set_position(&["moves", "e2e4"]).is_err());
+        assert_eq!(fen(&server), None);
+        server.set_position(&["startpos"]).unwrap();
+        assert_eq!(fen(&server), Some(Game::new().to_fen()));
+    }
+}
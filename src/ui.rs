@@ -0,0 +1,2011 @@
+use std::time::Duration;
+
+use chess_rs::clock::{Clock, format_clock, format_move_time};
+use chess_rs::difficulty::DIFFICULTIES;
+use chess_rs::search::{MATE_SCORE, MATE_THRESHOLD, TB_WIN_THRESHOLD};
+use chess_rs::{ColorChess, Game, PROMOTION_PIECES, Piece, PieceType, square_name};
+use tui::{
+    Frame,
+    backend::Backend,
+    layout::{Alignment, Rect},
+    style::{Color, Modifier, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Clear, Paragraph, Wrap},
+};
+
+use crate::app::{
+    App, COLOR_CHOICES, MenuItem, Prompt, QUIT_SAVE_PATH, Screen, display_to_square, format_score,
+    promotion_key,
+};
+use crate::arrow::Arrow;
+use crate::endgame::ENDGAMES;
+use crate::keymap::Action;
+use crate::layout::{BoardLayout, min_size};
+use crate::puzzle::MOTIFS;
+use crate::ratings::{self, Rating};
+use crate::review::{VERDICTS, Verdict};
+use crate::theme::Palette;
+use crate::tutorial::{LESSONS, Lesson};
+
+// Foreground color of the given side's pieces
+fn piece_color(palette: &Palette, color: ColorChess) -> Color {
+    match color {
+        ColorChess::White => palette.white_piece,
+        ColorChess::Black => palette.black_piece,
+    }
+}
+
+// Returns a width x height rectangle centered in area, clamped to fit inside it
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let width = width.min(area.width);
+    let height = height.min(area.height);
+    Rect::new(
+        area.x + (area.width - width) / 2,
+        area.y + (area.height - height) / 2,
+        width,
+        height,
+    )
+}
+
+// --- TUI Drawing Functions ---
+pub fn ui<B: Backend>(f: &mut Frame<B>, app: &App) {
+    match app.screen {
+        Screen::MainMenu => draw_menu(f, app),
+        Screen::Settings => draw_settings(f, app),
+        Screen::ColorSelect => draw_color_select(f, app),
+        Screen::Correspondence => draw_correspondence(f, app),
+        Screen::Broadcast => draw_broadcast(f, app),
+        Screen::Tactics => draw_tactics(f, app),
+        Screen::Review => draw_review(f, app),
+        Screen::Games => draw_games(f, app),
+        Screen::Explorer => draw_explorer(f, app),
+        Screen::Ratings => draw_ratings(f, app),
+        Screen::Editor => match app.layout {
+            Some(layout) => draw_editor(f, app, &layout),
+            None => draw_too_small(f, app),
+        },
+        Screen::Game => match app.layout {
+            Some(layout) => draw_game(f, app, &layout),
+            None => draw_too_small(f, app),
+        },
+    }
+    if app.confirm_quit {
+        draw_quit_confirmation(f, app);
+    }
+}
+
+// Modal asking whether to quit a game that is still being played
+fn draw_quit_confirmation<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let palette = app.settings.theme.palette();
+    let key_style = Style::default()
+        .fg(palette.accent)
+        .add_modifier(Modifier::BOLD);
+    let text_style = Style::default().fg(palette.text);
+    let option = |keys: String, text: String| {
+        Spans::from(vec![
+            Span::styled(format!(" {:<8}", keys), key_style),
+            Span::styled(text, text_style),
+        ])
+    };
+    let lines = vec![
+        Spans::from(Span::styled(
+            " The game in progress will be lost.",
+            text_style,
+        )),
+        Spans::from(""),
+        option(app.keys.describe(Action::Yes), "Quit".to_string()),
+        option(
+            "s".to_string(),
+            format!("Save it to {} and quit", QUIT_SAVE_PATH),
+        ),
+        option(app.keys.describe(Action::No), "Keep playing".to_string()),
+    ];
+    let popup_area = centered_rect(44, lines.len() as u16 + 2, f.size());
+    let popup =
+        Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" Quit? "));
+    f.render_widget(Clear, popup_area);
+    f.render_widget(popup, popup_area);
+}
+
+// Shown instead of the game while the terminal cannot fit the board
+fn draw_too_small<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let palette = app.settings.theme.palette();
+    let size = f.size();
+    // With squares sized automatically the smallest ones are the last resort
+    let square_height = app.square_height().unwrap_or(1);
+    let (min_width, min_height) = min_size(square_height);
+    let hint = if app.square_height().is_some() {
+        "Enlarge the window, pick a smaller square size in the settings, or Esc for the menu"
+    } else {
+        "Enlarge the window, or Esc for the menu"
+    };
+    let lines = vec![
+        Spans::from(Span::styled(
+            "Terminal too small",
+            Style::default()
+                .fg(palette.accent)
+                .add_modifier(Modifier::BOLD),
+        )),
+        Spans::from(format!(
+            "Resize to at least {}x{} (now {}x{})",
+            min_width, min_height, size.width, size.height
+        )),
+        Spans::from(Span::styled(hint, Style::default().fg(palette.dim))),
+    ];
+    let area = centered_rect(size.width, lines.len() as u16, size);
+    f.render_widget(
+        Paragraph::new(lines)
+            .alignment(Alignment::Center)
+            .wrap(Wrap { trim: true }),
+        area,
+    );
+}
+
+fn draw_menu<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let palette = app.settings.theme.palette();
+    let items = app.menu_items();
+    let selected = app.menu_index.min(items.len() - 1);
+    let mut lines = vec![
+        Spans::from(Span::styled(
+            "♚ Chess-rs ♔",
+            Style::default()
+                .fg(palette.accent)
+                .add_modifier(Modifier::BOLD),
+        )),
+        Spans::from(""),
+    ];
+    for (i, item) in items.iter().enumerate() {
+        let mut style = Style::default();
+        if i == selected {
+            style = style.add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        // Correspondence games waiting for a move are counted in the menu,
+        // the daily puzzle shows the streak and playing the AI your rating
+        // against it once it has one
+        let daily = &app.puzzles.daily;
+        let against_ai = app.ratings.against_ai(app.settings.difficulty);
+        let label = match app.correspondence.waiting() {
+            waiting if *item == MenuItem::Correspondence && waiting > 0 => {
+                format!("{} ({})", item.label(), waiting)
+            }
+            _ if *item == MenuItem::DailyPuzzle && daily.current_streak() > 0 => {
+                let done = if daily.solved_today() { " ✓" } else { "" };
+                format!(
+                    "{} (streak {}){}",
+                    item.label(),
+                    daily.current_streak(),
+                    done
+                )
+            }
+            _ if *item == MenuItem::VsAi && against_ai.games() > 0 => {
+                format!("{} ({})", item.label(), against_ai.rating)
+            }
+            _ => item.label().to_string(),
+        };
+        lines.push(Spans::from(Span::styled(format!("  {:<16}", label), style)));
+    }
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        "↑/↓ move  Enter select  q quit",
+        Style::default().fg(palette.dim),
+    )));
+
+    let menu_area = centered_rect(36, lines.len() as u16 + 2, f.size());
+    let menu = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title(" Main Menu "));
+    f.render_widget(menu, menu_area);
+    draw_message_line(f, app);
+}
+
+fn draw_settings<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let palette = app.settings.theme.palette();
+    let mut lines = vec![Spans::from("")];
+    for (i, (label, value)) in app.settings.entries().iter().enumerate() {
+        let mut style = Style::default();
+        if i == app.settings_index {
+            style = style.add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        lines.push(Spans::from(Span::styled(
+            format!("  {:<24}{:>14}  ", label, value),
+            style,
+        )));
+    }
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        "↑/↓ move  Enter change  Esc back",
+        Style::default().fg(palette.dim),
+    )));
+
+    let settings_area = centered_rect(46, lines.len() as u16 + 2, f.size());
+    let settings = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title(" Settings "));
+    f.render_widget(settings, settings_area);
+    draw_message_line(f, app);
+}
+
+fn draw_correspondence<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let palette = app.settings.theme.palette();
+    let games = app.correspondence.games();
+    let mut lines = vec![Spans::from("")];
+    if games.is_empty() {
+        lines.push(Spans::from("No correspondence games yet."));
+    }
+    for (i, game) in games.iter().enumerate() {
+        let mut style = Style::default();
+        if game.my_turn {
+            style = style.fg(palette.accent);
+        }
+        if i == app.correspondence_index {
+            style = style.add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        let turn = if game.my_turn { "Your move" } else { "Waiting" };
+        lines.push(Spans::from(Span::styled(
+            format!(
+                "  {:<10} vs {:<20} {:<6}",
+                turn,
+                game.opponent,
+                format!("{:?}", game.color)
+            ),
+            style,
+        )));
+    }
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        "↑/↓ move  Enter open  s new game  Esc back",
+        Style::default().fg(palette.dim),
+    )));
+
+    let area = centered_rect(50, lines.len() as u16 + 2, f.size());
+    let list = Paragraph::new(lines).alignment(Alignment::Center).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Correspondence "),
+    );
+    f.render_widget(list, area);
+    draw_message_line(f, app);
+}
+
+// The games in the database, newest first, with their date, opponent,
+// opening and result
+fn draw_games<B: Backend>(f: &mut Frame<B>, app: &App) {
+    const WIDTH: u16 = 84;
+    let palette = app.settings.theme.palette();
+    let row = |date: &str, opponent: &str, opening: &str, moves: &str, result: &str| {
+        let cut = |text: &str, width: usize| text.chars().take(width).collect::<String>();
+        format!(
+            "  {:<10} {:<18} {:<32} {:>5} {:<7}  ",
+            date,
+            cut(opponent, 18),
+            cut(opening, 32),
+            moves,
+            result
+        )
+    };
+    let mut lines = vec![Spans::from("")];
+    if app.games.len() == 0 {
+        lines.push(Spans::from("No finished games yet."));
+    } else {
+        lines.push(Spans::from(Span::styled(
+            row("Date", "Opponent", "Opening", "Moves", "Result"),
+            Style::default().fg(palette.dim),
+        )));
+    }
+    // Only as many as fit, scrolled to keep the one picked in view
+    let rows = f.size().height.saturating_sub(9).max(1) as usize;
+    let first = app.games_index.saturating_sub(rows - 1);
+    for (i, game) in app.games.games().enumerate().skip(first).take(rows) {
+        let mut style = Style::default();
+        if i == app.games_index {
+            style = style.add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        lines.push(Spans::from(Span::styled(
+            row(
+                &game.date,
+                &game.opponent,
+                game.opening.as_deref().unwrap_or("-"),
+                &game.moves.to_string(),
+                &game.outcome,
+            ),
+            style,
+        )));
+    }
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        "↑/↓ move  Enter replay  Esc back",
+        Style::default().fg(palette.dim),
+    )));
+
+    let area = centered_rect(WIDTH, lines.len() as u16 + 2, f.size());
+    let list = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title(" Games "));
+    f.render_widget(list, area);
+    draw_message_line(f, app);
+}
+
+// Your rating against each AI difficulty and the hot-seat players', with
+// the games they came from
+fn draw_ratings<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let palette = app.settings.theme.palette();
+    let row = |name: &str, rating: &str, won: &str, drawn: &str, lost: &str| {
+        format!(
+            "  {:<20} {:>6} {:>5} {:>5} {:>5}  ",
+            name, rating, won, drawn, lost
+        )
+    };
+    let rated = |name: &str, rating: &Rating| match rating.games() {
+        0 => row(name, "-", "", "", ""),
+        _ => row(
+            name,
+            &rating.rating.to_string(),
+            &rating.won.to_string(),
+            &rating.drawn.to_string(),
+            &rating.lost.to_string(),
+        ),
+    };
+    let heading = |text: &str| {
+        Spans::from(Span::styled(
+            row(text, "Rating", "Won", "Drawn", "Lost"),
+            Style::default().fg(palette.dim),
+        ))
+    };
+    let mut lines = vec![Spans::from(""), heading("Against the AI")];
+    for difficulty in DIFFICULTIES {
+        let name = format!(
+            "{} ({})",
+            difficulty.label(),
+            ratings::difficulty_rating(difficulty)
+        );
+        let rating = app.ratings.against_ai(difficulty);
+        lines.push(Spans::from(rated(&name, &rating)));
+    }
+    lines.push(Spans::from(""));
+    lines.push(heading("Hot-seat"));
+    let mut players = app.ratings.players().peekable();
+    if players.peek().is_none() {
+        lines.push(Spans::from(Span::styled(
+            "Name both players under [players] in config.toml to rate them.",
+            Style::default().fg(palette.dim),
+        )));
+    }
+    for (name, rating) in players {
+        lines.push(Spans::from(rated(name, rating)));
+    }
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(format!("Puzzle rating {}", app.puzzles.rating)));
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        "Esc back",
+        Style::default().fg(palette.dim),
+    )));
+
+    let area = centered_rect(70, lines.len() as u16 + 2, f.size());
+    let list = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title(" Ratings "));
+    f.render_widget(list, area);
+    draw_message_line(f, app);
+}
+
+// The moves played in your saved games from the explorer's position, most
+// played first, with your score after each
+fn draw_explorer<B: Backend>(f: &mut Frame<B>, app: &App) {
+    const WIDTH: u16 = 50;
+    let palette = app.settings.theme.palette();
+    let board = &app.explorer_board;
+    let moves: Vec<_> = board.get_history().iter().map(|record| record.mv).collect();
+    let line = match board.get_start_position().line_to_san(&moves) {
+        line if line.is_empty() => "Starting position".to_string(),
+        // The end of a long line is what matters
+        line => {
+            let room = WIDTH as usize - 6;
+            let skip = line.chars().count().saturating_sub(room);
+            match skip {
+                0 => line,
+                _ => format!("…{}", line.chars().skip(skip + 1).collect::<String>()),
+            }
+        }
+    };
+    let mut lines = vec![
+        Spans::from(Span::styled(line, Style::default().fg(palette.accent))),
+        Spans::from(""),
+    ];
+    let tree = app.opening_tree.as_ref();
+    let stats = tree.map_or(&[][..], |tree| tree.moves(board));
+    if stats.is_empty() {
+        lines.push(Spans::from("None of your games reached this position."));
+    } else {
+        let total: u32 = stats.iter().map(|stats| stats.games).sum();
+        lines.push(Spans::from(Span::styled(
+            format!(
+                "  {:<8} {:>12} {:>8}  ",
+                "Move",
+                format!("{} games", total),
+                "Score"
+            ),
+            Style::default().fg(palette.dim),
+        )));
+    }
+    for (i, stats) in stats.iter().enumerate() {
+        let mut style = Style::default();
+        if i == app.explorer_index {
+            style = style.add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        lines.push(Spans::from(Span::styled(
+            format!(
+                "  {:<8} {:>12} {:>8}  ",
+                stats.san,
+                stats.games,
+                format!("{}%", stats.score())
+            ),
+            style,
+        )));
+    }
+    if let Some(skipped) = tree.map(|tree| tree.skipped).filter(|&skipped| skipped > 0) {
+        lines.push(Spans::from(""));
+        lines.push(Spans::from(Span::styled(
+            format!("{} hot-seat or unreadable games left out", skipped),
+            Style::default().fg(palette.dim),
+        )));
+    }
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        "↑/↓ move  Enter follow  ← back  Esc game",
+        Style::default().fg(palette.dim),
+    )));
+
+    let area = centered_rect(WIDTH, lines.len() as u16 + 2, f.size());
+    let list = Paragraph::new(lines).alignment(Alignment::Center).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Opening explorer "),
+    );
+    f.render_widget(list, area);
+    draw_message_line(f, app);
+}
+
+// The board editor, laid out like the game screen: what is set besides the
+// pieces above the board, and what keeps the position from being played
+// beside it
+fn draw_editor<B: Backend>(f: &mut Frame<B>, app: &App, layout: &BoardLayout) {
+    let palette = app.settings.theme.palette();
+    let pieces = app.settings.piece_style;
+    let editor = &app.editor;
+    let dim = Style::default().fg(palette.dim);
+    let bold = Style::default()
+        .fg(palette.accent)
+        .add_modifier(Modifier::BOLD);
+
+    let castling: Vec<Span> = ["K", "Q", "k", "q"]
+        .iter()
+        .zip(editor.castling)
+        .enumerate()
+        .flat_map(|(i, (letter, on))| {
+            [
+                Span::styled(format!("[{}]", i + 1), dim),
+                Span::styled(format!("{} ", letter), if on { bold } else { dim }),
+            ]
+        })
+        .collect();
+    let info_text = vec![
+        Spans::from(vec![
+            Span::styled("Piece: ", dim),
+            Span::styled(
+                format!("{} ", pieces.glyph(editor.piece)),
+                Style::default()
+                    .fg(piece_color(&palette, editor.piece.color()))
+                    .add_modifier(Modifier::BOLD),
+            ),
+            Span::raw(format!(
+                "{:?} {:?}",
+                editor.piece.color(),
+                editor.piece.piece_type()
+            )),
+        ]),
+        Spans::from(vec![
+            Span::styled("To move: ", dim),
+            Span::styled(format!("{:?}", editor.turn), bold),
+        ]),
+        Spans::from([vec![Span::styled("Castling: ", dim)], castling].concat()),
+        Spans::from(vec![
+            Span::styled("En passant: ", dim),
+            Span::styled(editor.en_passant.map_or("-".to_string(), square_name), bold),
+        ]),
+        Spans::from(vec![Span::styled("FEN: ", dim), Span::raw(editor.fen())]),
+    ];
+    f.render_widget(
+        Paragraph::new(info_text).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Position setup "),
+        ),
+        layout.info,
+    );
+
+    f.render_widget(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Board editor "),
+        layout.board_block,
+    );
+    let label_row = layout.label_row();
+    for i_idx in 0..8 {
+        let (r, _) = display_to_square((i_idx, 0), editor.perspective);
+        f.render_widget(
+            Paragraph::new(Span::raw(format!("{}", r + 1))),
+            Rect::new(
+                layout.board_area.x + 1,
+                layout.square_rect((i_idx, 0)).y + label_row,
+                1,
+                1,
+            ),
+        );
+        for display_col in 0..8 {
+            let (_, c) = display_to_square((i_idx, display_col), editor.perspective);
+            let mut style = Style::default().bg(if (r + c) % 2 == 0 {
+                palette.dark_square
+            } else {
+                palette.light_square
+            });
+            if editor.en_passant == Some((r, c)) {
+                style = style.bg(palette.legal_move);
+            }
+            let piece = match editor.squares[r][c] {
+                Some(piece) => Span::styled(
+                    format!(
+                        "{:^width$}",
+                        pieces.glyph(piece).to_string(),
+                        width = layout.square_width as usize
+                    ),
+                    Style::default()
+                        .fg(piece_color(&palette, piece.color()))
+                        .add_modifier(Modifier::BOLD),
+                ),
+                None => Span::raw(""),
+            };
+            let mut square_text = vec![Spans::from(""); label_row as usize];
+            square_text.push(Spans::from(piece));
+            f.render_widget(
+                Paragraph::new(square_text).style(style),
+                layout.square_rect((i_idx, display_col)),
+            );
+        }
+    }
+    let squares = layout.squares;
+    let file_labels: Vec<Span> = (0..8)
+        .map(|display_col| display_to_square((0, display_col), editor.perspective).1)
+        .map(|c| {
+            Span::raw(format!(
+                "{:^width$}",
+                ((b'a' + c as u8) as char).to_string(),
+                width = layout.square_width as usize
+            ))
+        })
+        .collect();
+    f.render_widget(
+        Paragraph::new(Spans::from(file_labels)),
+        Rect::new(squares.x, squares.y + squares.height, squares.width, 1),
+    );
+
+    if let (Some(area), _) = layout.side_panels(true) {
+        let problems = editor.problems();
+        let lines: Vec<Spans> = if problems.is_empty() {
+            vec![Spans::from(Span::styled(
+                "Ready to play.",
+                Style::default().fg(palette.legal_move),
+            ))]
+        } else {
+            problems
+                .into_iter()
+                .map(|problem| {
+                    Spans::from(Span::styled(problem, Style::default().fg(palette.check)))
+                })
+                .collect()
+        };
+        f.render_widget(
+            Paragraph::new(lines)
+                .wrap(Wrap { trim: true })
+                .block(Block::default().borders(Borders::ALL).title(" Problems ")),
+            area,
+        );
+    }
+
+    f.render_widget(
+        Paragraph::new(app.toasts.latest())
+            .style(Style::default().fg(palette.text))
+            .block(Block::default().borders(Borders::ALL).title(" Messages ")),
+        layout.messages,
+    );
+    f.render_widget(
+        Paragraph::new(Span::styled(
+            " Letters piece  Tab side  1-4 castling  e en passant  x clear  s start  f flip  \
+             Enter play  v vs AI  a analyse  Esc menu",
+            dim,
+        )),
+        layout.status,
+    );
+    draw_toasts(f, app, layout.messages.y);
+}
+
+// Motifs to drill puzzles on, each with how many were solved
+fn draw_tactics<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let palette = app.settings.theme.palette();
+    let stats = |label: &str, solved: u32, failed: u32| {
+        let tried = solved + failed;
+        let share = (solved * 100)
+            .checked_div(tried)
+            .map_or("-".to_string(), |share| format!("{}%", share));
+        format!(
+            "  {:<16}{:>9}{:>6}  ",
+            label,
+            format!("{}/{}", solved, tried),
+            share
+        )
+    };
+    let mut rows = vec![stats("All themes", app.puzzles.solved, app.puzzles.failed)];
+    for motif in MOTIFS {
+        let motif_stats = app.puzzles.motif_stats(motif);
+        rows.push(stats(motif.label(), motif_stats.solved, motif_stats.failed));
+    }
+
+    let mut lines = vec![
+        Spans::from(Span::styled(
+            format!("Puzzle rating {}", app.puzzles.rating),
+            Style::default().fg(palette.dim),
+        )),
+        Spans::from(""),
+    ];
+    for (i, row) in rows.into_iter().enumerate() {
+        let mut style = Style::default();
+        if i == app.tactics_index {
+            style = style.add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        lines.push(Spans::from(Span::styled(row, style)));
+    }
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        "↑/↓ move  Enter drill  Esc back",
+        Style::default().fg(palette.dim),
+    )));
+
+    let area = centered_rect(40, lines.len() as u16 + 2, f.size());
+    let list = Paragraph::new(lines).alignment(Alignment::Center).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Tactics by theme "),
+    );
+    f.render_widget(list, area);
+    draw_message_line(f, app);
+}
+
+// Each side's accuracy and moves by verdict, then the mistakes and blunders
+// to look at on the board
+fn draw_review<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let palette = app.settings.theme.palette();
+    let Some(review) = &app.review else {
+        return;
+    };
+    let label = Style::default().fg(palette.dim);
+    let accuracy = |color| {
+        review
+            .accuracy(color)
+            .map_or("-".to_string(), |accuracy| format!("{:.1}%", accuracy))
+    };
+    let mut lines = vec![
+        Spans::from(Span::styled(
+            format!("  {:<16}{:>9}{:>9}  ", "", "White", "Black"),
+            label,
+        )),
+        Spans::from(Span::styled(
+            format!(
+                "  {:<16}{:>9}{:>9}  ",
+                "Accuracy",
+                accuracy(ColorChess::White),
+                accuracy(ColorChess::Black)
+            ),
+            Style::default().add_modifier(Modifier::BOLD),
+        )),
+    ];
+    for verdict in VERDICTS {
+        lines.push(Spans::from(format!(
+            "  {:<16}{:>9}{:>9}  ",
+            verdict.label(),
+            review.count(ColorChess::White, verdict),
+            review.count(ColorChess::Black, verdict)
+        )));
+    }
+    lines.push(Spans::from(""));
+
+    let key_moves = review.key_moves();
+    if key_moves.is_empty() {
+        lines.push(Spans::from("No mistakes in this game."));
+    }
+    for (i, key_move) in key_moves.iter().enumerate() {
+        let mut style = Style::default();
+        if key_move.verdict == Verdict::Blunder {
+            style = style.fg(palette.accent);
+        }
+        if i == app.review_index {
+            style = style.add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        let best = key_move
+            .best
+            .as_ref()
+            .map_or(String::new(), |best| format!("{} was better", best));
+        lines.push(Spans::from(Span::styled(
+            format!(
+                "  {:<14}{:<11}{:>6}  {:<18}",
+                key_move.label,
+                key_move.verdict.label(),
+                format!("-{:.1}", f64::from(key_move.loss) / 100.0),
+                best
+            ),
+            style,
+        )));
+    }
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        "↑/↓ move  Enter show on the board  Esc back",
+        label,
+    )));
+
+    let area = centered_rect(60, lines.len() as u16 + 2, f.size());
+    let list = Paragraph::new(lines).alignment(Alignment::Center).block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(" Game review "),
+    );
+    f.render_widget(list, area);
+    draw_message_line(f, app);
+}
+
+fn draw_broadcast<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let palette = app.settings.theme.palette();
+    let games = app
+        .broadcast
+        .as_ref()
+        .map_or(&[][..], |b| b.games.as_slice());
+    let mut lines = vec![Spans::from("")];
+    if games.is_empty() {
+        lines.push(Spans::from("Waiting for the round's games..."));
+    }
+    for (i, game) in games.iter().enumerate() {
+        let mut style = Style::default();
+        if i == app.broadcast_index {
+            style = style.add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        // An unfinished game shows how far it has got
+        let progress = match game.result() {
+            Some(result) => result.pgn_tag().to_string(),
+            None => format!("move {}", game.board.get_fullmove_number()),
+        };
+        lines.push(Spans::from(Span::styled(
+            format!(
+                "  {:<20} {:<20} {:>8}  ",
+                game.tag("White").unwrap_or("?"),
+                game.tag("Black").unwrap_or("?"),
+                progress
+            ),
+            style,
+        )));
+    }
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        "↑/↓ move  Enter watch  Esc leave",
+        Style::default().fg(palette.dim),
+    )));
+
+    let area = centered_rect(58, lines.len() as u16 + 2, f.size());
+    let list = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title(" Broadcast "));
+    f.render_widget(list, area);
+    draw_message_line(f, app);
+}
+
+fn draw_color_select<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let palette = app.settings.theme.palette();
+    let mut lines = vec![
+        Spans::from("Which side do you want to play?"),
+        Spans::from(""),
+    ];
+    for (i, choice) in COLOR_CHOICES.iter().enumerate() {
+        let mut style = Style::default();
+        if i == app.color_index {
+            style = style.add_modifier(Modifier::REVERSED | Modifier::BOLD);
+        }
+        lines.push(Spans::from(Span::styled(
+            format!("  {:<12}", choice.label()),
+            style,
+        )));
+    }
+    lines.push(Spans::from(""));
+    let time_control = app
+        .time_control
+        .map_or("Untimed".to_string(), |control| control.label());
+    lines.push(Spans::from(vec![
+        Span::raw("Clock: "),
+        Span::styled(
+            format!("◀ {} ▶", time_control),
+            Style::default()
+                .fg(palette.accent)
+                .add_modifier(Modifier::BOLD),
+        ),
+    ]));
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        "↑/↓ side  ←/→ clock  Enter start  Esc back",
+        Style::default().fg(palette.dim),
+    )));
+
+    let area = centered_rect(48, lines.len() as u16 + 2, f.size());
+    let color_select = Paragraph::new(lines)
+        .alignment(Alignment::Center)
+        .block(Block::default().borders(Borders::ALL).title(" New Game "));
+    f.render_widget(color_select, area);
+    draw_message_line(f, app);
+}
+
+// Vertical bar beside the board: the White part grows from White's side of the
+// board as the engine's score improves for White
+fn draw_eval_bar<B: Backend>(f: &mut Frame<B>, app: &App, layout: &BoardLayout, x: u16, y: u16) {
+    const BAR_WIDTH: u16 = 2;
+    const LABEL_WIDTH: u16 = 6;
+    let palette = app.settings.theme.palette();
+    let board_area = layout.board_area;
+    let height = layout.squares.height;
+    if x + LABEL_WIDTH > board_area.x + board_area.width
+        || y + height + 1 > board_area.y + board_area.height
+    {
+        return;
+    }
+
+    // A finished game shows its result; otherwise the latest engine score
+    let white_score = match (app.board.result(), &app.analysis) {
+        (Some(result), _) => Some(match result.winner {
+            Some(ColorChess::White) => MATE_SCORE,
+            Some(ColorChess::Black) => -MATE_SCORE,
+            None => 0,
+        }),
+        (None, Some(analysis)) => Some(analysis.white_score),
+        (None, None) => None,
+    };
+    let white_share = match white_score {
+        Some(score) if score >= MATE_THRESHOLD => 1.0,
+        Some(score) if score <= -MATE_THRESHOLD => 0.0,
+        // Logistic curve, so +4.00 already looks like a clear advantage
+        Some(score) => 1.0 / (1.0 + 10f64.powf(-score as f64 / 400.0)),
+        None => 0.5,
+    };
+    let white_rows = (white_share * height as f64).round() as u16;
+
+    for i in 0..height {
+        // Rows counted from White's side of the board
+        let from_white = match app.player_perspective {
+            ColorChess::White => height - 1 - i,
+            ColorChess::Black => i,
+        };
+        let color = if from_white < white_rows {
+            Color::White
+        } else {
+            Color::DarkGray
+        };
+        f.render_widget(
+            Paragraph::new("").style(Style::default().bg(color)),
+            Rect::new(x, y + i, BAR_WIDTH, 1),
+        );
+    }
+
+    // Scores for an older position are dimmed until the new one arrives
+    let stale = app.board.result().is_none()
+        && app
+            .analysis
+            .as_ref()
+            .is_none_or(|analysis| analysis.hash != app.shown_board().hash());
+    let label = match white_score {
+        Some(score) if score.abs() >= MATE_THRESHOLD => {
+            let moves = (MATE_SCORE - score.abs() + 1) / 2;
+            if moves == 0 {
+                "#".to_string()
+            } else if score > 0 {
+                format!("M{}", moves)
+            } else {
+                format!("-M{}", moves)
+            }
+        }
+        // A tablebase win has no move count to show
+        Some(score) if score >= TB_WIN_THRESHOLD => "TB".to_string(),
+        Some(score) if score <= -TB_WIN_THRESHOLD => "-TB".to_string(),
+        Some(score) => format!("{:+.1}", score as f64 / 100.0),
+        None => "...".to_string(),
+    };
+    let label_style = if stale {
+        Style::default().fg(Color::DarkGray)
+    } else {
+        Style::default().fg(palette.dim)
+    };
+    f.render_widget(
+        Paragraph::new(Span::styled(label, label_style)),
+        Rect::new(x, y + height, LABEL_WIDTH, 1),
+    );
+}
+
+// Side panel with the background engine's progress on the current position
+fn draw_analysis_panel<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let lines = match &app.analysis {
+        Some(analysis) => {
+            let stale = analysis.hash != app.shown_board().hash();
+            let style = if stale {
+                Style::default().fg(Color::DarkGray)
+            } else {
+                Style::default()
+            };
+            // Saved analysis has no search to count nodes for
+            let (nodes, nps) = if analysis.saved {
+                ("saved".to_string(), "-".to_string())
+            } else {
+                (
+                    analysis.nodes.to_string(),
+                    analysis.nodes_per_second.to_string(),
+                )
+            };
+            let mut lines = vec![
+                Spans::from(Span::styled(format!("Depth  {}", analysis.depth), style)),
+                Spans::from(Span::styled(format!("Nodes  {}", nodes), style)),
+                Spans::from(Span::styled(format!("NPS    {}", nps), style)),
+                Spans::from(Span::styled(
+                    format!("Score  {}", format_score(analysis.white_score)),
+                    style,
+                )),
+            ];
+            if let Some(exact) = &analysis.exact {
+                let exact_style = if stale {
+                    style
+                } else {
+                    Style::default()
+                        .fg(Color::Green)
+                        .add_modifier(Modifier::BOLD)
+                };
+                lines.push(Spans::from(Span::styled(
+                    format!("Exact  {}", exact),
+                    exact_style,
+                )));
+            }
+            lines.push(Spans::from(""));
+            match analysis.lines.as_slice() {
+                [line] => lines.push(Spans::from(Span::styled(line.pv.clone(), style))),
+                // Several candidates each get their score in front of the line
+                candidates => {
+                    for line in candidates {
+                        lines.push(Spans::from(Span::styled(
+                            format!("{}  {}", format_score(line.white_score), line.pv),
+                            style,
+                        )));
+                    }
+                }
+            }
+            lines
+        }
+        None => vec![Spans::from(Span::styled(
+            "Waiting for the engine...",
+            Style::default().fg(Color::DarkGray),
+        ))],
+    };
+
+    let panel = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(" Analysis "))
+        .wrap(Wrap { trim: true });
+    f.render_widget(panel, area);
+}
+
+// Side panel with the tutorial lesson being played, in place of the move list
+fn draw_lesson<B: Backend>(f: &mut Frame<B>, app: &App, lesson: &Lesson, area: Rect) {
+    let palette = app.settings.theme.palette();
+    let lines = vec![
+        Spans::from(Span::styled(
+            lesson.title,
+            Style::default()
+                .fg(palette.accent)
+                .add_modifier(Modifier::BOLD),
+        )),
+        Spans::from(""),
+        Spans::from(lesson.text),
+        Spans::from(""),
+        Spans::from(Span::styled(
+            format!(
+                "{} next lesson  {} start over",
+                app.keys.describe(Action::NewGame),
+                app.keys.describe(Action::Rematch)
+            ),
+            Style::default().fg(palette.dim),
+        )),
+    ];
+    let panel = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title(" Tutorial "))
+        .wrap(Wrap { trim: true });
+    f.render_widget(panel, area);
+}
+
+// Side panel with the game's moves in SAN, one numbered row per move pair,
+// and the variations set in under the move they branch off from; the move
+// that led to the position on the board is highlighted
+fn draw_move_list<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let palette = app.settings.theme.palette();
+    let plies = app.board.get_history().len();
+    let shown = app.shown_node();
+    let visible = area.height.saturating_sub(2) as usize;
+    let top = app.move_list_top(visible);
+
+    let mut rows = Vec::new();
+    for row in app.move_rows.iter().skip(top).take(visible) {
+        let mut spans = Vec::new();
+        for cell in &row.cells {
+            let Some(node) = cell.node else {
+                spans.push(Span::styled(
+                    cell.text.clone(),
+                    Style::default().fg(palette.dim),
+                ));
+                continue;
+            };
+            // Variations, and main-line moves taken back, are dimmed
+            let played = cell.mover.is_some() && app.tree.depth(node) <= plies;
+            let mut style = Style::default().fg(if played { palette.text } else { palette.dim });
+            if node == shown {
+                style = style.fg(palette.accent).add_modifier(Modifier::BOLD);
+            }
+            // The other side's moves are hidden in fog of war
+            let hidden = app
+                .fog_viewer()
+                .is_some_and(|viewer| cell.mover.is_some_and(|mover| mover != viewer));
+            let text = if hidden {
+                format!("{:<8}", "?")
+            } else {
+                cell.text.clone()
+            };
+            spans.push(Span::styled(text, style));
+        }
+        rows.push(Spans::from(spans));
+    }
+
+    // The highlighted move's time goes in the title
+    let sideline = app.in_sideline();
+    let mut title = if sideline {
+        " Moves (sideline) ".to_string()
+    } else if app.browse.is_some() {
+        " Moves (browsing) ".to_string()
+    } else {
+        " Moves ".to_string()
+    };
+    let ply = app.tree.depth(shown).checked_sub(1).filter(|_| !sideline);
+    if let Some(time) = ply.and_then(|ply| app.move_time(ply)) {
+        title.push_str(&format!("{} ", format_move_time(time)));
+    }
+    let panel = Paragraph::new(rows).block(Block::default().borders(Borders::ALL).title(title));
+    f.render_widget(panel, area);
+}
+
+// Columns of bars in the post-game time graph
+const TIME_GRAPH_WIDTH: usize = 30;
+
+// Bar heights for the time graph, from a short think to the longest
+const TIME_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+// The post-game time graph: a bar per move for each side showing how long it
+// took, on one scale for both, and each side's total. Long games put several
+// moves in a bar. Empty when no move was timed
+fn time_graph(app: &App, palette: &Palette) -> Vec<Spans<'static>> {
+    let mut times: [Vec<Duration>; 2] = Default::default();
+    for (ply, record) in app.board.get_history().iter().enumerate() {
+        if let Some(time) = app.move_time(ply) {
+            times[usize::from(record.turn == ColorChess::Black)].push(time);
+        }
+    }
+    let per_bar = times
+        .iter()
+        .map(|side| side.len().div_ceil(TIME_GRAPH_WIDTH))
+        .max()
+        .unwrap_or(0);
+    if per_bar == 0 {
+        return Vec::new();
+    }
+    let bars: Vec<Vec<Duration>> = times
+        .iter()
+        .map(|side| {
+            side.chunks(per_bar)
+                .map(|chunk| chunk.iter().sum())
+                .collect()
+        })
+        .collect();
+    let longest = bars.iter().flatten().max().copied().unwrap_or_default();
+    let width = bars.iter().map(Vec::len).max().unwrap_or(0);
+
+    let mut lines = vec![Spans::from(Span::styled(
+        "Time per move",
+        Style::default().fg(palette.dim),
+    ))];
+    for (color, side) in [ColorChess::White, ColorChess::Black]
+        .into_iter()
+        .zip(&bars)
+    {
+        let graph: String = side
+            .iter()
+            .map(|bar| {
+                let level = if longest.is_zero() {
+                    0
+                } else {
+                    (bar.as_secs_f64() / longest.as_secs_f64() * 7.0).round() as usize
+                };
+                TIME_BARS[level.min(7)]
+            })
+            .collect();
+        let total: Duration = times[usize::from(color == ColorChess::Black)].iter().sum();
+        lines.push(Spans::from(vec![
+            Span::styled(format!("{:?} ", color), Style::default().fg(palette.dim)),
+            Span::styled(
+                format!("{:<width$}", graph, width = width),
+                Style::default().fg(piece_color(palette, color)),
+            ),
+            Span::raw(format!(" {:>6}", format_move_time(total))),
+        ]));
+    }
+    lines.push(Spans::from(""));
+    lines
+}
+
+// Mouse controls of the game screen, as (what to click, what it does)
+const MOUSE_HELP: [(&str, &str); 2] = [
+    ("Click", "Select a piece, then its destination"),
+    ("Click a move", "Browse the game from that move"),
+];
+
+// Keyboard controls of the game screen; the keys come from the key map, so the
+// list follows the [keys] section
+const KEY_HELP: [(&[Action], &str); 25] = [
+    (&[Action::Select], "Select the square under the cursor"),
+    (
+        &[Action::Up, Action::Down, Action::Left, Action::Right],
+        "Move the cursor",
+    ),
+    (&[Action::Flip], "Flip the board"),
+    (&[Action::Undo, Action::Redo], "Undo / redo a move"),
+    (&[Action::Analysis], "Toggle the analysis panel"),
+    (&[Action::AttackMap], "Toggle the attack map"),
+    (&[Action::Theme], "Next color theme"),
+    (&[Action::Pause], "Pause / resume the game"),
+    (&[Action::Resign], "Resign"),
+    (&[Action::OfferDraw], "Offer a draw"),
+    (
+        &[Action::Yes, Action::No],
+        "Answer a resignation or draw offer",
+    ),
+    (
+        &[Action::ScrollUp, Action::ScrollDown],
+        "Scroll the move list",
+    ),
+    (
+        &[Action::Left, Action::Right],
+        "Step through the game while browsing",
+    ),
+    (&[Action::Explore], "Analysis board: browse and try moves"),
+    (
+        &[Action::PromoteLine, Action::DemoteLine],
+        "Move a variation up or down while browsing",
+    ),
+    (
+        &[Action::Annotate],
+        "Annotate the move shown: !, ?, !?, $14...",
+    ),
+    (&[Action::Comment], "Comment on the move shown"),
+    (&[Action::Review], "The engine's review of the game"),
+    (&[Action::Explorer], "Your games from this position"),
+    (
+        &[Action::NewGame, Action::Rematch],
+        "New game / rematch once it is over",
+    ),
+    (&[Action::TypeMove], "Type a move, e.g. Nf3"),
+    (&[Action::Peek], "Peek at the pieces when blindfold"),
+    (&[Action::Menu], "Main menu, or back to the game"),
+    (&[Action::Help], "This help"),
+    (&[Action::Quit], "Quit"),
+];
+
+// Extra controls in vim mode, which cannot be remapped
+const VIM_HELP: [(&str, &str); 5] = [
+    ("v", "Select the square under the cursor"),
+    (":w [file]", "Save the game as PGN (game.pgn)"),
+    (":fen", "Show the position's FEN"),
+    (":resign / :draw", "Resign or offer a draw"),
+    (":undo :redo :flip :pause :new :q", "Same as their keys"),
+];
+
+// Modal over the board listing the controls; any key closes it
+fn draw_help<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let palette = app.settings.theme.palette();
+    let key_style = Style::default()
+        .fg(palette.accent)
+        .add_modifier(Modifier::BOLD);
+    let mut rows: Vec<(String, &str)> = MOUSE_HELP
+        .iter()
+        .map(|&(click, action)| (click.to_string(), action))
+        .collect();
+    rows.extend(KEY_HELP.iter().map(|&(actions, action)| {
+        let keys: Vec<String> = actions.iter().map(|&a| app.keys.describe(a)).collect();
+        (keys.join(", "), action)
+    }));
+    if app.settings.vim_mode {
+        rows.extend(
+            VIM_HELP
+                .iter()
+                .map(|&(keys, action)| (keys.to_string(), action)),
+        );
+    }
+    let key_width = rows
+        .iter()
+        .map(|(keys, _)| keys.chars().count())
+        .max()
+        .unwrap_or(0)
+        + 2;
+    let action_width = rows
+        .iter()
+        .map(|(_, action)| action.len())
+        .max()
+        .unwrap_or(0);
+
+    let mut lines: Vec<Spans> = rows
+        .into_iter()
+        .map(|(keys, action)| {
+            Spans::from(vec![
+                Span::styled(format!(" {:<width$}", keys, width = key_width), key_style),
+                Span::styled(action, Style::default().fg(palette.text)),
+            ])
+        })
+        .collect();
+    lines.push(Spans::from(""));
+    lines.push(Spans::from(Span::styled(
+        " Press any key to close",
+        Style::default().fg(palette.dim),
+    )));
+
+    let width = (key_width + action_width + 4) as u16;
+    let popup_area = centered_rect(width, lines.len() as u16 + 2, area);
+    let popup = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title(" Help "));
+    f.render_widget(Clear, popup_area);
+    f.render_widget(popup, popup_area);
+}
+
+// Transient message shown along the bottom of the menu screens
+fn draw_message_line<B: Backend>(f: &mut Frame<B>, app: &App) {
+    let palette = app.settings.theme.palette();
+    let area = f.size();
+    if area.height == 0 {
+        return;
+    }
+    let line_area = Rect::new(area.x, area.y + area.height - 1, area.width, 1);
+    f.render_widget(
+        Paragraph::new(app.toasts.latest())
+            .alignment(Alignment::Center)
+            .style(Style::default().fg(palette.dim)),
+        line_area,
+    );
+    draw_toasts(f, app, line_area.y);
+}
+
+// Earlier notifications that have not expired yet, stacked right-aligned above
+// the row `bottom`, the newest lowest
+fn draw_toasts<B: Backend>(f: &mut Frame<B>, app: &App, bottom: u16) {
+    let palette = app.settings.theme.palette();
+    let area = f.size();
+    let style = Style::default().fg(palette.highlight_text).bg(palette.dim);
+    let toasts: Vec<&str> = app
+        .toasts
+        .recent()
+        .map(|toast| toast.text.as_str())
+        .collect();
+    for (i, text) in toasts.iter().rev().enumerate() {
+        let Some(y) = bottom.checked_sub(i as u16 + 1).filter(|&y| y >= area.y) else {
+            break;
+        };
+        let width = (text.chars().count() as u16 + 2).min(area.width);
+        let toast_area = Rect::new(area.x + area.width - width, y, width, 1);
+        f.render_widget(Clear, toast_area);
+        f.render_widget(
+            Paragraph::new(format!(" {} ", text)).style(style),
+            toast_area,
+        );
+    }
+}
+
+// One side's line in the info block: the opponent's pieces it has captured,
+// grouped by type from the queen down, its material lead if it has one and the
+// points it has scored
+fn captured_line(app: &App, board: &Game, side: ColorChess) -> Spans<'static> {
+    const ORDER: [PieceType; 5] = [
+        PieceType::Queen,
+        PieceType::Rook,
+        PieceType::Bishop,
+        PieceType::Knight,
+        PieceType::Pawn,
+    ];
+    let palette = app.settings.theme.palette();
+    let opponent = side.opposite();
+    let mut spans = vec![Span::styled(
+        format!("{:<7}", format!("{:?}", side)),
+        Style::default()
+            .fg(piece_color(&palette, side))
+            .add_modifier(Modifier::BOLD),
+    )];
+
+    let captured = board.get_captured(opponent);
+    for piece_type in ORDER {
+        let group: String = captured
+            .iter()
+            .filter(|piece| piece.is_type(piece_type))
+            .map(|&piece| app.settings.piece_style.glyph(piece))
+            .collect();
+        if !group.is_empty() {
+            spans.push(Span::styled(
+                group,
+                Style::default()
+                    .fg(piece_color(&palette, opponent))
+                    .add_modifier(Modifier::BOLD),
+            ));
+            spans.push(Span::raw(" "));
+        }
+    }
+
+    let lead = board.get_material(side) as i32 - board.get_material(opponent) as i32;
+    if lead > 0 {
+        spans.push(Span::styled(
+            format!(" +{}", lead),
+            Style::default()
+                .fg(palette.accent)
+                .add_modifier(Modifier::BOLD),
+        ));
+    }
+    let points = board.get_points(side);
+    spans.push(Span::styled(
+        format!("  ({} point{})", points, if points == 1 { "" } else { "s" }),
+        Style::default().fg(palette.dim),
+    ));
+    Spans::from(spans)
+}
+
+// Both clocks at the right of the info block, each on its side's line; the
+// running one is highlighted, and one low on time is in the warning color
+fn draw_clocks<B: Backend>(f: &mut Frame<B>, app: &App, clock: &Clock, info: Rect) {
+    let palette = app.settings.theme.palette();
+    let inner = Block::default().borders(Borders::ALL).inner(info);
+    let lines: Vec<Spans> = [ColorChess::White, ColorChess::Black]
+        .into_iter()
+        .map(|side| {
+            let remaining = clock.get_remaining(side);
+            let running = clock.get_running() == Some(side);
+            let low = remaining < app.low_time_threshold();
+            // A low running clock blinks off for half of every second
+            let blink_off =
+                running && low && app.settings.clock.blink && remaining.subsec_millis() < 500;
+            let mut style = Style::default().fg(if low { palette.low_time } else { palette.text });
+            if running && !blink_off {
+                style = style
+                    .fg(palette.highlight_text)
+                    .bg(if low {
+                        palette.low_time
+                    } else {
+                        palette.accent
+                    })
+                    .add_modifier(Modifier::BOLD);
+            }
+            Spans::from(Span::styled(
+                format!(" {} ", format_clock(remaining)),
+                style,
+            ))
+        })
+        .collect();
+    f.render_widget(
+        Paragraph::new(lines).alignment(Alignment::Right),
+        Rect { height: 2, ..inner },
+    );
+}
+
+// One line of game state that stays in view whatever the messages say: the
+// mode, whose turn it is, the move number, check and the time left
+fn draw_status_bar<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let palette = app.settings.theme.palette();
+    let board = app.shown_board();
+    let mode = if app.paused {
+        "PAUSED"
+    } else if app.in_sideline() {
+        "SIDELINE"
+    } else if app.browse.is_some() {
+        "REPLAY"
+    } else if app.show_analysis {
+        "ANALYSIS"
+    } else {
+        "PLAY"
+    };
+    let to_move = board.get_current_turn();
+    let turn = match app.board.result() {
+        Some(result) if app.browse.is_none() => format!("Game over {}", result.pgn_tag()),
+        _ => format!("{:?} to move", to_move),
+    };
+    let mut fields = vec![turn, format!("Move {}", board.get_fullmove_number())];
+    if board.is_checkmate(to_move) {
+        fields.push("Checkmate".to_string());
+    } else if board.is_in_check(to_move) {
+        fields.push("Check".to_string());
+    }
+    fields.push(match app.board.clock() {
+        Some(clock) => format!(
+            "{:?} {}",
+            to_move,
+            format_clock(clock.get_remaining(to_move))
+        ),
+        None => "Untimed".to_string(),
+    });
+    fields.push(match &app.online {
+        Some(game) => format!("{} vs {}", game.server.name(), game.opponent),
+        None if app.is_watching() => "Broadcast".to_string(),
+        None if app.puzzle.as_ref().is_some_and(|run| run.daily) => format!(
+            "Daily puzzle, streak {}",
+            app.puzzles.daily.current_streak()
+        ),
+        None if app.puzzle.is_some() => match app.puzzle_motif {
+            Some(motif) => format!("Puzzle rating {} ({})", app.puzzles.rating, motif.label()),
+            None => format!("Puzzle rating {}", app.puzzles.rating),
+        },
+        None => match (&app.guess, app.endgame, app.lesson) {
+            (Some(run), _, _) => format!("Guess the move {}/{}", run.points, run.possible),
+            _ if app.drill.is_some() => "Repertoire".to_string(),
+            (None, Some(index), _) => format!(
+                "Endgame drill, move {}/{}",
+                board.get_history().len().div_ceil(2),
+                ENDGAMES[index].moves
+            ),
+            (None, None, Some(index)) => format!(
+                "Lesson {}/{}: {}",
+                index + 1,
+                LESSONS.len(),
+                LESSONS[index].title
+            ),
+            _ if app.vs_ai => "vs AI".to_string(),
+            _ => "Hot-seat".to_string(),
+        },
+    });
+
+    let separator = Span::styled(" │ ", Style::default().fg(palette.dim));
+    let mut spans = vec![
+        Span::styled(
+            format!(" {} ", mode),
+            Style::default()
+                .fg(palette.highlight_text)
+                .bg(palette.accent)
+                .add_modifier(Modifier::BOLD),
+        ),
+        Span::raw(" "),
+    ];
+    for (i, field) in fields.into_iter().enumerate() {
+        if i > 0 {
+            spans.push(separator.clone());
+        }
+        spans.push(Span::styled(field, Style::default().fg(palette.text)));
+    }
+    f.render_widget(Paragraph::new(Spans::from(spans)), area);
+}
+
+// A square's color part of the way toward another; colors other than RGB ones
+// cannot be mixed, so any tint at all switches to the other color
+fn tint(base: Color, toward: Color, share: f32) -> Color {
+    match (base, toward) {
+        (Color::Rgb(r, g, b), Color::Rgb(to_r, to_g, to_b)) => {
+            let mix =
+                |from: u8, to: u8| (from as f32 + (to as f32 - from as f32) * share).round() as u8;
+            Color::Rgb(mix(r, to_r), mix(g, to_g), mix(b, to_b))
+        }
+        _ if share > 0.0 => toward,
+        _ => base,
+    }
+}
+
+fn draw_game<B: Backend>(f: &mut Frame<B>, app: &App, layout: &BoardLayout) {
+    let palette = app.settings.theme.palette();
+    let pieces = app.settings.piece_style;
+    // An earlier position while browsing the move list, otherwise the game's
+    let board = app.shown_board();
+    // Captured Pieces and Info Block
+    let captured_block = Block::default().borders(Borders::ALL).title(" Game Info ");
+
+    let mut info_text = vec![
+        captured_line(app, board, ColorChess::White),
+        captured_line(app, board, ColorChess::Black),
+        Spans::from(vec![
+            Span::styled("Current Turn: ", Style::default().fg(palette.dim)),
+            Span::styled(
+                format!("{:?}", board.get_current_turn()),
+                Style::default()
+                    .fg(match board.get_current_turn() {
+                        ColorChess::White => palette.white_piece,
+                        ColorChess::Black => palette.black_piece,
+                    })
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ]),
+    ];
+    // The opening would give away moves made in the fog
+    if let Some(opening) = board.opening().filter(|_| app.fog_viewer().is_none()) {
+        info_text.push(Spans::from(vec![
+            Span::styled("Opening: ", Style::default().fg(palette.dim)),
+            Span::raw(format!("{} {}", opening.eco, opening.name)),
+        ]));
+    }
+    if let Some(result) = app.board.result() {
+        info_text.push(Spans::from(vec![
+            Span::styled("Result: ", Style::default().fg(palette.dim)),
+            Span::styled(
+                result.pgn_tag(),
+                Style::default()
+                    .fg(palette.accent)
+                    .add_modifier(Modifier::BOLD),
+            ),
+        ]));
+    }
+    let info_paragraph = Paragraph::new(info_text).block(captured_block);
+    f.render_widget(info_paragraph, layout.info);
+    if let Some(clock) = app.board.clock() {
+        draw_clocks(f, app, clock, layout.info);
+    }
+
+    // Chess Board Block
+    let board_block = Block::default()
+        .borders(Borders::ALL)
+        .title(" Chess Board ");
+    f.render_widget(board_block, layout.board_block);
+
+    // Draw the board content manually within the board_block area
+    let board_area = layout.board_area;
+    let squares = layout.squares;
+    let label_row = layout.label_row();
+    let to_move = board.get_current_turn();
+    let king_in_check = board
+        .find_king(to_move)
+        .filter(|_| board.is_in_check(to_move));
+
+    // In fog of war, the squares the side being shown cannot see
+    let visible = app.visible_squares();
+    let hidden = |(r, c): (usize, usize)| visible.is_some_and(|visible| !visible[r][c]);
+
+    // A move out of or into the fog, or of a piece played blindfold, is not
+    // shown sliding
+    let animation = app.animation.as_ref().filter(|animation| {
+        app.browse.is_none()
+            && app.is_animating()
+            && !hidden(animation.from)
+            && !hidden(animation.to)
+            && !app.is_blindfolded(animation.piece.color())
+    });
+
+    // Who attacks each square, unless it would give away hidden pieces
+    let attacks = (app.show_attacks
+        && visible.is_none()
+        && !app.is_blindfolded(ColorChess::White)
+        && !app.is_blindfolded(ColorChess::Black))
+    .then(|| board.attack_table());
+    let me = app.player_perspective;
+    for i_idx in 0..8 {
+        let (r, _) = display_to_square((i_idx, 0), app.player_perspective);
+
+        // Rank numbers (e.g., '8', '7', ...), level with the pieces
+        f.render_widget(
+            Paragraph::new(Span::raw(format!("{}", r + 1))),
+            Rect::new(
+                board_area.x + 1,
+                layout.square_rect((i_idx, 0)).y + label_row,
+                1,
+                1,
+            ),
+        );
+
+        for display_col in 0..8 {
+            let (_, c) = display_to_square((i_idx, display_col), app.player_perspective);
+            let square_color = if (r + c) % 2 == 0 {
+                palette.dark_square
+            } else {
+                palette.light_square
+            };
+
+            let mut style = Style::default().bg(square_color);
+
+            // Attack map: tinted toward the side with more attackers, the more
+            // so the more it has, with your pieces in danger marked outright
+            let mut attack_counts = None;
+            if let Some(attacks) = &attacks {
+                let (mine, theirs) = (
+                    attacks.count(me, (r, c)),
+                    attacks.count(me.opposite(), (r, c)),
+                );
+                let (toward, lead) = if mine >= theirs {
+                    (palette.legal_move, mine - theirs)
+                } else {
+                    (palette.check, theirs - mine)
+                };
+                style = style.bg(tint(square_color, toward, lead.min(3) as f32 * 0.2));
+                if board
+                    .get_piece((r, c))
+                    .is_some_and(|piece| piece.is_color(me))
+                    && attacks.is_underdefended(board, (r, c))
+                {
+                    style = style.bg(palette.check);
+                }
+                attack_counts = (mine + theirs > 0).then_some((mine, theirs));
+            }
+
+            // Highlight selected square
+            if let Some(selected_sq) = app.selected_square
+                && selected_sq == (r, c)
+            {
+                style = style
+                    .bg(palette.selected)
+                    .fg(palette.highlight_text)
+                    .add_modifier(Modifier::BOLD);
+            }
+
+            // Highlight possible moves
+            if app.settings.show_legal_moves && app.possible_moves.iter().any(|mv| mv.to == (r, c))
+            {
+                style = style
+                    .bg(palette.legal_move)
+                    .fg(palette.highlight_text)
+                    .add_modifier(Modifier::BOLD);
+            }
+
+            // King in check
+            if Some((r, c)) == king_in_check {
+                style = style
+                    .bg(palette.check)
+                    .fg(palette.highlight_text)
+                    .add_modifier(Modifier::BOLD);
+            }
+
+            // Keyboard cursor
+            if app.cursor == (r, c) {
+                style = style.bg(palette.cursor).add_modifier(Modifier::BOLD);
+            }
+
+            // Fogged squares show nothing but the keyboard cursor
+            if hidden((r, c)) {
+                let fog = if app.cursor == (r, c) {
+                    palette.cursor
+                } else {
+                    palette.dim
+                };
+                f.render_widget(
+                    Paragraph::new("").style(Style::default().bg(fog)),
+                    layout.square_rect((i_idx, display_col)),
+                );
+                continue;
+            }
+
+            // The piece of a move still sliding in is drawn on its way below
+            let arriving = animation.is_some_and(|animation| animation.to == (r, c));
+            let piece_char = match board
+                .get_piece((r, c))
+                .filter(|piece| !arriving && !app.is_blindfolded(piece.color()))
+            {
+                Some(piece) => {
+                    let piece_tui_color = piece_color(&palette, piece.color());
+                    Span::styled(
+                        // Center the piece character within the larger square
+                        format!(
+                            "{:^width$}",
+                            pieces.glyph(piece).to_string(),
+                            width = layout.square_width as usize
+                        ),
+                        Style::default()
+                            .fg(piece_tui_color)
+                            .add_modifier(Modifier::BOLD),
+                    )
+                }
+                None => Span::raw(""),
+            };
+            let mut square_text = vec![Spans::from(""); label_row as usize];
+            square_text.push(Spans::from(piece_char));
+            // The attackers, yours first, along the bottom of squares with room
+            if let Some((mine, theirs)) = attack_counts
+                && layout.square_height > 1
+            {
+                square_text.resize(layout.square_height as usize - 1, Spans::from(""));
+                square_text.push(Spans::from(Span::styled(
+                    format!(
+                        "{:^width$}",
+                        format!("{}:{}", mine, theirs),
+                        width = layout.square_width as usize
+                    ),
+                    Style::default().fg(palette.highlight_text),
+                )));
+            }
+
+            f.render_widget(
+                Paragraph::new(square_text).style(style),
+                layout.square_rect((i_idx, display_col)),
+            );
+        }
+    }
+
+    // The engine's choice, pointed out on the board while the analysis panel
+    // is open and has caught up with the position shown
+    let best_move = app
+        .analysis
+        .as_ref()
+        .filter(|analysis| analysis.hash == board.hash())
+        .and_then(|analysis| analysis.best_move)
+        .filter(|mv| {
+            app.show_analysis
+                && visible.is_none()
+                && animation.is_none()
+                && !app.is_blindfolded(to_move)
+                && board.get_piece(mv.from).is_some()
+        });
+    if let Some(mv) = best_move {
+        let center = |square| {
+            let rect = layout.square_rect(display_to_square(square, app.player_perspective));
+            (rect.x + (layout.square_width - 1) / 2, rect.y + label_row)
+        };
+        f.render_widget(
+            Arrow {
+                from: center(mv.from),
+                to: center(mv.to),
+                color: palette.accent,
+            },
+            squares,
+        );
+    }
+
+    if let Some(animation) = animation {
+        // Part way between the two squares, on the row pieces are drawn on
+        let from = layout.square_rect(display_to_square(animation.from, app.player_perspective));
+        let to = layout.square_rect(display_to_square(animation.to, app.player_perspective));
+        let progress = animation.progress();
+        let between = |a: u16, b: u16| (a as f32 + (b as f32 - a as f32) * progress).round() as u16;
+        let glyph_area = Rect::new(
+            between(from.x, to.x) + (layout.square_width - 1) / 2,
+            between(from.y, to.y) + label_row,
+            1,
+            1,
+        );
+        f.render_widget(
+            Paragraph::new(Span::styled(
+                pieces.glyph(animation.piece).to_string(),
+                Style::default()
+                    .fg(piece_color(&palette, animation.piece.color()))
+                    .add_modifier(Modifier::BOLD),
+            )),
+            glyph_area,
+        );
+    }
+
+    let file_labels: Vec<Span> = (0..8)
+        .map(|display_col| display_to_square((0, display_col), app.player_perspective).1)
+        .map(|c| (b'a' + c as u8) as char)
+        .map(|c| {
+            Span::raw(format!(
+                "{:^width$}",
+                c.to_string(),
+                width = layout.square_width as usize
+            ))
+        })
+        .collect();
+    f.render_widget(
+        Paragraph::new(Spans::from(file_labels)),
+        Rect::new(squares.x, squares.y + squares.height, squares.width, 1),
+    );
+
+    // The engine would give away what is in the fog
+    let fog = app.fog_viewer().is_some();
+    if app.settings.show_eval_bar && !fog {
+        draw_eval_bar(f, app, layout, squares.x + squares.width + 2, squares.y);
+    }
+
+    let (analysis_area, moves_area) = layout.side_panels(app.show_analysis);
+    if let Some(area) = analysis_area
+        && !fog
+    {
+        draw_analysis_panel(f, app, area);
+    }
+    if let Some(area) = moves_area {
+        match app.lesson {
+            Some(index) => draw_lesson(f, app, &LESSONS[index], area),
+            None => draw_move_list(f, app, area),
+        }
+    }
+
+    // Messages and Input Block; in vim mode the title names the mode and a ':'
+    // command being typed takes the place of the message
+    let prompt = app.prompt.as_ref().map(|&(prompt, _)| prompt);
+    let title = match (app.settings.vim_mode, &app.command_line) {
+        _ if app.move_input.is_some() => " Your move (SAN or UCI) ",
+        _ if prompt == Some(Prompt::Annotations) => " Annotations (!, ?, !!, ??, !?, ?! or $n) ",
+        _ if prompt == Some(Prompt::Comment) => " Comment (empty removes it) ",
+        _ if prompt == Some(Prompt::Export) => " Export the analysis as PGN to (Esc skips) ",
+        (true, Some(_)) => " Messages -- COMMAND -- ",
+        (true, None) if app.selected_square.is_some() => " Messages -- VISUAL -- ",
+        (true, None) => " Messages -- NORMAL -- ",
+        (false, _) => " Messages ",
+    };
+    let message_block = Block::default().borders(Borders::ALL).title(title);
+    let input = app
+        .move_input
+        .as_ref()
+        .or(app.prompt.as_ref().map(|(_, input)| input));
+    let text = match (&app.command_line, input) {
+        (Some(command), _) => format!(":{}█", command),
+        (None, Some(input)) => format!("{}█", input),
+        (None, None) => app.toasts.latest().to_string(),
+    };
+    let message_paragraph = Paragraph::new(text)
+        .style(Style::default().fg(palette.text))
+        .block(message_block);
+    f.render_widget(message_paragraph, layout.messages);
+
+    draw_status_bar(f, app, layout.status);
+    draw_toasts(f, app, layout.messages.y);
+
+    // Promotion dialog drawn over the board
+    if app.pending_promotion.is_some() {
+        let color = app.board.get_current_turn();
+        let piece_tui_color = piece_color(&palette, color);
+        let options: Vec<Spans> = PROMOTION_PIECES
+            .iter()
+            .map(|&piece_type| {
+                Spans::from(vec![
+                    Span::styled(
+                        format!(" [{}] ", promotion_key(piece_type)),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ),
+                    Span::styled(
+                        pieces.glyph(Piece::new(piece_type, color)).to_string(),
+                        Style::default()
+                            .fg(piece_tui_color)
+                            .add_modifier(Modifier::BOLD),
+                    ),
+                    Span::raw(format!(" {:?}", piece_type)),
+                ])
+            })
+            .collect();
+
+        let popup_area = centered_rect(20, PROMOTION_PIECES.len() as u16 + 2, board_area);
+        let popup = Paragraph::new(options)
+            .block(Block::default().borders(Borders::ALL).title(" Promote to "));
+        f.render_widget(Clear, popup_area);
+        f.render_widget(popup, popup_area);
+    }
+
+    // A paused game is greyed out under a notice until it is resumed
+    if app.paused {
+        let dimmed = Style::default().fg(palette.dim).add_modifier(Modifier::DIM);
+        f.render_widget(Block::default().style(dimmed), board_area);
+        let notice = vec![
+            Spans::from(Span::styled(
+                "Paused",
+                Style::default()
+                    .fg(palette.accent)
+                    .add_modifier(Modifier::BOLD),
+            )),
+            Spans::from(Span::styled(
+                format!("Press {} to resume.", app.keys.describe(Action::Pause)),
+                Style::default().fg(palette.dim),
+            )),
+        ];
+        let popup_area = centered_rect(28, notice.len() as u16 + 2, board_area);
+        let popup = Paragraph::new(notice)
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL));
+        f.render_widget(Clear, popup_area);
+        f.render_widget(popup, popup_area);
+    }
+
+    // Hot-seat handover hides the position until the next player is ready
+    if app.awaiting_handover {
+        let to_move = app.board.get_current_turn();
+        let handover = vec![
+            Spans::from(Span::styled(
+                format!("{:?} to move", to_move),
+                Style::default()
+                    .fg(palette.accent)
+                    .add_modifier(Modifier::BOLD),
+            )),
+            Spans::from(""),
+            Spans::from(format!("Pass the keyboard to {:?}.", to_move)),
+            Spans::from(Span::styled(
+                "Press any key to continue.",
+                Style::default().fg(palette.dim),
+            )),
+        ];
+
+        let popup_area = centered_rect(36, handover.len() as u16 + 2, board_area);
+        let popup = Paragraph::new(handover)
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL).title(" Hot-seat "));
+        f.render_widget(Clear, board_area);
+        f.render_widget(popup, popup_area);
+    }
+
+    // Post-game summary drawn over the board, unless the game is being looked back on
+    if let Some(ending) = app.ending()
+        && app.browse.is_none()
+    {
+        let plies = app.board.get_history().len();
+        let label = Style::default().fg(palette.dim);
+        let key = Style::default().add_modifier(Modifier::BOLD);
+        let mut summary = vec![
+            Spans::from(Span::styled(
+                ending,
+                Style::default()
+                    .fg(palette.accent)
+                    .add_modifier(Modifier::BOLD),
+            )),
+            Spans::from(""),
+            Spans::from(vec![
+                Span::styled("White Points: ", label),
+                Span::raw(app.board.get_points(ColorChess::White).to_string()),
+                Span::styled("   Black Points: ", label),
+                Span::raw(app.board.get_points(ColorChess::Black).to_string()),
+            ]),
+            Spans::from(vec![
+                Span::styled("Moves played: ", label),
+                Span::raw(plies.div_ceil(2).to_string()),
+            ]),
+            Spans::from(""),
+        ];
+        summary.extend(time_graph(app, &palette));
+        // The engine's review, once it is done, and how far it has got before
+        if let Some(review) = &app.review {
+            let accuracy = |color| {
+                review
+                    .accuracy(color)
+                    .map_or("-".to_string(), |accuracy| format!("{:.0}%", accuracy))
+            };
+            summary.extend([
+                Spans::from(vec![
+                    Span::styled("Accuracy  White ", label),
+                    Span::raw(accuracy(ColorChess::White)),
+                    Span::styled("  Black ", label),
+                    Span::raw(accuracy(ColorChess::Black)),
+                    Span::styled(format!("  [{}]", app.keys.describe(Action::Review)), key),
+                    Span::raw(" Review"),
+                ]),
+                Spans::from(""),
+            ]);
+        } else if let Some((done, total)) = app.review_progress {
+            summary.extend([
+                Spans::from(Span::styled(
+                    format!("Reviewing the game... {}/{}", done, total),
+                    label,
+                )),
+                Spans::from(""),
+            ]);
+        }
+        summary.extend([Spans::from(vec![
+            Span::styled("[n]", key),
+            Span::raw(" New game  "),
+            Span::styled("[m]", key),
+            Span::raw(" Rematch  "),
+            Span::styled("[u]", key),
+            Span::raw(" Undo  "),
+            Span::styled("[q]", key),
+            Span::raw(" Quit"),
+        ])]);
+
+        let popup_area = centered_rect(46, summary.len() as u16 + 2, board_area);
+        let popup = Paragraph::new(summary)
+            .alignment(Alignment::Center)
+            .block(Block::default().borders(Borders::ALL).title(" Game Over "));
+        f.render_widget(Clear, popup_area);
+        f.render_widget(popup, popup_area);
+    }
+
+    if app.show_help {
+        let area = f.size();
+        draw_help(f, app, area);
+    }
+}
@@ -0,0 +1,398 @@
+use std::{
+    io::{self, BufRead},
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, Sender},
+    },
+    thread,
+    time::Duration,
+};
+
+use chess_rs::search::{DEFAULT_MOVES_TO_GO, MAX_DEPTH};
+use chess_rs::{ColorChess, Game, SearchLimits, SearchResult, Searcher, Tablebase, Termination};
+
+// Clock assumed until the GUI sends `level` and `time`
+const DEFAULT_CLOCK: Duration = Duration::from_secs(300);
+
+// Most search threads `cores` can ask for
+const MAX_THREADS: usize = 64;
+
+// Input lines and finished searches arrive on the same channel, so the engine
+// can answer `?` and `ping` while it thinks
+enum Event {
+    Line(String),
+    // Search id, the searcher handed back, and what it found
+    Done(u64, Box<Searcher>, SearchResult),
+}
+
+// How the GUI set the engine's thinking time
+enum TimeControl {
+    // `level MPS BASE INC`; the remaining time itself comes with `time`
+    Clock {
+        moves_per_session: u32,
+        increment: Duration,
+    },
+    // `st SECONDS`: a fixed time per move
+    PerMove(Duration),
+}
+
+// Plays the engine side of the XBoard/WinBoard protocol (CECP) on stdin/stdout
+struct XboardEngine {
+    // None after a `setboard` that could not be read, until `new` or the next
+    // one, so the engine does not play on in the position before it
+    board: Option<Game>,
+    // None while a search has it
+    searcher: Option<Searcher>,
+    // Side the engine plays; None in force mode, where it only follows the moves
+    engine_side: Option<ColorChess>,
+    time_control: TimeControl,
+    max_depth: u32,
+    // Engine's remaining time, from `time`
+    clock: Duration,
+    // Whether to print thinking output
+    post: bool,
+    // Id and stop flag of the search whose move will be played
+    thinking: Option<(u64, Arc<AtomicBool>)>,
+    next_search_id: u64,
+    // From `egtpath syzygy`; kept for searchers made later
+    tablebase: Option<Arc<Tablebase>>,
+    // From `cores`
+    threads: usize,
+    events: Sender<Event>,
+}
+
+// Reads commands until `quit` or the end of input
+pub fn run() -> io::Result<()> {
+    let (events, event_rx) = mpsc::channel();
+    let line_tx = events.clone();
+    thread::spawn(move || {
+        for line in io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line_tx.send(Event::Line(line)).is_err() {
+                return;
+            }
+        }
+        let _ = line_tx.send(Event::Line("quit".to_string()));
+    });
+
+    let mut engine = XboardEngine::new(events);
+    while let Ok(event) = event_rx.recv() {
+        match event {
+            Event::Line(line) => {
+                if !engine.handle_command(&line) {
+                    break;
+                }
+            }
+            Event::Done(id, searcher, result) => engine.finish_search(id, *searcher, result),
+        }
+    }
+    engine.interrupt();
+    Ok(())
+}
+
+impl XboardEngine {
+    fn new(events: Sender<Event>) -> XboardEngine {
+        XboardEngine {
+            board: Some(Game::new()),
+            searcher: Some(Searcher::new()),
+            engine_side: Some(ColorChess::Black),
+            time_control: TimeControl::Clock {
+                moves_per_session: 0,
+                increment: Duration::ZERO,
+            },
+            max_depth: MAX_DEPTH,
+            clock: DEFAULT_CLOCK,
+            post: false,
+            thinking: None,
+            next_search_id: 0,
+            tablebase: None,
+            threads: 1,
+            events,
+        }
+    }
+
+    // Returns false once the engine should exit
+    fn handle_command(&mut self, line: &str) -> bool {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return true;
+        };
+        let args: Vec<&str> = words.collect();
+        let number = |index: usize| args.get(index).and_then(|w| w.parse::<u64>().ok());
+        match command {
+            "protover" => println!(
+                "feature myname=\"Chess-rs {}\" ping=1 setboard=1 usermove=1 colors=0 \
+                 sigint=0 sigterm=0 egt=\"syzygy\" smp=1 done=1",
+                env!("CARGO_PKG_VERSION")
+            ),
+            "new" => {
+                self.interrupt();
+                self.board = Some(Game::new());
+                self.engine_side = Some(ColorChess::Black);
+                self.max_depth = MAX_DEPTH;
+                if let Some(searcher) = &mut self.searcher {
+                    searcher.clear();
+                }
+            }
+            "force" | "result" => {
+                self.interrupt();
+                self.engine_side = None;
+            }
+            "go" | "playother" => {
+                let Some(board) = &self.board else {
+                    println!("Error (no position): {}", command);
+                    return true;
+                };
+                let turn = board.get_current_turn();
+                if command == "go" {
+                    self.engine_side = Some(turn);
+                    self.start_search();
+                } else {
+                    self.engine_side = Some(turn.opposite());
+                }
+            }
+            "level" => {
+                if let Some(base) = args.get(1).and_then(|base| parse_base_time(base)) {
+                    self.clock = base;
+                }
+                self.time_control = TimeControl::Clock {
+                    moves_per_session: number(0).unwrap_or(0) as u32,
+                    increment: args
+                        .get(2)
+                        .and_then(|w| w.parse::<f64>().ok())
+                        .map_or(Duration::ZERO, Duration::from_secs_f64),
+                };
+            }
+            "st" => {
+                if let Some(seconds) = number(0) {
+                    self.time_control = TimeControl::PerMove(Duration::from_secs(seconds));
+                }
+            }
+            "sd" => {
+                if let Some(depth) = number(0) {
+                    self.max_depth = (depth as u32).clamp(1, MAX_DEPTH);
+                }
+            }
+            // The engine's clock in centiseconds
+            "time" => {
+                if let Some(centis) = number(0) {
+                    self.clock = Duration::from_millis(centis * 10);
+                }
+            }
+            "usermove" => {
+                if let Some(text) = args.first() {
+                    self.user_move(text);
+                }
+            }
+            "setboard" => {
+                self.interrupt();
+                let board = Game::from_fen(&args.join(" "));
+                if let Err(e) = &board {
+                    println!("tellusererror Illegal position: {}", e);
+                }
+                self.board = board.ok();
+            }
+            "undo" => {
+                self.interrupt();
+                if let Some(board) = &mut self.board {
+                    board.undo();
+                }
+            }
+            "remove" => {
+                self.interrupt();
+                if let Some(board) = &mut self.board {
+                    board.undo();
+                    board.undo();
+                }
+            }
+            // Move now: the search stops and its best move so far is played
+            "?" => {
+                if let Some((_, stop)) = &self.thinking {
+                    stop.store(true, Ordering::Relaxed);
+                }
+            }
+            "egtpath" if args.first() == Some(&"syzygy") => {
+                self.interrupt();
+                match Tablebase::open(&args[1..].join(" ")) {
+                    Ok(tablebase) => self.tablebase = Some(Arc::new(tablebase)),
+                    Err(e) => println!("tellusererror {}", e),
+                }
+                if let Some(searcher) = &mut self.searcher {
+                    searcher.set_tablebase(self.tablebase.clone());
+                }
+            }
+            "cores" => {
+                self.threads = number(0).unwrap_or(1).clamp(1, MAX_THREADS as u64) as usize;
+                if let Some(searcher) = &mut self.searcher {
+                    searcher.set_threads(self.threads);
+                }
+            }
+            "ping" => println!("pong {}", args.first().unwrap_or(&"")),
+            "post" => self.post = true,
+            "nopost" => self.post = false,
+            "quit" => return false,
+            "xboard" | "accepted" | "rejected" | "random" | "hard" | "easy" | "computer"
+            | "otim" | "name" | "rating" | "draw" => {}
+            _ => println!("Error (unknown command): {}", command),
+        }
+        true
+    }
+
+    fn user_move(&mut self, text: &str) {
+        let Some(board) = &mut self.board else {
+            println!("Error (no position): usermove {}", text);
+            return;
+        };
+        if board
+            .parse_uci_move(text)
+            .and_then(|mv| board.play_move(mv))
+            .is_err()
+        {
+            println!("Illegal move: {}", text);
+            return;
+        }
+        let turn = board.get_current_turn();
+        if !self.report_result() && self.engine_side == Some(turn) {
+            self.start_search();
+        }
+    }
+
+    fn start_search(&mut self) {
+        let Some(board) = &self.board else {
+            return;
+        };
+        if self.thinking.is_some() || board.get_result().is_some() {
+            return;
+        }
+        let mut limits = match self.time_control {
+            TimeControl::PerMove(time) => SearchLimits::time(time),
+            TimeControl::Clock {
+                moves_per_session,
+                increment,
+            } => {
+                let moves_to_go = if moves_per_session > 0 {
+                    moves_per_session - (board.get_fullmove_number() - 1) % moves_per_session
+                } else {
+                    DEFAULT_MOVES_TO_GO
+                };
+                SearchLimits::clock(self.clock, increment, moves_to_go)
+            }
+        };
+        limits.max_depth = self.max_depth;
+
+        let id = self.next_search_id;
+        self.next_search_id += 1;
+        let stop = Arc::new(AtomicBool::new(false));
+        // A searcher still busy with an abandoned search is replaced by a fresh one
+        let mut searcher = self.searcher.take().unwrap_or_else(|| {
+            let mut searcher = Searcher::new();
+            searcher.set_tablebase(self.tablebase.clone());
+            searcher.set_threads(self.threads);
+            searcher
+        });
+        let board = board.clone();
+        let post = self.post;
+        let events = self.events.clone();
+        let thread_stop = stop.clone();
+        thread::spawn(move || {
+            searcher.set_stop_signal(Some(thread_stop));
+            let result = searcher.search_with_progress(&board, limits, |result| {
+                if post {
+                    println!("{}", thinking_line(result));
+                }
+            });
+            searcher.set_stop_signal(None);
+            let _ = events.send(Event::Done(id, Box::new(searcher), result));
+        });
+        self.thinking = Some((id, stop));
+    }
+
+    fn finish_search(&mut self, id: u64, searcher: Searcher, result: SearchResult) {
+        self.searcher = Some(searcher);
+        if self
+            .thinking
+            .as_ref()
+            .is_none_or(|(current, _)| *current != id)
+        {
+            return;
+        }
+        self.thinking = None;
+        let (Some(mv), Some(board)) = (result.best_move, &mut self.board) else {
+            return;
+        };
+        if board.play_move(mv).is_err() {
+            return;
+        }
+        println!("move {}", mv.to_uci());
+        self.report_result();
+    }
+
+    // Abandons the current search; its move will not be played
+    fn interrupt(&mut self) {
+        if let Some((_, stop)) = self.thinking.take() {
+            stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    // Tells the GUI if the game has ended; returns whether it has
+    fn report_result(&self) -> bool {
+        let Some(result) = self.board.as_ref().and_then(|board| board.get_result()) else {
+            return false;
+        };
+        let reason = match (result.reason, result.winner) {
+            (Termination::Checkmate, Some(ColorChess::White)) => "White mates",
+            (Termination::Checkmate, _) => "Black mates",
+            (Termination::Repetition, _) => "Draw by repetition",
+            (Termination::FiftyMoves, _) => "Draw by fifty move rule",
+            (Termination::InsufficientMaterial, _) => "Insufficient material",
+            _ => "Stalemate",
+        };
+        println!("{} {{{}}}", result.pgn_tag(), reason);
+        true
+    }
+}
+
+// BASE of `level` is minutes, or minutes:seconds
+fn parse_base_time(text: &str) -> Option<Duration> {
+    let (minutes, seconds) = text.split_once(':').unwrap_or((text, "0"));
+    let minutes: u64 = minutes.parse().ok()?;
+    let seconds: u64 = seconds.parse().ok()?;
+    Some(Duration::from_secs(minutes * 60 + seconds))
+}
+
+// ply score time nodes pv, with the time in centiseconds
+fn thinking_line(result: &SearchResult) -> String {
+    let pv: Vec<String> = result.pv.iter().map(|mv| mv.to_uci()).collect();
+    format!(
+        "{} {} {} {} {}",
+        result.depth,
+        result.score,
+        result.elapsed.as_millis() / 10,
+        result.nodes,
+        pv.join(" ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_rejected_setboard_leaves_no_position_to_play_on() {
+        let (events, _event_rx) = mpsc::channel();
+        let mut engine = XboardEngine::new(events);
+        engine.handle_command("usermove e2e4");
+        engine.handle_command("setboard 8/8/8 w");
+        assert!(engine.board.is_none());
+        engine.handle_command("go");
+        assert!(engine.thinking.is_none());
+        engine.handle_command("setboard 4k3/8/8/8/8/8/8/4K2R w K - 0 1");
+        assert_eq!(
+            engine.board.as_ref().map(|board| board.to_fen()).as_deref(),
+            Some("4k3/8/8/8/8/8/8/4K2R w K - 0 1")
+        );
+    }
+}
@@ -0,0 +1,162 @@
+use crate::bitboard::pawn_attacks;
+use crate::board::{ColorChess, Piece, PieceType};
+use crate::polyglot_keys::RANDOM64;
+use crate::position::Position;
+
+// The position hash is the Polyglot key, so the same number serves the
+// transposition table, repetition checks and opening book lookups
+
+pub(crate) fn piece_key(piece: Piece, (row, col): (usize, usize)) -> u64 {
+    let kind = piece.piece_type().index() * 2 + usize::from(piece.is_color(ColorChess::White));
+    RANDOM64[64 * kind + 8 * row + col]
+}
+
+// Toggled whenever the turn passes (Polyglot hashes it in with White to move)
+pub(crate) fn side_key() -> u64 {
+    RANDOM64[780]
+}
+
+impl Position {
+    // Remaining castling rights as a 4-bit mask (KQkq)
+    pub(crate) fn castling_rights(&self) -> usize {
+        let mut rights = 0;
+        if !self.white_king_moved && !self.white_rook_king_side_moved {
+            rights |= 1;
+        }
+        if !self.white_king_moved && !self.white_rook_queen_side_moved {
+            rights |= 2;
+        }
+        if !self.black_king_moved && !self.black_rook_king_side_moved {
+            rights |= 4;
+        }
+        if !self.black_king_moved && !self.black_rook_queen_side_moved {
+            rights |= 8;
+        }
+        rights
+    }
+
+    pub(crate) fn castling_key(&self) -> u64 {
+        let rights = self.castling_rights();
+        (0..4)
+            .filter(|bit| rights & (1 << bit) != 0)
+            .fold(0, |key, bit| key ^ RANDOM64[768 + bit])
+    }
+
+    // The en passant file only counts when a pawn of `capturer` could actually
+    // take there. apply_move passes the color explicitly, since the turn has
+    // not switched yet when it updates the hash.
+    pub(crate) fn en_passant_key(&self, capturer: ColorChess) -> u64 {
+        match self.en_passant_target {
+            Some(target)
+                if pawn_attacks(target, capturer.opposite())
+                    & self.pieces_of(capturer, PieceType::Pawn)
+                    != 0 =>
+            {
+                RANDOM64[772 + target.1]
+            }
+            _ => 0,
+        }
+    }
+
+    // Hashes the position from scratch; make/unmake keep `hash` up to date
+    // so this is only needed when a position is set up directly
+    pub fn compute_hash(&self) -> u64 {
+        let mut hash = 0;
+        for row in 0..8 {
+            for col in 0..8 {
+                if let Some(piece) = self.squares[row][col] {
+                    hash ^= piece_key(piece, (row, col));
+                }
+            }
+        }
+        if self.current_turn == ColorChess::White {
+            hash ^= side_key();
+        }
+        hash ^ self.castling_key() ^ self.en_passant_key(self.current_turn)
+    }
+
+    // Zobrist key of the current position, kept up to date move by move
+    pub fn hash(&self) -> u64 {
+        self.hash
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::game::Game;
+    use crate::position::Position;
+
+    // The reference keys of the Polyglot book format, each after the moves
+    // before it
+    const KEYS: [(&str, &str, u64); 9] = [
+        (
+            "",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w KQkq - 0 1",
+            0x463b96181691fc9c,
+        ),
+        (
+            "e2e4",
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b KQkq e3 0 1",
+            0x823c9b50fd114196,
+        ),
+        (
+            "e2e4 d7d5",
+            "rnbqkbnr/ppp1pppp/8/3p4/4P3/8/PPPP1PPP/RNBQKBNR w KQkq d6 0 2",
+            0x0756b94461c50fb0,
+        ),
+        (
+            "e2e4 d7d5 e4e5",
+            "rnbqkbnr/ppp1pppp/8/3pP3/8/8/PPPP1PPP/RNBQKBNR b KQkq - 0 2",
+            0x662fafb965db29d4,
+        ),
+        (
+            "e2e4 d7d5 e4e5 f7f5",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPP1PPP/RNBQKBNR w KQkq f6 0 3",
+            0x22a48b5a8e47ff78,
+        ),
+        (
+            "e2e4 d7d5 e4e5 f7f5 e1e2",
+            "rnbqkbnr/ppp1p1pp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR b kq - 0 3",
+            0x652a607ca3f242c1,
+        ),
+        (
+            "e2e4 d7d5 e4e5 f7f5 e1e2 e8f7",
+            "rnbq1bnr/ppp1pkpp/8/3pPp2/8/8/PPPPKPPP/RNBQ1BNR w - - 0 4",
+            0x00fdd303c946bdd9,
+        ),
+        (
+            "a2a4 b7b5 h2h4 b5b4 c2c4",
+            "rnbqkbnr/p1pppppp/8/8/PpP4P/8/1P1PPPP1/RNBQKBNR b KQkq c3 0 3",
+            0x3c8123ea7b067637,
+        ),
+        (
+            "a2a4 b7b5 h2h4 b5b4 c2c4 b4c3 a1a3",
+            "rnbqkbnr/p1pppppp/8/8/P6P/R1p5/1P1PPPP1/1NBQKBNR b Kkq - 0 4",
+            0x5c3f9b829b279560,
+        ),
+    ];
+
+    #[test]
+    fn fen_positions_hash_to_the_polyglot_keys() {
+        for (_, fen, key) in KEYS {
+            let position = Position::from_fen(fen).unwrap();
+            assert_eq!(position.hash(), key, "{}", fen);
+            assert_eq!(position.compute_hash(), key, "{}", fen);
+        }
+    }
+
+    #[test]
+    fn moves_keep_the_hash_up_to_date() {
+        for (moves, fen, key) in KEYS {
+            let mut game = Game::new();
+            game.play_moves(moves).unwrap();
+            assert_eq!(game.hash(), key, "after {:?}", moves);
+            // The reference FENs do not keep the halfmove clock
+            let placement = |fen: &str| fen.split(' ').take(4).collect::<Vec<_>>().join(" ");
+            assert_eq!(placement(&game.to_fen()), placement(fen));
+            // Taking the moves back restores the start position's key
+            while game.undo().is_some() {}
+            assert_eq!(game.hash(), KEYS[0].2, "undoing {:?}", moves);
+        }
+    }
+}
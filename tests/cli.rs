@@ -0,0 +1,244 @@
+// Drives the binary with --no-tui --json and checks the lines it prints, and
+// plays a game through its fog-of-war server
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+use std::thread;
+use std::time::Duration;
+
+use serde_json::Value;
+
+// A config.toml that does not exist, so each run starts from the defaults
+// and leaves the player's own settings alone
+fn config_path(test: &str) -> PathBuf {
+    std::env::temp_dir().join(format!(
+        "chess-rs-{}-{}/config.toml",
+        test,
+        std::process::id()
+    ))
+}
+
+// Runs the binary with `args`, typing `input` into it
+fn run(test: &str, args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_chess-rs"))
+        .arg("--config")
+        .arg(config_path(test))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    // A run that ends before reading everything closes its input early
+    let _ = child.stdin.take().unwrap().write_all(input.as_bytes());
+    child.wait_with_output().unwrap()
+}
+
+// The JSON lines of a successful run
+fn events(output: &Output) -> Vec<Value> {
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout.clone())
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect()
+}
+
+#[test]
+fn moves_that_mate_end_the_game() {
+    let output = run(
+        "mate",
+        &["--no-tui", "--json", "--moves", "f3 e5 g4 Qh4"],
+        "",
+    );
+    let events = events(&output);
+    assert_eq!(events.len(), 2);
+    assert_eq!(events[0]["event"], "start");
+    assert_eq!(
+        events[0]["fen"],
+        "rnb1kbnr/pppp1ppp/8/4p3/6Pq/5P2/PPPPP2P/RNBQKBNR w KQkq - 1 3"
+    );
+    assert_eq!(
+        events[1],
+        serde_json::json!({
+            "event": "game_over",
+            "result": "0-1",
+            "reason": "checkmate",
+            "message": "Checkmate! Black wins. (0-1)",
+        })
+    );
+}
+
+#[test]
+fn play_goes_on_from_the_moves_given() {
+    let output = run(
+        "continue",
+        &["--no-tui", "--json", "--moves", "e2e4 e7e5"],
+        "Nf3\nNf3\nquit\n",
+    );
+    let events = events(&output);
+    assert_eq!(
+        events[0]["fen"],
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2"
+    );
+    assert_eq!(events[1]["event"], "move");
+    assert_eq!(events[1]["san"], "Nf3");
+    assert_eq!(events[1]["uci"], "g1f3");
+    assert_eq!(events[1]["engine"], false);
+    assert_eq!(
+        events[1]["fen"],
+        "rnbqkbnr/pppp1ppp/8/4p3/4P3/5N2/PPPP1PPP/RNBQKB1R b KQkq - 1 2"
+    );
+    // Black has no knight that reaches f3
+    assert_eq!(events[2]["event"], "illegal");
+    assert_eq!(events[2]["input"], "Nf3");
+    assert_eq!(events.len(), 3);
+}
+
+#[test]
+fn the_engine_answers_the_moves_given() {
+    let output = run(
+        "engine",
+        &["--no-tui", "--json", "--color", "white", "--moves", "e4"],
+        "",
+    );
+    let events = events(&output);
+    assert_eq!(events[0]["engine"], "black");
+    assert_eq!(events[1]["event"], "move");
+    assert_eq!(events[1]["side"], "black");
+    assert_eq!(events[1]["engine"], true);
+    // White is to move again after the reply
+    let fen = events[1]["fen"].as_str().unwrap();
+    assert_eq!(fen.split(' ').nth(1), Some("w"));
+}
+
+#[test]
+fn illegal_moves_are_refused_on_start_up() {
+    let output = run("illegal", &["--no-tui", "--json", "--moves", "e4 e4"], "");
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+    let error = String::from_utf8_lossy(&output.stderr);
+    assert!(error.contains("--moves: e4 is not legal"), "{}", error);
+}
+
+#[test]
+fn game_options_are_refused_by_other_commands() {
+    for (args, error) in [
+        (
+            &["perft", "2", "--moves", "e2e4"][..],
+            "--moves does not apply to perft",
+        ),
+        (
+            &["perft", "1", "--color", "white"],
+            "--color does not apply to perft",
+        ),
+        (
+            &["validate", "8/8/8/8/8/8/8/8 w - - 0 1", "--no-tui"],
+            "--no-tui does not apply to validate",
+        ),
+        (
+            &["bench", "--variant", "fog"],
+            "--variant does not apply to bench",
+        ),
+    ] {
+        let output = run("options", args, "");
+        assert!(!output.status.success());
+        assert!(output.stdout.is_empty());
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(error), "{}", stderr);
+    }
+}
+
+#[test]
+fn the_fog_server_plays_a_game_between_two_connections() {
+    // A port nothing is listening on
+    let port = TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port();
+    let mut server = Command::new(env!("CARGO_BIN_EXE_chess-rs"))
+        .args(["fog-server", "--port", &port.to_string()])
+        .stdout(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let connect = || {
+        for _ in 0..100 {
+            if let Ok(stream) = TcpStream::connect(("127.0.0.1", port)) {
+                return stream;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+        panic!("the server never listened on {}", port);
+    };
+    let (mut writers, mut readers): (Vec<_>, Vec<_>) = (0..2)
+        .map(|_| {
+            let stream = connect();
+            let reader = BufReader::new(stream.try_clone().unwrap());
+            (stream, reader)
+        })
+        .unzip();
+    let mut next = |player: usize| {
+        let mut line = String::new();
+        readers[player].read_line(&mut line).unwrap();
+        serde_json::from_str::<Value>(&line).unwrap()
+    };
+    for (player, color) in [(0, "white"), (1, "black")] {
+        assert_eq!(
+            next(player),
+            serde_json::json!({"type": "start", "color": color})
+        );
+        let view = next(player);
+        assert_eq!(view["type"], "view");
+        assert_eq!(view["hidden"].as_array().unwrap().len(), 32);
+    }
+
+    let mut play = |player: usize, uci: &str| {
+        let line = serde_json::json!({"type": "move", "uci": uci}).to_string();
+        writeln!(writers[player], "{}", line).unwrap();
+    };
+    // Black is not on move
+    play(1, "e7e5");
+    assert_eq!(next(1)["type"], "illegal");
+    assert_eq!(next(1)["type"], "view");
+    for (player, uci) in [(0, "f2f3"), (1, "e7e5"), (0, "g2g4")] {
+        play(player, uci);
+        for viewer in 0..2 {
+            let view = next(viewer);
+            assert_eq!(view["type"], "view");
+            // Only the side to move is sent its moves
+            assert_eq!(
+                view["moves"].as_array().unwrap().is_empty(),
+                viewer == player
+            );
+        }
+    }
+    play(1, "d8h4");
+    // White is not checkmated, as there is no check, and plays on
+    for viewer in 0..2 {
+        assert_eq!(next(viewer)["type"], "view");
+    }
+    play(0, "a2a3");
+    for viewer in 0..2 {
+        assert_eq!(next(viewer)["type"], "view");
+    }
+    play(1, "h4e1");
+    for player in 0..2 {
+        assert_eq!(
+            next(player),
+            serde_json::json!({
+                "type": "end",
+                "result": "0-1",
+                "reason": "king_captured",
+                "fen": "rnb1kbnr/pppp1ppp/8/4p3/6P1/P4P2/1PPPP2P/RNBQqBNR w kq - 0 4",
+            })
+        );
+    }
+    assert!(server.wait().unwrap().success());
+}